@@ -0,0 +1,55 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    grpc();
+}
+
+/// Generate the tonic service stubs for `src/grpc.rs`
+///
+/// The service is described in Rust rather than compiled from
+/// `proto/ellm.proto` so that building does not require `protoc`; the two
+/// must be kept in sync.
+#[cfg(feature = "grpc")]
+fn grpc() {
+    use tonic_build::manual::{Builder, Method, Service};
+
+    let method = |name: &str, route: &str, input: &str, output: &str| {
+        Method::builder()
+            .name(name)
+            .route_name(route)
+            .input_type(format!("crate::grpc::{}", input))
+            .output_type(format!("crate::grpc::{}", output))
+            .codec_path("tonic_prost::ProstCodec")
+            .build()
+    };
+
+    let service = Service::builder()
+        .name("Ellm")
+        .package("ellm.v1")
+        .method(method("send", "Send", "SendRequest", "SendReply"))
+        .method(
+            Method::builder()
+                .name("stream")
+                .route_name("Stream")
+                .input_type("crate::grpc::SendRequest")
+                .output_type("crate::grpc::StreamReply")
+                .codec_path("tonic_prost::ProstCodec")
+                .server_streaming()
+                .build(),
+        )
+        .method(method(
+            "structured",
+            "Structured",
+            "StructuredRequest",
+            "StructuredReply",
+        ))
+        .method(method("ask_bool", "AskBool", "BoolRequest", "BoolReply"))
+        .method(method(
+            "translate",
+            "Translate",
+            "TranslateRequest",
+            "TranslateReply",
+        ))
+        .build();
+
+    Builder::new().build_client(false).compile(&[service]);
+}
@@ -0,0 +1,385 @@
+//! Python bindings for ellm
+//!
+//! Exposes `Config` and a blocking `Client` to Python. Requests run on a
+//! tokio runtime owned by the client with the GIL released, so other Python
+//! threads keep running while a request is in flight. Configuration is loaded
+//! the same way as the CLI, so the organization policy, guardrails and
+//! redaction apply to notebooks too.
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+create_exception!(ellm, EllmError, PyException);
+
+fn to_py_err(error: ellm::ClaudeError) -> PyErr {
+    EllmError::new_err(error.to_string())
+}
+
+/// Convert a Python object to JSON via the `json` module
+fn to_json(py: Python<'_>, value: &Bound<'_, PyAny>) -> PyResult<serde_json::Value> {
+    let text: String = py
+        .import("json")?
+        .call_method1("dumps", (value,))?
+        .extract()?;
+    serde_json::from_str(&text).map_err(|e| EllmError::new_err(e.to_string()))
+}
+
+/// Convert JSON to a Python object via the `json` module
+fn from_json<'py>(py: Python<'py>, value: &serde_json::Value) -> PyResult<Bound<'py, PyAny>> {
+    py.import("json")?
+        .call_method1("loads", (value.to_string(),))
+}
+
+/// Client configuration
+#[pyclass(name = "Config")]
+#[derive(Clone)]
+struct Config {
+    inner: ellm::Config,
+}
+
+#[pymethods]
+impl Config {
+    #[new]
+    fn new(api_key: String) -> Self {
+        Self {
+            inner: ellm::Config::new(api_key),
+        }
+    }
+
+    /// Load configuration the same way as the `ellm` CLI
+    #[staticmethod]
+    #[pyo3(signature = (api_key=None))]
+    fn load(api_key: Option<String>) -> PyResult<Self> {
+        let inner = ellm::Config::load(api_key).map_err(to_py_err)?;
+        Ok(Self { inner })
+    }
+
+    #[getter]
+    fn model(&self) -> String {
+        self.inner.model.clone()
+    }
+
+    #[setter]
+    fn set_model(&mut self, model: String) {
+        self.inner.model = model;
+    }
+
+    #[getter]
+    fn max_tokens(&self) -> u32 {
+        self.inner.max_tokens
+    }
+
+    #[setter]
+    fn set_max_tokens(&mut self, max_tokens: u32) {
+        self.inner.max_tokens = max_tokens;
+    }
+
+    #[getter]
+    fn base_url(&self) -> String {
+        self.inner.base_url.clone()
+    }
+
+    #[setter]
+    fn set_base_url(&mut self, base_url: String) {
+        self.inner.base_url = base_url;
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Config(model={:?}, max_tokens={})",
+            self.inner.model, self.inner.max_tokens
+        )
+    }
+}
+
+/// Blocking client for the Claude API
+#[pyclass(name = "Client")]
+struct Client {
+    inner: Arc<ellm::Client>,
+    runtime: Arc<tokio::runtime::Runtime>,
+}
+
+/// The text of a reply as it is generated, returned by `Client.stream`
+///
+/// The reply is read on the client's runtime in the background; iterating
+/// waits for the next chunk with the GIL released. Dropping the iterator
+/// ends the request.
+#[pyclass(name = "TextStream")]
+struct TextStream {
+    chunks: mpsc::Receiver<ellm::Result<String>>,
+    // Keeps the runtime reading the reply alive as long as the iterator
+    _runtime: Arc<tokio::runtime::Runtime>,
+}
+
+#[pymethods]
+impl TextStream {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<String>> {
+        let chunks = &mut self.chunks;
+        py.detach(|| chunks.blocking_recv())
+            .transpose()
+            .map_err(to_py_err)
+    }
+}
+
+/// Send the text of the reply to `messages` to `chunks` as it arrives
+///
+/// Text that output guardrails or response hooks may change is only sent
+/// once, when it is final.
+async fn stream_text(
+    client: &ellm::Client,
+    messages: ellm::Messages,
+    system: Option<String>,
+    chunks: &mpsc::Sender<ellm::Result<String>>,
+) -> ellm::Result<()> {
+    let live = !client.config().guardrails.filters_output() && !client.has_response_hooks();
+    let mut stream = client
+        .stream_message(messages, system, Vec::new(), None)
+        .await?;
+
+    while let Some(event) = stream.next_event().await {
+        if let (ellm::StreamEvent::TextDelta { text, .. }, true) = (event?, live) {
+            // The iterator is gone; dropping the stream ends the request
+            if chunks.send(Ok(text)).await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+
+    let message = stream.finish().await?;
+    if !live {
+        let _ = chunks.send(Ok(message.text())).await;
+    }
+    Ok(())
+}
+
+impl Client {
+    /// Run a request to completion with the GIL released
+    fn block_on<T: Send>(
+        &self,
+        py: Python<'_>,
+        future: impl std::future::Future<Output = ellm::Result<T>> + Send,
+    ) -> PyResult<T> {
+        py.detach(|| self.runtime.block_on(future))
+            .map_err(to_py_err)
+    }
+}
+
+#[pymethods]
+impl Client {
+    /// Create a client, loading the configuration when none is given
+    #[new]
+    #[pyo3(signature = (config=None))]
+    fn new(config: Option<Config>) -> PyResult<Self> {
+        let config = match config {
+            Some(config) => config.inner,
+            None => ellm::Config::load(None).map_err(to_py_err)?,
+        };
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+
+        Ok(Self {
+            inner: Arc::new(ellm::Client::new(config).map_err(to_py_err)?),
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    /// Send a prompt and return the reply text, which starts with `prefill` if given
+    #[pyo3(signature = (prompt, system=None, prefill=None))]
+    fn send(
+        &self,
+        py: Python<'_>,
+        prompt: String,
+        system: Option<String>,
+        prefill: Option<String>,
+    ) -> PyResult<String> {
+        let messages = ellm::Messages::new().push_user(prompt).clone();
+        self.block_on(py, self.inner.send_message(messages, prefill, system))
+    }
+
+    /// Send a prompt and iterate over the text of the reply as it is generated
+    #[pyo3(signature = (prompt, system=None))]
+    fn stream(&self, prompt: String, system: Option<String>) -> TextStream {
+        let messages = ellm::Messages::new().push_user(prompt).clone();
+        let client = self.inner.clone();
+        let (sender, chunks) = mpsc::channel(64);
+        self.runtime.spawn(async move {
+            if let Err(error) = stream_text(&client, messages, system, &sender).await {
+                let _ = sender.send(Err(error)).await;
+            }
+        });
+
+        TextStream {
+            chunks,
+            _runtime: self.runtime.clone(),
+        }
+    }
+
+    /// Extract a dict matching a JSON schema from text
+    ///
+    /// Text too long for the model's context window is extracted from in parts
+    /// that are merged.
+    #[pyo3(signature = (text, schema, instructions=None, max_retries=3))]
+    fn extract<'py>(
+        &self,
+        py: Python<'py>,
+        text: String,
+        schema: &Bound<'py, PyAny>,
+        instructions: Option<String>,
+        max_retries: usize,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let schema = to_json(py, schema)?;
+        let value = self.block_on(
+            py,
+            self.inner.extract(
+                &text,
+                &schema,
+                instructions,
+                max_retries,
+                &ellm::LongDocOptions::default(),
+            ),
+        )?;
+        from_json(py, &value)
+    }
+
+    /// Ask a yes/no question; returns a dict with answer, confidence and explanation
+    fn ask_bool<'py>(&self, py: Python<'py>, question: String) -> PyResult<Bound<'py, PyDict>> {
+        let response = self.block_on(py, self.inner.ask_bool(question))?;
+
+        let dict = PyDict::new(py);
+        dict.set_item("answer", response.answer.to_string())?;
+        dict.set_item("confidence", response.confidence)?;
+        dict.set_item("explanation", response.explanation)?;
+        Ok(dict)
+    }
+
+    /// Summarize text of any length
+    #[pyo3(signature = (text, instructions=None))]
+    fn summarize(
+        &self,
+        py: Python<'_>,
+        text: String,
+        instructions: Option<String>,
+    ) -> PyResult<String> {
+        self.block_on(
+            py,
+            self.inner.summarize(
+                &text,
+                instructions.as_deref(),
+                &ellm::LongDocOptions::default(),
+            ),
+        )
+    }
+
+    /// Translate text, preserving code blocks and placeholders
+    #[pyo3(signature = (text, to, source=None))]
+    fn translate(
+        &self,
+        py: Python<'_>,
+        text: String,
+        to: String,
+        source: Option<String>,
+    ) -> PyResult<String> {
+        self.block_on(py, self.inner.translate(&text, &to, source.as_deref()))
+    }
+
+    #[getter]
+    fn config(&self) -> Config {
+        Config {
+            inner: self.inner.config().clone(),
+        }
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "ellm")]
+fn ellm_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Config>()?;
+    m.add_class::<Client>()?;
+    m.add_class::<TextStream>()?;
+    m.add("EllmError", m.py().get_type::<EllmError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_round_trip() {
+        Python::initialize();
+        Python::attach(|py| {
+            let value = serde_json::json!({ "name": "ellm", "tags": ["a", 1, null] });
+            let object = from_json(py, &value).unwrap();
+            assert!(object.is_instance_of::<PyDict>());
+            assert_eq!(to_json(py, &object).unwrap(), value);
+        });
+    }
+
+    /// An HTTP server streaming `events` as the reply to every request
+    fn sse_stub(events: &[&str]) -> String {
+        use std::io::{Read, Write};
+
+        let body: String = events
+            .iter()
+            .map(|data| format!("data: {}\n\n", data))
+            .collect();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut buffer = [0u8; 65536];
+                let _ = stream.read(&mut buffer);
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+        format!("http://{}", address)
+    }
+
+    #[test]
+    fn test_stream() {
+        let url = sse_stub(&[
+            r#"{"type":"message_start","message":{"id":"msg_1","model":"claude-haiku-4-5","usage":{"input_tokens":5}}}"#,
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hel"}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"lo"}}"#,
+            r#"{"type":"content_block_stop","index":0}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":2}}"#,
+            r#"{"type":"message_stop"}"#,
+        ]);
+        let mut config = Config::new("sk-ant-test-key".into());
+        config.set_base_url(url);
+        let client = Client::new(Some(config)).unwrap();
+
+        Python::initialize();
+        Python::attach(|py| {
+            let mut stream = client.stream("hi".into(), None);
+            let mut chunks = Vec::new();
+            while let Some(chunk) = stream.__next__(py).unwrap() {
+                chunks.push(chunk);
+            }
+            assert_eq!(chunks, ["Hel", "lo"]);
+        });
+    }
+
+    #[test]
+    fn test_config_properties() {
+        let mut config = Config::new("sk-ant-test-key".into());
+        config.set_model("claude-haiku-4-5".into());
+        assert_eq!(config.model(), "claude-haiku-4-5");
+        assert!(config.__repr__().contains("claude-haiku-4-5"));
+    }
+}
@@ -0,0 +1,369 @@
+//! Client for the Anthropic Admin API
+//!
+//! Covers organization usage and cost reports, API key listing and workspace
+//! management. These endpoints require an admin key (`sk-ant-admin...`),
+//! which is separate from the key used for requests to the Messages API.
+
+use crate::error::{ApiError, ConfigError, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_BASE_URL: &str = "https://api.anthropic.com/v1";
+
+/// Environment variable holding the admin key
+pub const ADMIN_KEY_ENV: &str = "ANTHROPIC_ADMIN_KEY";
+
+/// Client for the organization Admin API
+pub struct AdminClient {
+    http_client: reqwest::Client,
+    base_url: String,
+    admin_key: String,
+}
+
+/// Filters for the usage and cost reports
+#[derive(Debug, Clone, Default)]
+pub struct ReportQuery {
+    /// Start of the report, RFC 3339 or a `YYYY-MM-DD` date
+    pub since: String,
+    /// End of the report (exclusive), RFC 3339 or a `YYYY-MM-DD` date
+    pub until: Option<String>,
+    /// Fields to break the report down by, e.g. `model` or `workspace_id`
+    pub group_by: Vec<String>,
+}
+
+/// Token counts for one time bucket and group
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UsageResult {
+    pub uncached_input_tokens: u64,
+    pub cache_creation: CacheCreation,
+    pub cache_read_input_tokens: u64,
+    pub output_tokens: u64,
+    pub model: Option<String>,
+    pub workspace_id: Option<String>,
+    pub api_key_id: Option<String>,
+    pub service_tier: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheCreation {
+    pub ephemeral_5m_input_tokens: u64,
+    pub ephemeral_1h_input_tokens: u64,
+}
+
+impl UsageResult {
+    /// Tokens written to the prompt cache, across all cache lifetimes
+    pub fn cache_write_tokens(&self) -> u64 {
+        self.cache_creation.ephemeral_5m_input_tokens
+            + self.cache_creation.ephemeral_1h_input_tokens
+    }
+}
+
+/// Costs for one time bucket and group
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CostResult {
+    pub currency: String,
+    /// Amount in the currency's smallest unit (cents), as a decimal string
+    pub amount: String,
+    pub workspace_id: Option<String>,
+    pub description: Option<String>,
+    pub cost_type: Option<String>,
+    pub model: Option<String>,
+}
+
+impl CostResult {
+    /// The amount in whole currency units, e.g. dollars
+    pub fn amount(&self) -> f64 {
+        self.amount.parse::<f64>().unwrap_or(0.0) / 100.0
+    }
+}
+
+/// One time bucket of a report
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bucket<T> {
+    pub starting_at: String,
+    pub ending_at: String,
+    pub results: Vec<T>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    pub status: String,
+    #[serde(default)]
+    pub workspace_id: Option<String>,
+    #[serde(default)]
+    pub partial_key_hint: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Workspace {
+    pub id: String,
+    pub name: String,
+    pub created_at: String,
+    #[serde(default)]
+    pub archived_at: Option<String>,
+}
+
+/// A page of a report, continued with `page`
+#[derive(Debug, Deserialize)]
+struct ReportPage<T> {
+    data: Vec<T>,
+    #[serde(default)]
+    has_more: bool,
+    #[serde(default)]
+    next_page: Option<String>,
+}
+
+/// A page of a list, continued with `after_id`
+#[derive(Debug, Deserialize)]
+struct ListPage<T> {
+    data: Vec<T>,
+    #[serde(default)]
+    has_more: bool,
+    #[serde(default)]
+    last_id: Option<String>,
+}
+
+/// Accept a bare date as the start of that day in UTC
+fn timestamp(value: &str) -> String {
+    if value.len() == 10 {
+        format!("{}T00:00:00Z", value)
+    } else {
+        value.to_string()
+    }
+}
+
+impl ReportQuery {
+    fn params(&self) -> Vec<(&'static str, String)> {
+        let mut params = vec![("starting_at", timestamp(&self.since))];
+        if let Some(until) = &self.until {
+            params.push(("ending_at", timestamp(until)));
+        }
+        params.extend(
+            self.group_by
+                .iter()
+                .map(|group| ("group_by[]", group.clone())),
+        );
+        params
+    }
+}
+
+impl AdminClient {
+    pub fn new(admin_key: impl Into<String>) -> Result<Self> {
+        let http_client = reqwest::Client::builder()
+            .build()
+            .map_err(|e| ApiError::InvalidRequest(e.to_string()))?;
+
+        Ok(Self {
+            http_client,
+            base_url: DEFAULT_BASE_URL.to_string(),
+            admin_key: admin_key.into(),
+        })
+    }
+
+    /// Create a client using the admin key from `ANTHROPIC_ADMIN_KEY`
+    pub fn from_env() -> Result<Self> {
+        let key = std::env::var(ADMIN_KEY_ENV).map_err(|_| ConfigError::AdminKeyNotFound)?;
+        Self::new(key)
+    }
+
+    /// Use a different API base URL, e.g. for testing
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Token usage for the organization, one bucket per day
+    pub async fn usage(&self, query: &ReportQuery) -> Result<Vec<Bucket<UsageResult>>> {
+        let mut params = query.params();
+        params.push(("bucket_width", "1d".to_string()));
+        self.report("organizations/usage_report/messages", params)
+            .await
+    }
+
+    /// Costs for the organization, one bucket per day
+    pub async fn cost(&self, query: &ReportQuery) -> Result<Vec<Bucket<CostResult>>> {
+        self.report("organizations/cost_report", query.params())
+            .await
+    }
+
+    /// All API keys in the organization
+    pub async fn api_keys(&self) -> Result<Vec<ApiKey>> {
+        self.list("organizations/api_keys").await
+    }
+
+    /// All workspaces in the organization
+    pub async fn workspaces(&self) -> Result<Vec<Workspace>> {
+        self.list("organizations/workspaces").await
+    }
+
+    pub async fn create_workspace(&self, name: &str) -> Result<Workspace> {
+        self.request(
+            reqwest::Method::POST,
+            "organizations/workspaces",
+            &[],
+            Some(serde_json::json!({ "name": name })),
+        )
+        .await
+    }
+
+    pub async fn archive_workspace(&self, id: &str) -> Result<Workspace> {
+        self.request(
+            reqwest::Method::POST,
+            &format!("organizations/workspaces/{}/archive", id),
+            &[],
+            None,
+        )
+        .await
+    }
+
+    async fn report<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        params: Vec<(&'static str, String)>,
+    ) -> Result<Vec<Bucket<T>>> {
+        let mut buckets = Vec::new();
+        let mut page = None;
+
+        loop {
+            let mut params = params.clone();
+            if let Some(page) = page.take() {
+                params.push(("page", page));
+            }
+
+            let response: ReportPage<Bucket<T>> = self
+                .request(reqwest::Method::GET, path, &params, None)
+                .await?;
+            buckets.extend(response.data);
+
+            match response.next_page {
+                Some(next) if response.has_more => page = Some(next),
+                _ => return Ok(buckets),
+            }
+        }
+    }
+
+    async fn list<T: DeserializeOwned>(&self, path: &str) -> Result<Vec<T>> {
+        let mut items = Vec::new();
+        let mut params = vec![("limit", "100".to_string())];
+
+        loop {
+            let response: ListPage<T> = self
+                .request(reqwest::Method::GET, path, &params, None)
+                .await?;
+            items.extend(response.data);
+
+            match response.last_id {
+                Some(last) if response.has_more => {
+                    params.retain(|(name, _)| *name != "after_id");
+                    params.push(("after_id", last));
+                }
+                _ => return Ok(items),
+            }
+        }
+    }
+
+    async fn request<T: DeserializeOwned>(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        params: &[(&'static str, String)],
+        body: Option<serde_json::Value>,
+    ) -> Result<T> {
+        let mut request = self
+            .http_client
+            .request(method, format!("{}/{}", self.base_url, path))
+            .query(params)
+            .header("x-api-key", &self.admin_key)
+            .header("anthropic-version", "2023-06-01");
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+
+        let response = request.send().await?;
+        let status = response.status().as_u16();
+        let text = response.text().await?;
+
+        if !(200..300).contains(&status) {
+            let message = serde_json::from_str::<serde_json::Value>(&text)
+                .ok()
+                .and_then(|body| {
+                    body.pointer("/error/message")
+                        .and_then(|message| message.as_str())
+                        .map(str::to_string)
+                })
+                .unwrap_or(text);
+            return Err(match status {
+                401 => ApiError::AuthenticationFailed(message),
+                _ => ApiError::ApiError { status, message },
+            }
+            .into());
+        }
+
+        serde_json::from_str(&text).map_err(|e| ApiError::UnexpectedResponse(e.to_string()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_params() {
+        let query = ReportQuery {
+            since: "2024-06-01".to_string(),
+            until: Some("2024-07-01T00:00:00Z".to_string()),
+            group_by: vec!["model".to_string(), "workspace_id".to_string()],
+        };
+        assert_eq!(
+            query.params(),
+            vec![
+                ("starting_at", "2024-06-01T00:00:00Z".to_string()),
+                ("ending_at", "2024-07-01T00:00:00Z".to_string()),
+                ("group_by[]", "model".to_string()),
+                ("group_by[]", "workspace_id".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_usage_page() {
+        let page: ReportPage<Bucket<UsageResult>> = serde_json::from_str(
+            r#"{
+                "data": [{
+                    "starting_at": "2024-06-01T00:00:00Z",
+                    "ending_at": "2024-06-02T00:00:00Z",
+                    "results": [{
+                        "uncached_input_tokens": 1500,
+                        "cache_creation": {"ephemeral_5m_input_tokens": 100, "ephemeral_1h_input_tokens": 20},
+                        "cache_read_input_tokens": 300,
+                        "output_tokens": 500,
+                        "server_tool_use": {"web_search_requests": 0},
+                        "model": "claude-sonnet-4-5-20250929",
+                        "workspace_id": null
+                    }]
+                }],
+                "has_more": true,
+                "next_page": "page_2"
+            }"#,
+        )
+        .unwrap();
+
+        assert!(page.has_more);
+        let result = &page.data[0].results[0];
+        assert_eq!(result.cache_write_tokens(), 120);
+        assert_eq!(result.model.as_deref(), Some("claude-sonnet-4-5-20250929"));
+    }
+
+    #[test]
+    fn test_cost_amount_in_dollars() {
+        let cost: CostResult =
+            serde_json::from_str(r#"{"currency": "USD", "amount": "1234.5"}"#).unwrap();
+        assert!((cost.amount() - 12.345).abs() < 1e-9);
+    }
+}
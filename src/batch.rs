@@ -0,0 +1,432 @@
+//! The Message Batches API
+//!
+//! A batch runs many Messages API requests asynchronously at half the price.
+//! Each request carries a `custom_id` that its result is matched back by, as
+//! results are not returned in order.
+
+use crate::client::{api_error, Client, MessageResponse};
+use crate::daemon::RawResponse;
+use crate::error::{ApiError, ClaudeError, Result};
+use crate::guardrails::Stage;
+use crate::middleware::{Method, Request};
+use crate::retry;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// One request in a batch, as a line of a batch requests file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchRequest {
+    pub custom_id: String,
+    /// Messages API request parameters; `model` and `max_tokens` default to the configuration
+    pub params: serde_json::Value,
+}
+
+/// A batch and its progress
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Batch {
+    pub id: String,
+    /// `in_progress`, `canceling` or `ended`
+    pub processing_status: String,
+    pub request_counts: RequestCounts,
+    pub created_at: String,
+    #[serde(default)]
+    pub ended_at: Option<String>,
+    #[serde(default)]
+    pub expires_at: Option<String>,
+    /// Where the results can be downloaded once the batch has ended
+    #[serde(default)]
+    pub results_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RequestCounts {
+    pub processing: u64,
+    pub succeeded: u64,
+    pub errored: u64,
+    pub canceled: u64,
+    pub expired: u64,
+}
+
+/// The result of one request, as a line of the results file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchResult {
+    pub custom_id: String,
+    pub result: BatchOutcome,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BatchOutcome {
+    /// The Messages API response
+    Succeeded {
+        message: serde_json::Value,
+    },
+    Errored {
+        error: serde_json::Value,
+    },
+    Canceled,
+    Expired,
+    /// An outcome this version of the library does not know about, as the API sent it
+    #[serde(untagged)]
+    Other(serde_json::Value),
+}
+
+/// Build batch requests by filling a template with each line of an input file
+///
+/// Every `{{field}}` in the template's strings is replaced with that field of
+/// the input object. The request's `custom_id` is the input's `custom_id` or
+/// `id` field, or `request-N` for line N.
+pub fn from_template(template: &serde_json::Value, inputs: &str) -> Result<Vec<BatchRequest>> {
+    inputs
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let input: serde_json::Map<String, serde_json::Value> = serde_json::from_str(line)
+                .map_err(|e| ClaudeError::Batch(format!("input line {}: {}", index + 1, e)))?;
+            let custom_id = ["custom_id", "id"]
+                .iter()
+                .find_map(|field| input.get(*field).map(field_text))
+                .unwrap_or_else(|| format!("request-{}", index + 1));
+
+            Ok(BatchRequest {
+                custom_id,
+                params: fill(template, &input),
+            })
+        })
+        .collect()
+}
+
+fn fill(
+    template: &serde_json::Value,
+    input: &serde_json::Map<String, serde_json::Value>,
+) -> serde_json::Value {
+    match template {
+        serde_json::Value::String(text) => {
+            let mut text = text.clone();
+            for (field, value) in input {
+                text = text.replace(&format!("{{{{{}}}}}", field), &field_text(value));
+            }
+            serde_json::Value::String(text)
+        }
+        serde_json::Value::Array(items) => items.iter().map(|item| fill(item, input)).collect(),
+        serde_json::Value::Object(object) => object
+            .iter()
+            .map(|(key, value)| (key.clone(), fill(value, input)))
+            .collect(),
+        other => other.clone(),
+    }
+}
+
+fn field_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+impl BatchOutcome {
+    /// `succeeded`, `errored`, `canceled`, `expired`, or `other` for an
+    /// outcome this version doesn't know
+    pub fn kind(&self) -> &'static str {
+        match self {
+            BatchOutcome::Succeeded { .. } => "succeeded",
+            BatchOutcome::Errored { .. } => "errored",
+            BatchOutcome::Canceled => "canceled",
+            BatchOutcome::Expired => "expired",
+            BatchOutcome::Other(_) => "other",
+        }
+    }
+}
+
+/// The requests that did not succeed in a batch, to submit again
+///
+/// Requests without a result, e.g. because the results were cut short, are
+/// included too.
+pub fn unsucceeded(requests: Vec<BatchRequest>, results: &[BatchResult]) -> Vec<BatchRequest> {
+    let succeeded: HashSet<&str> = results
+        .iter()
+        .filter(|result| matches!(result.result, BatchOutcome::Succeeded { .. }))
+        .map(|result| result.custom_id.as_str())
+        .collect();
+    requests
+        .into_iter()
+        .filter(|request| !succeeded.contains(request.custom_id.as_str()))
+        .collect()
+}
+
+impl Client {
+    /// Submit requests as a new batch
+    ///
+    /// Requests get the configured model and max tokens unless they set their
+    /// own, and their user messages pass through the input guardrails.
+    pub async fn create_batch(&self, requests: Vec<BatchRequest>) -> Result<Batch> {
+        let requests = requests
+            .into_iter()
+            .map(|request| self.batch_request(request))
+            .collect::<Result<Vec<_>>>()?;
+        let body = serde_json::json!({ "requests": requests });
+
+        let response = self
+            .post("messages/batches", &body, retry::idempotency_key())
+            .await?;
+        parse(response)
+    }
+
+    pub async fn batch(&self, id: &str) -> Result<Batch> {
+        let url = format!("{}/messages/batches/{}", self.config().base_url, id);
+        parse(self.get(&url).await?)
+    }
+
+    /// Stop processing a batch; requests already done keep their results
+    pub async fn cancel_batch(&self, id: &str) -> Result<Batch> {
+        let response = self
+            .post(
+                &format!("messages/batches/{}/cancel", id),
+                &serde_json::json!({}),
+                retry::idempotency_key(),
+            )
+            .await?;
+        parse(response)
+    }
+
+    /// Download the results of an ended batch, recording their usage in the ledger
+    pub async fn batch_results(&self, id: &str) -> Result<Vec<BatchResult>> {
+        let mut results = Vec::new();
+        self.stream_batch_results(id, |result| {
+            self.record_batch_usage(&result);
+            results.push(result);
+            Ok(())
+        })
+        .await?;
+        Ok(results)
+    }
+
+    /// Pass each result of an ended batch to `on_result` as it is downloaded
+    ///
+    /// Results files can be large, so they are not held in memory. Usage is
+    /// not recorded; call [`Client::record_batch_usage`] for results kept.
+    pub async fn stream_batch_results(
+        &self,
+        id: &str,
+        mut on_result: impl FnMut(BatchResult) -> Result<()>,
+    ) -> Result<()> {
+        let batch = self.batch(id).await?;
+        let url = batch.results_url.ok_or_else(|| {
+            ClaudeError::Batch(format!(
+                "batch {} has no results yet (status {})",
+                id, batch.processing_status
+            ))
+        })?;
+
+        let mut response = self
+            .send_http(self.get_request(&url), true)
+            .await?
+            .into_reqwest()?;
+        let status = response.status().as_u16();
+        if !(200..300).contains(&status) {
+            return Err(api_error(status, response.text().await?));
+        }
+
+        let mut pending = Vec::new();
+        let mut parse_line = |line: &[u8]| -> Result<()> {
+            if line.iter().all(u8::is_ascii_whitespace) {
+                return Ok(());
+            }
+            let result = serde_json::from_slice::<BatchResult>(line)
+                .map_err(|e| ApiError::UnexpectedResponse(e.to_string()))?;
+            on_result(result)
+        };
+        while let Some(chunk) = response.chunk().await? {
+            pending.extend_from_slice(&chunk);
+            while let Some(newline) = pending.iter().position(|&byte| byte == b'\n') {
+                let line: Vec<u8> = pending.drain(..=newline).collect();
+                parse_line(&line)?;
+            }
+        }
+        parse_line(&pending)
+    }
+
+    /// Record the usage of a successful batch request in the ledger, at the batch price
+    pub fn record_batch_usage(&self, result: &BatchResult) {
+        if let BatchOutcome::Succeeded { message } = &result.result {
+            if let Ok(message) = serde_json::from_value::<MessageResponse>(message.clone()) {
+                self.record(self.ledger_entry(&message.model, &message.usage).batch());
+            }
+        }
+    }
+
+    fn batch_request(&self, mut request: BatchRequest) -> Result<BatchRequest> {
+        let params = request.params.as_object_mut().ok_or_else(|| {
+            ClaudeError::Batch(format!("{}: params must be an object", request.custom_id))
+        })?;
+        let config = self.config();
+        params
+            .entry("model")
+            .or_insert_with(|| config.model.clone().into());
+        params
+            .entry("max_tokens")
+            .or_insert_with(|| config.max_tokens.into());
+        if let Some(instructions) = config.safety.instructions() {
+            let system = params.entry("system").or_insert_with(|| "".into());
+            match system {
+                serde_json::Value::Array(blocks) => {
+                    blocks.insert(0, serde_json::json!({"type": "text", "text": instructions}))
+                }
+                serde_json::Value::String(text) if text.is_empty() => {
+                    *text = instructions.to_string()
+                }
+                serde_json::Value::String(text) => *text = format!("{}\n\n{}", instructions, text),
+                _ => {}
+            }
+        }
+
+        // Checked in place, so content the client doesn't model, such as
+        // images or cache control, is sent as written
+        let messages = params
+            .get_mut("messages")
+            .and_then(|messages| messages.as_array_mut())
+            .into_iter()
+            .flatten()
+            .filter(|message| message["role"] == "user");
+        for message in messages {
+            match &mut message["content"] {
+                serde_json::Value::Array(blocks) => {
+                    for block in blocks.iter_mut().filter(|block| block["type"] == "text") {
+                        self.check_text(&mut block["text"])?;
+                    }
+                }
+                content => self.check_text(content)?,
+            }
+        }
+
+        Ok(request)
+    }
+
+    fn check_text(&self, value: &mut serde_json::Value) -> Result<()> {
+        if let serde_json::Value::String(text) = value {
+            *text = self.check_guardrails(Stage::Input, std::mem::take(text))?;
+        }
+        Ok(())
+    }
+
+    async fn get(&self, url: &str) -> Result<RawResponse> {
+        RawResponse::from_response(self.send_http(self.get_request(url), false).await?).await
+    }
+
+    fn get_request(&self, url: &str) -> Request {
+        Request {
+            method: Method::GET,
+            url: url.to_string(),
+            headers: self.headers(retry::idempotency_key()),
+            body: None,
+        }
+    }
+}
+
+fn parse(response: RawResponse) -> Result<Batch> {
+    if !(200..300).contains(&response.status) {
+        return Err(api_error(response.status, response.body));
+    }
+    serde_json::from_str(&response.body)
+        .map_err(|e| ApiError::UnexpectedResponse(e.to_string()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::{http_stub, stub_client};
+
+    #[test]
+    fn test_from_template() {
+        let template = serde_json::json!({
+            "messages": [{"role": "user", "content": "Summarize {{title}}: {{text}}"}],
+            "max_tokens": 100,
+        });
+        let inputs = "{\"id\": 7, \"title\": \"A\", \"text\": \"x\"}\n\n{\"title\": \"B\", \"text\": \"y\"}\n";
+
+        let requests = from_template(&template, inputs).unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].custom_id, "7");
+        assert_eq!(requests[1].custom_id, "request-3");
+        assert_eq!(
+            requests[1].params["messages"][0]["content"],
+            "Summarize B: y"
+        );
+        assert_eq!(requests[1].params["max_tokens"], 100);
+    }
+
+    #[tokio::test]
+    async fn test_create_batch_fills_defaults() {
+        const BATCH: &str = r#"{"id":"msgbatch_1","type":"message_batch","processing_status":"in_progress","request_counts":{"processing":1},"created_at":"2024-09-24T18:37:24Z"}"#;
+        let (url, requests) = http_stub(vec![Some((200, BATCH))]).await;
+        let client = stub_client(url);
+
+        let batch = client
+            .create_batch(vec![BatchRequest {
+                custom_id: "a".into(),
+                params: serde_json::json!({"messages": [{"role": "user", "content": "hi"}]}),
+            }])
+            .await
+            .unwrap();
+        assert_eq!(batch.id, "msgbatch_1");
+        assert_eq!(batch.request_counts.processing, 1);
+
+        let request = &requests.lock().unwrap()[0];
+        assert!(request.starts_with("post /messages/batches "));
+        assert!(request.contains("\"model\":"));
+        assert!(request.contains("\"max_tokens\":"));
+    }
+
+    #[tokio::test]
+    async fn test_stream_batch_results() {
+        const RESULTS: &str = concat!(
+            r#"{"custom_id":"a","result":{"type":"succeeded","message":{"id":"msg_1","type":"message","role":"assistant","model":"claude-haiku-4-5","content":[],"stop_reason":"end_turn","usage":{"input_tokens":1,"output_tokens":1}}}}"#,
+            "\n",
+            r#"{"custom_id":"b","result":{"type":"expired"}}"#,
+        );
+        let (results_url, _) = http_stub(vec![Some((200, RESULTS))]).await;
+        let batch: &'static str = Box::leak(
+            format!(
+                r#"{{"id":"msgbatch_1","processing_status":"ended","request_counts":{{"succeeded":1,"expired":1}},"created_at":"2024-09-24T18:37:24Z","results_url":"{}/results"}}"#,
+                results_url
+            )
+            .into_boxed_str(),
+        );
+        let (url, _) = http_stub(vec![Some((200, batch))]).await;
+        let client = stub_client(url);
+
+        let mut kinds = Vec::new();
+        let mut results = Vec::new();
+        client
+            .stream_batch_results("msgbatch_1", |result| {
+                kinds.push(result.result.kind());
+                results.push(result);
+                Ok(())
+            })
+            .await
+            .unwrap();
+        assert_eq!(kinds, vec!["succeeded", "expired"]);
+
+        let request = |id: &str| BatchRequest {
+            custom_id: id.into(),
+            params: serde_json::json!({}),
+        };
+        let retry = unsucceeded(vec![request("a"), request("b"), request("c")], &results);
+        let ids: Vec<&str> = retry.iter().map(|r| r.custom_id.as_str()).collect();
+        assert_eq!(ids, vec!["b", "c"]);
+    }
+
+    #[test]
+    fn test_parse_results() {
+        let line = r#"{"custom_id":"a","result":{"type":"errored","error":{"type":"error","error":{"type":"invalid_request_error","message":"bad"}}}}"#;
+        let result: BatchResult = serde_json::from_str(line).unwrap();
+        assert!(matches!(result.result, BatchOutcome::Errored { .. }));
+
+        let line = r#"{"custom_id":"b","result":{"type":"deferred","until":"tomorrow"}}"#;
+        let result: BatchResult = serde_json::from_str(line).unwrap();
+        assert_eq!(result.result.kind(), "other");
+        assert_eq!(serde_json::to_string(&result).unwrap(), line);
+    }
+}
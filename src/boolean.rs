@@ -0,0 +1,101 @@
+use crate::client::{Client, Messages};
+use crate::error::Result;
+use crate::structured::StructuredRetry;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The outcome of a yes/no question
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BoolAnswer {
+    True,
+    False,
+    Unsure,
+}
+
+impl BoolAnswer {
+    /// Process exit code for scripting: 0 for true, 1 for false, 2 for unsure
+    pub fn exit_code(self) -> i32 {
+        match self {
+            BoolAnswer::True => 0,
+            BoolAnswer::False => 1,
+            BoolAnswer::Unsure => 2,
+        }
+    }
+}
+
+impl fmt::Display for BoolAnswer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BoolAnswer::True => write!(f, "true"),
+            BoolAnswer::False => write!(f, "false"),
+            BoolAnswer::Unsure => write!(f, "unsure"),
+        }
+    }
+}
+
+/// Structured response to a yes/no question
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BoolResponse {
+    /// answer true or false; when unable to assess the input clearly, answer unsure
+    pub answer: BoolAnswer,
+    /// how confident you are in the answer, from 0.0 (guessing) to 1.0 (certain)
+    pub confidence: f64,
+    /// provide an explanation of how you reached the answer
+    pub explanation: String,
+}
+
+impl BoolResponse {
+    /// The answer, downgraded to `Unsure` when confidence is below `min_confidence`
+    pub fn outcome(&self, min_confidence: f64) -> BoolAnswer {
+        if self.confidence < min_confidence {
+            BoolAnswer::Unsure
+        } else {
+            self.answer
+        }
+    }
+}
+
+impl Client {
+    /// Ask a yes/no question and get a structured answer with confidence
+    pub async fn ask_bool(&self, question: impl Into<String>) -> Result<BoolResponse> {
+        let system =
+            "consider the question or statement and answer with true, false, or unsure.".into();
+
+        let messages = Messages::new().push_user(question.into()).clone();
+
+        self.send_structured::<BoolResponse>(messages, Some(system), StructuredRetry::default())
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes() {
+        assert_eq!(BoolAnswer::True.exit_code(), 0);
+        assert_eq!(BoolAnswer::False.exit_code(), 1);
+        assert_eq!(BoolAnswer::Unsure.exit_code(), 2);
+    }
+
+    #[test]
+    fn test_outcome_confidence_threshold() {
+        let response: BoolResponse = serde_json::from_str(
+            r#"{"answer": "true", "confidence": 0.4, "explanation": "maybe"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(response.outcome(0.0), BoolAnswer::True);
+        assert_eq!(response.outcome(0.5), BoolAnswer::Unsure);
+    }
+
+    #[test]
+    fn test_schema_mentions_unsure() {
+        let schema = serde_json::to_string(&schemars::schema_for!(BoolResponse)).unwrap();
+        assert!(schema.contains("unsure"));
+        assert!(schema.contains("confidence"));
+    }
+}
@@ -1,67 +1,1385 @@
+use crate::completions;
 use clap::{Parser, Subcommand};
+use clap_complete::engine::ArgValueCandidates;
+use ellm::ledger::{GroupBy, Period};
+use ellm::{
+    Constraint, GoldenComparison, LogFormat, OcrFormat, PostProcessor, SafetyPreset, Selector,
+};
+use std::path::PathBuf;
+use std::str::FromStr;
 
 /// Claude CLI - Interact with Claude AI from the command line
 #[derive(Parser, Debug)]
 #[command(name = "ellm")]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
+    /// Read this config file instead of the default one (also ELLM_CONFIG_PATH)
+    #[arg(long, value_name = "PATH", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Use the endpoint and key of this entry of the config file's providers
+    #[arg(long, global = true)]
+    pub provider: Option<String>,
+
+    /// Base URL of the API, e.g. an Anthropic-compatible gateway
+    #[arg(long, global = true)]
+    pub base_url: Option<String>,
+
     /// API key for authentication (overrides environment and config file)
     #[arg(long, global = true)]
     pub api_key: Option<String>,
 
-    /// Model to use
-    #[arg(long, default_value = "claude-sonnet-4-5-20250929", global = true)]
+    /// Model to use [default: the config file's, or claude-sonnet-4-5-20250929]
+    #[arg(long, global = true, add = ArgValueCandidates::new(completions::models))]
     pub model: Option<String>,
 
-    /// Maximum tokens to generate
-    #[arg(long, default_value_t = 4096, global = true)]
-    pub max_tokens: u32,
+    /// Maximum tokens to generate, or `auto` to use what the context window leaves
+    /// after the prompt, up to the model's output limit [default: the config file's, or 4096]
+    #[arg(long, global = true)]
+    pub max_tokens: Option<MaxTokens>,
+
+    /// Continue a response cut off at max_tokens up to this many times [default: the config file's, or 0]
+    #[arg(long, value_name = "N", global = true)]
+    pub max_continuations: Option<u32>,
+
+    /// Enable extended thinking with this many tokens of budget (at least 1024, below --max-tokens)
+    #[arg(long, value_name = "BUDGET", global = true)]
+    pub thinking: Option<u32>,
+
+    /// Switch a deprecated or retired model to its closest current model instead of failing
+    #[arg(long, global = true)]
+    pub auto_migrate: bool,
+
+    /// Mark the system prompt for prompt caching, so repeated requests reuse it
+    #[arg(long, global = true)]
+    pub cache_system: bool,
+
+    /// Post-process the response (repeatable): trim, strip-markdown, regex:<pattern>, json:<path>
+    ///
+    /// Replaces any steps configured for the command in the config file.
+    #[arg(long = "post", value_name = "STEP", global = true)]
+    pub post: Vec<PostProcessor>,
+
+    /// Print only this part of a command's JSON output, e.g. answer, items[0].name or results[?score>0.5].id
+    ///
+    /// Strings are printed without quotes and other values as JSON, one per line.
+    /// `[]` selects every element and `[?field op value]` the elements matching a
+    /// comparison (==, !=, <, <=, >, >=). Applies to commands printing JSON, with
+    /// --json or --output json.
+    #[arg(long, value_name = "PATH", global = true)]
+    pub select: Option<Selector>,
+
+    /// Send prompts without masking secrets and personal data
+    #[arg(long, global = true)]
+    pub no_redact: bool,
+
+    /// Connect directly instead of through a running `ellm daemon`
+    #[arg(long, global = true)]
+    pub no_daemon: bool,
+
+    /// Attribute usage to this workspace in the usage ledger (overrides the config file)
+    #[arg(long, global = true)]
+    pub workspace: Option<String>,
+
+    /// Start the prompt with this saved snippet, and use its system prompt unless --system is given
+    ///
+    /// Applies to send, bool, tokens, summarize and extract (whose --instructions it precedes).
+    #[arg(long, value_name = "NAME", global = true, add = ArgValueCandidates::new(completions::snippets))]
+    pub snippet: Option<String>,
+
+    /// Write diagnostics such as retries and cache use to stderr as text or JSON lines [default: the config file's, or text]
+    #[arg(long, value_name = "FORMAT", global = true)]
+    pub log_format: Option<LogFormat>,
+
+    /// Prepend safety instructions to every system prompt: strict fails on refusals, standard warns [default: the config file's, or off]
+    #[arg(long, value_name = "PRESET", global = true)]
+    pub safety: Option<SafetyPreset>,
+
+    /// Language to write responses in, e.g. German, whatever the prompt's language; send also re-asks responses detected in another language [default: the config file's]
+    #[arg(long, value_name = "LANGUAGE", global = true)]
+    pub respond_in: Option<String>,
+
+    /// How to print the error a command fails with; json writes one object to stderr
+    #[arg(long, default_value = "text", value_parser = ["text", "json"], global = true)]
+    pub error_format: String,
+
+    /// POST a notification to this URL when a long operation finishes (overrides the config file)
+    #[arg(long, value_name = "URL", global = true)]
+    pub notify_webhook: Option<String>,
+
+    /// Run this shell command when a long operation finishes (overrides the config file)
+    ///
+    /// The notification is passed as JSON on stdin.
+    #[arg(long, value_name = "COMMAND", global = true)]
+    pub notify_command: Option<String>,
+
+    /// Record or replay the API requests, set by `fixtures record` and `fixtures replay`
+    #[arg(skip)]
+    pub fixtures: Option<FixtureMode>,
 
     #[command(subcommand)]
     pub command: Commands,
 }
 
+/// What to do with the API requests of a command run by `ellm fixtures`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FixtureMode {
+    /// Send them, saving each with its response in the directory
+    Record(PathBuf),
+    /// Answer them from the fixtures in the directory instead of sending them
+    Replay(PathBuf),
+}
+
 #[derive(Subcommand, Debug, Clone)]
 pub enum Commands {
     /// Send a message to Claude
+    ///
+    /// The response is printed as it is generated, unless it is post-processed
+    /// or printed as JSON.
     Send {
         /// The message to send
         message: String,
+
+        /// System prompt
+        #[arg(long)]
+        system: Option<String>,
+
+        /// Attach a file (repeatable): text and PDFs as documents, PNG, JPEG, GIF and WebP as images
+        ///
+        /// The type is recognized from the file's contents, not its name.
+        #[arg(long = "file", short, value_name = "PATH")]
+        files: Vec<PathBuf>,
+
+        /// Mark the attached files for prompt caching
+        #[arg(long, requires = "files")]
+        cache_files: bool,
+
+        /// Attach a PNG, JPEG, GIF or WebP image (repeatable); the same as --file
+        #[arg(long = "image", value_name = "PATH")]
+        images: Vec<PathBuf>,
+
+        /// Capture the screen and attach it as an image; --screenshot=region to select part of it
+        ///
+        /// Uses screencapture on macOS, PowerShell on Windows, and grim and
+        /// slurp, maim, gnome-screenshot or ImageMagick on Linux.
+        #[arg(
+            long,
+            value_name = "AREA",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "screen",
+            value_parser = ["screen", "region"]
+        )]
+        screenshot: Option<String>,
+
+        /// Print the model's reasoning (dimmed, on stderr) when --thinking is enabled
+        #[arg(long)]
+        show_thinking: bool,
+
+        /// Output format; json puts the reasoning under a separate "thinking" key
+        #[arg(long, default_value = "text", value_parser = ["text", "json"])]
+        output: String,
+
+        /// Check the response by chain of verification and print it corrected
+        ///
+        /// Questions about the response's claims are answered independently,
+        /// and the response is corrected where they contradict it. The checks
+        /// are printed to stderr, or under "verification" with --output json.
+        #[arg(long)]
+        verify: bool,
+
+        /// Require the response to meet a constraint, re-asking until it does (repeatable)
+        ///
+        /// One of max-words:<n>, min-words:<n>, json, section:<name>,
+        /// contains:<text>, regex:<pattern> or language:<name>. A response that breaks a
+        /// constraint is shown back to the model with what's wrong, up to
+        /// three attempts in all.
+        #[arg(long = "constraint", value_name = "SPEC", conflicts_with = "verify")]
+        constraints: Vec<Constraint>,
+
+        /// Continue a conversation saved in the OpenAI chat format, e.g. from a prompt dataset
+        ///
+        /// The file holds a list of messages or an object with a "messages"
+        /// list. Its system messages are the system prompt unless --system is
+        /// given.
+        #[arg(long, value_name = "PATH")]
+        import_openai: Option<PathBuf>,
+
+        /// Write the conversation and its response to this file in the OpenAI chat format
+        #[arg(long, value_name = "PATH")]
+        export_openai: Option<PathBuf>,
     },
 
     /// Show current configuration
     Config,
 
-    /// Ask Claude a yes/no question and get a boolean response
-    Bool {
-        /// The question or prompt to ask
-        question: String,
-    },
-}
+    /// Ask Claude a yes/no question and get a boolean response
+    ///
+    /// Exits with 0 for true, 1 for false, and 2 when the answer is unsure.
+    Bool {
+        /// The question or prompt to ask
+        question: String,
+
+        /// Report unsure when the model's confidence is below this value (0.0-1.0)
+        #[arg(long, default_value_t = 0.5)]
+        min_confidence: f64,
+
+        /// Print nothing; communicate the answer only through the exit code
+        #[arg(long, short, conflicts_with = "json")]
+        quiet: bool,
+
+        /// Print the full response, including confidence and explanation, as JSON
+        #[arg(long)]
+        json: bool,
+
+        #[command(flatten)]
+        sampling: SamplingArgs,
+    },
+
+    /// Translate text, auto-detecting the source language
+    ///
+    /// Reads the text from the argument, --file, or stdin. Code blocks and
+    /// placeholders such as {name} or %s are preserved.
+    Translate {
+        /// The text to translate (reads --file or stdin when omitted)
+        text: Option<String>,
+
+        /// Target language, e.g. "de" or "Brazilian Portuguese"
+        #[arg(long)]
+        to: String,
+
+        /// Source language (detected automatically when omitted)
+        #[arg(long)]
+        from: Option<String>,
+
+        /// Read the text to translate from a file
+        #[arg(long, conflicts_with = "text")]
+        file: Option<PathBuf>,
+
+        /// Treat the input as JSONL and translate one field of each line
+        #[arg(long)]
+        jsonl: bool,
+
+        /// The field to translate in --jsonl mode
+        #[arg(long, default_value = "text", requires = "jsonl")]
+        field: String,
+    },
+
+    /// Ask several models the same question and combine their answers
+    ///
+    /// The models are asked at once. Their answers are concatenated, voted on
+    /// (for short answers), or given to a synthesizer model that writes one
+    /// answer from them. Models that fail are reported and left out.
+    Council {
+        /// The question to ask
+        question: String,
+
+        /// Models to ask, comma-separated or repeated
+        #[arg(long, value_delimiter = ',', required = true, add = ArgValueCandidates::new(completions::models))]
+        models: Vec<String>,
+
+        /// How to combine the answers
+        #[arg(long, default_value = "synthesize", value_parser = ["concatenate", "vote", "synthesize"])]
+        aggregate: String,
+
+        /// Model that writes the synthesized answer [default: the configured model]
+        #[arg(long, value_name = "MODEL", add = ArgValueCandidates::new(completions::models))]
+        synthesizer: Option<String>,
+
+        /// System prompt for every model
+        #[arg(long)]
+        system: Option<String>,
+
+        /// Print each model's answer along with the combined one as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Run a prompt across a grid of models and temperatures and tabulate the results
+    ///
+    /// Each combination runs in turn, and the table shows its latency, output
+    /// tokens and, with --judge, a 0-10 score from the configured model,
+    /// followed by every output.
+    Sweep {
+        /// File with the prompt to send
+        #[arg(long)]
+        prompt: PathBuf,
+
+        /// Temperatures to try, comma-separated or repeated
+        #[arg(long, value_delimiter = ',', default_value = "0,0.3,0.7,1.0")]
+        temperature: Vec<f32>,
+
+        /// Models to try, comma-separated or repeated [default: the configured model]
+        #[arg(long, value_delimiter = ',', add = ArgValueCandidates::new(completions::models))]
+        models: Vec<String>,
+
+        /// System prompt for every run
+        #[arg(long)]
+        system: Option<String>,
+
+        /// Criteria to score each output against, e.g. "concise and correct"
+        #[arg(long, value_name = "CRITERIA")]
+        judge: Option<String>,
+
+        /// Print the runs as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Answer a prompt, then critique and revise the answer in rounds
+    ///
+    /// Reads the prompt from the argument, --file, or stdin. Each round a
+    /// critic reviews the latest answer against --criteria, and the answer is
+    /// revised until the critic is satisfied or the rounds run out. Reviews
+    /// are printed to stderr and the final answer to stdout.
+    Critique {
+        /// The prompt to answer (reads --file or stdin when omitted)
+        prompt: Option<String>,
+
+        /// Read the prompt from a file
+        #[arg(long, conflicts_with = "prompt")]
+        file: Option<PathBuf>,
+
+        /// What a good answer is
+        #[arg(long, default_value = "correct, complete and clear")]
+        criteria: String,
+
+        /// Most critique and revision rounds
+        #[arg(long, default_value_t = 2)]
+        rounds: usize,
+
+        /// Model that critiques the answers [default: the configured model]
+        #[arg(long, value_name = "MODEL", add = ArgValueCandidates::new(completions::models))]
+        critic: Option<String>,
+
+        /// System prompt for answering
+        #[arg(long)]
+        system: Option<String>,
+
+        /// Print the draft, every review and revision, and the answer as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Rewrite a prompt to score better on evaluation cases
+    ///
+    /// Each line of the cases file is a JSON object with an "input" sent
+    /// after the prompt, and optionally the "expected" output and "criteria"
+    /// to judge it by. The outputs are scored from 0 to 10, and the best
+    /// prompt so far is rewritten from its weakest outputs for each round.
+    /// Scores are printed to stderr and the best prompt to stdout.
+    OptimizePrompt {
+        /// File with the prompt to optimize
+        #[arg(long)]
+        prompt: PathBuf,
+
+        /// JSONL file of evaluation cases
+        #[arg(long)]
+        cases: PathBuf,
+
+        /// Rewrites to try
+        #[arg(long, default_value_t = 3)]
+        rounds: usize,
+
+        /// What a good output is, for cases without their own criteria
+        #[arg(long, default_value = "correct, complete and clear")]
+        criteria: String,
+
+        /// Model that rewrites the prompt [default: the configured model]
+        #[arg(long, value_name = "MODEL", add = ArgValueCandidates::new(completions::models))]
+        optimizer: Option<String>,
+
+        /// System prompt for every case
+        #[arg(long)]
+        system: Option<String>,
+
+        /// Print every prompt with its outputs and scores as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Regression-test prompts against stored outputs
+    Eval {
+        #[command(subcommand)]
+        command: EvalCommand,
+    },
+
+    /// Summarize text of any length
+    ///
+    /// Reads the text from the argument, --file, or stdin. Text too long for
+    /// the model's context window is summarized in parts that are combined.
+    Summarize {
+        /// The text to summarize (reads --file or stdin when omitted)
+        text: Option<String>,
+
+        /// Read the text to summarize from a file
+        #[arg(long, conflicts_with = "text")]
+        file: Option<PathBuf>,
+
+        /// What to focus on or how to format the summary
+        #[arg(long)]
+        instructions: Option<String>,
+
+        #[command(flatten)]
+        chunking: ChunkArgs,
+    },
+
+    /// Extract a JSON object matching a schema from text of any length
+    ///
+    /// Reads the text from the argument, --file, or stdin. Text too long for
+    /// the model's context window is extracted from in parts that are merged.
+    Extract {
+        /// The text to extract from (reads --file or stdin when omitted)
+        text: Option<String>,
+
+        /// Read the text to extract from from a file
+        #[arg(long, conflicts_with = "text")]
+        file: Option<PathBuf>,
+
+        /// JSON schema file the result must match
+        #[arg(long)]
+        schema: PathBuf,
+
+        /// What to extract, beyond what the schema says
+        #[arg(long)]
+        instructions: Option<String>,
+
+        #[command(flatten)]
+        chunking: ChunkArgs,
+
+        #[command(flatten)]
+        sampling: SamplingArgs,
+    },
+
+    /// Transcribe the text and tables in an image or PDF
+    ///
+    /// The image is read with vision, so handwriting and photos work too.
+    /// Text that can't be read is marked [illegible].
+    Ocr {
+        /// PNG, JPEG, GIF or WebP image, or PDF, to transcribe
+        image: PathBuf,
+
+        /// Write the transcription as text, markdown, or csv (tables only)
+        #[arg(long, default_value = "text", value_name = "FORMAT")]
+        format: OcrFormat,
+
+        /// Keep the line breaks, indentation and column alignment of the image
+        /// instead of joining lines into paragraphs
+        #[arg(long)]
+        layout: bool,
+    },
+
+    /// Answer a question about a CSV table
+    ///
+    /// The model sees a profile of the columns and as many rows as fit the
+    /// budget. With --verify, the numbers in the answer come from a query the
+    /// model writes, run locally over every row; the query and its result are
+    /// printed to stderr.
+    Table {
+        /// CSV file; tab, semicolon and pipe delimiters are recognized too
+        path: PathBuf,
+
+        /// The question to answer
+        question: String,
+
+        /// Compute the numbers for the answer locally from every row
+        #[arg(long)]
+        verify: bool,
+
+        /// Tokens the table may take in the prompt (counted as bytes, which is never fewer)
+        #[arg(long, value_name = "TOKENS", default_value_t = 20_000)]
+        budget: usize,
+
+        /// Replace identifier-like columns (IDs, names, emails) with consistent
+        /// pseudonyms before sending, and restore them in the answer
+        #[arg(long)]
+        pseudonymize: bool,
+
+        /// Pseudonymize this column instead of detecting them (repeatable)
+        #[arg(long = "pseudonymize-column", value_name = "NAME")]
+        pseudonymize_columns: Vec<String>,
+
+        /// Print the answer, and any query and its result, as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Serve ellm's commands as MCP tools over stdio
+    McpServe,
+
+    /// Run a background daemon that keeps API connections warm
+    ///
+    /// Other ellm invocations detect the daemon's socket and relay their
+    /// requests through it, avoiding a new TLS handshake per command.
+    Daemon {
+        /// Socket path to listen on (defaults to the per-user runtime directory)
+        #[arg(long)]
+        socket: Option<PathBuf>,
+
+        /// Serve Prometheus metrics at http://ADDR/metrics, e.g. 127.0.0.1:9464
+        #[arg(long, value_name = "ADDR")]
+        metrics: Option<std::net::SocketAddr>,
+    },
+
+    /// Report usage recorded in the local usage ledger
+    Usage {
+        #[command(subcommand)]
+        command: UsageCommand,
+    },
+
+    /// Show how often each command ran, how long it took and what it cost
+    ///
+    /// Statistics are kept per command and snippet in a local file that is
+    /// never uploaded, most expensive first.
+    Stats {
+        /// Print the statistics as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Forget all recorded statistics
+        #[arg(long, conflicts_with = "json")]
+        clear: bool,
+    },
+
+    /// Submit and manage Message Batches, processed asynchronously at half price
+    Batch {
+        #[command(subcommand)]
+        command: BatchCommand,
+    },
+
+    /// Generate a regex from a description and test it against sample lines
+    ///
+    /// Each line of the samples file must match, except lines starting with
+    /// "- ", which must not. Wrongly classified samples are fed back to the
+    /// model until all pass or the attempts run out; the exit code is 1 then.
+    Regex {
+        /// What the regex should match
+        description: String,
+
+        /// File of sample lines
+        #[arg(long, value_name = "PATH")]
+        samples: PathBuf,
+
+        /// Require the regex to match whole lines rather than anywhere in them
+        #[arg(long)]
+        full_match: bool,
+
+        /// Patterns to try before giving up
+        #[arg(long, default_value_t = 5)]
+        attempts: usize,
+
+        /// Print the pattern, explanation, attempts and failures as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Re-run a prompt about files whenever they change
+    ///
+    /// The watched files are attached as documents to the prompt from
+    /// --template, which is watched too. After the first run, each run prints
+    /// how the response differs from the previous one. Stop with Ctrl-C.
+    Watch {
+        /// Files to watch and attach
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// File containing the prompt
+        #[arg(long, value_name = "PATH", required_unless_present = "prompt")]
+        template: Option<PathBuf>,
+
+        /// The prompt, instead of --template
+        #[arg(long, conflicts_with = "template")]
+        prompt: Option<String>,
+
+        /// System prompt
+        #[arg(long)]
+        system: Option<String>,
+
+        /// Wait until the files have been unchanged this long before re-running
+        #[arg(long, value_name = "MS", default_value_t = 500)]
+        debounce: u64,
+
+        /// Print every response in full instead of the changes
+        #[arg(long)]
+        no_diff: bool,
+    },
+
+    /// Summarize recent items of RSS and Atom feeds into a Markdown digest
+    ///
+    /// Items published within --since are grouped into topics, and each
+    /// topic is summarized, with links to its items.
+    Digest {
+        /// File listing one feed URL per line (# starts a comment)
+        #[arg(long, value_name = "PATH")]
+        feeds: PathBuf,
+
+        /// Include items published this recently, e.g. 12h, 24h, 7d or 2w
+        #[arg(long, default_value = "24h")]
+        since: String,
+
+        #[command(flatten)]
+        chunking: ChunkArgs,
+    },
+
+    /// Explain a diff for reviewers: intent, behavioral changes, risks, tests and reviewers
+    ///
+    /// Reads a unified diff from stdin, or diffs --range in the current
+    /// repository. Recent authors of the changed files are suggested as reviewers.
+    DiffExplain {
+        /// Revision range to diff, e.g. main..HEAD
+        #[arg(long, value_name = "A..B")]
+        range: Option<String>,
+
+        /// Print the explanation as JSON, e.g. for a CI comment
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// GitHub helpers (uses GITHUB_TOKEN when set)
+    Gh {
+        #[command(subcommand)]
+        command: GhCommand,
+    },
+
+    /// Git hooks that draft commit messages and check them against the change
+    Git {
+        #[command(subcommand)]
+        command: GitCommand,
+    },
+
+    /// Save, list and use frequently used prompts and system prompts
+    ///
+    /// Snippets are TOML files in the snippets directory next to the config
+    /// file, with `prompt` and `system` keys, and can be shared as files.
+    Snippets {
+        #[command(subcommand)]
+        command: SnippetCommand,
+    },
+
+    /// Record real API traffic as fixtures for deterministic tests, and replay it
+    ///
+    /// Each request is saved with its response as a numbered JSON file,
+    /// without the host or headers and with secrets and personal data masked.
+    /// Applications can answer requests from the files with the library's
+    /// fixtures::Replayer middleware, or run ellm commands against them with
+    /// `fixtures replay`.
+    Fixtures {
+        #[command(subcommand)]
+        command: FixturesCommand,
+    },
+
+    /// Count the tokens of a prompt for the selected model
+    ///
+    /// Reads the prompt from the argument, --file, or stdin, and prints its
+    /// token count, the context left for the response after --max-tokens, and
+    /// the estimated cost of the input.
+    Tokens {
+        /// The prompt (reads --file or stdin when omitted)
+        text: Option<String>,
+
+        /// Read the prompt from a file
+        #[arg(long, conflicts_with = "text")]
+        file: Option<PathBuf>,
+
+        /// System prompt to count along with the prompt
+        #[arg(long)]
+        system: Option<String>,
+
+        /// Estimate locally instead of asking the API (requires the `estimate` feature)
+        ///
+        /// The estimate is also used when the API cannot be reached.
+        #[arg(long)]
+        offline: bool,
+
+        /// Print the counts as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show the account's current rate limits
+    ///
+    /// Makes a minimal one-token request and prints the remaining requests
+    /// and tokens reported by the API, with their reset times.
+    Limits {
+        /// Print the limits as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Print a shell completion script
+    ///
+    /// For example, add `source <(ellm completions bash)` to ~/.bashrc.
+    /// Completions call back into ellm, so suggested values such as models
+    /// follow your configuration.
+    Completions {
+        /// Shell to generate the script for
+        #[arg(value_parser = completions::SHELLS.to_vec())]
+        shell: String,
+    },
+
+    /// Organization usage, costs, API keys and workspaces (requires an admin key)
+    #[cfg(feature = "admin")]
+    Admin {
+        /// Admin API key (defaults to the ANTHROPIC_ADMIN_KEY environment variable)
+        #[arg(long)]
+        admin_key: Option<String>,
+
+        #[command(subcommand)]
+        command: AdminCommand,
+    },
+
+    /// Serve ellm as a gRPC service (see proto/ellm.proto)
+    #[cfg(feature = "grpc")]
+    GrpcServe {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:50051")]
+        addr: std::net::SocketAddr,
+    },
+
+    /// Chat in a full-screen interface with sessions and streaming responses
+    ///
+    /// Enter sends, Ctrl-R regenerates the last response, Ctrl-Y copies it,
+    /// Esc stops a response, Ctrl-N starts a session, Tab switches between
+    /// them, Page Up and Page Down scroll, and Ctrl-Q quits. Sessions last
+    /// until the interface is quit.
+    #[cfg(feature = "tui")]
+    Tui {
+        /// System prompt for every session
+        #[arg(long)]
+        system: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum GhCommand {
+    /// Classify an issue and draft a first response, printed as JSON
+    ///
+    /// The JSON has the kind (bug, feature, question, other), severity,
+    /// affected module, labels chosen from the repository's, a summary and
+    /// the draft response, for a labeling bot to act on.
+    Triage {
+        /// Issue URL, owner/repo#number, or number in --repo
+        issue: String,
+
+        /// Repository of an issue given by number [default: the origin remote's]
+        #[arg(long, value_name = "OWNER/NAME")]
+        repo: Option<String>,
+    },
+    /// Write a pull request title, summary and test plan for the current branch
+    ///
+    /// Uses the branch's commit messages and its diff against --base.
+    PrDescribe {
+        /// Branch the pull request targets [default: origin's default branch, or main]
+        #[arg(long)]
+        base: Option<String>,
+
+        /// Set the title and description on the pull request (needs GITHUB_TOKEN)
+        #[arg(long)]
+        update: bool,
+
+        /// Pull request to update [default: the open one for the current branch]
+        #[arg(long, value_name = "NUMBER", requires = "update")]
+        pr: Option<u64>,
+
+        /// Repository of the pull request [default: the origin remote's]
+        #[arg(long, value_name = "OWNER/NAME", requires = "update")]
+        repo: Option<String>,
+
+        /// Print the title, summary and test plan as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum GitCommand {
+    /// Install prepare-commit-msg and commit-msg hooks in the current repository
+    ///
+    /// prepare-commit-msg drafts a message from the staged changes for a plain
+    /// `git commit`; commit-msg warns when the message doesn't match them.
+    /// Neither ever blocks a commit.
+    InstallHooks {
+        /// Replace existing hooks that ellm didn't install
+        #[arg(long)]
+        force: bool,
+    },
+    /// Run a hook; called by the installed hook scripts
+    ///
+    /// Gives up silently after --timeout or on any error, and always succeeds.
+    Hook {
+        /// The hook being run
+        #[arg(value_parser = ["prepare-commit-msg", "commit-msg"])]
+        name: String,
+
+        /// The arguments git passed to the hook
+        #[arg(allow_hyphen_values = true)]
+        args: Vec<String>,
+
+        /// Seconds to wait for the API
+        #[arg(long, default_value_t = 20)]
+        timeout: u64,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum SnippetCommand {
+    /// List saved snippets
+    List,
+    /// Print a snippet
+    Show {
+        #[arg(add = ArgValueCandidates::new(completions::snippets))]
+        name: String,
+    },
+    /// Save a snippet, replacing any of the same name
+    Save {
+        name: String,
+
+        /// Prompt the snippet starts messages with
+        #[arg(long, required_unless_present_any = ["system", "from"])]
+        prompt: Option<String>,
+
+        /// System prompt the snippet sets
+        #[arg(long)]
+        system: Option<String>,
+
+        /// Copy a shared snippet file instead
+        #[arg(long, value_name = "PATH", conflicts_with_all = ["prompt", "system"])]
+        from: Option<PathBuf>,
+    },
+    /// Remove a snippet
+    Remove {
+        #[arg(add = ArgValueCandidates::new(completions::snippets))]
+        name: String,
+    },
+    /// Send a snippet's prompt, followed by any extra text, with its system prompt
+    Use {
+        #[arg(add = ArgValueCandidates::new(completions::snippets))]
+        name: String,
+
+        /// Text to add after the snippet's prompt
+        text: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum EvalCommand {
+    /// Compare outputs with the golden outputs stored in a cases file, failing on regressions
+    ///
+    /// Each line of the cases file is a JSON object with an "input", sent
+    /// after --prompt when given, and its golden output as "expected". The
+    /// command fails, for CI, when any output no longer matches its golden
+    /// output or a case has none; --update stores the current outputs instead.
+    Golden {
+        /// JSONL file of cases with their golden outputs
+        cases: PathBuf,
+
+        /// File with a prompt each case's input follows
+        #[arg(long, value_name = "PATH")]
+        prompt: Option<PathBuf>,
+
+        /// System prompt for every case
+        #[arg(long)]
+        system: Option<String>,
+
+        /// How outputs must match: exact, normalized (ignoring case, punctuation and whitespace) or similarity
+        #[arg(long, default_value = "normalized")]
+        compare: GoldenComparison,
+
+        /// The least word similarity, from 0 to 1, that passes with --compare similarity
+        #[arg(long, default_value_t = 0.8)]
+        threshold: f64,
+
+        /// Store the current outputs as the golden outputs instead of comparing
+        #[arg(long)]
+        update: bool,
+
+        /// Print every case with its output and score as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum FixturesCommand {
+    /// Run an ellm command, saving its API requests and their responses in DIR
+    ///
+    /// For example `ellm fixtures record tests/fixtures/greeting send "Hello"`.
+    /// Options such as --model belong to the command, after DIR. Responses
+    /// are printed all at once rather than streamed while recording.
+    Record {
+        /// Directory for the fixtures, numbered after any already in it
+        dir: PathBuf,
+
+        /// The command to run and its arguments
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Run an ellm command with its API requests answered from the fixtures in DIR
+    ///
+    /// Nothing is sent, so no API key is needed. A request no fixture
+    /// matches fails.
+    Replay {
+        /// Directory the fixtures were recorded in
+        dir: PathBuf,
+
+        /// The command to run and its arguments
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum UsageCommand {
+    /// Total requests, tokens and cost per workspace
+    Workspaces {
+        /// Print the totals as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Usage and cost per period, including cache and batch savings
+    ///
+    /// For example, `ellm usage report --period month --format csv` gives
+    /// a monthly breakdown per model that can be opened as a spreadsheet.
+    Report {
+        /// Break down by model, workspace or none
+        #[arg(long, default_value = "model")]
+        group_by: GroupBy,
+
+        /// Sum over each day, month or all time
+        #[arg(long, default_value = "month")]
+        period: Period,
+
+        /// Output format
+        #[arg(long, default_value = "table", value_parser = ["table", "csv", "json"])]
+        format: String,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum BatchCommand {
+    /// Submit a batch and print its ID
+    ///
+    /// Each line of the requests file is a `{"custom_id": ..., "params": ...}`
+    /// object, where params is a Messages API request. Alternatively, build the
+    /// requests from a params template and an input JSONL file: every
+    /// `{{field}}` in the template is replaced with the field of an input line.
+    /// Model and max tokens default to the configuration.
+    Submit {
+        /// JSONL file of batch requests
+        #[arg(required_unless_present = "template", conflicts_with = "template")]
+        requests: Option<PathBuf>,
+
+        /// JSON file with the params of every request, containing {{field}} placeholders
+        #[arg(long, requires = "input")]
+        template: Option<PathBuf>,
+
+        /// JSONL file of objects to fill the template with; an "id" field becomes the custom_id
+        #[arg(long, requires = "template")]
+        input: Option<PathBuf>,
+
+        /// Only submit the requests that did not succeed in this earlier batch
+        #[arg(long, value_name = "BATCH_ID")]
+        failed_in: Option<String>,
+    },
+
+    /// Show the progress of a batch
+    Status {
+        id: String,
+
+        /// Print the batch as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Wait until a batch has ended, printing its progress
+    ///
+    /// Sends the configured completion notification when it ends.
+    Wait {
+        id: String,
+
+        /// Seconds between checks
+        #[arg(long, default_value_t = 60)]
+        interval: u64,
+    },
+
+    /// Download the results of an ended batch as JSONL
+    ///
+    /// Results are written as they arrive. The usage of successful requests is
+    /// recorded in the usage ledger.
+    Results {
+        id: String,
+
+        /// Write the results to this file instead of stdout
+        #[arg(long, short, conflicts_with = "split")]
+        output: Option<PathBuf>,
+
+        /// Write succeeded.jsonl, errored.jsonl, expired.jsonl and canceled.jsonl to this directory
+        #[arg(long, value_name = "DIR")]
+        split: Option<PathBuf>,
+    },
+
+    /// Cancel a batch; requests that already finished keep their results
+    Cancel { id: String },
+}
+
+#[cfg(feature = "admin")]
+#[derive(Subcommand, Debug, Clone)]
+pub enum AdminCommand {
+    /// Daily token usage for the organization
+    Usage {
+        #[command(flatten)]
+        report: ReportArgs,
+    },
+
+    /// Daily costs for the organization
+    Cost {
+        #[command(flatten)]
+        report: ReportArgs,
+    },
+
+    /// List the organization's API keys
+    Keys {
+        /// Print the keys as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// List, create or archive workspaces
+    Workspaces {
+        #[command(subcommand)]
+        command: Option<WorkspaceCommand>,
+    },
+}
+
+/// How `summarize` and `extract` split long text
+#[derive(clap::Args, Debug, Clone)]
+pub struct ChunkArgs {
+    /// Bytes per part (defaults to what the model's context window holds)
+    #[arg(long)]
+    pub chunk_bytes: Option<usize>,
+
+    /// Bytes repeated between consecutive parts
+    #[arg(long, default_value_t = 2000)]
+    pub overlap_bytes: usize,
+
+    /// Parts processed at the same time
+    #[arg(long, default_value_t = 4)]
+    pub concurrency: usize,
+}
+
+/// Self-consistency sampling for `bool` and `extract`
+#[derive(clap::Args, Debug, Clone)]
+pub struct SamplingArgs {
+    /// Ask this many times and return the most common answer (self-consistency)
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    pub samples: usize,
+
+    /// Sampling temperature when --samples is above 1
+    #[arg(long, value_name = "TEMPERATURE", default_value_t = 0.7)]
+    pub sample_temperature: f32,
+}
+
+impl SamplingArgs {
+    /// The sampling to do, if more than one answer is asked for
+    pub fn sampling(&self) -> Option<ellm::Sampling> {
+        (self.samples > 1).then_some(ellm::Sampling {
+            samples: self.samples,
+            temperature: self.sample_temperature,
+        })
+    }
+}
+
+impl From<ChunkArgs> for ellm::LongDocOptions {
+    fn from(args: ChunkArgs) -> Self {
+        Self {
+            chunk_bytes: args.chunk_bytes,
+            overlap_bytes: args.overlap_bytes,
+            concurrency: args.concurrency,
+        }
+    }
+}
+
+#[cfg(feature = "admin")]
+#[derive(clap::Args, Debug, Clone)]
+pub struct ReportArgs {
+    /// Start date, e.g. 2024-06-01 or an RFC 3339 timestamp
+    #[arg(long)]
+    pub since: String,
+
+    /// End date (exclusive); defaults to now
+    #[arg(long)]
+    pub until: Option<String>,
+
+    /// Break down by these fields, e.g. model,workspace_id
+    #[arg(long, value_delimiter = ',')]
+    pub group_by: Vec<String>,
+
+    /// Print the raw report as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[cfg(feature = "admin")]
+#[derive(Subcommand, Debug, Clone)]
+pub enum WorkspaceCommand {
+    /// List workspaces (the default)
+    List,
+    /// Create a workspace
+    Create { name: String },
+    /// Archive a workspace
+    Archive { id: String },
+}
+
+/// The `--max-tokens` setting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaxTokens {
+    Fixed(u32),
+    Auto,
+}
+
+impl FromStr for MaxTokens {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "auto" => Ok(MaxTokens::Auto),
+            _ => value
+                .parse()
+                .map(MaxTokens::Fixed)
+                .map_err(|_| format!("expected a number or auto, got {:?}", value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cli_parse_send() {
+        let args = vec!["ellm", "send", "Hello, Claude!"];
+        let cli = Cli::try_parse_from(args).unwrap();
+
+        match cli.command {
+            Commands::Send {
+                message,
+                files,
+                show_thinking,
+                output,
+                verify,
+                ..
+            } => {
+                assert_eq!(message, "Hello, Claude!");
+                assert!(files.is_empty());
+                assert!(!show_thinking);
+                assert_eq!(output, "text");
+                assert!(!verify);
+            }
+            _ => panic!("Expected Send command"),
+        }
+
+        let args = vec!["ellm", "send", "--verify", "Who wrote Dune?"];
+        match Cli::try_parse_from(args).unwrap().command {
+            Commands::Send { verify, .. } => assert!(verify),
+            _ => panic!("Expected Send command"),
+        }
+
+        let args = vec![
+            "ellm",
+            "send",
+            "--constraint",
+            "max-words:50",
+            "--constraint",
+            "section:Summary",
+            "Describe Rust",
+        ];
+        match Cli::try_parse_from(args).unwrap().command {
+            Commands::Send { constraints, .. } => assert_eq!(
+                constraints,
+                vec![
+                    Constraint::MaxWords(50),
+                    Constraint::Section("Summary".into())
+                ]
+            ),
+            _ => panic!("Expected Send command"),
+        }
+        assert!(Cli::try_parse_from(["ellm", "send", "--constraint", "short", "Hi"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_parse_send_with_cached_files() {
+        let args = vec![
+            "ellm",
+            "send",
+            "--cache-system",
+            "--system",
+            "Be brief",
+            "-f",
+            "a.txt",
+            "--file",
+            "b.txt",
+            "--cache-files",
+            "Summarize",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+        assert!(cli.cache_system);
+
+        match cli.command {
+            Commands::Send {
+                system,
+                files,
+                cache_files,
+                ..
+            } => {
+                assert_eq!(system.as_deref(), Some("Be brief"));
+                assert_eq!(files, vec![PathBuf::from("a.txt"), PathBuf::from("b.txt")]);
+                assert!(cache_files);
+            }
+            _ => panic!("Expected Send command"),
+        }
+
+        let args = vec!["ellm", "send", "--cache-files", "Summarize"];
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn test_cli_parse_send_with_screenshot() {
+        let screenshot = |args: &[&str]| match Cli::try_parse_from(args).unwrap().command {
+            Commands::Send { screenshot, .. } => screenshot,
+            _ => panic!("Expected Send command"),
+        };
+        assert_eq!(screenshot(&["ellm", "send", "Hi"]), None);
+        assert_eq!(
+            screenshot(&["ellm", "send", "--screenshot", "What's wrong?"]).as_deref(),
+            Some("screen")
+        );
+        assert_eq!(
+            screenshot(&["ellm", "send", "--screenshot=region", "What's wrong?"]).as_deref(),
+            Some("region")
+        );
+        assert!(Cli::try_parse_from(["ellm", "send", "--screenshot=window", "Hi"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_parse_send_with_openai_conversation() {
+        let args = [
+            "ellm",
+            "send",
+            "--import-openai",
+            "chat.json",
+            "--export-openai",
+            "out.json",
+            "And then?",
+        ];
+        match Cli::try_parse_from(args).unwrap().command {
+            Commands::Send {
+                import_openai,
+                export_openai,
+                ..
+            } => {
+                assert_eq!(import_openai, Some(PathBuf::from("chat.json")));
+                assert_eq!(export_openai, Some(PathBuf::from("out.json")));
+            }
+            _ => panic!("Expected Send command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parse_fixtures() {
+        let args = [
+            "ellm", "fixtures", "record", "fx", "send", "--system", "Be brief", "Hi",
+        ];
+        match Cli::try_parse_from(args).unwrap().command {
+            Commands::Fixtures {
+                command: FixturesCommand::Record { dir, args },
+            } => {
+                assert_eq!(dir, PathBuf::from("fx"));
+                assert_eq!(args, ["send", "--system", "Be brief", "Hi"]);
+            }
+            _ => panic!("Expected Fixtures command"),
+        }
+        assert!(Cli::try_parse_from(["ellm", "fixtures", "replay", "fx"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_parse_select() {
+        let cli = Cli::try_parse_from(["ellm", "bool", "--json", "--select", "answer", "Is it?"])
+            .unwrap();
+        assert_eq!(cli.select.unwrap().to_string(), "answer");
+        assert!(Cli::try_parse_from(["ellm", "--select", "items[", "bool", "Is it?"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_parse_send_with_thinking() {
+        let args = vec![
+            "ellm",
+            "send",
+            "--thinking",
+            "2048",
+            "--show-thinking",
+            "--output",
+            "json",
+            "Why?",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+
+        assert_eq!(cli.thinking, Some(2048));
+        assert!(matches!(
+            cli.command,
+            Commands::Send { show_thinking: true, ref output, .. } if output == "json"
+        ));
+    }
+
+    #[test]
+    fn test_cli_parse_with_api_key() {
+        let args = vec!["ellm", "--api-key", "sk-ant-test", "send", "Hello"];
+        let cli = Cli::try_parse_from(args).unwrap();
+
+        assert_eq!(cli.api_key, Some("sk-ant-test".to_string()));
+        assert!(!cli.no_redact);
+    }
+
+    #[test]
+    fn test_cli_parse_no_redact() {
+        let args = vec!["ellm", "send", "Hello", "--no-redact"];
+        let cli = Cli::try_parse_from(args).unwrap();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        assert!(cli.no_redact);
+    }
 
     #[test]
-    fn test_cli_parse_send() {
-        let args = vec!["ellm", "send", "Hello, Claude!"];
+    fn test_cli_parse_log_and_error_format() {
+        let args = vec!["ellm", "send", "Hello", "--log-format", "json"];
         let cli = Cli::try_parse_from(args).unwrap();
+        assert_eq!(cli.log_format, Some(LogFormat::Json));
+        assert_eq!(cli.error_format, "text");
 
-        match cli.command {
-            Commands::Send { message } => {
-                assert_eq!(message, "Hello, Claude!");
-            }
-            _ => panic!("Expected Send command"),
-        }
+        let args = vec!["ellm", "--log-format", "yaml", "send", "Hello"];
+        assert!(Cli::try_parse_from(args).is_err());
     }
 
     #[test]
-    fn test_cli_parse_with_api_key() {
-        let args = vec!["ellm", "--api-key", "sk-ant-test", "send", "Hello"];
+    fn test_cli_parse_safety() {
+        let cli = Cli::try_parse_from(vec!["ellm", "send", "Hello"]).unwrap();
+        assert_eq!(cli.safety, None);
+
+        let args = vec!["ellm", "bool", "Is it safe?", "--safety", "strict"];
         let cli = Cli::try_parse_from(args).unwrap();
+        assert_eq!(cli.safety, Some(SafetyPreset::Strict));
 
-        assert_eq!(cli.api_key, Some("sk-ant-test".to_string()));
+        let args = vec!["ellm", "--safety", "lax", "send", "Hello"];
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn test_cli_parse_respond_in() {
+        let cli = Cli::try_parse_from(vec!["ellm", "send", "Hello"]).unwrap();
+        assert_eq!(cli.respond_in, None);
+
+        let args = vec!["ellm", "summarize", "notes.md", "--respond-in", "Japanese"];
+        let cli = Cli::try_parse_from(args).unwrap();
+        assert_eq!(cli.respond_in.as_deref(), Some("Japanese"));
     }
 
     #[test]
@@ -77,7 +1395,29 @@ mod tests {
         let args = vec!["ellm", "--max-tokens", "1000", "send", "Hello"];
         let cli = Cli::try_parse_from(args).unwrap();
 
-        assert_eq!(cli.max_tokens, 1000);
+        assert_eq!(cli.max_tokens, Some(MaxTokens::Fixed(1000)));
+
+        let cli = Cli::try_parse_from(vec!["ellm", "--max-tokens", "auto", "send", "Hi"]).unwrap();
+        assert_eq!(cli.max_tokens, Some(MaxTokens::Auto));
+        assert!(Cli::try_parse_from(vec!["ellm", "--max-tokens", "lots", "send", "Hi"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_parse_post_steps() {
+        let args = vec![
+            "ellm",
+            "send",
+            "Hello",
+            "--post",
+            "strip-markdown",
+            "--post",
+            "json:answer",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+
+        assert_eq!(cli.post.len(), 2);
+        assert!(matches!(cli.post[1], PostProcessor::JsonField(ref path) if path == "answer"));
+        assert!(Cli::try_parse_from(vec!["ellm", "--post", "bogus", "config"]).is_err());
     }
 
     #[test]
@@ -94,13 +1434,703 @@ mod tests {
         let cli = Cli::try_parse_from(args).unwrap();
 
         match cli.command {
-            Commands::Bool { question } => {
+            Commands::Bool {
+                question,
+                min_confidence,
+                quiet,
+                json,
+                sampling,
+            } => {
                 assert_eq!(question, "Is Rust a systems programming language?");
+                assert_eq!(min_confidence, 0.5);
+                assert!(!quiet);
+                assert!(!json);
+                assert_eq!(sampling.sampling(), None);
             }
             _ => panic!("Expected Bool command"),
         }
     }
 
+    #[test]
+    fn test_cli_parse_bool_samples() {
+        let args = vec!["ellm", "bool", "Is it spam?", "--samples", "7"];
+        match Cli::try_parse_from(args).unwrap().command {
+            Commands::Bool { sampling, .. } => assert_eq!(
+                sampling.sampling(),
+                Some(ellm::Sampling {
+                    samples: 7,
+                    temperature: 0.7
+                })
+            ),
+            _ => panic!("Expected Bool command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parse_translate() {
+        let args = vec!["ellm", "translate", "--to", "de", "Hello {name}"];
+        let cli = Cli::try_parse_from(args).unwrap();
+
+        match cli.command {
+            Commands::Translate {
+                text,
+                to,
+                from,
+                jsonl,
+                field,
+                ..
+            } => {
+                assert_eq!(text, Some("Hello {name}".to_string()));
+                assert_eq!(to, "de");
+                assert_eq!(from, None);
+                assert!(!jsonl);
+                assert_eq!(field, "text");
+            }
+            _ => panic!("Expected Translate command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parse_translate_jsonl() {
+        let args = vec![
+            "ellm",
+            "translate",
+            "--to",
+            "fr",
+            "--file",
+            "strings.jsonl",
+            "--jsonl",
+            "--field",
+            "message",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+
+        match cli.command {
+            Commands::Translate {
+                file, jsonl, field, ..
+            } => {
+                assert_eq!(file, Some(PathBuf::from("strings.jsonl")));
+                assert!(jsonl);
+                assert_eq!(field, "message");
+            }
+            _ => panic!("Expected Translate command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parse_summarize_and_extract() {
+        let cli = Cli::try_parse_from(vec!["ellm", "summarize", "--file", "report.txt"]).unwrap();
+        match cli.command {
+            Commands::Summarize {
+                text,
+                file,
+                instructions,
+                chunking,
+            } => {
+                assert_eq!(text, None);
+                assert_eq!(file, Some(PathBuf::from("report.txt")));
+                assert_eq!(instructions, None);
+                assert_eq!(
+                    ellm::LongDocOptions::from(chunking),
+                    ellm::LongDocOptions::default()
+                );
+            }
+            _ => panic!("Expected Summarize command"),
+        }
+
+        let args = vec![
+            "ellm",
+            "extract",
+            "--schema",
+            "parties.json",
+            "--concurrency",
+            "8",
+            "--chunk-bytes",
+            "50000",
+            "The contract text",
+        ];
+        match Cli::try_parse_from(args).unwrap().command {
+            Commands::Extract {
+                text,
+                schema,
+                chunking,
+                ..
+            } => {
+                assert_eq!(text, Some("The contract text".to_string()));
+                assert_eq!(schema, PathBuf::from("parties.json"));
+                assert_eq!(chunking.concurrency, 8);
+                assert_eq!(chunking.chunk_bytes, Some(50000));
+            }
+            _ => panic!("Expected Extract command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parse_ocr() {
+        let args = vec!["ellm", "ocr", "scan.png", "--format", "csv", "--layout"];
+        match Cli::try_parse_from(args).unwrap().command {
+            Commands::Ocr {
+                image,
+                format,
+                layout,
+            } => {
+                assert_eq!(image, PathBuf::from("scan.png"));
+                assert_eq!(format, OcrFormat::Csv);
+                assert!(layout);
+            }
+            _ => panic!("Expected Ocr command"),
+        }
+
+        let cli = Cli::try_parse_from(["ellm", "ocr", "scan.png"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Ocr {
+                format: OcrFormat::Text,
+                layout: false,
+                ..
+            }
+        ));
+        assert!(Cli::try_parse_from(["ellm", "ocr", "scan.png", "--format", "html"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_parse_table() {
+        let args = vec![
+            "ellm",
+            "table",
+            "sales.csv",
+            "Which region grew fastest?",
+            "--verify",
+        ];
+        match Cli::try_parse_from(args).unwrap().command {
+            Commands::Table {
+                path,
+                question,
+                verify,
+                budget,
+                pseudonymize,
+                pseudonymize_columns,
+                json,
+            } => {
+                assert!(!pseudonymize);
+                assert!(pseudonymize_columns.is_empty());
+                assert_eq!(path, PathBuf::from("sales.csv"));
+                assert_eq!(question, "Which region grew fastest?");
+                assert!(verify);
+                assert_eq!(budget, 20_000);
+                assert!(!json);
+            }
+            _ => panic!("Expected Table command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parse_regex() {
+        let args = vec![
+            "ellm",
+            "regex",
+            "match ISO dates",
+            "--samples",
+            "samples.txt",
+        ];
+        match Cli::try_parse_from(args).unwrap().command {
+            Commands::Regex {
+                description,
+                samples,
+                full_match,
+                attempts,
+                json,
+            } => {
+                assert_eq!(description, "match ISO dates");
+                assert_eq!(samples, PathBuf::from("samples.txt"));
+                assert!(!full_match);
+                assert_eq!(attempts, 5);
+                assert!(!json);
+            }
+            _ => panic!("Expected Regex command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parse_council() {
+        let args = vec![
+            "ellm",
+            "council",
+            "Is P equal to NP?",
+            "--models",
+            "claude-opus-4-1,claude-sonnet-4-5",
+            "--models",
+            "claude-haiku-4-5",
+            "--aggregate",
+            "vote",
+        ];
+        match Cli::try_parse_from(args).unwrap().command {
+            Commands::Council {
+                question,
+                models,
+                aggregate,
+                synthesizer,
+                ..
+            } => {
+                assert_eq!(question, "Is P equal to NP?");
+                assert_eq!(
+                    models,
+                    ["claude-opus-4-1", "claude-sonnet-4-5", "claude-haiku-4-5"]
+                );
+                assert_eq!(aggregate, "vote");
+                assert_eq!(synthesizer, None);
+            }
+            _ => panic!("Expected Council command"),
+        }
+        assert!(Cli::try_parse_from(vec!["ellm", "council", "Why?"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_parse_eval_golden() {
+        let args = [
+            "ellm",
+            "eval",
+            "golden",
+            "cases.jsonl",
+            "--compare",
+            "similarity",
+            "--threshold",
+            "0.9",
+        ];
+        match Cli::try_parse_from(args).unwrap().command {
+            Commands::Eval {
+                command:
+                    EvalCommand::Golden {
+                        cases,
+                        compare,
+                        threshold,
+                        update,
+                        ..
+                    },
+            } => {
+                assert_eq!(cases, PathBuf::from("cases.jsonl"));
+                assert_eq!(compare, GoldenComparison::Similarity);
+                assert_eq!(threshold, 0.9);
+                assert!(!update);
+            }
+            _ => panic!("Expected Eval command"),
+        }
+        let args = [
+            "ellm",
+            "eval",
+            "golden",
+            "cases.jsonl",
+            "--compare",
+            "fuzzy",
+        ];
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn test_cli_parse_critique() {
+        let args = vec![
+            "ellm",
+            "critique",
+            "Explain borrowing",
+            "--rounds",
+            "3",
+            "--critic",
+            "claude-opus-4-1",
+        ];
+        match Cli::try_parse_from(args).unwrap().command {
+            Commands::Critique {
+                prompt,
+                criteria,
+                rounds,
+                critic,
+                ..
+            } => {
+                assert_eq!(prompt.as_deref(), Some("Explain borrowing"));
+                assert_eq!(criteria, "correct, complete and clear");
+                assert_eq!(rounds, 3);
+                assert_eq!(critic.as_deref(), Some("claude-opus-4-1"));
+            }
+            _ => panic!("Expected Critique command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parse_optimize_prompt() {
+        let args = vec![
+            "ellm",
+            "optimize-prompt",
+            "--prompt",
+            "p.md",
+            "--cases",
+            "cases.jsonl",
+        ];
+        match Cli::try_parse_from(args).unwrap().command {
+            Commands::OptimizePrompt {
+                prompt,
+                cases,
+                rounds,
+                optimizer,
+                ..
+            } => {
+                assert_eq!(prompt, PathBuf::from("p.md"));
+                assert_eq!(cases, PathBuf::from("cases.jsonl"));
+                assert_eq!(rounds, 3);
+                assert_eq!(optimizer, None);
+            }
+            _ => panic!("Expected OptimizePrompt command"),
+        }
+        assert!(Cli::try_parse_from(vec!["ellm", "optimize-prompt", "--prompt", "p.md"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_parse_sweep() {
+        let args = vec!["ellm", "sweep", "--prompt", "p.md"];
+        match Cli::try_parse_from(args).unwrap().command {
+            Commands::Sweep {
+                prompt,
+                temperature,
+                models,
+                judge,
+                ..
+            } => {
+                assert_eq!(prompt, PathBuf::from("p.md"));
+                assert_eq!(temperature, [0.0, 0.3, 0.7, 1.0]);
+                assert!(models.is_empty());
+                assert_eq!(judge, None);
+            }
+            _ => panic!("Expected Sweep command"),
+        }
+
+        let args = vec!["ellm", "sweep", "--prompt", "p.md", "--temperature", "0.5"];
+        match Cli::try_parse_from(args).unwrap().command {
+            Commands::Sweep { temperature, .. } => assert_eq!(temperature, [0.5]),
+            _ => panic!("Expected Sweep command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parse_stats() {
+        let cli = Cli::try_parse_from(vec!["ellm", "stats", "--json"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Stats {
+                json: true,
+                clear: false
+            }
+        ));
+        assert!(Cli::try_parse_from(vec!["ellm", "stats", "--json", "--clear"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_parse_batch_wait() {
+        let args = vec![
+            "ellm",
+            "batch",
+            "wait",
+            "msgbatch_1",
+            "--notify-webhook",
+            "https://hooks.example/x",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+        assert_eq!(
+            cli.notify_webhook.as_deref(),
+            Some("https://hooks.example/x")
+        );
+        match cli.command {
+            Commands::Batch {
+                command: BatchCommand::Wait { id, interval },
+            } => {
+                assert_eq!(id, "msgbatch_1");
+                assert_eq!(interval, 60);
+            }
+            _ => panic!("Expected Batch Wait command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parse_digest() {
+        let args = vec!["ellm", "digest", "--feeds", "feeds.txt", "--since", "7d"];
+        match Cli::try_parse_from(args).unwrap().command {
+            Commands::Digest {
+                feeds,
+                since,
+                chunking,
+            } => {
+                assert_eq!(feeds, PathBuf::from("feeds.txt"));
+                assert_eq!(since, "7d");
+                assert_eq!(chunking.concurrency, 4);
+            }
+            _ => panic!("Expected Digest command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parse_diff_explain() {
+        let args = vec!["ellm", "diff-explain", "--range", "main..HEAD", "--json"];
+        match Cli::try_parse_from(args).unwrap().command {
+            Commands::DiffExplain { range, json } => {
+                assert_eq!(range, Some("main..HEAD".to_string()));
+                assert!(json);
+            }
+            _ => panic!("Expected DiffExplain command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parse_gh_triage() {
+        let args = vec!["ellm", "gh", "triage", "42", "--repo", "altendky/ellm"];
+        match Cli::try_parse_from(args).unwrap().command {
+            Commands::Gh {
+                command: GhCommand::Triage { issue, repo },
+            } => {
+                assert_eq!(issue, "42");
+                assert_eq!(repo, Some("altendky/ellm".to_string()));
+            }
+            _ => panic!("Expected Gh triage command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parse_gh_pr_describe() {
+        let args = vec!["ellm", "gh", "pr-describe", "--update", "--pr", "7"];
+        match Cli::try_parse_from(args).unwrap().command {
+            Commands::Gh {
+                command:
+                    GhCommand::PrDescribe {
+                        base,
+                        update,
+                        pr,
+                        repo,
+                        json,
+                    },
+            } => {
+                assert_eq!(base, None);
+                assert!(update);
+                assert_eq!(pr, Some(7));
+                assert_eq!(repo, None);
+                assert!(!json);
+            }
+            _ => panic!("Expected Gh pr-describe command"),
+        }
+        assert!(Cli::try_parse_from(vec!["ellm", "gh", "pr-describe", "--pr", "7"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_parse_git() {
+        let cli = Cli::try_parse_from(vec!["ellm", "git", "install-hooks"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Git {
+                command: GitCommand::InstallHooks { force: false }
+            }
+        ));
+
+        let args = vec![
+            "ellm",
+            "git",
+            "hook",
+            "prepare-commit-msg",
+            ".git/COMMIT_EDITMSG",
+            "message",
+        ];
+        match Cli::try_parse_from(args).unwrap().command {
+            Commands::Git {
+                command:
+                    GitCommand::Hook {
+                        name,
+                        args,
+                        timeout,
+                    },
+            } => {
+                assert_eq!(name, "prepare-commit-msg");
+                assert_eq!(args, [".git/COMMIT_EDITMSG", "message"]);
+                assert_eq!(timeout, 20);
+            }
+            _ => panic!("Expected Git hook command"),
+        }
+        assert!(Cli::try_parse_from(vec!["ellm", "git", "hook", "pre-push"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_parse_watch() {
+        let args = vec!["ellm", "watch", "src/lib.rs", "--template", "review.md"];
+        match Cli::try_parse_from(args).unwrap().command {
+            Commands::Watch {
+                paths,
+                template,
+                prompt,
+                debounce,
+                no_diff,
+                ..
+            } => {
+                assert_eq!(paths, [PathBuf::from("src/lib.rs")]);
+                assert_eq!(template, Some(PathBuf::from("review.md")));
+                assert_eq!(prompt, None);
+                assert_eq!(debounce, 500);
+                assert!(!no_diff);
+            }
+            _ => panic!("Expected Watch command"),
+        }
+
+        // A prompt is required, and so is something to watch
+        assert!(Cli::try_parse_from(vec!["ellm", "watch", "src/lib.rs"]).is_err());
+        assert!(Cli::try_parse_from(vec!["ellm", "watch", "--prompt", "Document this"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_parse_snippets() {
+        let args = vec!["ellm", "snippets", "save", "review", "--prompt", "Review:"];
+        match Cli::try_parse_from(args).unwrap().command {
+            Commands::Snippets {
+                command:
+                    SnippetCommand::Save {
+                        name,
+                        prompt,
+                        system,
+                        from,
+                    },
+            } => {
+                assert_eq!(name, "review");
+                assert_eq!(prompt, Some("Review:".to_string()));
+                assert_eq!(system, None);
+                assert_eq!(from, None);
+            }
+            _ => panic!("Expected Snippets save command"),
+        }
+
+        // A snippet needs some content, and --from replaces the rest
+        assert!(Cli::try_parse_from(vec!["ellm", "snippets", "save", "empty"]).is_err());
+        assert!(Cli::try_parse_from(vec![
+            "ellm", "snippets", "save", "shared", "--from", "a.toml", "--prompt", "x"
+        ])
+        .is_err());
+
+        let cli = Cli::try_parse_from(vec!["ellm", "send", "--snippet", "review", "code"]).unwrap();
+        assert_eq!(cli.snippet, Some("review".to_string()));
+    }
+
+    #[test]
+    fn test_cli_parse_mcp_serve() {
+        let cli = Cli::try_parse_from(vec!["ellm", "mcp-serve"]).unwrap();
+        assert!(matches!(cli.command, Commands::McpServe));
+    }
+
+    #[test]
+    fn test_cli_parse_daemon() {
+        let cli = Cli::try_parse_from(vec![
+            "ellm",
+            "daemon",
+            "--socket",
+            "/tmp/e.sock",
+            "--metrics",
+            "127.0.0.1:9464",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Daemon { socket, metrics } => {
+                assert_eq!(socket, Some(PathBuf::from("/tmp/e.sock")));
+                assert_eq!(metrics.unwrap().port(), 9464);
+            }
+            _ => panic!("Expected Daemon command"),
+        }
+
+        let cli = Cli::try_parse_from(vec!["ellm", "--no-daemon", "config"]).unwrap();
+        assert!(cli.no_daemon);
+    }
+
+    #[test]
+    fn test_cli_parse_usage_workspaces() {
+        let cli = Cli::try_parse_from(vec![
+            "ellm",
+            "--workspace",
+            "research",
+            "usage",
+            "workspaces",
+        ])
+        .unwrap();
+        assert_eq!(cli.workspace.as_deref(), Some("research"));
+        assert!(matches!(
+            cli.command,
+            Commands::Usage {
+                command: UsageCommand::Workspaces { json: false }
+            }
+        ));
+    }
+
+    #[test]
+    fn test_cli_parse_limits() {
+        let cli = Cli::try_parse_from(vec!["ellm", "limits", "--json"]).unwrap();
+        assert!(matches!(cli.command, Commands::Limits { json: true }));
+    }
+
+    #[test]
+    fn test_cli_parse_tokens() {
+        let cli = Cli::try_parse_from(vec!["ellm", "tokens", "--file", "prompt.txt"]).unwrap();
+        match cli.command {
+            Commands::Tokens { text, file, .. } => {
+                assert!(text.is_none());
+                assert_eq!(file, Some(PathBuf::from("prompt.txt")));
+            }
+            _ => panic!("Expected Tokens command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_parse_completions() {
+        let cli = Cli::try_parse_from(vec!["ellm", "completions", "zsh"]).unwrap();
+        match cli.command {
+            Commands::Completions { shell } => assert_eq!(shell, "zsh"),
+            _ => panic!("Expected Completions command"),
+        }
+
+        assert!(Cli::try_parse_from(vec!["ellm", "completions", "tcsh"]).is_err());
+    }
+
+    #[cfg(feature = "admin")]
+    #[test]
+    fn test_cli_parse_admin_usage() {
+        let cli = Cli::try_parse_from(vec![
+            "ellm",
+            "admin",
+            "usage",
+            "--since",
+            "2024-06-01",
+            "--group-by",
+            "model,workspace_id",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Admin {
+                command: AdminCommand::Usage { report },
+                ..
+            } => {
+                assert_eq!(report.since, "2024-06-01");
+                assert_eq!(report.group_by, vec!["model", "workspace_id"]);
+            }
+            _ => panic!("Expected Admin Usage command"),
+        }
+    }
+
+    #[cfg(feature = "grpc")]
+    #[test]
+    fn test_cli_parse_grpc_serve() {
+        let cli = Cli::try_parse_from(vec!["ellm", "grpc-serve"]).unwrap();
+        match cli.command {
+            Commands::GrpcServe { addr } => assert_eq!(addr.port(), 50051),
+            _ => panic!("Expected GrpcServe command"),
+        }
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_cli_parse_tui() {
+        let cli = Cli::try_parse_from(vec!["ellm", "tui", "--system", "Be brief"]).unwrap();
+        match cli.command {
+            Commands::Tui { system } => assert_eq!(system.as_deref(), Some("Be brief")),
+            _ => panic!("Expected Tui command"),
+        }
+    }
+
     #[test]
     fn test_cli_parse_bool_with_options() {
         let args = vec![
@@ -115,13 +2145,49 @@ mod tests {
         let cli = Cli::try_parse_from(args).unwrap();
 
         assert_eq!(cli.api_key, Some("sk-ant-test".to_string()));
-        assert_eq!(cli.max_tokens, 10);
+        assert_eq!(cli.max_tokens, Some(MaxTokens::Fixed(10)));
 
         match cli.command {
-            Commands::Bool { question } => {
+            Commands::Bool { question, .. } => {
                 assert_eq!(question, "Is the sky blue?");
             }
             _ => panic!("Expected Bool command"),
         }
     }
+
+    #[test]
+    fn test_cli_parse_bool_quiet_conflicts_with_json() {
+        let args = vec!["ellm", "bool", "--quiet", "Is the sky blue?"];
+        let cli = Cli::try_parse_from(args).unwrap();
+        assert!(matches!(cli.command, Commands::Bool { quiet: true, .. }));
+
+        let args = vec!["ellm", "bool", "--quiet", "--json", "Is the sky blue?"];
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn test_cli_parse_usage_report() {
+        let args = vec![
+            "ellm", "usage", "report", "--period", "day", "--format", "csv",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+        match cli.command {
+            Commands::Usage {
+                command:
+                    UsageCommand::Report {
+                        group_by,
+                        period,
+                        format,
+                    },
+            } => {
+                assert_eq!(group_by, GroupBy::Model);
+                assert_eq!(period, Period::Day);
+                assert_eq!(format, "csv");
+            }
+            _ => panic!("Expected Usage Report command"),
+        }
+
+        let args = vec!["ellm", "usage", "report", "--period", "fortnight"];
+        assert!(Cli::try_parse_from(args).is_err());
+    }
 }
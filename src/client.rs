@@ -1,31 +1,306 @@
 use crate::config::Config;
-use crate::error::{ApiError, Result};
+use crate::constraints::language_instruction;
+use crate::daemon::{self, RawResponse, RelayRequest, TlsSettings};
+use crate::diagnostics;
+use crate::error::{ApiError, ClaudeError, ConfigError, Result};
+use crate::guardrails::{Guardrail, Stage, StageRule};
+use crate::ledger::{Ledger, LedgerEntry};
+use crate::middleware::{Method, Middleware, Next, Request, Response};
+use crate::models::{self, ModelInfo};
+use crate::observer::{Completion, Observer, RequestInfo};
+use crate::policy::Policy;
+use crate::postprocess::ResponseHook;
+use crate::pricing;
+use crate::ratelimit::{self, Limiter, RateLimits};
+use crate::redact::Redactor;
+use crate::retry;
+use crate::tokens;
+use crate::tools::{Tool, ToolChoice};
+use base64::prelude::*;
+use futures::future::BoxFuture;
 use reqwest::Client as HttpClient;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 /// Claude API client
 pub struct Client {
     http_client: HttpClient,
     config: Config,
+    guardrails: Vec<Arc<dyn Guardrail>>,
+    daemon: Option<PathBuf>,
+    ledger: Option<Ledger>,
+    /// The organization policy the config was checked against, for its spend limit
+    policy: Policy,
+    observers: Vec<Arc<dyn Observer>>,
+    middleware: Vec<Arc<dyn Middleware>>,
+    response_hooks: Vec<Arc<dyn ResponseHook>>,
+    rate_limits: Mutex<Option<RateLimits>>,
+    /// Holds requests back to stay under the configured rate limits
+    limiter: Option<Arc<Limiter>>,
+    idempotency_key: Mutex<Option<String>>,
+    last_usage: Mutex<Option<Usage>>,
+    last_stop_reason: Mutex<Option<StopReason>>,
+}
+
+/// Build the HTTP client used for API requests
+pub(crate) fn build_http_client(config: &Config) -> Result<HttpClient> {
+    let http = &config.http;
+    let mut builder = HttpClient::builder();
+    if let Some(idle) = http.pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(idle);
+    }
+    if let Some(seconds) = http.pool_idle_timeout_secs {
+        builder = builder.pool_idle_timeout(Duration::from_secs(seconds));
+    }
+    if let Some(seconds) = http.http2_keep_alive_interval_secs {
+        builder = builder.http2_keep_alive_interval(Duration::from_secs(seconds));
+    }
+    if let Some(seconds) = http.http2_keep_alive_timeout_secs {
+        builder = builder.http2_keep_alive_timeout(Duration::from_secs(seconds));
+    }
+    if let Some(while_idle) = http.http2_keep_alive_while_idle {
+        builder = builder.http2_keep_alive_while_idle(while_idle);
+    }
+    if let Some(nodelay) = http.tcp_nodelay {
+        builder = builder.tcp_nodelay(nodelay);
+    }
+
+    if let Some(path) = &http.ca_bundle {
+        let certificates = reqwest::Certificate::from_pem_bundle(&read_pem(path)?)
+            .map_err(|e| tls_error(path, e))?;
+        if certificates.is_empty() {
+            return Err(tls_error(path, "no certificates found"));
+        }
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    match (&http.client_cert, &http.client_key) {
+        (Some(cert), key) => {
+            // The identity is read from a single PEM holding both the certificate and the key
+            let mut pem = read_pem(cert)?;
+            if let Some(key) = key {
+                pem.push(b'\n');
+                pem.extend(read_pem(key)?);
+            }
+            let identity = reqwest::Identity::from_pem(&pem).map_err(|e| tls_error(cert, e))?;
+            builder = builder.identity(identity);
+        }
+        (None, Some(key)) => {
+            return Err(tls_error(key, "client_key is set without client_cert"));
+        }
+        (None, None) => {}
+    }
+
+    builder
+        .build()
+        .map_err(|e| ApiError::InvalidRequest(e.to_string()).into())
+}
+
+fn read_pem(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).map_err(|e| tls_error(path, e))
+}
+
+fn tls_error(path: &Path, error: impl std::fmt::Display) -> ClaudeError {
+    ConfigError::Tls(format!("{}: {}", path.display(), error)).into()
 }
 
 /// Request structure for the Messages API
 #[derive(Debug, Serialize)]
-struct MessageRequest {
-    model: String,
-    max_tokens: u32,
+pub(crate) struct MessageRequest {
+    pub(crate) model: String,
+    pub(crate) max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) system: Option<SystemPrompt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) temperature: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    system: Option<String>,
+    pub(crate) thinking: Option<Thinking>,
+    pub(crate) messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) tools: Vec<Tool>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    temperature: Option<f32>,
-    messages: Vec<Message>,
+    pub(crate) tool_choice: Option<ToolChoice>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) stream: bool,
+    /// The start of the response, sent as the final assistant turn
+    #[serde(skip)]
+    pub(crate) prefill: Option<String>,
+}
+
+impl MessageRequest {
+    /// Have the model continue from `prefill` instead of starting its response afresh
+    ///
+    /// Trailing whitespace is removed, as the API rejects it.
+    pub(crate) fn set_prefill(&mut self, prefill: &str) {
+        if self.prefill.is_some() {
+            self.messages.pop();
+        }
+        let prefill = prefill.trim_end().to_string();
+        self.messages.push(Message {
+            role: "assistant".to_string(),
+            content: prefill.clone().into(),
+        });
+        self.prefill = Some(prefill);
+    }
+
+    /// Offer the model tools, optionally constraining whether and which it calls
+    pub(crate) fn set_tools(
+        &mut self,
+        tools: Vec<Tool>,
+        tool_choice: Option<ToolChoice>,
+    ) -> Result<()> {
+        // The API only lets the model decide for itself when it is thinking
+        if self.thinking.is_some() && tool_choice.as_ref().is_some_and(ToolChoice::forces_call) {
+            return Err(ClaudeError::Tool(
+                "a tool call cannot be forced with extended thinking enabled".into(),
+            ));
+        }
+        self.tools = tools;
+        self.tool_choice = tool_choice;
+        Ok(())
+    }
+
+    /// Whether any part of the request is marked for prompt caching
+    pub(crate) fn uses_cache(&self) -> bool {
+        matches!(self.system, Some(SystemPrompt::Cached(_)))
+            || self.messages.iter().any(|message| {
+                matches!(&message.content, Content::Blocks(blocks) if blocks.iter().any(|block| {
+                    matches!(block, ContentBlock::Document { cache_control: Some(_), .. })
+                }))
+            })
+    }
+}
+
+/// A system prompt, as plain text or as a block marked for caching
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub(crate) enum SystemPrompt {
+    Text(String),
+    Cached([CachedText; 1]),
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct CachedText {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    text: String,
+    cache_control: CacheControl,
+}
+
+impl SystemPrompt {
+    fn new(text: String, cache: bool) -> Self {
+        if cache {
+            SystemPrompt::Cached([CachedText {
+                kind: "text",
+                text,
+                cache_control: CacheControl::Ephemeral,
+            }])
+        } else {
+            SystemPrompt::Text(text)
+        }
+    }
+}
+
+/// Marks the end of a prompt prefix for the API to cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CacheControl {
+    /// Cached for five minutes after its last use
+    Ephemeral,
+}
+
+/// Extended thinking settings for a request
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum Thinking {
+    Enabled { budget_tokens: u32 },
 }
 
 /// Message structure for API requests
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Message {
     pub role: String,
-    pub content: String,
+    pub content: Content,
+}
+
+/// The content of a message: plain text, or blocks such as tool calls and their results
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Content {
+    Text(String),
+    Blocks(Vec<ContentBlock>),
+}
+
+impl Content {
+    /// Apply `check` to all text the content carries, e.g. to redact it
+    fn map_text(self, check: impl Fn(String) -> Result<String>) -> Result<Self> {
+        Ok(match self {
+            Content::Text(text) => Content::Text(check(text)?),
+            Content::Blocks(blocks) => Content::Blocks(
+                blocks
+                    .into_iter()
+                    .map(|block| {
+                        Ok(match block {
+                            ContentBlock::Text { text } => {
+                                ContentBlock::Text { text: check(text)? }
+                            }
+                            ContentBlock::ToolResult {
+                                tool_use_id,
+                                content,
+                                is_error,
+                            } => ContentBlock::ToolResult {
+                                tool_use_id,
+                                content: check(content)?,
+                                is_error,
+                            },
+                            ContentBlock::Document {
+                                source: DocumentSource::Text { media_type, data },
+                                title,
+                                cache_control,
+                            } => ContentBlock::Document {
+                                source: DocumentSource::Text {
+                                    media_type,
+                                    data: check(data)?,
+                                },
+                                title,
+                                cache_control,
+                            },
+                            block => block,
+                        })
+                    })
+                    .collect::<Result<_>>()?,
+            ),
+        })
+    }
+}
+
+impl From<String> for Content {
+    fn from(text: String) -> Self {
+        Content::Text(text)
+    }
+}
+
+impl From<&str> for Content {
+    fn from(text: &str) -> Self {
+        Content::Text(text.to_string())
+    }
+}
+
+impl From<Vec<ContentBlock>> for Content {
+    fn from(blocks: Vec<ContentBlock>) -> Self {
+        Content::Blocks(blocks)
+    }
+}
+
+impl PartialEq<&str> for Content {
+    fn eq(&self, other: &&str) -> bool {
+        matches!(self, Content::Text(text) if text == other)
+    }
 }
 
 // TODO: do i really want Clone?
@@ -46,22 +321,37 @@ impl Messages {
     }
 
     pub fn push_user(&mut self, content: String) -> &mut Self {
-        self._messages.push(Message {
+        self.push(Message {
             role: "user".into(),
-            content,
-        });
-
-        self
+            content: content.into(),
+        })
     }
 
     pub fn push_assistant(&mut self, content: String) -> &mut Self {
-        self._messages.push(Message {
+        self.push(Message {
             role: "assistant".into(),
-            content,
-        });
+            content: content.into(),
+        })
+    }
+
+    /// Add a message of any content, such as an assistant turn with tool calls
+    pub fn push(&mut self, message: Message) -> &mut Self {
+        self._messages.push(message);
 
         self
     }
+
+    pub fn len(&self) -> usize {
+        self._messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self._messages.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Message> {
+        self._messages.iter()
+    }
 }
 
 impl From<Messages> for Vec<Message> {
@@ -70,143 +360,1051 @@ impl From<Messages> for Vec<Message> {
     }
 }
 
-/// Response structure from the Messages API
-#[derive(Debug, Deserialize)]
-#[allow(dead_code)]
-struct MessageResponse {
-    id: String,
-    #[serde(rename = "type")]
-    response_type: String,
-    role: String,
-    content: Vec<ContentBlock>,
-    model: String,
-    stop_reason: Option<String>,
-    usage: Usage,
+/// A complete response from the Messages API
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MessageResponse {
+    pub id: String,
+    pub model: String,
+    /// Every block of the response, e.g. thinking followed by one or more text blocks
+    pub content: Vec<ContentBlock>,
+    pub stop_reason: Option<StopReason>,
+    pub usage: Usage,
 }
 
-/// Content block in the response
-#[derive(Debug, Deserialize)]
-#[allow(dead_code)]
-struct ContentBlock {
-    #[serde(rename = "type")]
-    block_type: String,
-    text: String,
+impl MessageResponse {
+    /// The text of all text blocks
+    pub fn text(&self) -> String {
+        self.content
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The model's reasoning from all thinking blocks
+    pub fn thinking(&self) -> String {
+        self.content
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::Thinking { thinking, .. } => Some(thinking.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The tool calls the model made, as (id, name, input)
+    pub fn tool_uses(&self) -> impl Iterator<Item = (&str, &str, &serde_json::Value)> {
+        self.content.iter().filter_map(|block| match block {
+            ContentBlock::ToolUse { id, name, input } => Some((id.as_str(), name.as_str(), input)),
+            _ => None,
+        })
+    }
+
+    /// Append the response to a continuation of this one, joining the text
+    /// where this one was cut off
+    pub(crate) fn extend(&mut self, next: MessageResponse) {
+        let mut blocks = next.content.into_iter().peekable();
+        if let (Some(ContentBlock::Text { text }), Some(ContentBlock::Text { text: more })) =
+            (self.content.last_mut(), blocks.peek())
+        {
+            text.push_str(more);
+            blocks.next();
+        }
+        self.content.extend(blocks);
+        self.stop_reason = next.stop_reason;
+        self.usage += &next.usage;
+    }
+
+    /// Remove trailing whitespace from the last text block
+    pub(crate) fn trim_end(&mut self) {
+        if let Some(ContentBlock::Text { text }) = self.content.last_mut() {
+            text.truncate(text.trim_end().len());
+        }
+    }
 }
 
-/// Usage statistics from the API
-#[derive(Debug, Deserialize)]
-#[allow(dead_code)]
-struct Usage {
-    input_tokens: u32,
-    output_tokens: u32,
+/// A block of content in a response
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentBlock {
+    Text {
+        text: String,
+    },
+    /// The model's reasoning before its answer, when extended thinking is enabled
+    Thinking {
+        thinking: String,
+        signature: String,
+    },
+    /// Reasoning the API withheld, encrypted so it can be sent back in later turns
+    RedactedThinking {
+        data: String,
+    },
+    /// A call the model wants to make to one of the request's tools
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    /// A document attached to a user message
+    Document {
+        source: DocumentSource,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    /// An image attached to a user message
+    Image {
+        source: ImageSource,
+    },
+    /// The outcome of a tool call, sent back to the model in a user message
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        is_error: bool,
+    },
+    /// A kind of block this version of the library does not know about, kept
+    /// as the API sent it so it can be sent back unchanged
+    #[serde(untagged)]
+    Other(serde_json::Value),
+}
+
+impl ContentBlock {
+    /// A plain text document, e.g. the contents of a file
+    pub fn text_document(title: Option<String>, text: String) -> Self {
+        ContentBlock::Document {
+            source: DocumentSource::Text {
+                media_type: "text/plain".into(),
+                data: text,
+            },
+            title,
+            cache_control: None,
+        }
+    }
+
+    /// A PDF document, sent inline
+    pub fn pdf_document(title: Option<String>, bytes: &[u8]) -> Self {
+        ContentBlock::Document {
+            source: DocumentSource::Base64 {
+                media_type: "application/pdf".into(),
+                data: BASE64_STANDARD.encode(bytes),
+            },
+            title,
+            cache_control: None,
+        }
+    }
+
+    /// An image of `media_type` (e.g. `image/png`), sent inline
+    pub fn image(media_type: impl Into<String>, bytes: &[u8]) -> Self {
+        ContentBlock::Image {
+            source: ImageSource::Base64 {
+                media_type: media_type.into(),
+                data: BASE64_STANDARD.encode(bytes),
+            },
+        }
+    }
+}
+
+/// Where the contents of an image block come from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ImageSource {
+    Base64 {
+        media_type: String,
+        data: String,
+    },
+    /// A source this version of the library does not know about, e.g. a URL,
+    /// kept as the API sent it
+    #[serde(untagged)]
+    Other(serde_json::Value),
+}
+
+/// Where the contents of a document block come from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DocumentSource {
+    Text {
+        media_type: String,
+        data: String,
+    },
+    /// A binary document such as a PDF, base64-encoded
+    Base64 {
+        media_type: String,
+        data: String,
+    },
+    /// A source this version of the library does not know about, e.g. a URL,
+    /// kept as the API sent it
+    #[serde(untagged)]
+    Other(serde_json::Value),
+}
+
+/// Token counts of a response
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    /// Input tokens that were neither written to nor read from the prompt cache
+    #[serde(default)]
+    pub input_tokens: u64,
+    /// Output tokens, including any extended thinking
+    #[serde(default)]
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub cache_creation_input_tokens: u64,
+    #[serde(default)]
+    pub cache_read_input_tokens: u64,
+}
+
+impl Usage {
+    /// Estimated cost in USD at `model`'s published prices, if they are known
+    pub fn cost(&self, model: &str) -> Option<f64> {
+        pricing::price(model).map(|price| {
+            price.cost(
+                self.input_tokens,
+                self.output_tokens,
+                self.cache_creation_input_tokens,
+                self.cache_read_input_tokens,
+            )
+        })
+    }
+}
+
+impl std::ops::AddAssign<&Usage> for Usage {
+    fn add_assign(&mut self, other: &Usage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_creation_input_tokens += other.cache_creation_input_tokens;
+        self.cache_read_input_tokens += other.cache_read_input_tokens;
+    }
+}
+
+/// Why the model stopped generating
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum StopReason {
+    /// The model finished its turn
+    EndTurn,
+    /// The output hit `max_tokens` and is incomplete
+    MaxTokens,
+    /// One of the request's stop sequences was generated
+    StopSequence,
+    /// The model is waiting for the result of a tool call
+    ToolUse,
+    /// A long-running turn was paused and can be continued by sending it back
+    PauseTurn,
+    /// The model declined to continue for safety reasons
+    Refusal,
+    /// The output hit the end of the model's context window and is incomplete
+    ModelContextWindowExceeded,
+    /// A reason this version of the library does not know about
+    Other(String),
+}
+
+impl StopReason {
+    pub fn as_str(&self) -> &str {
+        match self {
+            StopReason::EndTurn => "end_turn",
+            StopReason::MaxTokens => "max_tokens",
+            StopReason::StopSequence => "stop_sequence",
+            StopReason::ToolUse => "tool_use",
+            StopReason::PauseTurn => "pause_turn",
+            StopReason::Refusal => "refusal",
+            StopReason::ModelContextWindowExceeded => "model_context_window_exceeded",
+            StopReason::Other(reason) => reason,
+        }
+    }
+
+    /// Whether the output was cut off by a token limit rather than finished
+    pub fn is_truncated(&self) -> bool {
+        matches!(
+            self,
+            StopReason::MaxTokens | StopReason::ModelContextWindowExceeded
+        )
+    }
+}
+
+impl From<String> for StopReason {
+    fn from(reason: String) -> Self {
+        match reason.as_str() {
+            "end_turn" => StopReason::EndTurn,
+            "max_tokens" => StopReason::MaxTokens,
+            "stop_sequence" => StopReason::StopSequence,
+            "tool_use" => StopReason::ToolUse,
+            "pause_turn" => StopReason::PauseTurn,
+            "refusal" => StopReason::Refusal,
+            "model_context_window_exceeded" => StopReason::ModelContextWindowExceeded,
+            _ => StopReason::Other(reason),
+        }
+    }
+}
+
+impl From<StopReason> for String {
+    fn from(reason: StopReason) -> Self {
+        reason.as_str().to_string()
+    }
+}
+
+impl std::fmt::Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 /// Error response from the API
 #[derive(Debug, Deserialize)]
-#[allow(dead_code)]
 struct ErrorResponse {
+    error: ErrorDetail,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ErrorDetail {
     #[serde(rename = "type")]
-    error_type: String,
-    message: String,
+    pub(crate) error_type: String,
+    pub(crate) message: String,
+}
+
+impl ErrorDetail {
+    /// The error for an API error of this type, returned with `status`
+    pub(crate) fn into_error(self, status: u16) -> ClaudeError {
+        if status == 400 {
+            if let Some((have, limit)) = tokens::context_overflow(&self.message) {
+                return ClaudeError::ContextTooLarge { have, limit };
+            }
+        }
+        match (status, self.error_type.as_str()) {
+            (401, _) => ApiError::AuthenticationFailed(self.message),
+            (429, _) => ApiError::RateLimitExceeded,
+            (529, _) | (_, "overloaded_error") => ApiError::Overloaded(self.message),
+            _ => ApiError::ApiError {
+                status,
+                message: self.message,
+            },
+        }
+        .into()
+    }
+}
+
+/// The error for a non-success response
+pub(crate) fn api_error(status: u16, body: String) -> ClaudeError {
+    if let Ok(ErrorResponse { error }) = serde_json::from_str::<ErrorResponse>(&body) {
+        return error.into_error(status);
+    }
+
+    match status {
+        529 => ApiError::Overloaded(body),
+        _ => ApiError::ApiError {
+            status,
+            message: body,
+        },
+    }
+    .into()
 }
 
 impl Client {
     /// Create a new Claude API client
+    ///
+    /// The system-wide organization policy, if installed, is applied to the config.
     pub fn new(config: Config) -> Result<Self> {
         config.validate()?;
+        let policy = Policy::load_system()?;
+        let config = policy.apply(models::migrate(config))?;
+
+        let http_client = build_http_client(&config)?;
 
-        let http_client = HttpClient::builder()
-            .build()
-            .map_err(|e| ApiError::InvalidRequest(e.to_string()))?;
+        let stages = [
+            (Stage::Input, &config.guardrails.pre),
+            (Stage::Output, &config.guardrails.post),
+        ];
+        let mut guardrails: Vec<Arc<dyn Guardrail>> = Vec::new();
+        if config.redaction.enabled {
+            guardrails.push(Arc::new(Redactor::new(&config.redaction)?));
+        }
+        guardrails.extend(stages.into_iter().flat_map(|(stage, rules)| {
+            rules.iter().map(move |rule| {
+                Arc::new(StageRule {
+                    stage,
+                    rule: rule.clone(),
+                }) as Arc<dyn Guardrail>
+            })
+        }));
 
         Ok(Self {
             http_client,
+            limiter: ratelimit::limiter(&config.api_key, &config.rate_limit),
             config,
+            guardrails,
+            daemon: None,
+            ledger: None,
+            policy,
+            observers: Vec::new(),
+            middleware: Vec::new(),
+            response_hooks: Vec::new(),
+            rate_limits: Mutex::new(None),
+            idempotency_key: Mutex::new(None),
+            last_usage: Mutex::new(None),
+            last_stop_reason: Mutex::new(None),
         })
     }
 
-    /// Send a message to Claude and get a response
+    /// A client like this one that sends its requests to `model`
+    ///
+    /// It shares the connection pool, guardrails, observers, middleware,
+    /// response hooks and ledger of this client. The organization policy is
+    /// applied to the new model as in [`Client::new`].
+    pub fn for_model(&self, model: impl Into<String>) -> Result<Self> {
+        let config = self.config.clone().with_model(model);
+        self.with_config(Policy::load_system()?.apply(models::migrate(config))?)
+    }
+
+    /// A client like this one that samples its responses at `temperature`
+    ///
+    /// It shares everything else with this client, as for [`Client::for_model`].
+    pub fn with_temperature(&self, temperature: f32) -> Result<Self> {
+        let mut config = self.config.clone();
+        config.temperature = Some(temperature);
+        self.with_config(config)
+    }
+
+    fn with_config(&self, config: Config) -> Result<Self> {
+        Ok(Self {
+            http_client: self.http_client.clone(),
+            limiter: ratelimit::limiter(&config.api_key, &config.rate_limit),
+            config,
+            guardrails: self.guardrails.clone(),
+            daemon: self.daemon.clone(),
+            ledger: self.ledger.clone(),
+            policy: self.policy.clone(),
+            observers: self.observers.clone(),
+            middleware: self.middleware.clone(),
+            response_hooks: self.response_hooks.clone(),
+            rate_limits: Mutex::new(None),
+            idempotency_key: Mutex::new(None),
+            last_usage: Mutex::new(None),
+            last_stop_reason: Mutex::new(None),
+        })
+    }
+
+    /// Route requests through the daemon listening on `socket`
+    ///
+    /// If the daemon cannot be reached, requests are sent directly instead.
+    pub fn with_daemon(mut self, socket: impl Into<PathBuf>) -> Self {
+        self.daemon = Some(socket.into());
+        self
+    }
+
+    /// Refuse to send once the policy's monthly spend limit is reached
+    ///
+    /// Spend is read from this client's ledger, or the default one.
+    fn check_spend(&self) -> Result<()> {
+        if self.policy.max_spend.is_none() {
+            return Ok(());
+        }
+        match &self.ledger {
+            Some(ledger) => self.policy.check_spend(ledger),
+            None => self.policy.check_spend(&Ledger::open_default()?),
+        }
+    }
+
+    /// Record the usage of every successful request in `ledger`
+    pub fn with_ledger(mut self, ledger: Ledger) -> Self {
+        self.ledger = Some(ledger);
+        self
+    }
+
+    /// Add an observer that is told about every request as it happens
+    pub fn with_observer(mut self, observer: impl Observer + 'static) -> Self {
+        self.observers.push(Arc::new(observer));
+        self
+    }
+
+    /// Run `middleware` around every API request; see [`crate::middleware`]
+    pub fn with_middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Add a guardrail that runs on every prompt and response, after those from the config
+    pub fn with_guardrail(mut self, guardrail: impl Guardrail + 'static) -> Self {
+        self.guardrails.push(Arc::new(guardrail));
+        self
+    }
+
+    /// Rewrite the text of every response with `hook`; see [`ResponseHook`]
+    pub fn with_response_hook(mut self, hook: impl ResponseHook + 'static) -> Self {
+        self.response_hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Whether response hooks may rewrite responses, so streamed text differs
+    /// from the finished message's
+    pub fn has_response_hooks(&self) -> bool {
+        !self.response_hooks.is_empty()
+    }
+
+    pub(crate) fn check_guardrails(&self, stage: Stage, text: String) -> Result<String> {
+        self.guardrails
+            .iter()
+            .try_fold(text, |text, guardrail| guardrail.check(stage, text))
+    }
+
+    /// Check response text with the output guardrails and run it through the response hooks
+    pub(crate) fn finish_output(&self, text: String) -> Result<String> {
+        let text = self.check_guardrails(Stage::Output, text)?;
+        self.transform_output(text)
+    }
+
+    fn transform_output(&self, text: String) -> Result<String> {
+        self.response_hooks
+            .iter()
+            .try_fold(text, |text, hook| hook.transform(text))
+    }
+
+    /// Send a message to Claude and get the text of its response
+    ///
+    /// With a `prefill`, the model continues from it rather than starting its
+    /// response afresh, e.g. from `{` to make it answer with JSON. The returned
+    /// text starts with the prefill, minus any trailing whitespace, which the
+    /// API rejects. Thinking is disabled for prefilled requests.
     pub async fn send_message(
         &self,
-        mut messages: Messages,
-        lead: Option<String>,
+        messages: Messages,
+        prefill: Option<String>,
         system: Option<String>,
     ) -> Result<String> {
-        if let Some(lead) = lead {
-            messages.push_assistant(lead);
+        let response = self
+            .send_message_response(messages, prefill, system)
+            .await?;
+        Ok(response.text())
+    }
+
+    /// Send a message to Claude and get its complete response
+    ///
+    /// Each text block has passed the output guardrails and response hooks.
+    /// The first starts with `prefill`, as for [`Client::send_message`].
+    pub async fn send_message_response(
+        &self,
+        messages: Messages,
+        prefill: Option<String>,
+        system: Option<String>,
+    ) -> Result<MessageResponse> {
+        let mut request = self.message_request(messages, prefill, system)?;
+        let info = self.start_request(&request.model, false);
+
+        let result = self.send_request(&mut request, &info).await;
+        if let Err(error) = &result {
+            self.notify(|observer| observer.on_error(&info, error));
+        }
+        result
+    }
+
+    async fn send_request(
+        &self,
+        request: &mut MessageRequest,
+        info: &RequestInfo,
+    ) -> Result<MessageResponse> {
+        let mut response = self
+            .post_message(request, info.idempotency_key.clone())
+            .await?;
+        self.check_refusal(response.stop_reason.as_ref())?;
+        if !response
+            .content
+            .iter()
+            .any(|block| matches!(block, ContentBlock::Text { .. }))
+        {
+            return Err(ApiError::UnexpectedResponse("No content in response".to_string()).into());
+        }
+
+        let mut prefill = request.prefill.clone().unwrap_or_default();
+
+        // Continue a response cut off by max_tokens by prefilling what it said so far
+        let mut continuations = 0;
+        while response.stop_reason == Some(StopReason::MaxTokens)
+            && continuations < self.config.max_continuations
+            && request.thinking.is_none()
+        {
+            // Trimmed here too, so the pieces join up
+            response.trim_end();
+            request.set_prefill(&format!("{}{}", prefill, response.text()));
+
+            // A different body, so a new key: the same one could get the first answer again
+            let next = self
+                .post_message(request, self.new_idempotency_key())
+                .await?;
+            response.extend(next);
+            continuations += 1;
+        }
+        if continuations > 0 {
+            self.set_last_usage(response.usage.clone());
+        }
+
+        for block in &mut response.content {
+            if let ContentBlock::Text { text } = block {
+                // The first text block continues the prefill
+                let text_with_prefill = format!("{}{}", std::mem::take(&mut prefill), text);
+                *text = self.finish_output(text_with_prefill)?;
+            }
+        }
+
+        self.observe_response(info, &response, Some(&response.text()));
+        Ok(response)
+    }
+
+    /// Tell the observers about a buffered response and the text the caller sees of it
+    pub(crate) fn observe_response(
+        &self,
+        info: &RequestInfo,
+        response: &MessageResponse,
+        text: Option<&str>,
+    ) {
+        self.notify(|observer| {
+            observer.on_first_token(info);
+            if let Some(text) = text {
+                observer.on_token(info, text);
+            }
+            observer.on_complete(
+                info,
+                &Completion {
+                    input_tokens: response.usage.input_tokens,
+                    output_tokens: response.usage.output_tokens,
+                    cache_creation_input_tokens: response.usage.cache_creation_input_tokens,
+                    cache_read_input_tokens: response.usage.cache_read_input_tokens,
+                    stop_reason: response.stop_reason.clone(),
+                    elapsed: info.elapsed(),
+                },
+            );
+        });
+    }
+
+    /// Make a single buffered request, sent with `idempotency_key`, and parse
+    /// the response
+    pub(crate) async fn post_message(
+        &self,
+        request: &MessageRequest,
+        idempotency_key: String,
+    ) -> Result<MessageResponse> {
+        self.check_model(request)?;
+        let max_tokens = self.fit_context(request).await?;
+        let mut body = serde_json::to_value(request)?;
+        body["max_tokens"] = max_tokens.into();
+        let mut response = self.post("messages", &body, idempotency_key).await?;
+        let mut model = request.model.clone();
+
+        // A model retired since the client was created is only noticed now
+        if !(200..300).contains(&response.status) {
+            let error = models::not_found(&model, api_error(response.status, response.body));
+            model = match error {
+                ClaudeError::ModelNotFound {
+                    suggestion: Some(replacement),
+                    ..
+                } if self.config.auto_migrate => replacement,
+                error => return Err(error),
+            };
+            diagnostics::warn(
+                "model_fallback",
+                format!("{} was not found, using {} instead", request.model, model),
+                json!({"model": request.model, "replacement": model}),
+            );
+            body["model"] = model.clone().into();
+            response = self
+                .post("messages", &body, self.new_idempotency_key())
+                .await?;
+        }
+        if !(200..300).contains(&response.status) {
+            return Err(models::not_found(
+                &model,
+                api_error(response.status, response.body),
+            ));
+        }
+
+        let message_response: MessageResponse = serde_json::from_str(&response.body)
+            .map_err(|e| ApiError::UnexpectedResponse(e.to_string()))?;
+        self.record_usage(&message_response.model, &message_response.usage);
+        self.set_last_usage(message_response.usage.clone());
+        *self
+            .last_stop_reason
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = message_response.stop_reason.clone();
+
+        Ok(message_response)
+    }
+
+    /// Reject a request the model is known not to support
+    pub(crate) fn check_model(&self, request: &MessageRequest) -> Result<()> {
+        match models::model_info(&request.model) {
+            Some(info) => info.check(request),
+            None => Ok(()),
+        }
+    }
+
+    /// Assign a new request its idempotency key and tell the observers about it
+    pub(crate) fn start_request(&self, model: &str, stream: bool) -> RequestInfo {
+        let info = RequestInfo::new(self.new_idempotency_key(), model.to_string(), stream);
+        self.notify(|observer| observer.on_request(&info));
+        info
+    }
+
+    /// A key for a request with a new body, remembered as the latest one
+    pub(crate) fn new_idempotency_key(&self) -> String {
+        let key = retry::idempotency_key();
+        *self
+            .idempotency_key
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Some(key.clone());
+        key
+    }
+
+    pub(crate) fn notify(&self, event: impl Fn(&dyn Observer)) {
+        for observer in &self.observers {
+            event(observer.as_ref());
+        }
+    }
+
+    /// Build a request from the conversation, with the input guardrails applied
+    pub(crate) fn message_request(
+        &self,
+        messages: Messages,
+        prefill: Option<String>,
+        system: Option<String>,
+    ) -> Result<MessageRequest> {
+        // The API doesn't allow prefilling the response when thinking
+        let thinking = match prefill {
+            Some(_) => None,
+            None => self
+                .config
+                .thinking_budget
+                .map(|budget_tokens| Thinking::Enabled { budget_tokens }),
         };
 
-        let request = MessageRequest {
+        let system = system
+            .map(|system| self.check_guardrails(Stage::Input, system))
+            .transpose()?;
+        let system = match &self.config.respond_in {
+            Some(language) => {
+                let instruction = language_instruction(language);
+                Some(match system {
+                    Some(system) => format!("{}\n\n{}", system, instruction),
+                    None => instruction,
+                })
+            }
+            None => system,
+        };
+        let system = self
+            .config
+            .safety
+            .system_prompt(system)
+            .map(|system| SystemPrompt::new(system, self.config.cache_system));
+        let mut messages: Vec<Message> = messages.into();
+        for message in messages.iter_mut().filter(|message| message.role == "user") {
+            let content = std::mem::replace(&mut message.content, Content::Text(String::new()));
+            message.content = content.map_text(|text| self.check_guardrails(Stage::Input, text))?;
+        }
+
+        let mut request = MessageRequest {
             model: self.config.model.clone(),
-            max_tokens: self.config.max_tokens,
+            max_tokens: self.config.request_max_tokens(),
             system,
-            temperature: Some(0f32),
-            messages: messages.into(),
+            // Thinking requires the default temperature
+            temperature: thinking
+                .is_none()
+                .then_some(self.config.temperature.unwrap_or(0.0)),
+            thinking,
+            messages,
+            tools: Vec::new(),
+            tool_choice: None,
+            stream: false,
+            prefill: None,
         };
+        if let Some(prefill) = prefill {
+            request.set_prefill(&prefill);
+        }
+        Ok(request)
+    }
+
+    pub(crate) fn record_usage(&self, model: &str, usage: &Usage) {
+        if let Some(limiter) = &self.limiter {
+            limiter.charge(usage);
+        }
+        self.record(self.ledger_entry(model, usage));
+    }
+
+    pub(crate) fn ledger_entry(&self, model: &str, usage: &Usage) -> LedgerEntry {
+        LedgerEntry::new(
+            model,
+            self.config.workspace.clone(),
+            usage.input_tokens,
+            usage.output_tokens,
+            usage.cache_creation_input_tokens,
+            usage.cache_read_input_tokens,
+        )
+    }
+
+    pub(crate) fn record(&self, entry: LedgerEntry) {
+        let Some(ledger) = &self.ledger else {
+            return;
+        };
+
+        // The request already succeeded, so don't fail it over bookkeeping
+        if let Err(error) = ledger.append(&entry) {
+            diagnostics::warn(
+                "ledger",
+                format!("could not record usage: {}", error),
+                json!({}),
+            );
+        }
+    }
 
-        let url = format!("{}/messages", self.config.base_url);
-
-        let request = self
-            .http_client
-            .post(&url)
-            .header("x-api-key", &self.config.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("content-type", "application/json")
-            .json(&request);
-
-        let response = request.send().await?;
-        let status = response.status();
-        let body = response.text().await?;
-
-        if !status.is_success() {
-            // Try to parse as error response
-            if let Ok(error_resp) = serde_json::from_str::<ErrorResponse>(&body) {
-                return match status.as_u16() {
-                    401 => Err(ApiError::AuthenticationFailed(error_resp.message).into()),
-                    429 => Err(ApiError::RateLimitExceeded.into()),
-                    _ => Err(ApiError::ApiError {
-                        status: status.as_u16(),
-                        message: error_resp.message,
+    /// The rate limits reported with the most recent API response, if any
+    pub fn rate_limits(&self) -> Option<RateLimits> {
+        self.rate_limits
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// The idempotency key of the most recent request, for correlating it with logs
+    pub fn last_idempotency_key(&self) -> Option<String> {
+        self.idempotency_key
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Tokens used by the most recent call
+    ///
+    /// For calls that make several requests, such as structured output that
+    /// needed retries or a tool loop, this is the total over all of them.
+    pub fn last_usage(&self) -> Option<Usage> {
+        self.last_usage
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Why the most recent buffered response ended
+    ///
+    /// Check it for [`StopReason::MaxTokens`] to tell a complete answer from
+    /// one that was cut off.
+    pub fn last_stop_reason(&self) -> Option<StopReason> {
+        self.last_stop_reason
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub(crate) fn set_last_usage(&self, usage: Usage) {
+        *self.last_usage.lock().unwrap_or_else(|e| e.into_inner()) = Some(usage);
+    }
+
+    /// POST a JSON body to an API endpoint, through the daemon when configured
+    pub(crate) async fn post(
+        &self,
+        path: &str,
+        body: &serde_json::Value,
+        idempotency_key: String,
+    ) -> Result<RawResponse> {
+        self.with_retries(
+            idempotency_key,
+            |key| self.post_raw(path, body, key),
+            |response| (response.status, &response.headers),
+        )
+        .await
+    }
+
+    /// Make a request, retrying attempts the API rejected unprocessed
+    ///
+    /// Every attempt is sent with the same idempotency `key`; see [`retry`]
+    /// for when retrying is safe. `status` gives the status and headers of a
+    /// response.
+    pub(crate) async fn with_retries<T, F>(
+        &self,
+        key: String,
+        send: impl Fn(String) -> F,
+        status: impl Fn(&T) -> (u16, &Vec<(String, String)>),
+    ) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        // Nothing has been sent yet, so this failure is certain
+        self.check_spend()?;
+        let retries = &self.config.retry;
+
+        let mut attempt = 0;
+        loop {
+            let last = attempt >= retries.max_retries;
+
+            let response = match send(key.clone()).await {
+                Ok(response) => response,
+                Err(error) if retry::is_unsent(&error) => {
+                    if last {
+                        return Err(error);
                     }
-                    .into()),
-                };
+                    let delay = retries.backoff(attempt, None);
+                    diagnostics::warn(
+                        "retry",
+                        format!(
+                            "request {} could not be sent ({}), retrying in {:?}",
+                            key, error, delay
+                        ),
+                        json!({
+                            "idempotency_key": key,
+                            "attempt": attempt + 1,
+                            "delay_ms": delay.as_millis() as u64,
+                            "error": error.to_string(),
+                        }),
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                    continue;
+                }
+                // Replayed requests are never sent, and the daemon reports
+                // requests it refused or the API answered, so their outcome is known
+                Err(
+                    error
+                    @ (ClaudeError::Fixture(_) | ClaudeError::Config(_) | ClaudeError::Api(_)),
+                ) => return Err(error),
+                Err(error) => {
+                    return Err(ApiError::OutcomeUnknown {
+                        idempotency_key: key,
+                        message: error.to_string(),
+                    }
+                    .into())
+                }
+            };
+
+            let (code, headers) = status(&response);
+            let limits = RateLimits::from_headers(headers);
+            let retry_after = limits.as_ref().and_then(|limits| limits.retry_after);
+            if let Some(limits) = limits {
+                *self.rate_limits.lock().unwrap_or_else(|e| e.into_inner()) = Some(limits);
             }
 
-            return Err(ApiError::ApiError {
-                status: status.as_u16(),
-                message: body,
+            if last || !retry::is_retryable_status(code) {
+                return Ok(response);
             }
-            .into());
+            let delay = match code {
+                retry::OVERLOADED => retries.overloaded_backoff(attempt, retry_after),
+                _ => retries.backoff(attempt, retry_after),
+            };
+            diagnostics::warn(
+                "retry",
+                format!(
+                    "request {} was rejected with status {}, retrying in {:?}",
+                    key, code, delay
+                ),
+                json!({
+                    "idempotency_key": key,
+                    "attempt": attempt + 1,
+                    "status": code,
+                    "delay_ms": delay.as_millis() as u64,
+                }),
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
         }
+    }
 
-        let message_response: MessageResponse =
-            serde_json::from_str(&body).map_err(|e| ApiError::UnexpectedResponse(e.to_string()))?;
+    /// The headers sent with every API request
+    pub(crate) fn headers(&self, idempotency_key: String) -> Vec<(String, String)> {
+        vec![
+            ("x-api-key".to_string(), self.config.api_key.clone()),
+            ("anthropic-version".to_string(), "2023-06-01".to_string()),
+            ("content-type".to_string(), "application/json".to_string()),
+            (retry::IDEMPOTENCY_HEADER.to_string(), idempotency_key),
+        ]
+    }
 
-        // Extract the text from the first content block
-        let text = message_response
-            .content
-            .first()
-            .map(|block| block.text.clone())
-            .ok_or_else(|| ApiError::UnexpectedResponse("No content in response".to_string()))?;
+    async fn post_raw(
+        &self,
+        path: &str,
+        body: &serde_json::Value,
+        idempotency_key: String,
+    ) -> Result<RawResponse> {
+        let request = Request {
+            method: Method::POST,
+            url: format!("{}/{}", self.config.base_url, path),
+            headers: self.headers(idempotency_key),
+            body: Some(body.clone()),
+        };
+        RawResponse::from_response(self.send_http(request, false).await?).await
+    }
+
+    /// Send `request` through the middleware
+    ///
+    /// The body of a `stream`ed response is left to the caller to read.
+    pub(crate) async fn send_http(&self, request: Request, stream: bool) -> Result<Response> {
+        let transport = |request: Request| -> BoxFuture<'_, Result<Response>> {
+            Box::pin(self.transport(request, stream))
+        };
+        Next::new(&self.middleware, &transport).run(request).await
+    }
 
-        Ok(text)
+    /// Actually send `request`, through the daemon when configured
+    ///
+    /// The rate limits are enforced here, or by the daemon for the requests
+    /// it relays, so that it can share them between processes.
+    async fn transport(&self, request: Request, stream: bool) -> Result<Response> {
+        if let (Some(socket), false, Some(body)) = (&self.daemon, stream, &request.body) {
+            let relayed = RelayRequest {
+                url: request.url.clone(),
+                headers: request.headers.clone(),
+                body: body.clone(),
+                rate_limit: self.config.rate_limit,
+                tls: TlsSettings::of(&self.config.http),
+            };
+            match daemon::relay(socket, &relayed).await {
+                Ok(result) => {
+                    let raw = result?;
+                    return Ok(Response::new(raw.status, raw.headers, raw.body));
+                }
+                Err(error) => diagnostics::warn(
+                    "daemon",
+                    format!("daemon unavailable ({}), connecting directly", error),
+                    json!({}),
+                ),
+            }
+        }
+
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire().await;
+        }
+        let mut builder = self.http_client.request(request.method, &request.url);
+        if let Some(body) = &request.body {
+            builder = builder.json(body);
+        }
+        for (name, value) in &request.headers {
+            builder = builder.header(name, value);
+        }
+
+        let response = Response::streaming(builder.send().await?);
+        if stream {
+            return Ok(response);
+        }
+        let (status, headers) = (response.status, response.headers.clone());
+        Ok(Response::new(status, headers, response.into_text().await?))
     }
 
     /// Get a reference to the configuration
     pub fn config(&self) -> &Config {
         &self.config
     }
+
+    /// Limits and supported features of the configured model, if it is known
+    pub fn model_info(&self) -> Option<ModelInfo> {
+        models::model_info(&self.config.model)
+    }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use crate::guardrails::{Check, GuardAction, Rule};
 
     #[test]
     fn test_message_creation() {
         let message = Message {
             role: "user".to_string(),
-            content: "Hello".to_string(),
+            content: "Hello".into(),
         };
         assert_eq!(message.role, "user");
         assert_eq!(message.content, "Hello");
@@ -219,10 +1417,15 @@ mod tests {
             max_tokens: 1024,
             system: None,
             temperature: None,
+            thinking: None,
             messages: vec![Message {
                 role: "user".to_string(),
-                content: "Hello".to_string(),
+                content: "Hello".into(),
             }],
+            tools: Vec::new(),
+            tool_choice: None,
+            stream: false,
+            prefill: None,
         };
 
         let json = serde_json::to_string(&request).unwrap();
@@ -231,6 +1434,132 @@ mod tests {
         assert!(json.contains("1024"));
     }
 
+    #[test]
+    fn test_prefill() {
+        let mut config = Config::new("sk-ant-test-key");
+        config.thinking_budget = Some(2048);
+        let client = Client::new(config).unwrap();
+        let messages = Messages::new().push_user("Name a color".into()).clone();
+
+        let mut request = client
+            .message_request(messages, Some("The color is ".into()), None)
+            .unwrap();
+        assert!(request.thinking.is_none());
+        assert_eq!(request.prefill.as_deref(), Some("The color is"));
+        assert_eq!(request.messages.last().unwrap().content, "The color is");
+
+        // A new prefill replaces the previous one
+        request.set_prefill("The color is blue and ");
+        assert_eq!(request.messages.len(), 2);
+        assert_eq!(request.messages[1].content, "The color is blue and");
+    }
+
+    #[test]
+    fn test_thinking_request() {
+        let mut config = Config::new("sk-ant-test-key");
+        config.thinking_budget = Some(2048);
+        let client = Client::new(config).unwrap();
+        let messages = Messages::new().push_user("Hello".into()).clone();
+
+        let request = client
+            .message_request(messages.clone(), None, None)
+            .unwrap();
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            json["thinking"],
+            serde_json::json!({"type": "enabled", "budget_tokens": 2048})
+        );
+        assert!(json.get("temperature").is_none());
+
+        let mut request = client
+            .message_request(messages.clone(), None, None)
+            .unwrap();
+        assert!(request
+            .set_tools(Vec::new(), Some(ToolChoice::Any))
+            .is_err());
+        assert!(request
+            .set_tools(Vec::new(), Some(ToolChoice::None))
+            .is_ok());
+
+        let request = client
+            .message_request(messages, Some("{".into()), None)
+            .unwrap();
+        let json = serde_json::to_value(&request).unwrap();
+        assert!(json.get("thinking").is_none());
+        assert_eq!(json["temperature"], 0.0);
+    }
+
+    #[test]
+    fn test_cached_system_prompt_and_documents() {
+        let mut config = Config::new("sk-ant-test-key");
+        config.cache_system = true;
+        let client = Client::new(config).unwrap();
+
+        let mut document = ContentBlock::text_document(Some("notes.txt".into()), "notes".into());
+        if let ContentBlock::Document { cache_control, .. } = &mut document {
+            *cache_control = Some(CacheControl::Ephemeral);
+        }
+        let message = Message {
+            role: "user".into(),
+            content: vec![document].into(),
+        };
+        let request = client
+            .message_request(
+                Messages::new().push(message).clone(),
+                None,
+                Some("Be brief".into()),
+            )
+            .unwrap();
+        let json = serde_json::to_value(&request).unwrap();
+
+        assert_eq!(
+            json["system"],
+            serde_json::json!([{"type": "text", "text": "Be brief", "cache_control": {"type": "ephemeral"}}])
+        );
+        let block = &json["messages"][0]["content"][0];
+        assert_eq!(block["type"], "document");
+        assert_eq!(block["source"]["data"], "notes");
+        assert_eq!(block["cache_control"]["type"], "ephemeral");
+    }
+
+    #[test]
+    fn test_stop_reason() {
+        let reasons: Vec<StopReason> =
+            serde_json::from_str(r#"["end_turn", "max_tokens", "tool_use", "compaction"]"#)
+                .unwrap();
+        assert_eq!(
+            reasons,
+            [
+                StopReason::EndTurn,
+                StopReason::MaxTokens,
+                StopReason::ToolUse,
+                StopReason::Other("compaction".into())
+            ]
+        );
+        assert!(reasons[1].is_truncated() && !reasons[0].is_truncated());
+        assert_eq!(
+            serde_json::to_string(&reasons).unwrap(),
+            r#"["end_turn","max_tokens","tool_use","compaction"]"#
+        );
+    }
+
+    #[test]
+    fn test_usage_cost_and_sum() {
+        let mut usage = Usage {
+            input_tokens: 1_000_000,
+            ..Default::default()
+        };
+        usage += &Usage {
+            output_tokens: 100_000,
+            cache_read_input_tokens: 1_000_000,
+            ..Default::default()
+        };
+        assert_eq!(usage.output_tokens, 100_000);
+        let cost = usage.cost("claude-sonnet-4-5").unwrap();
+        assert!((cost - (3.0 + 1.5 + 0.3)).abs() < 1e-9);
+        assert!(usage.cost("unknown-model").is_none());
+    }
+
     #[test]
     fn test_client_creation_with_valid_config() {
         let config = Config::new("sk-ant-test-key");
@@ -245,6 +1574,446 @@ mod tests {
         assert!(client.is_err());
     }
 
+    #[test]
+    fn test_tls_settings() {
+        let fixture = |name: &str| {
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/fixtures")
+                .join(name)
+        };
+        let client = |http: crate::config::HttpConfig| {
+            let mut config = Config::new("sk-ant-test-key");
+            config.http = http;
+            Client::new(config)
+        };
+
+        assert!(client(crate::config::HttpConfig {
+            ca_bundle: Some(fixture("test-cert.pem")),
+            client_cert: Some(fixture("test-cert.pem")),
+            client_key: Some(fixture("test-key.pem")),
+            ..Default::default()
+        })
+        .is_ok());
+
+        for http in [
+            crate::config::HttpConfig {
+                ca_bundle: Some(fixture("missing.pem")),
+                ..Default::default()
+            },
+            crate::config::HttpConfig {
+                ca_bundle: Some(fixture("test-key.pem")),
+                ..Default::default()
+            },
+            crate::config::HttpConfig {
+                client_cert: Some(fixture("test-cert.pem")),
+                ..Default::default()
+            },
+            crate::config::HttpConfig {
+                client_key: Some(fixture("test-key.pem")),
+                ..Default::default()
+            },
+        ] {
+            assert!(matches!(
+                client(http),
+                Err(ClaudeError::Config(ConfigError::Tls(_)))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_guardrails_from_config() {
+        let mut config = Config::new("sk-ant-test-key");
+        config.guardrails.pre.push(Rule::new(
+            Check::BannedTerms {
+                terms: vec!["forbidden".to_string()],
+            },
+            GuardAction::Block,
+        ));
+        let client = Client::new(config).unwrap();
+
+        assert!(client
+            .check_guardrails(Stage::Input, "a forbidden word".to_string())
+            .is_err());
+        assert!(client
+            .check_guardrails(Stage::Output, "a forbidden word".to_string())
+            .is_ok());
+    }
+
+    #[test]
+    fn test_redaction_enabled_by_default() {
+        let client = Client::new(Config::new("sk-ant-test-key")).unwrap();
+        assert_eq!(
+            client
+                .check_guardrails(Stage::Input, "mail bob@example.com".to_string())
+                .unwrap(),
+            "mail [REDACTED:email]"
+        );
+
+        let mut config = Config::new("sk-ant-test-key");
+        config.redaction.enabled = false;
+        let client = Client::new(config).unwrap();
+        assert_eq!(
+            client
+                .check_guardrails(Stage::Input, "mail bob@example.com".to_string())
+                .unwrap(),
+            "mail bob@example.com"
+        );
+    }
+
+    #[test]
+    fn test_with_guardrail() {
+        struct Upper;
+        impl Guardrail for Upper {
+            fn check(&self, _stage: Stage, text: String) -> Result<String> {
+                Ok(text.to_uppercase())
+            }
+        }
+
+        let client = Client::new(Config::new("sk-ant-test-key"))
+            .unwrap()
+            .with_guardrail(Upper);
+        assert_eq!(
+            client
+                .check_guardrails(Stage::Output, "hi".to_string())
+                .unwrap(),
+            "HI"
+        );
+    }
+
+    /// An HTTP server answering successive connections with `responses`
+    /// (status and body), or by hanging up for `None`, recording each request
+    pub(crate) async fn http_stub(
+        responses: Vec<Option<(u16, &'static str)>>,
+    ) -> (String, Arc<Mutex<Vec<String>>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            for response in responses {
+                let Ok((mut stream, _)) = listener.accept().await else {
+                    return;
+                };
+                let mut request = Vec::new();
+                let mut buffer = [0u8; 8192];
+                while !is_complete(&request) {
+                    match stream.read(&mut buffer).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => request.extend_from_slice(&buffer[..read]),
+                    }
+                }
+                seen.lock()
+                    .unwrap()
+                    .push(String::from_utf8_lossy(&request).to_lowercase());
+
+                if let Some((status, body)) = response {
+                    let response = format!(
+                        "HTTP/1.1 {} Stub\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                        status,
+                        body.len(),
+                        body
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                }
+            }
+        });
+
+        (format!("http://{}", address), requests)
+    }
+
+    /// Whether `request` holds the headers and as much body as they announce
+    fn is_complete(request: &[u8]) -> bool {
+        let text = String::from_utf8_lossy(request);
+        let Some(end) = text.find("\r\n\r\n") else {
+            return false;
+        };
+        let length = text[..end]
+            .lines()
+            .find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.eq_ignore_ascii_case("content-length")
+                    .then(|| value.trim().parse::<usize>().ok())?
+            })
+            .unwrap_or(0);
+        request.len() >= end + 4 + length
+    }
+
+    pub(crate) const REPLY: &str = r#"{"id":"msg_1","type":"message","role":"assistant","model":"claude-haiku-4-5","content":[{"type":"text","text":"hi"}],"stop_reason":"end_turn","usage":{"input_tokens":1,"output_tokens":1}}"#;
+
+    pub(crate) fn stub_client(base_url: String) -> Client {
+        let mut config = Config::new("sk-ant-test-key");
+        config.base_url = base_url;
+        config.retry.initial_backoff_ms = 1;
+        config.retry.overloaded_initial_backoff_ms = 1;
+        Client::new(config).unwrap()
+    }
+
+    pub(crate) fn idempotency_key_of(request: &str) -> &str {
+        request
+            .lines()
+            .find_map(|line| line.strip_prefix("idempotency-key: "))
+            .unwrap()
+            .trim()
+    }
+
+    #[tokio::test]
+    async fn test_spend_limit_stops_requests() {
+        let (url, requests) = http_stub(vec![Some((200, REPLY)), Some((200, REPLY))]).await;
+        let path =
+            std::env::temp_dir().join(format!("ellm-spend-ledger-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut client = stub_client(url).with_ledger(Ledger::new(&path));
+        client.policy.max_spend = Some(1.0);
+
+        let messages = Messages::new().push_user("hi".into()).clone();
+        client
+            .send_message(messages.clone(), None, None)
+            .await
+            .unwrap();
+
+        let mut spent = LedgerEntry::new("claude-haiku-4-5", None, 0, 0, 0, 0);
+        spent.cost_usd = Some(1.0);
+        Ledger::new(&path).append(&spent).unwrap();
+        let error = client.send_message(messages, None, None).await.unwrap_err();
+        assert!(
+            matches!(error, ClaudeError::Config(ConfigError::PolicyViolation(_))),
+            "{}",
+            error
+        );
+        assert_eq!(requests.lock().unwrap().len(), 1);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_overloaded_request_is_retried_with_same_key() {
+        let (url, requests) = http_stub(vec![
+            Some((529, "{}")),
+            Some((429, "{}")),
+            Some((200, REPLY)),
+        ])
+        .await;
+        let client = stub_client(url);
+
+        let text = client
+            .send_message(Messages::new().push_user("hi".into()).clone(), None, None)
+            .await
+            .unwrap();
+        assert_eq!(text, "hi");
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        let key = idempotency_key_of(&requests[0]);
+        assert!(requests
+            .iter()
+            .all(|request| idempotency_key_of(request) == key));
+        assert_eq!(client.last_idempotency_key().unwrap().to_lowercase(), key);
+    }
+
+    #[tokio::test]
+    async fn test_overloaded_error() {
+        const OVERLOADED: &str =
+            r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        let (url, requests) = http_stub(vec![Some((529, OVERLOADED)); 3]).await;
+        let client = stub_client(url);
+
+        let error = client
+            .send_message(Messages::new().push_user("hi".into()).clone(), None, None)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error,
+            ClaudeError::Api(ApiError::Overloaded(ref message)) if message == "Overloaded"
+        ));
+        assert_eq!(requests.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_api_error_message() {
+        const INVALID: &str = r#"{"type":"error","error":{"type":"invalid_request_error","message":"max_tokens: too large"}}"#;
+        let (url, _) = http_stub(vec![Some((400, INVALID))]).await;
+        let client = stub_client(url);
+
+        let error = client
+            .send_message(Messages::new().push_user("hi".into()).clone(), None, None)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error,
+            ClaudeError::Api(ApiError::ApiError { status: 400, ref message })
+                if message == "max_tokens: too large"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_all_content_blocks() {
+        const BLOCKS: &str = r#"{"id":"msg_1","type":"message","role":"assistant","model":"claude-haiku-4-5","content":[{"type":"thinking","thinking":"Two parts.","signature":"sig"},{"type":"text","text":"First, "},{"type":"text","text":"second."}],"stop_reason":"end_turn","usage":{"input_tokens":1,"output_tokens":9}}"#;
+
+        let (url, _) = http_stub(vec![Some((200, BLOCKS)), Some((200, BLOCKS))]).await;
+        let client = stub_client(url);
+        let messages = Messages::new().push_user("hi".into()).clone();
+
+        let response = client
+            .send_message_response(messages.clone(), None, None)
+            .await
+            .unwrap();
+        assert_eq!(response.content.len(), 3);
+        assert_eq!(response.thinking(), "Two parts.");
+        assert_eq!(response.text(), "First, second.");
+        assert_eq!(response.stop_reason, Some(StopReason::EndTurn));
+
+        let text = client.send_message(messages, None, None).await.unwrap();
+        assert_eq!(text, "First, second.");
+    }
+
+    #[test]
+    fn test_unknown_fields_and_blocks() {
+        let response: MessageResponse = serde_json::from_value(json!({
+            "id": "msg_1",
+            "type": "message",
+            "role": "assistant",
+            "model": "claude-haiku-4-5",
+            "container": {"id": "c1"},
+            "content": [
+                {"type": "server_tool_use", "id": "srv_1", "name": "web_search", "input": {"query": "rust"}},
+                {"type": "text", "text": "Found it.", "citations": []},
+                {"type": "image", "source": {"type": "url", "url": "https://example.org/a.png"}}
+            ],
+            "stop_reason": "end_turn",
+            "usage": {"input_tokens": 1, "output_tokens": 2, "server_tool_use": {"web_search_requests": 1}}
+        }))
+        .unwrap();
+
+        assert_eq!(response.text(), "Found it.");
+        assert!(matches!(
+            &response.content[0],
+            ContentBlock::Other(block) if block["name"] == "web_search"
+        ));
+        assert!(matches!(
+            &response.content[2],
+            ContentBlock::Image { source: ImageSource::Other(source) } if source["type"] == "url"
+        ));
+        assert_eq!(response.usage.output_tokens, 2);
+
+        // Unknown blocks are sent back as they came
+        let sent = serde_json::to_value(&response.content).unwrap();
+        assert_eq!(
+            sent[0],
+            json!({"type": "server_tool_use", "id": "srv_1", "name": "web_search", "input": {"query": "rust"}})
+        );
+        assert_eq!(sent[2]["source"]["url"], "https://example.org/a.png");
+    }
+
+    #[tokio::test]
+    async fn test_continue_after_max_tokens() {
+        const CUT_OFF: &str = r#"{"id":"msg_1","type":"message","role":"assistant","model":"claude-haiku-4-5","content":[{"type":"text","text":" upon \n"}],"stop_reason":"max_tokens","usage":{"input_tokens":1,"output_tokens":2}}"#;
+        const REST: &str = r#"{"id":"msg_2","type":"message","role":"assistant","model":"claude-haiku-4-5","content":[{"type":"text","text":" a time"}],"stop_reason":"end_turn","usage":{"input_tokens":3,"output_tokens":2}}"#;
+
+        let (url, requests) = http_stub(vec![Some((200, CUT_OFF)), Some((200, REST))]).await;
+        let mut config = Config::new("sk-ant-test-key");
+        config.base_url = url;
+        config.max_continuations = 1;
+        let client = Client::new(config).unwrap();
+
+        let text = client
+            .send_message(
+                Messages::new().push_user("a story".into()).clone(),
+                Some("Once".into()),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(text, "Once upon a time");
+        assert_eq!(client.last_stop_reason(), Some(StopReason::EndTurn));
+        assert_eq!(client.last_usage().unwrap().output_tokens, 4);
+
+        // The second request prefills everything so far, without trailing whitespace
+        let requests = requests.lock().unwrap();
+        assert!(requests[1].contains(r#"{"content":"once upon","role":"assistant"}]"#));
+
+        // A continuation is a new request, not a retry of the first
+        let key = idempotency_key_of(&requests[1]);
+        assert_ne!(idempotency_key_of(&requests[0]), key);
+        assert_eq!(client.last_idempotency_key().unwrap().to_lowercase(), key);
+    }
+
+    #[tokio::test]
+    async fn test_response_hooks() {
+        struct Exclaim;
+        impl Guardrail for Exclaim {
+            fn check(&self, _stage: Stage, text: String) -> Result<String> {
+                Ok(format!("{}!", text))
+            }
+        }
+
+        let (url, _) = http_stub(vec![Some((200, REPLY)), Some((200, REPLY))]).await;
+        let client = stub_client(url)
+            .with_guardrail(Exclaim)
+            .with_response_hook(|text: String| Ok(format!("  {}  ", text.to_uppercase())))
+            .with_response_hook("trim".parse::<crate::PostProcessor>().unwrap());
+        let messages = Messages::new().push_user("hi".into()).clone();
+
+        // Guardrails run first, then the hooks in order
+        let text = client.send_message(messages.clone(), None, None).await;
+        assert_eq!(text.unwrap(), "HI!");
+
+        let client = client.with_response_hook(|_: String| -> Result<String> {
+            Err(ClaudeError::PostProcess("rejected".into()))
+        });
+        let error = client.send_message(messages, None, None).await.unwrap_err();
+        assert!(matches!(error, ClaudeError::PostProcess(_)));
+    }
+
+    #[tokio::test]
+    async fn test_observer_callbacks() {
+        use crate::observer::tests::Recorder;
+
+        let (url, _) = http_stub(vec![Some((200, REPLY)), Some((400, "{}"))]).await;
+        let recorder = Recorder::default();
+        let client = stub_client(url).with_observer(recorder.clone());
+        let messages = Messages::new().push_user("hi".into()).clone();
+
+        client
+            .send_message(messages.clone(), None, None)
+            .await
+            .unwrap();
+        assert!(client.send_message(messages, None, None).await.is_err());
+
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            vec![
+                "request",
+                "first_token",
+                "token:hi",
+                "complete:1",
+                "request",
+                "error"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dropped_connection_is_not_retried() {
+        let (url, requests) = http_stub(vec![None, Some((200, REPLY))]).await;
+        let client = stub_client(url);
+
+        let error = client
+            .send_message(Messages::new().push_user("hi".into()).clone(), None, None)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error,
+            ClaudeError::Api(ApiError::OutcomeUnknown { ref idempotency_key, .. })
+                if Some(idempotency_key) == client.last_idempotency_key().as_ref()
+        ));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
     // Note: We don't test actual API calls here to avoid requiring real API keys
     // Integration tests with mocking would be in the tests/ directory
 }
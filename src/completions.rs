@@ -0,0 +1,85 @@
+//! Shell completion support
+//!
+//! Completions are dynamic: the registration script printed by
+//! `ellm completions <shell>` calls back into `ellm` on each tab press, so
+//! value candidates such as `--model` reflect the current configuration.
+
+use clap_complete::engine::CompletionCandidate;
+use clap_complete::env::Shells;
+use ellm::{Config, SnippetStore};
+
+/// Environment variable through which the shell asks `ellm` for completions
+pub const COMPLETE_VAR: &str = "COMPLETE";
+
+/// Shells that `ellm completions` can generate a registration script for
+pub const SHELLS: &[&str] = &["bash", "elvish", "fish", "powershell", "zsh"];
+
+/// Current models offered for `--model` in addition to the configured one
+const MODELS: &[&str] = &[
+    "claude-opus-4-5-20251101",
+    "claude-sonnet-4-5-20250929",
+    "claude-haiku-4-5-20251001",
+    "claude-opus-4-1-20250805",
+    "claude-sonnet-4-20250514",
+];
+
+/// Candidates for `--model`: the model from the config file first, then known models
+pub fn models() -> Vec<CompletionCandidate> {
+    let configured = Config::from_file().ok().map(|config| config.model);
+
+    configured
+        .iter()
+        .map(|model| CompletionCandidate::new(model).help(Some("configured".into())))
+        .chain(
+            MODELS
+                .iter()
+                .filter(|model| configured.as_deref() != Some(**model))
+                .map(CompletionCandidate::new),
+        )
+        .collect()
+}
+
+/// Candidates for snippet names: the saved snippets
+pub fn snippets() -> Vec<CompletionCandidate> {
+    SnippetStore::open_default()
+        .and_then(|store| store.list())
+        .unwrap_or_default()
+        .into_iter()
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+/// Write the script that registers `ellm`'s completions with `shell`
+pub fn write_registration(shell: &str, out: &mut dyn std::io::Write) -> std::io::Result<()> {
+    let shells = Shells::builtins();
+    let completer = shells
+        .completer(shell)
+        .ok_or_else(|| std::io::Error::other(format!("unsupported shell: {}", shell)))?;
+
+    completer.write_registration(COMPLETE_VAR, "ellm", "ellm", "ellm", out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registration_for_each_shell() {
+        for shell in SHELLS {
+            let mut out = Vec::new();
+            write_registration(shell, &mut out).unwrap();
+            let script = String::from_utf8(out).unwrap();
+            assert!(script.contains(COMPLETE_VAR), "{}", shell);
+        }
+
+        assert!(write_registration("tcsh", &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_model_candidates() {
+        let candidates = models();
+        assert!(candidates
+            .iter()
+            .any(|candidate| candidate.get_value() == "claude-sonnet-4-5-20250929"));
+    }
+}
@@ -1,17 +1,40 @@
-use crate::error::{ConfigError, Result};
+use crate::diagnostics::{self, LogFormat};
+use crate::error::{ClaudeError, ConfigError, Result};
+use crate::guardrails::GuardrailsConfig;
+use crate::images::ImageConfig;
+use crate::models;
+use crate::notify::NotifyConfig;
+use crate::postprocess::{Pipeline, PostProcessor};
+use crate::ratelimit::RateLimitConfig;
+use crate::redact::RedactionConfig;
+use crate::retry::RetryConfig;
+use crate::safety::SafetyPreset;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 
 /// Configuration for the Claude API client
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
-    /// API key for authentication
+    /// API key for authentication; may be left out of the config file and
+    /// given through the environment or the command line instead
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub api_key: String,
 
     /// Base URL for the API (defaults to Anthropic's API)
     #[serde(default = "default_base_url")]
     pub base_url: String,
 
+    /// Entry of `providers` whose endpoint and key are used unless another is chosen
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+
+    /// Named Anthropic-compatible endpoints, e.g. a corporate gateway, each
+    /// with its own key
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub providers: HashMap<String, Provider>,
+
     /// Model to use (defaults to claude-sonnet-4-5)
     #[serde(default = "default_model")]
     pub model: String,
@@ -19,8 +42,164 @@ pub struct Config {
     /// Maximum tokens to generate
     #[serde(default = "default_max_tokens")]
     pub max_tokens: u32,
+
+    /// Size max_tokens for each request to what the context window leaves after
+    /// the prompt, up to [`Config::auto_max_tokens_ceiling`], instead of using `max_tokens`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub auto_max_tokens: bool,
+
+    /// Upper bound on automatically sized max_tokens; defaults to the model's output limit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens_ceiling: Option<u32>,
+
+    /// Continue a response cut off at max_tokens up to this many times, by
+    /// sending back what it said so far for the model to pick up from
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub max_continuations: u32,
+
+    /// Switch a deprecated model to its closest current model instead of only warning
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub auto_migrate: bool,
+
+    /// Safety instructions prepended to every system prompt, and how refusals are handled
+    #[serde(default, skip_serializing_if = "SafetyPreset::is_off")]
+    pub safety: SafetyPreset,
+
+    /// Language every response is written in, e.g. "German", whatever the prompt's language
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub respond_in: Option<String>,
+
+    /// Token budget for extended thinking; thinking is off when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thinking_budget: Option<u32>,
+
+    /// Sampling temperature of requests without thinking; 0 when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+
+    /// Mark system prompts for prompt caching
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cache_system: bool,
+
+    /// Workspace that usage is attributed to in the local usage ledger
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+
+    /// Post-processing steps applied to responses, keyed by command name
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub postprocess: HashMap<String, Vec<PostProcessor>>,
+
+    /// Checks applied to prompts before sending and to responses after receiving
+    #[serde(default, skip_serializing_if = "GuardrailsConfig::is_empty")]
+    pub guardrails: GuardrailsConfig,
+
+    /// Masking of secrets and personal data in outgoing prompts
+    #[serde(default, skip_serializing_if = "RedactionConfig::is_default")]
+    pub redaction: RedactionConfig,
+
+    /// Retrying of requests the API rejected without processing
+    #[serde(default, skip_serializing_if = "RetryConfig::is_default")]
+    pub retry: RetryConfig,
+
+    /// Requests and tokens per minute to stay under with this API key
+    #[serde(default, skip_serializing_if = "RateLimitConfig::is_default")]
+    pub rate_limit: RateLimitConfig,
+
+    /// Connection pool and socket tuning
+    #[serde(default, skip_serializing_if = "HttpConfig::is_default")]
+    pub http: HttpConfig,
+
+    /// Where to report that long operations, such as waiting for a batch, finished
+    #[serde(default, skip_serializing_if = "NotifyConfig::is_empty")]
+    pub notify: NotifyConfig,
+
+    /// Scaling down of images that are too large to attach as they are
+    #[serde(default, skip_serializing_if = "ImageConfig::is_default")]
+    pub images: ImageConfig,
+
+    /// How diagnostics such as retries and cache use are written to stderr
+    #[serde(default, skip_serializing_if = "LogFormat::is_text")]
+    pub log_format: LogFormat,
+}
+
+/// HTTP connection settings; anything unset keeps the HTTP client's default
+///
+/// Services sending many concurrent requests may want a larger pool and
+/// longer-lived idle connections than a CLI invocation needs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// Idle connections kept open per host
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool_max_idle_per_host: Option<usize>,
+
+    /// How long an idle connection is kept open, in seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool_idle_timeout_secs: Option<u64>,
+
+    /// Interval between HTTP/2 pings that keep connections alive, in seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http2_keep_alive_interval_secs: Option<u64>,
+
+    /// How long to wait for a ping to be acknowledged before closing the connection, in seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http2_keep_alive_timeout_secs: Option<u64>,
+
+    /// Send HTTP/2 pings even when no request is in flight
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http2_keep_alive_while_idle: Option<bool>,
+
+    /// Disable Nagle's algorithm (on by default)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tcp_nodelay: Option<bool>,
+
+    /// PEM file of additional root certificates to trust, e.g. a corporate
+    /// proxy's CA; the built-in roots are still trusted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca_bundle: Option<PathBuf>,
+
+    /// PEM client certificate for gateways requiring mutual TLS
+    ///
+    /// The private key may be in the same file or in `client_key`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_cert: Option<PathBuf>,
+
+    /// PEM private key for `client_cert`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_key: Option<PathBuf>,
 }
 
+impl HttpConfig {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// An Anthropic-compatible endpoint and the key to use with it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Provider {
+    /// Base URL of the endpoint; Anthropic's API when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+
+    /// Environment variable holding the key, to keep it out of the config file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_env: Option<String>,
+
+    /// Limits for this provider, in place of the top-level `rate_limit`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimitConfig>,
+}
+
+/// Environment variable pointing at an alternate config file
+pub const CONFIG_PATH_ENV: &str = "ELLM_CONFIG_PATH";
+
+/// Smallest thinking budget the API accepts
+const MIN_THINKING_BUDGET: u32 = 1024;
+
 fn default_base_url() -> String {
     "https://api.anthropic.com/v1".to_string()
 }
@@ -33,38 +212,119 @@ fn default_max_tokens() -> u32 {
     4096
 }
 
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
 impl Config {
     /// Create a new Config with the given API key
     pub fn new(api_key: impl Into<String>) -> Self {
         Self {
             api_key: api_key.into(),
             base_url: default_base_url(),
+            provider: None,
+            providers: HashMap::new(),
             model: default_model(),
             max_tokens: default_max_tokens(),
+            auto_max_tokens: false,
+            max_tokens_ceiling: None,
+            max_continuations: 0,
+            auto_migrate: false,
+            safety: SafetyPreset::Off,
+            respond_in: None,
+            thinking_budget: None,
+            temperature: None,
+            cache_system: false,
+            workspace: None,
+            postprocess: HashMap::new(),
+            guardrails: GuardrailsConfig::default(),
+            redaction: RedactionConfig::default(),
+            retry: RetryConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            http: HttpConfig::default(),
+            notify: NotifyConfig::default(),
+            images: ImageConfig::default(),
+            log_format: LogFormat::default(),
         }
     }
 
-    /// Load configuration from multiple sources with priority:
-    /// 1. Provided api_key argument
-    /// 2. Environment variable
-    /// 3. Config file
+    /// Load configuration from multiple sources, merged field by field
+    ///
+    /// The config file is read first, if it exists. Environment variables
+    /// (`ANTHROPIC_API_KEY`, `ANTHROPIC_BASE_URL`, `ANTHROPIC_MODEL`) override
+    /// its fields, and the provided `api_key` overrides both.
     pub fn load(api_key: Option<String>) -> Result<Self> {
-        // Priority 1: Provided API key
-        if let Some(key) = api_key {
-            return Ok(Self::new(key));
-        }
+        Self::load_from(None, api_key)
+    }
+
+    /// Like [`Config::load`], but reading the config file at `path` instead of
+    /// [`Config::config_path`]
+    ///
+    /// A file given explicitly, here or through `ELLM_CONFIG_PATH`, must exist.
+    pub fn load_from(path: Option<&Path>, api_key: Option<String>) -> Result<Self> {
+        Self::load_with_provider(path, None, api_key)
+    }
+
+    /// Like [`Config::load_from`], using the endpoint and key of `provider`
+    /// from the config file's `providers`
+    ///
+    /// The provider takes precedence over the environment, and `api_key` over
+    /// the provider's key.
+    pub fn load_with_provider(
+        path: Option<&Path>,
+        provider: Option<&str>,
+        api_key: Option<String>,
+    ) -> Result<Self> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => (
+                Self::config_path()?,
+                std::env::var_os(CONFIG_PATH_ENV).is_some(),
+            ),
+        };
+        let file = match Self::from_path(&path) {
+            Ok(config) => Some(config),
+            Err(ClaudeError::Config(ConfigError::FileNotFound(_))) if !required => None,
+            Err(error) => return Err(error),
+        };
+        Self::merge(file, |name| std::env::var(name).ok(), provider, api_key)
+    }
 
-        // Priority 2: Environment variable
-        if let Ok(key) = std::env::var("ANTHROPIC_API_KEY") {
-            return Ok(Self::new(key));
+    /// Layer the environment, a chosen provider and an explicit API key over
+    /// the file's config
+    fn merge(
+        file: Option<Config>,
+        env: impl Fn(&str) -> Option<String>,
+        provider: Option<&str>,
+        api_key: Option<String>,
+    ) -> Result<Self> {
+        let mut config = file.unwrap_or_else(|| Self::new(""));
+
+        // The file's default provider is part of the file's settings
+        if let (None, Some(default)) = (provider, config.provider.clone()) {
+            config.use_provider(&default, &env)?;
         }
 
-        // Priority 3: Config file
-        if let Ok(config) = Self::from_file() {
-            return Ok(config);
+        if let Some(key) = env("ANTHROPIC_API_KEY") {
+            config.api_key = key;
+        }
+        if let Some(base_url) = env("ANTHROPIC_BASE_URL") {
+            config.base_url = base_url;
+        }
+        if let Some(model) = env("ANTHROPIC_MODEL") {
+            config.model = model;
+        }
+        if let Some(provider) = provider {
+            config.use_provider(provider, &env)?;
+        }
+        if let Some(key) = api_key {
+            config.api_key = key;
         }
 
-        Err(ConfigError::ApiKeyNotFound.into())
+        if config.api_key.is_empty() {
+            return Err(ConfigError::ApiKeyNotFound.into());
+        }
+        Ok(config)
     }
 
     /// Load configuration from environment variables only
@@ -74,26 +334,74 @@ impl Config {
         Ok(Self::new(api_key))
     }
 
+    /// Switch to the endpoint and key of the provider called `name`
+    fn use_provider(&mut self, name: &str, env: impl Fn(&str) -> Option<String>) -> Result<()> {
+        let provider = self.providers.get(name).ok_or_else(|| {
+            let mut known: Vec<&String> = self.providers.keys().collect();
+            known.sort();
+            ConfigError::Provider(format!(
+                "unknown provider {:?} (configured: {:?})",
+                name, known
+            ))
+        })?;
+
+        let api_key = match (&provider.api_key, &provider.api_key_env) {
+            (Some(key), _) => Some(key.clone()),
+            (None, Some(var)) => Some(env(var).ok_or_else(|| {
+                ConfigError::Provider(format!("{} is not set for provider {:?}", var, name))
+            })?),
+            (None, None) => None,
+        };
+        self.base_url = provider.base_url.clone().unwrap_or_else(default_base_url);
+        if let Some(key) = api_key {
+            self.api_key = key;
+        }
+        if let Some(limits) = provider.rate_limit {
+            self.rate_limit = limits;
+        }
+        self.provider = Some(name.to_string());
+        Ok(())
+    }
+
     /// Load configuration from file
     pub fn from_file() -> Result<Self> {
-        let config_path = Self::config_path()?;
+        Self::from_path(&Self::config_path()?)
+    }
 
-        if !config_path.exists() {
-            return Err(ConfigError::FileNotFound(config_path.display().to_string()).into());
+    /// Load configuration from the file at `path`
+    pub fn from_path(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Err(ConfigError::FileNotFound(path.display().to_string()).into());
         }
 
-        let contents = std::fs::read_to_string(&config_path)?;
-        let config: Config =
-            toml::from_str(&contents).map_err(|e| ConfigError::ParseError(e.to_string()))?;
+        let contents = std::fs::read_to_string(path)?;
+        let config: Config = toml::from_str(&contents)
+            .map_err(|e| ConfigError::ParseError(format!("{}: {}", path.display(), e)))?;
 
         Ok(config)
     }
 
-    /// Get the default config file path
+    /// Get the config file path
+    ///
+    /// This is `ELLM_CONFIG_PATH` if set, otherwise `ellm/config.toml` under
+    /// `XDG_CONFIG_HOME` if set, otherwise under the platform's config directory.
     pub fn config_path() -> Result<PathBuf> {
-        let config_dir = dirs::config_dir().ok_or_else(|| {
-            ConfigError::ParseError("Could not determine config directory".to_string())
-        })?;
+        Self::config_path_in(|name| std::env::var_os(name))
+    }
+
+    fn config_path_in(env: impl Fn(&str) -> Option<OsString>) -> Result<PathBuf> {
+        if let Some(path) = env(CONFIG_PATH_ENV).filter(|path| !path.is_empty()) {
+            return Ok(PathBuf::from(path));
+        }
+
+        // The XDG spec ignores relative paths
+        let config_dir = env("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(dirs::config_dir)
+            .ok_or_else(|| {
+                ConfigError::ParseError("Could not determine config directory".to_string())
+            })?;
 
         Ok(config_dir.join("ellm").join("config.toml"))
     }
@@ -106,7 +414,33 @@ impl Config {
 
         // Basic validation: API keys should start with "sk-ant-"
         if !self.api_key.starts_with("sk-ant-") {
-            eprintln!("Warning: API key does not start with 'sk-ant-'. This may be invalid.");
+            diagnostics::warn(
+                "config",
+                "API key does not start with 'sk-ant-'. This may be invalid.",
+                serde_json::json!({}),
+            );
+        }
+
+        let limits = self
+            .providers
+            .values()
+            .filter_map(|provider| provider.rate_limit.as_ref())
+            .chain([&self.rate_limit]);
+        for limits in limits {
+            if limits.requests_per_minute == Some(0) || limits.tokens_per_minute == Some(0) {
+                return Err(ConfigError::ParseError("rate limits must be above 0".into()).into());
+            }
+        }
+
+        if let Some(budget) = self.thinking_budget {
+            let max_tokens = self.request_max_tokens();
+            if budget < MIN_THINKING_BUDGET || budget >= max_tokens {
+                return Err(ConfigError::InvalidThinkingBudget(format!(
+                    "{} must be at least {} and less than max_tokens ({})",
+                    budget, MIN_THINKING_BUDGET, max_tokens
+                ))
+                .into());
+            }
         }
 
         Ok(())
@@ -124,30 +458,80 @@ impl Config {
         self
     }
 
-    /// Build a Client from CLI-like arguments
+    /// Size max_tokens automatically for each request; see [`Config::auto_max_tokens`]
+    pub fn with_auto_max_tokens(mut self) -> Self {
+        self.auto_max_tokens = true;
+        self
+    }
+
+    /// The most tokens an automatically sized response may be given
+    ///
+    /// This is `max_tokens_ceiling`, but no more than the model's output limit.
+    pub fn auto_max_tokens_ceiling(&self) -> u32 {
+        let limit = models::max_output_tokens(&self.model);
+        match (self.max_tokens_ceiling, limit) {
+            (Some(ceiling), Some(limit)) => ceiling.min(limit),
+            (ceiling, limit) => ceiling.or(limit).unwrap_or(self.max_tokens),
+        }
+    }
+
+    /// The max_tokens requests are built with, before any automatic sizing
+    pub(crate) fn request_max_tokens(&self) -> u32 {
+        if self.auto_max_tokens {
+            self.auto_max_tokens_ceiling()
+        } else {
+            self.max_tokens
+        }
+    }
+
+    /// Get the post-processing pipeline for a command
+    ///
+    /// Steps given explicitly (e.g. via `--post`) replace the configured steps
+    /// for the command rather than being appended to them.
+    pub fn pipeline_for(&self, command: &str, overrides: &[PostProcessor]) -> Pipeline {
+        if !overrides.is_empty() {
+            return Pipeline::new(overrides.to_vec());
+        }
+
+        Pipeline::new(self.postprocess.get(command).cloned().unwrap_or_default())
+    }
+
+    /// Load a Config from CLI-like arguments
     /// This is a convenience method that:
     /// 1. Loads config from multiple sources (CLI arg > env var > config file)
     /// 2. Applies CLI overrides for model and max_tokens
-    /// 3. Creates and returns a Client
     ///
     /// # Arguments
     /// * `api_key` - Optional API key from CLI
     /// * `model` - Optional model override from CLI
-    /// * `max_tokens` - Max tokens from CLI
-    pub fn build_from_cli(
+    /// * `max_tokens` - Optional max tokens override from CLI
+    pub fn load_from_cli(
         api_key: Option<String>,
         model: Option<String>,
-        max_tokens: u32,
-    ) -> Result<crate::Client> {
+        max_tokens: Option<u32>,
+    ) -> Result<Self> {
         let mut config = Self::load(api_key)?;
 
         // Apply CLI overrides
         if let Some(model) = model {
             config = config.with_model(model);
         }
-        config = config.with_max_tokens(max_tokens);
+        if let Some(max_tokens) = max_tokens {
+            config = config.with_max_tokens(max_tokens);
+        }
 
-        crate::Client::new(config)
+        Ok(config)
+    }
+
+    /// Build a Client from CLI-like arguments
+    ///
+    /// Same as [`Config::load_from_cli`] followed by [`crate::Client::new`].
+    pub fn build_from_cli(
+        api_key: Option<String>,
+        model: Option<String>,
+        max_tokens: Option<u32>,
+    ) -> Result<crate::Client> {
+        crate::Client::new(Self::load_from_cli(api_key, model, max_tokens)?)
     }
 }
 
@@ -182,12 +566,169 @@ mod tests {
         assert!(config.validate().is_err());
     }
 
+    #[test]
+    fn test_validate_thinking_budget() {
+        let mut config = Config::new("sk-ant-test-key");
+        config.thinking_budget = Some(2048);
+        assert!(config.validate().is_ok());
+
+        config.thinking_budget = Some(512);
+        assert!(config.validate().is_err());
+
+        config.thinking_budget = Some(config.max_tokens);
+        assert!(config.validate().is_err());
+    }
+
     #[test]
     fn test_validate_valid_key() {
         let config = Config::new("sk-ant-test-key");
         assert!(config.validate().is_ok());
     }
 
+    #[test]
+    fn test_load_merges_file_env_and_argument() {
+        let file: Config = toml::from_str(
+            r#"
+            model = "claude-haiku-4-5"
+            max_tokens = 1000
+            base_url = "http://file"
+            "#,
+        )
+        .unwrap();
+        let env = |name: &str| match name {
+            "ANTHROPIC_API_KEY" => Some("sk-ant-env".to_string()),
+            "ANTHROPIC_BASE_URL" => Some("http://env".to_string()),
+            _ => None,
+        };
+
+        let config = Config::merge(Some(file.clone()), env, None, None).unwrap();
+        assert_eq!(config.api_key, "sk-ant-env");
+        assert_eq!(config.base_url, "http://env");
+        // The file's settings survive a key from the environment
+        assert_eq!(config.model, "claude-haiku-4-5");
+        assert_eq!(config.max_tokens, 1000);
+
+        let config =
+            Config::merge(Some(file.clone()), env, None, Some("sk-ant-arg".into())).unwrap();
+        assert_eq!(config.api_key, "sk-ant-arg");
+        assert_eq!(config.model, "claude-haiku-4-5");
+
+        assert!(matches!(
+            Config::merge(Some(file), |_| None, None, None),
+            Err(ClaudeError::Config(ConfigError::ApiKeyNotFound))
+        ));
+        let config = Config::merge(None, env, None, None).unwrap();
+        assert_eq!(config.model, "claude-sonnet-4-5-20250929");
+    }
+
+    #[test]
+    fn test_providers() {
+        let file: Config = toml::from_str(
+            r#"
+            api_key = "sk-ant-personal"
+            provider = "work"
+
+            [rate_limit]
+            requests_per_minute = 50
+
+            [providers.work]
+            base_url = "https://gateway.example.com/v1"
+            api_key_env = "WORK_KEY"
+            rate_limit = { requests_per_minute = 1000, tokens_per_minute = 400000 }
+
+            [providers.personal]
+            "#,
+        )
+        .unwrap();
+        let env = |name: &str| match name {
+            "WORK_KEY" => Some("sk-ant-work".to_string()),
+            "ANTHROPIC_BASE_URL" => Some("http://env".to_string()),
+            _ => None,
+        };
+
+        // The file's default provider is overridden by the environment
+        let config = Config::merge(Some(file.clone()), env, None, None).unwrap();
+        assert_eq!(config.api_key, "sk-ant-work");
+        assert_eq!(config.base_url, "http://env");
+
+        // A chosen provider overrides the environment
+        let config = Config::merge(Some(file.clone()), env, Some("work"), None).unwrap();
+        assert_eq!(config.base_url, "https://gateway.example.com/v1");
+        assert_eq!(config.rate_limit.tokens_per_minute, Some(400_000));
+        let config = Config::merge(Some(file.clone()), env, Some("personal"), None).unwrap();
+        // Without a key of its own, the top-level key is used
+        assert_eq!(config.base_url, "https://api.anthropic.com/v1");
+        assert_eq!(config.api_key, "sk-ant-personal");
+        assert_eq!(config.provider.as_deref(), Some("personal"));
+        assert_eq!(config.rate_limit.requests_per_minute, Some(50));
+
+        assert!(matches!(
+            Config::merge(Some(file.clone()), env, Some("home"), None),
+            Err(ClaudeError::Config(ConfigError::Provider(_)))
+        ));
+        assert!(matches!(
+            Config::merge(Some(file), |_| None, Some("work"), None),
+            Err(ClaudeError::Config(ConfigError::Provider(_)))
+        ));
+    }
+
+    #[test]
+    fn test_http_settings() {
+        let config: Config = toml::from_str(
+            r#"
+            api_key = "sk-ant-test-key"
+
+            [http]
+            pool_max_idle_per_host = 64
+            http2_keep_alive_interval_secs = 30
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.http.pool_max_idle_per_host, Some(64));
+        assert_eq!(config.http.tcp_nodelay, None);
+        assert!(crate::Client::new(config.clone()).is_ok());
+
+        let written = toml::to_string(&Config::new("sk-ant-test-key")).unwrap();
+        assert!(!written.contains("[http]"));
+    }
+
+    #[test]
+    fn test_config_path() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| OsString::from(value))
+            }
+        };
+
+        let path = Config::config_path_in(env(&[
+            ("ELLM_CONFIG_PATH", "/etc/work.toml"),
+            ("XDG_CONFIG_HOME", "/xdg"),
+        ]))
+        .unwrap();
+        assert_eq!(path, Path::new("/etc/work.toml"));
+
+        let path = Config::config_path_in(env(&[("XDG_CONFIG_HOME", "/xdg")])).unwrap();
+        assert_eq!(path, Path::new("/xdg/ellm/config.toml"));
+
+        let path = Config::config_path_in(env(&[("XDG_CONFIG_HOME", "relative")])).unwrap();
+        assert!(path.is_absolute());
+    }
+
+    #[test]
+    fn test_load_from_missing_explicit_path() {
+        let error = Config::load_from(
+            Some(Path::new("/nonexistent/ellm.toml")),
+            Some("sk-ant-test-key".into()),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error,
+            ClaudeError::Config(ConfigError::FileNotFound(_))
+        ));
+    }
+
     #[test]
     fn test_config_serialization() {
         let config = Config::new("sk-ant-test-key");
@@ -208,4 +749,42 @@ mod tests {
         let config: Config = toml::from_str(toml_str).unwrap();
         assert_eq!(config.api_key, "sk-ant-test-key");
     }
+
+    #[test]
+    fn test_pipeline_for_command() {
+        let toml_str = r#"
+            api_key = "sk-ant-test-key"
+
+            [postprocess]
+            send = ["strip-markdown", "trim"]
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(
+            config.pipeline_for("send", &[]).apply(" **hi** ").unwrap(),
+            "hi"
+        );
+        assert!(config.pipeline_for("bool", &[]).is_empty());
+
+        let overrides = vec![PostProcessor::Trim];
+        assert_eq!(
+            config
+                .pipeline_for("send", &overrides)
+                .apply(" **hi** ")
+                .unwrap(),
+            "**hi**"
+        );
+    }
+
+    #[test]
+    fn test_invalid_postprocess_config() {
+        let toml_str = r#"
+            api_key = "sk-ant-test-key"
+
+            [postprocess]
+            send = ["bogus"]
+        "#;
+
+        assert!(toml::from_str::<Config>(toml_str).is_err());
+    }
 }
@@ -0,0 +1,184 @@
+use crate::boolean::BoolResponse;
+use crate::client::Client;
+use crate::error::Result;
+use serde::Serialize;
+
+/// How many answers to sample for self-consistency, and how randomly
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sampling {
+    pub samples: usize,
+    /// Above 0, so that the samples can differ
+    pub temperature: f32,
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Self {
+            samples: 5,
+            temperature: 0.7,
+        }
+    }
+}
+
+/// The most common of several sampled answers
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Consensus<T> {
+    /// The first sample giving the most common answer
+    pub answer: T,
+    /// Samples that gave that answer
+    pub votes: usize,
+    /// Samples that gave any answer; failed samples don't count
+    pub samples: usize,
+}
+
+impl<T> Consensus<T> {
+    /// The most common of the successful `results`, compared by `key`; ties
+    /// go to the earliest
+    ///
+    /// Fails with the first error only if no result succeeded.
+    pub fn of<K: PartialEq>(results: Vec<Result<T>>, key: impl Fn(&T) -> K) -> Result<Self> {
+        let mut answers = Vec::with_capacity(results.len());
+        let mut first_error = None;
+        for result in results {
+            match result {
+                Ok(answer) => answers.push(answer),
+                Err(error) => {
+                    first_error.get_or_insert(error);
+                }
+            }
+        }
+
+        let keys: Vec<K> = answers.iter().map(&key).collect();
+        let votes = |key: &K| keys.iter().filter(|other| *other == key).count();
+        // max_by_key keeps the last maximum, so search from the end
+        let Some((winner, votes)) = keys
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(index, key)| (index, votes(key)))
+            .max_by_key(|(_, votes)| *votes)
+        else {
+            return Err(first_error.expect("results to have an answer or an error"));
+        };
+
+        let samples = answers.len();
+        Ok(Self {
+            answer: answers.swap_remove(winner),
+            votes,
+            samples,
+        })
+    }
+
+    /// The share of samples that gave the answer, from 0.0 to 1.0
+    pub fn agreement(&self) -> f64 {
+        self.votes as f64 / self.samples.max(1) as f64
+    }
+}
+
+impl Client {
+    /// Ask a yes/no question several times and take the most common answer
+    ///
+    /// The samples are requested at once, at `sampling.temperature`. The
+    /// returned response's confidence is the share of samples that agree,
+    /// which is a steadier measure than any one sample's own confidence.
+    pub async fn ask_bool_consistent(
+        &self,
+        question: impl Into<String>,
+        sampling: &Sampling,
+    ) -> Result<Consensus<BoolResponse>> {
+        let question = question.into();
+        let sampler = self.with_temperature(sampling.temperature)?;
+        let results = futures::future::join_all(
+            (0..sampling.samples.max(1)).map(|_| sampler.ask_bool(question.clone())),
+        )
+        .await;
+
+        let mut consensus = Consensus::of(results, |response| response.answer)?;
+        consensus.answer.confidence = consensus.agreement();
+        Ok(consensus)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boolean::BoolAnswer;
+    use crate::client::tests::{http_stub, stub_client};
+    use crate::error::ClaudeError;
+
+    #[test]
+    fn test_consensus_of() {
+        let consensus = Consensus::of(
+            vec![
+                Ok("b"),
+                Ok("a"),
+                Err(ClaudeError::Bool("no".into())),
+                Ok("A"),
+            ],
+            |answer| answer.to_lowercase(),
+        )
+        .unwrap();
+        assert_eq!(consensus.answer, "a");
+        assert_eq!((consensus.votes, consensus.samples), (2, 3));
+        assert!((consensus.agreement() - 2.0 / 3.0).abs() < 1e-9);
+
+        // Ties go to the earliest answer
+        assert_eq!(Consensus::of(vec![Ok(1), Ok(2)], |n| *n).unwrap().answer, 1);
+        assert!(matches!(
+            Consensus::<u8>::of(vec![Err(ClaudeError::Bool("no".into()))], |n| *n),
+            Err(ClaudeError::Bool(_))
+        ));
+    }
+
+    fn reply(answer: &str) -> &'static str {
+        let text = serde_json::json!({
+            "answer": answer,
+            "confidence": 0.9,
+            "explanation": "because",
+        });
+        // The response continues the "{" prefilled for structured output
+        let text = text.to_string()[1..].to_string();
+        Box::leak(
+            serde_json::json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-haiku-4-5",
+                "content": [{"type": "text", "text": text}],
+                "stop_reason": "end_turn",
+                "usage": {"input_tokens": 1, "output_tokens": 1},
+            })
+            .to_string()
+            .into_boxed_str(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_ask_bool_consistent() {
+        let (url, requests) = http_stub(vec![
+            Some((200, reply("true"))),
+            Some((200, reply("false"))),
+            Some((200, reply("true"))),
+        ])
+        .await;
+        let client = stub_client(url);
+        let sampling = Sampling {
+            samples: 3,
+            temperature: 0.8,
+        };
+
+        let consensus = client
+            .ask_bool_consistent("Is water wet?", &sampling)
+            .await
+            .unwrap();
+        assert_eq!(consensus.answer.answer, BoolAnswer::True);
+        assert_eq!((consensus.votes, consensus.samples), (2, 3));
+        assert!((consensus.answer.confidence - 2.0 / 3.0).abs() < 1e-9);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert!(requests
+            .iter()
+            .all(|request| request.contains(r#""temperature":0.8"#)));
+    }
+}
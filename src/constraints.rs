@@ -0,0 +1,454 @@
+//! Length and format constraints on responses
+//!
+//! Where structured output re-asks until a response matches a schema,
+//! constraints do the same for plain text: the response is checked against
+//! each one, and the violations are described to the model when asking again.
+//! The response language can't be checked locally, so the model detects it.
+
+use crate::client::{Client, Messages, StopReason, Usage};
+use crate::error::{ClaudeError, Result};
+use crate::structured::{FailedAttempt, StructuredRetry};
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// A requirement on the text of a response
+///
+/// Constraints are written as short specs so they can be given on the command
+/// line or in the config file:
+///
+/// * `max-words:<n>` / `min-words:<n>` - at most or at least `n` words
+/// * `json` - the whole response is valid JSON
+/// * `section:<name>` - a Markdown heading named `name`, ignoring case
+/// * `contains:<text>` - the text appears somewhere
+/// * `regex:<pattern>` - the pattern matches somewhere
+/// * `language:<name>` - the response is written in the language, e.g. `language:German`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Constraint {
+    MaxWords(usize),
+    MinWords(usize),
+    Json,
+    Section(String),
+    Contains(String),
+    Regex(String),
+    Language(String),
+}
+
+/// Longest start of a response shown to the model to detect its language
+const LANGUAGE_SAMPLE_CHARS: usize = 2000;
+
+/// The system prompt instruction to respond in `language`
+pub(crate) fn language_instruction(language: &str) -> String {
+    format!(
+        "Write your response in {}, whatever language the conversation is in, unless you are \
+         explicitly asked for another language. Keep code, identifiers and quotations as they are.",
+        language
+    )
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct LanguageCheck {
+    /// the main language of the text, ignoring code, names and quotations
+    language: String,
+    /// true when that is the language asked about
+    matches: bool,
+}
+
+impl Constraint {
+    /// How `text` violates this constraint, if it does
+    ///
+    /// Language constraints can't be checked locally and never report a
+    /// violation here; [`Client::send_constrained`] has the model check them.
+    pub fn violation(&self, text: &str) -> Option<String> {
+        let words = || text.split_whitespace().count();
+        match self {
+            Constraint::MaxWords(max) if words() > *max => Some(format!(
+                "it has {} words, more than the {} allowed",
+                words(),
+                max
+            )),
+            Constraint::MinWords(min) if words() < *min => Some(format!(
+                "it has {} words, fewer than the {} required",
+                words(),
+                min
+            )),
+            Constraint::Json => serde_json::from_str::<serde_json::Value>(text.trim())
+                .err()
+                .map(|error| format!("it is not valid JSON: {}", error)),
+            Constraint::Section(name) if !has_heading(text, name) => {
+                Some(format!("it has no \"{}\" section heading", name))
+            }
+            Constraint::Contains(needle) if !text.contains(needle.as_str()) => {
+                Some(format!("it does not contain \"{}\"", needle))
+            }
+            Constraint::Regex(pattern) => match Regex::new(pattern) {
+                Ok(regex) if regex.is_match(text) => None,
+                _ => Some(format!("it does not match the pattern {:?}", pattern)),
+            },
+            _ => None,
+        }
+    }
+
+    /// The constraint as an instruction to the model
+    fn instruction(&self) -> String {
+        match self {
+            Constraint::MaxWords(max) => format!("Use at most {} words.", max),
+            Constraint::MinWords(min) => format!("Use at least {} words.", min),
+            Constraint::Json => "Reply with valid JSON only, without a code fence.".to_string(),
+            Constraint::Section(name) => format!("Include a Markdown section headed \"{}\".", name),
+            Constraint::Contains(needle) => format!("Include the exact text \"{}\".", needle),
+            Constraint::Regex(pattern) => {
+                format!(
+                    "Make sure the text matches the regular expression {}.",
+                    pattern
+                )
+            }
+            Constraint::Language(language) => language_instruction(language),
+        }
+    }
+}
+
+/// Whether `text` has a Markdown heading named `name`, ignoring case and a trailing colon
+fn has_heading(text: &str, name: &str) -> bool {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with('#'))
+        .map(|line| line.trim_start_matches('#').trim().trim_end_matches(':'))
+        .any(|heading| heading.eq_ignore_ascii_case(name.trim()))
+}
+
+impl FromStr for Constraint {
+    type Err = ClaudeError;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let (name, arg) = match spec.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (spec, None),
+        };
+        let count = |arg: &str| {
+            arg.parse::<usize>()
+                .map_err(|_| ClaudeError::Constraint(format!("{:?} needs a number of words", spec)))
+        };
+
+        match (name, arg) {
+            ("max-words", Some(arg)) => Ok(Constraint::MaxWords(count(arg)?)),
+            ("min-words", Some(arg)) => Ok(Constraint::MinWords(count(arg)?)),
+            ("json", None) => Ok(Constraint::Json),
+            ("section", Some(name)) => Ok(Constraint::Section(name.to_string())),
+            ("contains", Some(text)) => Ok(Constraint::Contains(text.to_string())),
+            ("language", Some(language)) if !language.trim().is_empty() => {
+                Ok(Constraint::Language(language.trim().to_string()))
+            }
+            ("regex", Some(pattern)) => {
+                Regex::new(pattern).map_err(|e| {
+                    ClaudeError::Constraint(format!("invalid pattern {:?}: {}", pattern, e))
+                })?;
+                Ok(Constraint::Regex(pattern.to_string()))
+            }
+            _ => Err(ClaudeError::Constraint(format!(
+                "unknown constraint {:?} (expected max-words:<n>, min-words:<n>, json, section:<name>, contains:<text>, regex:<pattern> or language:<name>)",
+                spec
+            ))),
+        }
+    }
+}
+
+impl TryFrom<String> for Constraint {
+    type Error = ClaudeError;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<Constraint> for String {
+    fn from(value: Constraint) -> Self {
+        value.to_string()
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constraint::MaxWords(max) => write!(f, "max-words:{}", max),
+            Constraint::MinWords(min) => write!(f, "min-words:{}", min),
+            Constraint::Json => write!(f, "json"),
+            Constraint::Section(name) => write!(f, "section:{}", name),
+            Constraint::Contains(text) => write!(f, "contains:{}", text),
+            Constraint::Regex(pattern) => write!(f, "regex:{}", pattern),
+            Constraint::Language(language) => write!(f, "language:{}", language),
+        }
+    }
+}
+
+impl Client {
+    /// How `text` violates each of `constraints`, having the model detect its language if needed
+    ///
+    /// The usage of the checks is added to `usage`.
+    async fn violations(
+        &self,
+        constraints: &[Constraint],
+        text: &str,
+        usage: &mut Usage,
+    ) -> Result<Vec<String>> {
+        let mut violations = Vec::new();
+        for constraint in constraints {
+            match constraint {
+                Constraint::Language(language) => {
+                    let sample: String = text.chars().take(LANGUAGE_SAMPLE_CHARS).collect();
+                    let check: LanguageCheck = self
+                        .send_structured_counted(
+                            Messages::new()
+                                .push_user(format!(
+                                    "<text>\n{}\n</text>\n\nIs this text written in {}?",
+                                    sample, language
+                                ))
+                                .clone(),
+                            Some("You identify the language texts are written in.".into()),
+                            StructuredRetry::default(),
+                            usage,
+                        )
+                        .await?;
+                    if !check.matches {
+                        violations.push(format!(
+                            "it is written in {}, not {}",
+                            check.language, language
+                        ));
+                    }
+                }
+                constraint => violations.extend(constraint.violation(text)),
+            }
+        }
+        Ok(violations)
+    }
+
+    /// Send a message and re-ask until the response meets every constraint
+    ///
+    /// The constraints are stated in the system prompt. Language constraints
+    /// cost a request per attempt to check. A response that
+    /// violates any of them is, as `retry` allows, shown back to the model
+    /// with the violations described, or just asked for again without
+    /// feedback. Local repair doesn't apply. [`Client::last_usage`] covers
+    /// every attempt.
+    pub async fn send_constrained(
+        &self,
+        mut messages: Messages,
+        system: Option<String>,
+        constraints: &[Constraint],
+        retry: impl Into<StructuredRetry>,
+    ) -> Result<String> {
+        let retry = retry.into();
+        let rules = constraints
+            .iter()
+            .map(|constraint| format!("- {}", constraint.instruction()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let system = match (system, constraints.is_empty()) {
+            (system, true) => system,
+            (Some(system), false) => Some(format!(
+                "{}\n\nThe response must meet these requirements:\n{}",
+                system, rules
+            )),
+            (None, false) => Some(format!(
+                "The response must meet these requirements:\n{}",
+                rules
+            )),
+        };
+
+        let mut last_error = String::from("no attempts were made");
+        let mut usage = Usage::default();
+        let result = async {
+            for attempt in 1..=retry.max_attempts {
+                let sent = self
+                    .send_message_response(messages.clone(), None, system.clone())
+                    .await?;
+                usage += &sent.usage;
+                let response = sent.text();
+
+                let violations = self.violations(constraints, &response, &mut usage).await?;
+                if violations.is_empty() {
+                    return Ok(response);
+                }
+                let error = violations.join("; ");
+                let failed = FailedAttempt {
+                    attempt,
+                    response,
+                    error,
+                };
+                if let Some(hook) = &retry.on_failure {
+                    hook(&failed);
+                }
+                // Asking again would be cut off the same way
+                if let Some(StopReason::MaxTokens) = sent.stop_reason {
+                    return Err(ClaudeError::Truncated {
+                        output_tokens: sent.usage.output_tokens,
+                    });
+                }
+                if retry.feed_back_errors {
+                    messages.push_assistant(failed.response);
+                    messages.push_user(format!(
+                        "Your response doesn't meet the requirements: {}. Reply again with the \
+                         whole corrected response.",
+                        failed.error
+                    ));
+                }
+                last_error = failed.error;
+            }
+
+            Err(ClaudeError::Constraint(format!(
+                "failed to get a response meeting the constraints despite {} attempts: {}",
+                retry.max_attempts, last_error
+            )))
+        }
+        .await;
+
+        self.set_last_usage(usage);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::{http_stub, stub_client};
+    use crate::config::Config;
+
+    #[test]
+    fn test_parse_and_display() {
+        for spec in [
+            "max-words:200",
+            "min-words:3",
+            "json",
+            "section:Next steps",
+            "contains:TODO:",
+            r"regex:\d+",
+            "language:German",
+        ] {
+            assert_eq!(spec.parse::<Constraint>().unwrap().to_string(), spec);
+        }
+        assert!("max-words:many".parse::<Constraint>().is_err());
+        assert!("regex:(".parse::<Constraint>().is_err());
+        assert!("shout".parse::<Constraint>().is_err());
+        assert!("language: ".parse::<Constraint>().is_err());
+    }
+
+    #[test]
+    fn test_violations() {
+        let text = "# Summary\n\nIt works.\n\n## Next steps:\n\nShip it.";
+        assert_eq!(Constraint::MaxWords(10).violation(text), None);
+        assert!(Constraint::MaxWords(5)
+            .violation(text)
+            .unwrap()
+            .contains("9 words"));
+        assert_eq!(
+            Constraint::Section("next Steps".into()).violation(text),
+            None
+        );
+        assert!(Constraint::Section("Risks".into())
+            .violation(text)
+            .is_some());
+        assert!(Constraint::Json.violation(text).is_some());
+        assert_eq!(Constraint::Json.violation(" {\"a\": 1}\n"), None);
+        assert_eq!(Constraint::Regex("Ship".into()).violation(text), None);
+    }
+
+    fn reply(text: &str) -> &'static str {
+        Box::leak(
+            serde_json::json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-haiku-4-5",
+                "content": [{"type": "text", "text": text}],
+                "stop_reason": "end_turn",
+                "usage": {"input_tokens": 1, "output_tokens": 3},
+            })
+            .to_string()
+            .into_boxed_str(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_send_constrained_reasks() {
+        let (url, requests) = http_stub(vec![
+            Some((200, reply("A long rambling answer with far too many words"))),
+            Some((200, reply("Short answer."))),
+        ])
+        .await;
+        let client = stub_client(url);
+
+        let text = client
+            .send_constrained(
+                Messages::new().push_user("Answer".into()).clone(),
+                None,
+                &[Constraint::MaxWords(3)],
+                StructuredRetry::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(text, "Short answer.");
+        assert_eq!(client.last_usage().unwrap().output_tokens, 6);
+
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].contains("use at most 3 words."));
+        assert!(requests[1].contains("it has 9 words, more than the 3 allowed"));
+    }
+
+    #[tokio::test]
+    async fn test_send_constrained_gives_up() {
+        let (url, _) = http_stub(vec![
+            Some((200, reply("no json"))),
+            Some((200, reply("nope"))),
+        ])
+        .await;
+        let client = stub_client(url);
+
+        let error = client
+            .send_constrained(
+                Messages::new().push_user("JSON please".into()).clone(),
+                None,
+                &[Constraint::Json],
+                2,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ClaudeError::Constraint(_)));
+        assert_eq!(error.kind(), crate::error::ErrorKind::Schema);
+    }
+
+    #[tokio::test]
+    async fn test_respond_in_language() {
+        // Structured output prefills "{", so language checks continue from there
+        let (url, requests) = http_stub(vec![
+            Some((200, reply("Good morning!"))),
+            Some((200, reply(r#""language": "English", "matches": false}"#))),
+            Some((200, reply("Guten Morgen!"))),
+            Some((200, reply(r#""language": "German", "matches": true}"#))),
+        ])
+        .await;
+        let mut config = Config::new("sk-ant-test-key");
+        config.base_url = url;
+        config.respond_in = Some("German".into());
+        let client = Client::new(config).unwrap();
+
+        let text = client
+            .send_constrained(
+                Messages::new().push_user("Say good morning".into()).clone(),
+                None,
+                &[Constraint::Language("German".into())],
+                StructuredRetry::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(text, "Guten Morgen!");
+        assert_eq!(client.last_usage().unwrap().output_tokens, 12);
+
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].contains("write your response in german"));
+        assert!(requests[1].contains("<text>\\ngood morning!\\n</text>"));
+        assert!(requests[2].contains("it is written in english, not german"));
+    }
+}
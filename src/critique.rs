@@ -0,0 +1,197 @@
+use crate::client::{Client, Messages};
+use crate::error::Result;
+use crate::structured::StructuredRetry;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// How [`Client::critique`] reviews and revises an answer
+#[derive(Debug, Clone, PartialEq)]
+pub struct CritiqueOptions {
+    /// What a good answer is, e.g. "correct, cites its sources"
+    pub criteria: String,
+    /// Most critique and revision rounds; fewer when the critic is satisfied
+    pub rounds: usize,
+    /// Model that critiques the answers (the client's own when `None`)
+    pub critic: Option<String>,
+}
+
+impl CritiqueOptions {
+    pub fn new(criteria: impl Into<String>) -> Self {
+        Self {
+            criteria: criteria.into(),
+            rounds: 2,
+            critic: None,
+        }
+    }
+}
+
+/// A critic's review of an answer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Review {
+    /// true when the response fully meets the criteria and needs no changes
+    pub satisfied: bool,
+    /// the specific problems of the response with respect to the criteria, and how to fix them
+    pub critique: String,
+}
+
+/// One round of [`Client::critique`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CritiqueRound {
+    pub review: Review,
+    /// The answer revised after the review, unless the critic was satisfied
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+}
+
+/// The result of [`Client::critique`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Critiqued {
+    /// The first answer, before any critique
+    pub draft: String,
+    pub rounds: Vec<CritiqueRound>,
+    /// The last revision, or the draft if there was none
+    pub answer: String,
+}
+
+/// A prompt presenting `response` to `messages` for review against `criteria`
+pub(crate) fn review_prompt(messages: &Messages, response: &str, criteria: &str) -> Result<String> {
+    Ok(format!(
+        "<conversation>\n{}\n</conversation>\n\n<response>\n{}\n</response>\n\n\
+         <criteria>\n{}\n</criteria>",
+        serde_json::to_string_pretty(messages)?,
+        response,
+        criteria
+    ))
+}
+
+impl Client {
+    /// Answer `messages`, then have the answer critiqued and revised in rounds
+    ///
+    /// Each round the critic reviews the latest answer against the criteria,
+    /// and unless it's satisfied, this client revises the answer in the same
+    /// conversation, seeing its previous answer and the critique.
+    pub async fn critique(
+        &self,
+        messages: Messages,
+        system: Option<String>,
+        options: &CritiqueOptions,
+    ) -> Result<Critiqued> {
+        let critic = match &options.critic {
+            Some(model) => self.for_model(model.as_str())?,
+            None => self.for_model(self.config().model.clone())?,
+        };
+        let critic_system = "You critique responses. Review how well the response to the \
+                             conversation meets the criteria, and be specific about what to change."
+            .to_string();
+
+        let draft = self
+            .send_message(messages.clone(), None, system.clone())
+            .await?;
+        let mut answer = draft.clone();
+        let mut rounds = Vec::with_capacity(options.rounds);
+        for _ in 0..options.rounds {
+            let prompt = review_prompt(&messages, &answer, &options.criteria)?;
+            let review: Review = critic
+                .send_structured(
+                    Messages::new().push_user(prompt).clone(),
+                    Some(critic_system.clone()),
+                    StructuredRetry::default(),
+                )
+                .await?;
+            if review.satisfied {
+                rounds.push(CritiqueRound {
+                    review,
+                    revision: None,
+                });
+                break;
+            }
+
+            let mut revise = messages.clone();
+            revise.push_assistant(answer).push_user(format!(
+                "A reviewer checked your answer against these criteria:\n{}\n\n\
+                 <critique>\n{}\n</critique>\n\nRevise your answer to address the critique. \
+                 Reply with the revised answer only.",
+                options.criteria, review.critique
+            ));
+            answer = self.send_message(revise, None, system.clone()).await?;
+            rounds.push(CritiqueRound {
+                review,
+                revision: Some(answer.clone()),
+            });
+        }
+
+        Ok(Critiqued {
+            draft,
+            rounds,
+            answer,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::{http_stub, stub_client};
+
+    fn reply(text: &str) -> &'static str {
+        Box::leak(
+            serde_json::json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-haiku-4-5",
+                "content": [{"type": "text", "text": text}],
+                "stop_reason": "end_turn",
+                "usage": {"input_tokens": 1, "output_tokens": 1},
+            })
+            .to_string()
+            .into_boxed_str(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_critique_stops_when_satisfied() {
+        // Structured output prefills "{", so reviews continue from there
+        let (url, requests) = http_stub(vec![
+            Some((200, reply("Rust is fast."))),
+            Some((200, reply(r#""satisfied": false, "critique": "Say why."}"#))),
+            Some((
+                200,
+                reply("Rust is fast because it compiles to native code."),
+            )),
+            Some((200, reply(r#""satisfied": true, "critique": "Good."}"#))),
+        ])
+        .await;
+        let client = stub_client(url);
+        let options = CritiqueOptions {
+            rounds: 3,
+            critic: Some("claude-opus-4-1".into()),
+            ..CritiqueOptions::new("explains why")
+        };
+
+        let critiqued = client
+            .critique(
+                Messages::new().push_user("Is Rust fast?".into()).clone(),
+                None,
+                &options,
+            )
+            .await
+            .unwrap();
+        assert_eq!(critiqued.draft, "Rust is fast.");
+        assert_eq!(critiqued.rounds.len(), 2);
+        assert_eq!(critiqued.rounds[0].review.critique, "Say why.");
+        assert!(critiqued.rounds[1].review.satisfied);
+        assert_eq!(critiqued.rounds[1].revision, None);
+        assert_eq!(
+            critiqued.answer,
+            "Rust is fast because it compiles to native code."
+        );
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 4);
+        assert!(requests[1].contains(r#""model":"claude-opus-4-1""#));
+        assert!(requests[1].contains("<response>\\nrust is fast.\\n</response>"));
+        assert!(requests[2].contains("<critique>\\nsay why.\\n</critique>"));
+        assert!(requests[3].contains("because it compiles"));
+    }
+}
@@ -0,0 +1,545 @@
+//! Background daemon that relays API requests over a local socket
+//!
+//! The daemon owns a single HTTP client, so connections (and their TLS
+//! sessions) stay warm across CLI invocations, and every shell talking to it
+//! shares the same connection pool. Requests and responses are exchanged as
+//! newline-delimited JSON over a Unix socket that only the owner can access.
+//!
+//! The daemon's HTTP client is built from its own `[http]` settings, so it
+//! refuses requests from callers configured with a different CA bundle or
+//! client certificate rather than sending them with the wrong identity.
+
+use crate::client::{api_error, Usage};
+use crate::config::{Config, HttpConfig};
+use crate::error::{ClaudeError, ConfigError, Result};
+use crate::metrics::Metrics;
+use crate::ratelimit::{self, RateLimitConfig};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// An HTTP request to be performed by the daemon on the caller's behalf
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct RelayRequest {
+    pub(crate) url: String,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: serde_json::Value,
+    /// The caller's limits, shared by every caller with the same API key
+    #[serde(default, skip_serializing_if = "RateLimitConfig::is_default")]
+    pub(crate) rate_limit: RateLimitConfig,
+    /// The caller's TLS settings, which must match the daemon's
+    #[serde(default, skip_serializing_if = "TlsSettings::is_default")]
+    pub(crate) tls: TlsSettings,
+}
+
+/// The TLS files an HTTP client is built with, as absolute paths where they exist
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct TlsSettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ca_bundle: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_cert: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_key: Option<PathBuf>,
+}
+
+impl TlsSettings {
+    pub(crate) fn of(http: &HttpConfig) -> Self {
+        let absolute = |path: &Option<PathBuf>| {
+            path.as_ref()
+                .map(|path| std::fs::canonicalize(path).unwrap_or_else(|_| path.clone()))
+        };
+        Self {
+            ca_bundle: absolute(&http.ca_bundle),
+            client_cert: absolute(&http.client_cert),
+            client_key: absolute(&http.client_key),
+        }
+    }
+
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// The raw HTTP response to an API call, whether made directly or via the daemon
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct RawResponse {
+    pub(crate) status: u16,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: String,
+}
+
+impl RawResponse {
+    pub(crate) async fn from_reqwest(response: reqwest::Response) -> Result<Self> {
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| {
+                Some((name.as_str().to_string(), value.to_str().ok()?.to_string()))
+            })
+            .collect();
+        let body = response.text().await?;
+
+        Ok(Self {
+            status,
+            headers,
+            body,
+        })
+    }
+
+    pub(crate) async fn from_response(response: crate::middleware::Response) -> Result<Self> {
+        let (status, headers) = (response.status, response.headers.clone());
+        Ok(Self {
+            status,
+            headers,
+            body: response.into_text().await?,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(not(unix), allow(dead_code))]
+enum RelayReply {
+    Ok(RawResponse),
+    Error(RelayError),
+}
+
+/// What went wrong relaying a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum RelayErrorKind {
+    /// The API could not be reached, so nothing was sent
+    Unsent,
+    /// The connection failed after the request may have been sent
+    Network,
+    /// The caller's TLS settings differ from the daemon's
+    Tls,
+    /// The daemon could not make sense of the request
+    Invalid,
+}
+
+/// A failure reported by the daemon, turned back into the caller's error
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(not(unix), allow(dead_code))]
+struct RelayError {
+    kind: RelayErrorKind,
+    /// The HTTP status, if the API answered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    message: String,
+}
+
+#[cfg_attr(not(unix), allow(dead_code))]
+impl RelayError {
+    fn new(kind: RelayErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            status: None,
+            message: message.into(),
+        }
+    }
+
+    fn from_error(error: ClaudeError) -> Self {
+        match error {
+            ClaudeError::Network(error) => Self {
+                kind: if error.is_connect() {
+                    RelayErrorKind::Unsent
+                } else {
+                    RelayErrorKind::Network
+                },
+                status: error.status().map(|status| status.as_u16()),
+                message: error.to_string(),
+            },
+            error => Self::new(RelayErrorKind::Network, error.to_string()),
+        }
+    }
+
+    fn into_error(self) -> ClaudeError {
+        if let Some(status) = self.status {
+            return api_error(status, self.message);
+        }
+        match self.kind {
+            RelayErrorKind::Unsent | RelayErrorKind::Network => ClaudeError::RelayedNetwork {
+                message: self.message,
+                sent: self.kind == RelayErrorKind::Network,
+            },
+            RelayErrorKind::Tls => ConfigError::Tls(self.message).into(),
+            RelayErrorKind::Invalid => ClaudeError::Daemon(self.message),
+        }
+    }
+}
+
+/// Default location of the daemon socket
+///
+/// Uses the per-user runtime directory where available, falling back to the
+/// cache directory.
+pub fn default_socket_path() -> Result<PathBuf> {
+    if let Some(runtime) = dirs::runtime_dir() {
+        return Ok(runtime.join("ellm.sock"));
+    }
+
+    let cache = dirs::cache_dir()
+        .ok_or_else(|| ClaudeError::Daemon("could not determine a socket directory".into()))?;
+    Ok(cache.join("ellm").join("daemon.sock"))
+}
+
+/// Perform an HTTP request and package the result for the caller
+#[cfg_attr(not(unix), allow(dead_code))]
+async fn perform(
+    http_client: &reqwest::Client,
+    tls: &TlsSettings,
+    metrics: &Metrics,
+    request: RelayRequest,
+) -> RelayReply {
+    if request.tls != *tls {
+        return RelayReply::Error(RelayError::new(
+            RelayErrorKind::Tls,
+            "the daemon was started with different ca_bundle, client_cert or client_key \
+             settings; restart it with the same [http] settings or pass --no-daemon",
+        ));
+    }
+
+    let api_key = request
+        .headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("x-api-key"))
+        .map_or("", |(_, value)| value.as_str());
+    let limiter = ratelimit::limiter(api_key, &request.rate_limit);
+    if let Some(limiter) = &limiter {
+        limiter.acquire().await;
+    }
+
+    let started = Instant::now();
+    let mut builder = http_client.post(&request.url).json(&request.body);
+    for (name, value) in &request.headers {
+        builder = builder.header(name, value);
+    }
+
+    let result = match builder.send().await {
+        Ok(response) => RawResponse::from_reqwest(response).await,
+        Err(error) => Err(error.into()),
+    };
+
+    if let (Some(limiter), Ok(response)) = (&limiter, &result) {
+        let usage = serde_json::from_str::<serde_json::Value>(&response.body)
+            .ok()
+            .and_then(|body| serde_json::from_value::<Usage>(body["usage"].clone()).ok());
+        if let Some(usage) = usage {
+            limiter.charge(&usage);
+        }
+    }
+
+    match &result {
+        Ok(response) => metrics.record(
+            &request.body,
+            Some(response.status),
+            &response.body,
+            started.elapsed(),
+        ),
+        Err(_) => metrics.record(&request.body, None, "", started.elapsed()),
+    }
+
+    match result {
+        Ok(response) => RelayReply::Ok(response),
+        Err(error) => RelayReply::Error(RelayError::from_error(error)),
+    }
+}
+
+/// Run the daemon, accepting connections on `socket` until the process is stopped
+///
+/// The HTTP client is built from `config`; API keys come from each request,
+/// which is refused if made with other TLS settings. When `metrics` is given, Prometheus metrics are served on that address.
+#[cfg(unix)]
+pub async fn serve(socket: &Path, config: &Config, metrics: Option<SocketAddr>) -> Result<()> {
+    use crate::client::build_http_client;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    if let Some(parent) = socket.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // A leftover socket from a previous run would make bind fail
+    if socket.exists() {
+        if tokio::net::UnixStream::connect(socket).await.is_ok() {
+            return Err(ClaudeError::Daemon(format!(
+                "a daemon is already listening on {}",
+                socket.display()
+            )));
+        }
+        std::fs::remove_file(socket)?;
+    }
+
+    let http_client = build_http_client(config)?;
+    let tls = std::sync::Arc::new(TlsSettings::of(&config.http));
+    let listener = bind_private(socket)?;
+
+    let recorder = std::sync::Arc::new(Metrics::new());
+    if let Some(address) = metrics {
+        let listener = tokio::net::TcpListener::bind(address).await?;
+        tokio::spawn(crate::metrics::serve(listener, recorder.clone()));
+    }
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let http_client = http_client.clone();
+        let tls = tls.clone();
+        let recorder = recorder.clone();
+
+        tokio::spawn(async move {
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+
+            while let Ok(Some(line)) = lines.next_line().await {
+                let reply = match serde_json::from_str::<RelayRequest>(&line) {
+                    Ok(request) => perform(&http_client, &tls, &recorder, request).await,
+                    Err(error) => RelayReply::Error(RelayError::new(
+                        RelayErrorKind::Invalid,
+                        format!("invalid request: {}", error),
+                    )),
+                };
+
+                let Ok(mut out) = serde_json::to_vec(&reply) else {
+                    break;
+                };
+                out.push(b'\n');
+                if writer.write_all(&out).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+/// Listen on `socket`, which only the owner can connect to from the start
+///
+/// The socket is bound in a new directory only the owner can enter, so it
+/// can't be reached before its permissions are restricted, and then moved to
+/// `socket`.
+#[cfg(unix)]
+fn bind_private(socket: &Path) -> Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    let parent = socket.parent().unwrap_or(Path::new("."));
+    let private = parent.join(format!(".ellm-daemon-{}", std::process::id()));
+    std::fs::DirBuilder::new().mode(0o700).create(&private)?;
+    let staged = private.join("daemon.sock");
+    let bound = tokio::net::UnixListener::bind(&staged).and_then(|listener| {
+        std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600))?;
+        std::fs::rename(&staged, socket)?;
+        Ok(listener)
+    });
+    let _ = std::fs::remove_file(&staged);
+    std::fs::remove_dir(&private)?;
+    Ok(bound?)
+}
+
+#[cfg(not(unix))]
+pub async fn serve(_socket: &Path, _config: &Config, _metrics: Option<SocketAddr>) -> Result<()> {
+    Err(ClaudeError::Daemon(
+        "daemon mode is only supported on Unix platforms".into(),
+    ))
+}
+
+/// Ask the daemon at `socket` to perform a request
+///
+/// The outer error means the daemon could not be reached, so nothing was sent
+/// and the caller may safely fall back to a direct request. The inner result is
+/// the outcome of the request the daemon made.
+#[cfg(unix)]
+pub(crate) async fn relay(
+    socket: &Path,
+    request: &RelayRequest,
+) -> std::io::Result<Result<RawResponse>> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let stream = tokio::net::UnixStream::connect(socket).await?;
+    let (reader, mut writer) = stream.into_split();
+
+    let mut line = serde_json::to_vec(request)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+
+    // From here on the daemon may have sent the request, so failures are final
+    let mut reply = String::new();
+    let read = BufReader::new(reader).read_line(&mut reply).await;
+
+    Ok(match read {
+        Ok(0) => Err(ClaudeError::Daemon("daemon closed the connection".into())),
+        Ok(_) => match serde_json::from_str::<RelayReply>(&reply) {
+            Ok(RelayReply::Ok(response)) => Ok(response),
+            Ok(RelayReply::Error(error)) => Err(error.into_error()),
+            Err(error) => Err(error.into()),
+        },
+        Err(error) => Err(error.into()),
+    })
+}
+
+#[cfg(not(unix))]
+pub(crate) async fn relay(
+    _socket: &Path,
+    _request: &RelayRequest,
+) -> std::io::Result<Result<RawResponse>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "daemon mode is only supported on Unix platforms",
+    ))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// A one-shot HTTP server answering every connection with `body`
+    async fn http_stub(body: &'static str) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buffer = [0u8; 4096];
+                let _ = stream.read(&mut buffer).await;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nx-test: yes\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        format!("http://{}/v1/messages", address)
+    }
+
+    /// Start a daemon on a socket of its own, returning once it is listening
+    async fn start_daemon(name: &str) -> PathBuf {
+        let socket =
+            std::env::temp_dir().join(format!("ellm-test-{}-{}.sock", name, std::process::id()));
+
+        let server_socket = socket.clone();
+        tokio::spawn(
+            async move { serve(&server_socket, &Config::new("sk-ant-test-key"), None).await },
+        );
+        for _ in 0..100 {
+            if socket.exists() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        socket
+    }
+
+    fn request(url: String) -> RelayRequest {
+        RelayRequest {
+            url,
+            headers: vec![],
+            body: serde_json::json!({}),
+            rate_limit: RateLimitConfig::default(),
+            tls: TlsSettings::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_relay_through_daemon() {
+        let url = http_stub("{\"ok\":true}").await;
+        let socket = start_daemon("relay").await;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let request = RelayRequest {
+            url,
+            headers: vec![("x-api-key".into(), "sk-ant-test".into())],
+            body: serde_json::json!({}),
+            rate_limit: RateLimitConfig {
+                requests_per_minute: Some(60),
+                ..Default::default()
+            },
+            tls: TlsSettings::default(),
+        };
+        let response = relay(&socket, &request).await.unwrap().unwrap();
+
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, "{\"ok\":true}");
+        assert!(response
+            .headers
+            .iter()
+            .any(|(name, value)| name == "x-test" && value == "yes"));
+
+        let _ = std::fs::remove_file(&socket);
+    }
+
+    #[tokio::test]
+    async fn test_relay_without_daemon_is_connect_error() {
+        let socket = std::env::temp_dir().join("ellm-test-missing.sock");
+        let request = request("http://127.0.0.1:9/".into());
+        assert!(relay(&socket, &request).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_relayed_connect_failure_is_unsent_network_error() {
+        // Nothing listens on a port that was just released
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/v1/messages", listener.local_addr().unwrap());
+        drop(listener);
+        let socket = start_daemon("unsent").await;
+
+        let error = relay(&socket, &request(url)).await.unwrap().unwrap_err();
+
+        assert!(matches!(
+            error,
+            ClaudeError::RelayedNetwork { sent: false, .. }
+        ));
+        assert!(crate::retry::is_unsent(&error));
+        assert_eq!(error.kind(), crate::error::ErrorKind::Network);
+        let _ = std::fs::remove_file(&socket);
+    }
+
+    #[tokio::test]
+    async fn test_mismatched_tls_settings_are_refused() {
+        let url = http_stub("{\"ok\":true}").await;
+        let socket = start_daemon("tls").await;
+        let mut request = request(url);
+        request.tls = TlsSettings::of(&HttpConfig {
+            ca_bundle: Some("/etc/ellm/proxy-ca.pem".into()),
+            ..Default::default()
+        });
+
+        let error = relay(&socket, &request).await.unwrap().unwrap_err();
+
+        assert!(
+            matches!(error, ClaudeError::Config(ConfigError::Tls(ref message)) if message.contains("ca_bundle")),
+            "{}",
+            error
+        );
+        let _ = std::fs::remove_file(&socket);
+    }
+
+    #[test]
+    fn test_relay_error_round_trip() {
+        let error = |json: &str| {
+            serde_json::from_str::<RelayError>(json)
+                .unwrap()
+                .into_error()
+        };
+
+        assert!(matches!(
+            error(r#"{"kind":"network","message":"reset"}"#),
+            ClaudeError::RelayedNetwork { sent: true, .. }
+        ));
+        assert!(matches!(
+            error(r#"{"kind":"network","status":529,"message":"busy"}"#),
+            ClaudeError::Api(crate::error::ApiError::Overloaded(_))
+        ));
+        assert!(matches!(
+            error(r#"{"kind":"invalid","message":"invalid request"}"#),
+            ClaudeError::Daemon(_)
+        ));
+    }
+}
@@ -0,0 +1,143 @@
+//! Diagnostic messages on stderr
+//!
+//! Warnings and notes such as retries, cache use and configuration changes
+//! are written as text by default. With [`LogFormat::Json`] each one is a JSON
+//! line instead, with a timestamp, level, event name and the event's fields,
+//! for log pipelines; responses on stdout are unaffected.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// How diagnostics are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Plain messages, warnings prefixed with `Warning:`
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+impl LogFormat {
+    pub fn is_text(&self) -> bool {
+        *self == LogFormat::Text
+    }
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format {:?}; use text or json", s)),
+        }
+    }
+}
+
+/// Write diagnostics of the whole process in `format`
+pub fn set_format(format: LogFormat) {
+    JSON.store(format == LogFormat::Json, Ordering::Relaxed);
+}
+
+pub fn format() -> LogFormat {
+    if JSON.load(Ordering::Relaxed) {
+        LogFormat::Json
+    } else {
+        LogFormat::Text
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Level {
+    Info,
+    Warning,
+}
+
+/// Report something worth knowing that isn't a problem, e.g. cache use
+///
+/// `event` names the kind of message for filtering, and `fields` is an object
+/// of the values the message is about, included in JSON output.
+pub fn info(event: &str, message: impl Display, fields: Value) {
+    eprintln!("{}", line(format(), Level::Info, event, message, fields));
+}
+
+/// Report a problem that didn't stop the operation, e.g. a retried request
+pub fn warn(event: &str, message: impl Display, fields: Value) {
+    eprintln!("{}", line(format(), Level::Warning, event, message, fields));
+}
+
+fn line(
+    format: LogFormat,
+    level: Level,
+    event: &str,
+    message: impl Display,
+    fields: Value,
+) -> String {
+    match format {
+        LogFormat::Text => match level {
+            Level::Info => message.to_string(),
+            Level::Warning => format!("Warning: {}", message),
+        },
+        LogFormat::Json => {
+            let mut line = serde_json::json!({
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+                "level": match level {
+                    Level::Info => "info",
+                    Level::Warning => "warning",
+                },
+                "event": event,
+                "message": message.to_string(),
+            });
+            if let (Value::Object(line), Value::Object(fields)) = (&mut line, fields) {
+                for (key, value) in fields {
+                    line.entry(key).or_insert(value);
+                }
+            }
+            line.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_text_line() {
+        let line = |level| line(LogFormat::Text, level, "retry", "retrying", json!({}));
+        assert_eq!(line(Level::Info), "retrying");
+        assert_eq!(line(Level::Warning), "Warning: retrying");
+    }
+
+    #[test]
+    fn test_json_line() {
+        let line = line(
+            LogFormat::Json,
+            Level::Warning,
+            "retry",
+            "retrying in 2s",
+            json!({"status": 429, "delay_ms": 2000, "message": "ignored"}),
+        );
+        let line: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(line["level"], "warning");
+        assert_eq!(line["event"], "retry");
+        assert_eq!(line["message"], "retrying in 2s");
+        assert_eq!(line["status"], 429);
+        assert_eq!(line["delay_ms"], 2000);
+        assert!(line["timestamp"].is_string());
+    }
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!("json".parse::<LogFormat>(), Ok(LogFormat::Json));
+        assert!("yaml".parse::<LogFormat>().is_err());
+    }
+}
@@ -0,0 +1,380 @@
+//! Digests of RSS and Atom feeds
+//!
+//! `ellm digest` fetches feeds, keeps the items published within `--since`,
+//! groups them into topics with one structured request and summarizes each
+//! topic with [`Client::summarize`], so topics with many long items are
+//! summarized in parts. The result is a Markdown digest with the sources of
+//! every topic.
+//!
+//! Feeds are read with a few patterns rather than a full XML parser, which
+//! covers the RSS 2.0 and Atom that feeds publish in practice.
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use ellm::{diagnostics, Client, LongDocOptions, Messages};
+use futures::{StreamExt, TryStreamExt};
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+
+/// Characters of each item's text shown when grouping items into topics
+const PREVIEW_CHARS: usize = 200;
+
+/// An entry of a feed
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedItem {
+    pub title: String,
+    pub link: Option<String>,
+    pub published: Option<DateTime<Utc>>,
+    /// The item's description or content, as plain text
+    pub text: String,
+}
+
+static ITEM: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<(item|entry)\b[^>]*>(.*?)</(?:item|entry)>").unwrap());
+static TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]*>").unwrap());
+static CDATA: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)<!\[CDATA\[(.*?)\]\]>").unwrap());
+static ATOM_LINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"<link\b([^>]*?)href="([^"]*)"([^>]*)/?>"#).unwrap());
+
+/// The text of the first `name` element in `xml`, if any
+fn element(xml: &str, name: &str) -> Option<String> {
+    let pattern = format!(
+        r"(?s)<{}(?:\s[^>]*)?>(.*?)</{}>",
+        regex::escape(name),
+        regex::escape(name)
+    );
+    let captures = Regex::new(&pattern).ok()?.captures(xml)?;
+    Some(
+        unescape(&CDATA.replace_all(&captures[1], "$1"))
+            .trim()
+            .to_string(),
+    )
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+/// `html` as plain text, with tags removed and whitespace collapsed
+fn plain_text(html: &str) -> String {
+    let text = unescape(&TAG.replace_all(html, " "));
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The items of an RSS or Atom document
+pub fn parse_feed(xml: &str) -> Vec<FeedItem> {
+    ITEM.captures_iter(xml)
+        .map(|captures| {
+            let body = &captures[2];
+            let link = match &captures[1] {
+                "entry" => ATOM_LINK
+                    .captures_iter(body)
+                    .find(|link| {
+                        let attributes = format!("{}{}", &link[1], &link[3]);
+                        !attributes.contains("rel=") || attributes.contains(r#"rel="alternate""#)
+                    })
+                    .map(|link| unescape(&link[2])),
+                _ => element(body, "link"),
+            };
+            let published = ["pubDate", "published", "updated", "dc:date"]
+                .iter()
+                .find_map(|name| element(body, name))
+                .and_then(|date| {
+                    DateTime::parse_from_rfc2822(&date)
+                        .or_else(|_| DateTime::parse_from_rfc3339(&date))
+                        .ok()
+                })
+                .map(|date| date.with_timezone(&Utc));
+            let text = ["content:encoded", "content", "description", "summary"]
+                .iter()
+                .find_map(|name| element(body, name))
+                .map(|html| plain_text(&html))
+                .unwrap_or_default();
+
+            FeedItem {
+                title: element(body, "title")
+                    .map(|title| plain_text(&title))
+                    .unwrap_or_default(),
+                link,
+                published,
+                text,
+            }
+        })
+        .collect()
+}
+
+/// A duration such as `30m`, `24h`, `7d` or `2w`
+pub fn parse_since(since: &str) -> Result<chrono::Duration> {
+    let invalid = || anyhow!("not a duration like 24h, 7d or 2w: {}", since);
+    let unit = since.chars().last().ok_or_else(invalid)?;
+    let count: i64 = since[..since.len() - unit.len_utf8()]
+        .parse()
+        .map_err(|_| invalid())?;
+    match unit {
+        'm' => Ok(chrono::Duration::minutes(count)),
+        'h' => Ok(chrono::Duration::hours(count)),
+        'd' => Ok(chrono::Duration::days(count)),
+        'w' => Ok(chrono::Duration::weeks(count)),
+        _ => Err(invalid()),
+    }
+}
+
+/// The feed URLs in a feeds file: one per line, `#` starting a comment
+pub fn feed_urls(text: &str) -> Vec<String> {
+    text.lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+async fn fetch(http: &reqwest::Client, url: &str) -> Result<Vec<FeedItem>> {
+    let response = http
+        .get(url)
+        .header("user-agent", concat!("ellm/", env!("CARGO_PKG_VERSION")))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(parse_feed(&response.text().await?))
+}
+
+/// Items grouped under a topic
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+struct Topic {
+    /// a short name for what the items have in common
+    topic: String,
+    /// the numbers of the items about this topic
+    items: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+struct Topics {
+    /// the topics, most important first; every item belongs to exactly one
+    topics: Vec<Topic>,
+}
+
+/// A topic of the digest and its summary
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub topic: String,
+    pub summary: String,
+    pub items: Vec<FeedItem>,
+}
+
+/// Fetch `urls` and summarize their items published since `since`, by topic
+pub async fn digest(
+    client: &Client,
+    urls: &[String],
+    since: DateTime<Utc>,
+    options: &LongDocOptions,
+) -> Result<Vec<Section>> {
+    let http = reqwest::Client::new();
+    let mut items = Vec::new();
+    let mut fetched = futures::stream::iter(urls)
+        .map(|url| {
+            let http = &http;
+            async move { (url, fetch(http, url).await) }
+        })
+        .buffered(options.concurrency.max(1));
+    while let Some((url, result)) = fetched.next().await {
+        match result {
+            // Feeds that can't be read are left out rather than losing the digest
+            Err(error) => diagnostics::warn(
+                "feed",
+                format!("skipping {}: {:#}", url, error),
+                serde_json::json!({"url": url}),
+            ),
+            Ok(feed) => items.extend(
+                feed.into_iter()
+                    .filter(|item| item.published.is_some_and(|published| published >= since)),
+            ),
+        }
+    }
+    if items.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let topics = group(client, &items).await?;
+    futures::stream::iter(topics)
+        .map(|(topic, members)| {
+            let items: Vec<FeedItem> = members.iter().map(|&index| items[index].clone()).collect();
+            async move {
+                let text = items
+                    .iter()
+                    .map(|item| format!("{}\n{}", item.title, item.text))
+                    .collect::<Vec<_>>()
+                    .join("\n\n");
+                let instructions = format!(
+                    "The text is a set of feed items about {}. Summarize what is new \
+                     in a few Markdown bullet points.",
+                    topic
+                );
+                let summary = client
+                    .summarize(&text, Some(&instructions), options)
+                    .await?;
+                Ok(Section {
+                    topic,
+                    summary,
+                    items,
+                })
+            }
+        })
+        .buffered(options.concurrency.max(1))
+        .try_collect()
+        .await
+}
+
+/// Group `items` into topics, as indices into `items`
+async fn group(client: &Client, items: &[FeedItem]) -> Result<Vec<(String, Vec<usize>)>> {
+    let list = items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| {
+            let preview: String = item.text.chars().take(PREVIEW_CHARS).collect();
+            format!("{}. {} — {}", index + 1, item.title, preview)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let system = "Group the numbered feed items into topics of closely related items. \
+                  Unrelated items may form topics of their own."
+        .to_string();
+    let topics: Topics = client
+        .send_structured(Messages::new().push_user(list).clone(), Some(system), 3)
+        .await
+        .context("grouping items into topics")?;
+
+    // Keep each item once, in the first topic naming it, and collect the rest
+    let mut assigned = vec![false; items.len()];
+    let mut groups = Vec::new();
+    for topic in topics.topics {
+        let members: Vec<usize> = topic
+            .items
+            .into_iter()
+            .filter_map(|number| number.checked_sub(1))
+            .filter(|&index| index < items.len() && !std::mem::replace(&mut assigned[index], true))
+            .collect();
+        if !members.is_empty() {
+            groups.push((topic.topic, members));
+        }
+    }
+    let rest: Vec<usize> = (0..items.len()).filter(|&index| !assigned[index]).collect();
+    if !rest.is_empty() {
+        groups.push(("Other".to_string(), rest));
+    }
+    Ok(groups)
+}
+
+/// The digest as Markdown
+pub fn markdown(sections: &[Section], since: DateTime<Utc>) -> String {
+    let mut out = format!("# Digest since {}\n", since.format("%Y-%m-%d %H:%M UTC"));
+    if sections.is_empty() {
+        out.push_str("\nNo new items.\n");
+    }
+    for section in sections {
+        out.push_str(&format!(
+            "\n## {}\n\n{}\n\n",
+            section.topic,
+            section.summary.trim()
+        ));
+        for item in &section.items {
+            match &item.link {
+                Some(link) => out.push_str(&format!("- [{}]({})\n", item.title, link)),
+                None => out.push_str(&format!("- {}\n", item.title)),
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RSS: &str = r#"<?xml version="1.0"?>
+<rss version="2.0"><channel><title>News</title>
+<item>
+  <title>Rust 1.90 &amp; more</title>
+  <link>https://example.com/rust</link>
+  <pubDate>Tue, 14 Oct 2025 10:00:00 +0000</pubDate>
+  <description><![CDATA[<p>The <b>new</b> release.</p>]]></description>
+</item>
+<item><title>Undated</title><description>No date</description></item>
+</channel></rss>"#;
+
+    const ATOM: &str = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+<entry>
+  <title type="html">Atom entry</title>
+  <link rel="self" href="https://example.com/self"/>
+  <link href="https://example.com/entry"/>
+  <updated>2025-10-15T08:30:00Z</updated>
+  <summary>Short summary</summary>
+</entry>
+</feed>"#;
+
+    #[test]
+    fn test_parse_feed() {
+        let items = parse_feed(RSS);
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].title, "Rust 1.90 & more");
+        assert_eq!(items[0].link.as_deref(), Some("https://example.com/rust"));
+        assert_eq!(
+            items[0].published,
+            Some("2025-10-14T10:00:00Z".parse().unwrap())
+        );
+        assert_eq!(items[0].text, "The new release.");
+        assert_eq!(items[1].published, None);
+
+        let items = parse_feed(ATOM);
+        assert_eq!(
+            items,
+            [FeedItem {
+                title: "Atom entry".into(),
+                link: Some("https://example.com/entry".into()),
+                published: Some("2025-10-15T08:30:00Z".parse().unwrap()),
+                text: "Short summary".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_since() {
+        assert_eq!(parse_since("24h").unwrap(), chrono::Duration::hours(24));
+        assert_eq!(parse_since("7d").unwrap(), chrono::Duration::days(7));
+        assert_eq!(parse_since("2w").unwrap(), chrono::Duration::weeks(2));
+        assert!(parse_since("24").is_err());
+        assert!(parse_since("h").is_err());
+        assert!(parse_since("").is_err());
+    }
+
+    #[test]
+    fn test_feed_urls() {
+        assert_eq!(
+            feed_urls("# news\nhttps://a.example/rss\n\nhttps://b.example/atom # blog\n"),
+            ["https://a.example/rss", "https://b.example/atom"]
+        );
+    }
+
+    #[test]
+    fn test_markdown() {
+        let since = "2025-10-14T00:00:00Z".parse().unwrap();
+        let sections = vec![Section {
+            topic: "Rust".into(),
+            summary: "- A new release\n".into(),
+            items: parse_feed(RSS)[..1].to_vec(),
+        }];
+        assert_eq!(
+            markdown(&sections, since),
+            "# Digest since 2025-10-14 00:00 UTC\n\n## Rust\n\n- A new release\n\n\
+             - [Rust 1.90 & more](https://example.com/rust)\n"
+        );
+        assert!(markdown(&[], since).ends_with("No new items.\n"));
+    }
+}
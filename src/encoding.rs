@@ -0,0 +1,163 @@
+//! Decoding of text files that may not be UTF-8, and recognizing the binary
+//! files that can be attached instead
+//!
+//! Files are decoded by their byte order mark when they have one. Otherwise
+//! UTF-8 is tried first, then UTF-16 without a mark, recognized by the zero
+//! bytes of mostly-ASCII text, and finally Latin-1, which any bytes decode
+//! as. Files with zero bytes or many control characters are taken to be
+//! binary instead.
+
+use std::fmt;
+
+/// Bytes examined to tell text from binary data
+const SAMPLE_BYTES: usize = 8192;
+
+/// The encoding a text file was decoded from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Encoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Latin1 => "Latin-1",
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Decode `bytes` as text, with the encoding used, or `None` if they look binary
+pub fn decode(bytes: &[u8]) -> Option<(String, Encoding)> {
+    if let Some(rest) = bytes.strip_prefix(b"\xEF\xBB\xBF") {
+        return Some((String::from_utf8_lossy(rest).into_owned(), Encoding::Utf8));
+    }
+    if let Some(rest) = bytes.strip_prefix(b"\xFF\xFE") {
+        return Some((utf16(rest, u16::from_le_bytes), Encoding::Utf16Le));
+    }
+    if let Some(rest) = bytes.strip_prefix(b"\xFE\xFF") {
+        return Some((utf16(rest, u16::from_be_bytes), Encoding::Utf16Be));
+    }
+
+    let sample = &bytes[..bytes.len().min(SAMPLE_BYTES)];
+    // Mostly-ASCII UTF-16 has a zero byte in nearly every pair, on one side
+    let zeros = |offset: usize| {
+        sample
+            .iter()
+            .skip(offset)
+            .step_by(2)
+            .filter(|byte| **byte == 0)
+            .count()
+    };
+    let pairs = sample.len() / 2;
+    if pairs > 0 && bytes.len().is_multiple_of(2) {
+        if zeros(1) * 10 >= pairs * 9 && zeros(0) * 10 < pairs {
+            return Some((utf16(bytes, u16::from_le_bytes), Encoding::Utf16Le));
+        }
+        if zeros(0) * 10 >= pairs * 9 && zeros(1) * 10 < pairs {
+            return Some((utf16(bytes, u16::from_be_bytes), Encoding::Utf16Be));
+        }
+    }
+
+    let controls = sample
+        .iter()
+        .filter(|byte| byte.is_ascii_control() && !b"\t\n\r\x0C\x1B".contains(byte))
+        .count();
+    if sample.contains(&0) || controls * 10 > sample.len() {
+        return None;
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Some((text.to_string(), Encoding::Utf8)),
+        Err(_) => Some((
+            bytes.iter().map(|byte| char::from(*byte)).collect(),
+            Encoding::Latin1,
+        )),
+    }
+}
+
+/// The media type of an image or PDF the API accepts, from its leading bytes
+pub fn media_type(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [b'G', b'I', b'F', b'8', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        [b'%', b'P', b'D', b'F', b'-', ..] => Some("application/pdf"),
+        _ => None,
+    }
+}
+
+fn utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| unit([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_utf8() {
+        assert_eq!(
+            decode("Grüße\n".as_bytes()),
+            Some(("Grüße\n".to_string(), Encoding::Utf8))
+        );
+        assert_eq!(
+            decode(b"\xEF\xBB\xBFhi"),
+            Some(("hi".to_string(), Encoding::Utf8))
+        );
+        assert_eq!(decode(b""), Some((String::new(), Encoding::Utf8)));
+    }
+
+    #[test]
+    fn test_utf16() {
+        let le: Vec<u8> = "Grüße".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let be: Vec<u8> = "Grüße".encode_utf16().flat_map(u16::to_be_bytes).collect();
+        assert_eq!(decode(&le), Some(("Grüße".to_string(), Encoding::Utf16Le)));
+        assert_eq!(decode(&be), Some(("Grüße".to_string(), Encoding::Utf16Be)));
+
+        let mut marked = vec![0xFF, 0xFE];
+        marked.extend(&le);
+        assert_eq!(
+            decode(&marked),
+            Some(("Grüße".to_string(), Encoding::Utf16Le))
+        );
+    }
+
+    #[test]
+    fn test_latin1() {
+        assert_eq!(
+            decode(b"caf\xE9 cr\xE8me"),
+            Some(("café crème".to_string(), Encoding::Latin1))
+        );
+    }
+
+    #[test]
+    fn test_media_type() {
+        assert_eq!(media_type(b"\x89PNG\r\n\x1A\n"), Some("image/png"));
+        assert_eq!(media_type(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(media_type(b"%PDF-1.7\n"), Some("application/pdf"));
+        assert_eq!(media_type(b"RIFF\0\0\0\0WAVEfmt "), None);
+        assert_eq!(media_type(b"%PDF"), None);
+    }
+
+    #[test]
+    fn test_binary() {
+        assert_eq!(decode(b"\x89PNG\r\n\x1A\n\x00\x00\x00\rIHDR"), None);
+        assert_eq!(decode(&[0x01, 0x02, 0x03, b'a', 0x04]), None);
+    }
+}
@@ -25,6 +25,117 @@ pub enum ClaudeError {
 
     #[error("Non-boolean response: {0}")]
     Bool(String),
+
+    /// Structured output could not be obtained within the retry budget
+    #[error("Structured output error: {0}")]
+    StructuredOutput(String),
+
+    /// A response broke its constraints within the retry budget, or a
+    /// constraint spec was invalid
+    #[error("Constraint error: {0}")]
+    Constraint(String),
+
+    /// Translation errors
+    #[error("Translation error: {0}")]
+    Translate(String),
+
+    /// The model refused to respond under the strict safety preset
+    #[error("The model declined to respond, which the strict safety preset treats as an error")]
+    Refused,
+
+    /// A guardrail blocked the prompt or response
+    #[error("Blocked by guardrail: {0}")]
+    Guardrail(String),
+
+    /// Redaction configuration errors
+    #[error("Redaction error: {0}")]
+    Redaction(String),
+
+    /// Errors talking to or reported by the background daemon
+    #[error("Daemon error: {0}")]
+    Daemon(String),
+
+    /// A network failure the daemon hit while relaying a request
+    #[error("Network error (via daemon): {message}")]
+    RelayedNetwork {
+        message: String,
+        /// Whether the request may have reached the API
+        sent: bool,
+    },
+
+    /// Errors reading or writing the local usage ledger
+    #[error("Usage ledger error: {0}")]
+    Ledger(String),
+
+    /// Errors reading or writing the local statistics
+    #[error("Statistics error: {0}")]
+    Stats(String),
+
+    /// Invalid evaluation cases
+    #[error("Evaluation error: {0}")]
+    Eval(String),
+
+    /// Errors reading or writing saved snippets
+    #[error("Snippet error: {0}")]
+    Snippet(String),
+
+    /// Regex generation errors
+    #[error("Regex generation error: {0}")]
+    Regex(String),
+
+    /// Completion notifications that could not be delivered
+    #[error("Notification error: {0}")]
+    Notify(String),
+
+    /// Response post-processing errors
+    #[error("Post-processing error: {0}")]
+    PostProcess(String),
+
+    /// Invalid Message Batches requests or results
+    #[error("Batch error: {0}")]
+    Batch(String),
+
+    /// The tool loop could not reach a final answer
+    #[error("Tool error: {0}")]
+    Tool(String),
+
+    /// A table couldn't be parsed, or a query over it couldn't run
+    #[error("Table error: {0}")]
+    Table(String),
+
+    /// Fixtures couldn't be recorded or read, or none matched a request
+    #[error("Fixture error: {0}")]
+    Fixture(String),
+
+    /// A conversation couldn't be read from another tool's format
+    #[error("Conversation error: {0}")]
+    Conversation(String),
+
+    /// An image couldn't be decoded, or scaled down to fit the API's limits
+    #[error("Image error: {0}")]
+    Image(String),
+
+    /// The API doesn't know the model, e.g. because it was retired
+    #[error(
+        "Model {model} was not found{}",
+        suggestion.as_ref().map(|model| format!("; its closest current model is {} (set auto_migrate to switch automatically)", model)).unwrap_or_default()
+    )]
+    ModelNotFound {
+        model: String,
+        suggestion: Option<String>,
+    },
+
+    /// The request uses a feature the model doesn't support
+    #[error("Unsupported by the model: {0}")]
+    Unsupported(String),
+
+    /// The prompt and `max_tokens` together don't fit the model's context window
+    #[error("Context window exceeded: the request needs {have} tokens (input plus max_tokens) but the model allows {limit}; truncate or compact the conversation, or lower max_tokens")]
+    ContextTooLarge { have: u64, limit: u64 },
+
+    /// The response was cut off by `max_tokens` before it was complete
+    #[error("Response cut off at max_tokens after {output_tokens} output tokens; raise max_tokens to get all of it")]
+    Truncated { output_tokens: u64 },
 }
 
 /// Configuration-specific errors
@@ -33,14 +144,29 @@ pub enum ConfigError {
     #[error("API key not found. Please set ANTHROPIC_API_KEY environment variable, provide --api-key argument, or create a config file at ~/.config/ellm/config.toml")]
     ApiKeyNotFound,
 
+    #[error("Admin API key not found. Set the ANTHROPIC_ADMIN_KEY environment variable or pass --admin-key")]
+    AdminKeyNotFound,
+
     #[error("Invalid API key format")]
     InvalidApiKey,
 
+    #[error("Invalid thinking budget: {0}")]
+    InvalidThinkingBudget(String),
+
     #[error("Failed to parse config file: {0}")]
     ParseError(String),
 
     #[error("Config file not found at: {0}")]
     FileNotFound(String),
+
+    #[error("Policy violation: {0}")]
+    PolicyViolation(String),
+
+    #[error("Provider error: {0}")]
+    Provider(String),
+
+    #[error("Invalid TLS settings: {0}")]
+    Tls(String),
 }
 
 /// API-specific errors
@@ -55,11 +181,93 @@ pub enum ApiError {
     #[error("Rate limit exceeded")]
     RateLimitExceeded,
 
+    /// The API is temporarily overloaded (HTTP 529); another model may still be available
+    #[error("API overloaded: {0}")]
+    Overloaded(String),
+
     #[error("API returned error {status}: {message}")]
     ApiError { status: u16, message: String },
 
     #[error("Unexpected response format: {0}")]
     UnexpectedResponse(String),
+
+    /// The request was sent but failed before a response arrived, so it was not retried
+    #[error("Request {idempotency_key} may or may not have been processed: {message}")]
+    OutcomeUnknown {
+        idempotency_key: String,
+        message: String,
+    },
+}
+
+/// Broad categories of failure that scripts can branch on
+///
+/// Each has a fixed process exit code. Codes 0 to 2 are left to answers
+/// (`ellm bool`), so no failure uses them, and 130 to interruption.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Anything not covered below
+    Other,
+    /// The command line was invalid
+    Usage,
+    /// The API key is missing, malformed or rejected
+    Auth,
+    /// Rate limited or overloaded; worth retrying later
+    RateLimited,
+    /// The prompt doesn't fit the model's context window
+    ContextTooLarge,
+    /// The model's output never matched the requested schema
+    Schema,
+    /// The API could not be reached, or the connection failed
+    Network,
+}
+
+impl ErrorKind {
+    pub fn exit_code(self) -> u8 {
+        match self {
+            ErrorKind::Auth => 3,
+            ErrorKind::RateLimited => 4,
+            ErrorKind::ContextTooLarge => 5,
+            ErrorKind::Schema => 6,
+            ErrorKind::Network => 7,
+            ErrorKind::Other => 8,
+            ErrorKind::Usage => 9,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorKind::Other => "error",
+            ErrorKind::Usage => "usage",
+            ErrorKind::Auth => "auth",
+            ErrorKind::RateLimited => "rate_limited",
+            ErrorKind::ContextTooLarge => "context_too_large",
+            ErrorKind::Schema => "schema",
+            ErrorKind::Network => "network",
+        }
+    }
+}
+
+impl ClaudeError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ClaudeError::Config(
+                ConfigError::ApiKeyNotFound
+                | ConfigError::AdminKeyNotFound
+                | ConfigError::InvalidApiKey,
+            )
+            | ClaudeError::Api(ApiError::AuthenticationFailed(_))
+            | ClaudeError::Api(ApiError::ApiError { status: 403, .. }) => ErrorKind::Auth,
+            ClaudeError::Api(ApiError::RateLimitExceeded | ApiError::Overloaded(_)) => {
+                ErrorKind::RateLimited
+            }
+            ClaudeError::ContextTooLarge { .. } => ErrorKind::ContextTooLarge,
+            ClaudeError::StructuredOutput(_) | ClaudeError::Constraint(_) => ErrorKind::Schema,
+            ClaudeError::Network(_)
+            | ClaudeError::RelayedNetwork { .. }
+            | ClaudeError::Api(ApiError::OutcomeUnknown { .. }) => ErrorKind::Network,
+            _ => ErrorKind::Other,
+        }
+    }
 }
 
 /// Type alias for Results using ClaudeError
@@ -85,6 +293,28 @@ mod tests {
         assert!(err.to_string().contains("Unauthorized"));
     }
 
+    #[test]
+    fn test_error_kind() {
+        let kind = |error: ClaudeError| error.kind();
+        assert_eq!(kind(ConfigError::ApiKeyNotFound.into()), ErrorKind::Auth);
+        assert_eq!(
+            kind(ApiError::RateLimitExceeded.into()),
+            ErrorKind::RateLimited
+        );
+        assert_eq!(
+            kind(ClaudeError::ContextTooLarge { have: 2, limit: 1 }),
+            ErrorKind::ContextTooLarge
+        );
+        assert_eq!(
+            kind(ClaudeError::StructuredOutput("no".into())),
+            ErrorKind::Schema
+        );
+        assert_eq!(kind(ClaudeError::Batch("no".into())), ErrorKind::Other);
+        assert_eq!(ErrorKind::RateLimited.exit_code(), 4);
+        assert_eq!(ErrorKind::Other.exit_code(), 8);
+        assert_eq!(ErrorKind::ContextTooLarge.as_str(), "context_too_large");
+    }
+
     #[test]
     fn test_config_error_from() {
         let config_err = ConfigError::ApiKeyNotFound;
@@ -0,0 +1,281 @@
+use crate::client::{Client, Messages};
+use crate::error::{ClaudeError, Result};
+use serde::Serialize;
+use std::str::FromStr;
+
+/// How [`Client::fan_out`] combines the answers of several models
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Aggregator {
+    /// Every answer, under a heading naming its model
+    Concatenate,
+    /// The most common answer, compared ignoring case, surrounding whitespace
+    /// and a final period; ties go to the model listed first
+    Vote,
+    /// One answer written by `model` (the client's own when `None`) from all of them
+    Synthesize { model: Option<String> },
+}
+
+impl FromStr for Aggregator {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "concatenate" => Ok(Aggregator::Concatenate),
+            "vote" => Ok(Aggregator::Vote),
+            "synthesize" => Ok(Aggregator::Synthesize { model: None }),
+            _ => Err(format!(
+                "unknown aggregator {:?}; use concatenate, vote or synthesize",
+                s
+            )),
+        }
+    }
+}
+
+/// One model's answer, or why it has none
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ModelAnswer {
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub answer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The result of [`Client::fan_out`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FanOut {
+    /// The aggregated answer
+    pub answer: String,
+    /// Each model's answer, in the order the models were given
+    pub answers: Vec<ModelAnswer>,
+}
+
+/// The key answers are compared by when voting
+fn normalize(answer: &str) -> String {
+    answer.trim().trim_end_matches('.').trim().to_lowercase()
+}
+
+/// The most common of `answers`, preferring the earliest on a tie
+fn vote<'a>(answers: &[&'a str]) -> Option<&'a str> {
+    let keys: Vec<String> = answers.iter().map(|answer| normalize(answer)).collect();
+    let count = |key: &String| keys.iter().filter(|other| *other == key).count();
+    // max_by_key keeps the last maximum, so search from the end
+    keys.iter()
+        .enumerate()
+        .rev()
+        .max_by_key(|(_, key)| count(key))
+        .map(|(index, _)| answers[index])
+}
+
+impl Client {
+    /// Ask several models the same question at once and combine their answers
+    ///
+    /// Models that fail are reported in [`FanOut::answers`] and left out of
+    /// the aggregate; it's an error only if every model fails.
+    pub async fn fan_out(
+        &self,
+        messages: Messages,
+        system: Option<String>,
+        models: &[String],
+        aggregator: Aggregator,
+    ) -> Result<FanOut> {
+        if models.is_empty() {
+            return Err(ClaudeError::Unsupported(
+                "fan out needs at least one model".into(),
+            ));
+        }
+
+        let requests = models.iter().map(|model| {
+            let messages = messages.clone();
+            let system = system.clone();
+            async move {
+                self.for_model(model.as_str())?
+                    .send_message(messages, None, system)
+                    .await
+            }
+        });
+        let results = futures::future::join_all(requests).await;
+
+        let mut answers = Vec::with_capacity(models.len());
+        let mut first_error = None;
+        for (model, result) in models.iter().zip(results) {
+            let (answer, error) = match result {
+                Ok(answer) => (Some(answer), None),
+                Err(error) => {
+                    let message = error.to_string();
+                    first_error.get_or_insert(error);
+                    (None, Some(message))
+                }
+            };
+            answers.push(ModelAnswer {
+                model: model.clone(),
+                answer,
+                error,
+            });
+        }
+        let answered: Vec<(&str, &str)> = answers
+            .iter()
+            .filter_map(|a| Some((a.model.as_str(), a.answer.as_deref()?)))
+            .collect();
+        if answered.is_empty() {
+            return Err(first_error.expect("a failure for every model"));
+        }
+
+        let answer = match aggregator {
+            Aggregator::Concatenate => answered
+                .iter()
+                .map(|(model, answer)| format!("## {}\n\n{}", model, answer.trim()))
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            Aggregator::Vote => {
+                let texts: Vec<&str> = answered.iter().map(|(_, answer)| *answer).collect();
+                vote(&texts).unwrap_or_default().trim().to_string()
+            }
+            Aggregator::Synthesize { model } => {
+                let candidates = answered
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (_, answer))| {
+                        format!(
+                            "<answer id=\"{}\">\n{}\n</answer>",
+                            index + 1,
+                            answer.trim()
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                // Consecutive user turns are merged, so this follows the question
+                let mut synthesis = messages;
+                synthesis.push_user(format!(
+                    "Several assistants answered the above:\n{}\n\nWrite the single best \
+                     answer, using what the answers get right and resolving where they \
+                     disagree. Reply with the answer only.",
+                    candidates
+                ));
+                let synthesizer = match model {
+                    Some(model) => self.for_model(model)?,
+                    None => self.for_model(self.config().model.clone())?,
+                };
+                synthesizer.send_message(synthesis, None, system).await?
+            }
+        };
+
+        Ok(FanOut { answer, answers })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::{http_stub, stub_client};
+
+    #[test]
+    fn test_vote() {
+        assert_eq!(vote(&["Paris", "paris.", "Lyon"]), Some("Paris"));
+        assert_eq!(vote(&["No", "Yes", "yes", "no"]), Some("No"));
+        assert_eq!(vote(&[]), None);
+    }
+
+    #[test]
+    fn test_parse_aggregator() {
+        assert_eq!("vote".parse(), Ok(Aggregator::Vote));
+        assert_eq!(
+            "synthesize".parse(),
+            Ok(Aggregator::Synthesize { model: None })
+        );
+        assert!("average".parse::<Aggregator>().is_err());
+    }
+
+    fn reply(text: &str) -> &'static str {
+        Box::leak(
+            serde_json::json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-haiku-4-5",
+                "content": [{"type": "text", "text": text}],
+                "stop_reason": "end_turn",
+                "usage": {"input_tokens": 1, "output_tokens": 1},
+            })
+            .to_string()
+            .into_boxed_str(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_fan_out_synthesize() {
+        let (url, requests) = http_stub(vec![
+            Some((200, reply("Paris"))),
+            Some((200, reply("Paris"))),
+            Some((200, reply("It is Paris."))),
+        ])
+        .await;
+        let client = stub_client(url);
+        let models = vec![
+            "claude-haiku-4-5".to_string(),
+            "claude-opus-4-1".to_string(),
+        ];
+
+        let fan_out = client
+            .fan_out(
+                Messages::new()
+                    .push_user("Capital of France?".into())
+                    .clone(),
+                None,
+                &models,
+                Aggregator::Synthesize { model: None },
+            )
+            .await
+            .unwrap();
+        assert_eq!(fan_out.answer, "It is Paris.");
+        assert_eq!(fan_out.answers.len(), 2);
+        assert_eq!(fan_out.answers[1].model, "claude-opus-4-1");
+        assert_eq!(fan_out.answers[1].answer.as_deref(), Some("Paris"));
+
+        let requests = requests.lock().unwrap();
+        let asked: Vec<bool> = models
+            .iter()
+            .map(|model| {
+                requests[..2]
+                    .iter()
+                    .any(|request| request.contains(&format!(r#""model":"{}""#, model)))
+            })
+            .collect();
+        assert_eq!(asked, [true, true]);
+        assert!(requests[2].contains("capital of france?"));
+        assert!(requests[2].contains(r#"<answer id=\"2\">\nparis"#));
+    }
+
+    #[tokio::test]
+    async fn test_fan_out_keeps_partial_failures() {
+        let (url, _) = http_stub(vec![
+            Some((
+                400,
+                r#"{"type":"error","error":{"type":"invalid_request_error","message":"bad"}}"#,
+            )),
+            Some((200, reply("yes"))),
+        ])
+        .await;
+        let client = stub_client(url);
+        let models = vec!["a".to_string(), "b".to_string()];
+
+        let fan_out = client
+            .fan_out(
+                Messages::new().push_user("Ok?".into()).clone(),
+                None,
+                &models,
+                Aggregator::Vote,
+            )
+            .await
+            .unwrap();
+        assert_eq!(fan_out.answer, "yes");
+        assert_eq!(
+            fan_out
+                .answers
+                .iter()
+                .filter(|answer| answer.error.is_some())
+                .count(),
+            1
+        );
+    }
+}
@@ -0,0 +1,339 @@
+//! Recorded API exchanges for deterministic tests
+//!
+//! A [`Recorder`] is middleware that saves every request a client makes,
+//! with its response, as a numbered JSON file in a directory. The host and
+//! headers are left out, API keys among them, and secrets and personal data
+//! in the bodies are masked, so the files can be committed. A [`Replayer`]
+//! answers requests from such a directory without touching the network,
+//! standing in for the API in the tests of code built on the client.
+//!
+//! ```no_run
+//! use ellm::fixtures::Replayer;
+//! use ellm::{Client, Config, Messages};
+//!
+//! # async fn example() -> ellm::Result<()> {
+//! let client = Client::new(Config::new("unused"))?
+//!     .with_middleware(Replayer::load("tests/fixtures/greeting")?);
+//! let text = client
+//!     .send_message(Messages::new().push_user("Hello".into()).clone(), None, None)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::error::{ClaudeError, Result};
+use crate::middleware::{Middleware, Next, Request, Response};
+use crate::redact::Redactor;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// One request and the response the API gave it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Fixture {
+    pub request: FixtureRequest,
+    pub response: FixtureResponse,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FixtureRequest {
+    pub method: String,
+    /// The URL without the base URL, e.g. `v1/messages`
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FixtureResponse {
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// The body as JSON, or as a string when it isn't JSON, such as the
+    /// events of a streamed response
+    pub body: Value,
+}
+
+impl Fixture {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        serde_json::from_str(&text).map_err(|e| {
+            ClaudeError::Fixture(format!("{} is not a fixture: {}", path.display(), e))
+        })
+    }
+
+    fn response(&self) -> Response {
+        let headers = self
+            .response
+            .content_type
+            .iter()
+            .map(|content_type| ("content-type".to_string(), content_type.clone()))
+            .collect();
+        let body = match &self.response.body {
+            Value::String(text) => text.clone(),
+            body => body.to_string(),
+        };
+        Response::new(self.response.status, headers, body)
+    }
+}
+
+/// The part of `url` after its host, e.g. `v1/messages`
+fn path(url: &str) -> String {
+    let after_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    match after_scheme.split_once('/') {
+        Some((_, path)) => path.to_string(),
+        None => String::new(),
+    }
+}
+
+/// `value` with every string in it redacted
+fn redact_json(redactor: &Redactor, value: &Value) -> Value {
+    match value {
+        Value::String(text) => Value::String(redactor.redact(text).0),
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| redact_json(redactor, item))
+                .collect(),
+        ),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, value)| (key.clone(), redact_json(redactor, value)))
+                .collect(),
+        ),
+        value => value.clone(),
+    }
+}
+
+fn fixture_request(request: &Request, redactor: &Redactor) -> FixtureRequest {
+    FixtureRequest {
+        method: request.method.to_string(),
+        path: path(&request.url),
+        body: request
+            .body
+            .as_ref()
+            .map(|body| redact_json(redactor, body)),
+    }
+}
+
+/// Middleware that saves each request and its response to a directory
+///
+/// Streamed responses are read to the end before they are passed on, so
+/// they arrive all at once while recording.
+pub struct Recorder {
+    dir: PathBuf,
+    redactor: Redactor,
+    count: AtomicUsize,
+}
+
+impl Recorder {
+    /// Record into `dir`, creating it if needed, numbering the files after
+    /// any fixtures already there
+    pub fn new(dir: impl Into<PathBuf>, redactor: Redactor) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        let count = fixture_paths(&dir)?.len();
+        Ok(Self {
+            dir,
+            redactor,
+            count: AtomicUsize::new(count),
+        })
+    }
+
+    fn save(&self, fixture: &Fixture) -> Result<PathBuf> {
+        let number = self.count.fetch_add(1, Ordering::SeqCst) + 1;
+        let slug = fixture.request.path.replace(['/', '?', '&', '='], "-");
+        let path = self.dir.join(format!("{:04}-{}.json", number, slug));
+        std::fs::write(&path, serde_json::to_string_pretty(fixture)? + "\n")?;
+        Ok(path)
+    }
+}
+
+impl Middleware for Recorder {
+    fn handle<'a>(&'a self, request: Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let recorded = fixture_request(&request, &self.redactor);
+            let response = next.run(request).await?;
+            let (status, headers) = (response.status, response.headers.clone());
+            let content_type = response.header("content-type").map(str::to_string);
+            let text = response.into_text().await?;
+
+            let body = match serde_json::from_str(&text) {
+                Ok(json) => redact_json(&self.redactor, &json),
+                Err(_) => Value::String(self.redactor.redact(&text).0),
+            };
+            self.save(&Fixture {
+                request: recorded,
+                response: FixtureResponse {
+                    status,
+                    content_type,
+                    body,
+                },
+            })?;
+            Ok(Response::new(status, headers, text))
+        })
+    }
+}
+
+/// Middleware that answers requests from recorded fixtures, never sending them
+///
+/// A request is answered by a fixture of the same method, path and body,
+/// compared after masking it as it was masked when recorded. When several
+/// fixtures match, they answer in the order they were recorded, and the
+/// last keeps answering once all have been used. A request no fixture
+/// matches fails.
+pub struct Replayer {
+    dir: PathBuf,
+    fixtures: Vec<Fixture>,
+    redactor: Redactor,
+    used: Mutex<Vec<bool>>,
+}
+
+impl Replayer {
+    /// Load the fixtures in `dir`, masking requests with the built-in patterns
+    pub fn load(dir: impl Into<PathBuf>) -> Result<Self> {
+        Self::with_redactor(dir, Redactor::new(&Default::default())?)
+    }
+
+    /// Load the fixtures in `dir`, masking requests with `redactor`, which
+    /// should be the one they were recorded with
+    pub fn with_redactor(dir: impl Into<PathBuf>, redactor: Redactor) -> Result<Self> {
+        let dir = dir.into();
+        let fixtures = fixture_paths(&dir)?
+            .iter()
+            .map(|path| Fixture::load(path))
+            .collect::<Result<Vec<_>>>()?;
+        if fixtures.is_empty() {
+            return Err(ClaudeError::Fixture(format!(
+                "no fixtures in {}",
+                dir.display()
+            )));
+        }
+        Ok(Self {
+            dir,
+            used: Mutex::new(vec![false; fixtures.len()]),
+            fixtures,
+            redactor,
+        })
+    }
+
+    fn answer(&self, request: &Request) -> Result<Response> {
+        let wanted = fixture_request(request, &self.redactor);
+        let matching: Vec<usize> = (0..self.fixtures.len())
+            .filter(|&index| self.fixtures[index].request == wanted)
+            .collect();
+        let mut used = self.used.lock().unwrap_or_else(|e| e.into_inner());
+        let index = matching
+            .iter()
+            .copied()
+            .find(|&index| !used[index])
+            .or(matching.last().copied())
+            .ok_or_else(|| {
+                ClaudeError::Fixture(format!(
+                    "no fixture in {} matches the {} request to {}; record it again",
+                    self.dir.display(),
+                    wanted.method,
+                    wanted.path
+                ))
+            })?;
+        used[index] = true;
+        Ok(self.fixtures[index].response())
+    }
+}
+
+impl Middleware for Replayer {
+    fn handle<'a>(&'a self, request: Request, _next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move { self.answer(&request) })
+    }
+}
+
+/// The fixture files in `dir`, in the order they were recorded
+fn fixture_paths(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::{http_stub, stub_client, REPLY};
+    use crate::Messages;
+
+    fn ask(text: &str) -> Messages {
+        Messages::new().push_user(text.into()).clone()
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let dir = std::env::temp_dir().join(format!("ellm-fixtures-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let (url, _requests) = http_stub(vec![Some((200, REPLY))]).await;
+        let redactor = Redactor::new(&Default::default()).unwrap();
+        let mut config = stub_client(url).config().clone();
+        // Record the prompt as sent, to check the recorder masks it itself
+        config.redaction.enabled = false;
+        let recording = crate::Client::new(config)
+            .unwrap()
+            .with_middleware(Recorder::new(&dir, redactor).unwrap());
+
+        let prompt = "Say hi to ada@example.org";
+        let text = recording
+            .send_message(ask(prompt), None, None)
+            .await
+            .unwrap();
+        assert_eq!(text, "hi");
+
+        let paths = fixture_paths(&dir).unwrap();
+        assert_eq!(paths.len(), 1);
+        assert!(paths[0].ends_with("0001-messages.json"), "{:?}", paths[0]);
+        let saved = std::fs::read_to_string(&paths[0]).unwrap();
+        assert!(saved.contains("Say hi to [REDACTED:email]"));
+        assert!(!saved.contains("sk-ant") && !saved.contains("127.0.0.1"));
+        let fixture = Fixture::load(&paths[0]).unwrap();
+        assert_eq!(fixture.response.status, 200);
+        assert_eq!(fixture.response.body["content"][0]["text"], "hi");
+
+        // Nothing listens at this address, so every answer comes from the fixtures
+        let mut config = crate::Config::new("sk-ant-other-key");
+        config.base_url = "http://127.0.0.1:9".into();
+        config.redaction.enabled = false;
+        let replaying = crate::Client::new(config)
+            .unwrap()
+            .with_middleware(Replayer::load(&dir).unwrap());
+        for _ in 0..2 {
+            let text = replaying
+                .send_message(ask(prompt), None, None)
+                .await
+                .unwrap();
+            assert_eq!(text, "hi");
+        }
+        let error = replaying
+            .send_message(ask("Something else"), None, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ClaudeError::Fixture(_)), "{}", error);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(Replayer::load(&dir).is_err());
+    }
+
+    #[test]
+    fn test_path() {
+        assert_eq!(path("https://api.anthropic.com/v1/messages"), "v1/messages");
+        assert_eq!(
+            path("http://127.0.0.1:8080/gateway/v1/models?limit=5"),
+            "gateway/v1/models?limit=5"
+        );
+        assert_eq!(path("http://localhost"), "");
+    }
+}
@@ -0,0 +1,386 @@
+//! Git integration: commit message hooks and diff explanations
+//!
+//! `ellm git install-hooks` writes `prepare-commit-msg` and `commit-msg`
+//! hooks that call back into `ellm git hook`. In hook mode ellm is time-limited
+//! and silent on failure, and the hooks always exit successfully, so a missing
+//! key, an unreachable API or a slow response never blocks a commit.
+//!
+//! `ellm diff-explain` summarizes a unified diff for reviewers.
+
+use anyhow::{anyhow, Context, Result};
+use ellm::{BoolAnswer, Client, Messages};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// The hooks ellm installs
+pub const HOOKS: &[&str] = &["prepare-commit-msg", "commit-msg"];
+
+/// Marks hook scripts written by ellm, which may be replaced without --force
+const MARKER: &str = "Installed by `ellm git install-hooks`";
+
+/// Diffs are cut to this many bytes before being sent
+const MAX_DIFF_BYTES: usize = 100_000;
+
+/// The script for the hook `name`
+fn hook_script(name: &str) -> String {
+    format!(
+        "#!/bin/sh\n\
+         # {MARKER}; never blocks the commit\n\
+         command -v ellm >/dev/null 2>&1 || exit 0\n\
+         ellm git hook {name} \"$@\" </dev/null\n\
+         exit 0\n"
+    )
+}
+
+/// The hooks directory of the current repository
+pub fn hooks_dir() -> Result<PathBuf> {
+    let path = git(&["rev-parse", "--git-path", "hooks"])?;
+    Ok(PathBuf::from(path.trim()))
+}
+
+/// Write ellm's hooks into `dir`, returning their paths
+///
+/// Hooks that ellm didn't write are only replaced with `force`.
+pub fn install_hooks(dir: &Path, force: bool) -> Result<Vec<PathBuf>> {
+    let paths: Vec<PathBuf> = HOOKS.iter().map(|name| dir.join(name)).collect();
+    if !force {
+        let foreign: Vec<String> = paths
+            .iter()
+            .filter(|path| {
+                std::fs::read_to_string(path).is_ok_and(|script| !script.contains(MARKER))
+            })
+            .map(|path| path.display().to_string())
+            .collect();
+        if !foreign.is_empty() {
+            return Err(anyhow!(
+                "not replacing existing hooks (use --force): {}",
+                foreign.join(", ")
+            ));
+        }
+    }
+
+    std::fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    for (name, path) in HOOKS.iter().zip(&paths) {
+        std::fs::write(path, hook_script(name))
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))?;
+        }
+    }
+    Ok(paths)
+}
+
+/// Run the hook `name` with the arguments git passed it
+pub async fn run_hook(client: &Client, name: &str, args: &[String]) -> Result<()> {
+    let file = args
+        .first()
+        .map(Path::new)
+        .ok_or_else(|| anyhow!("{} needs the commit message file", name))?;
+    match name {
+        "prepare-commit-msg" => prepare_commit_msg(client, file, args.get(1)).await,
+        "commit-msg" => commit_msg(client, file).await,
+        _ => Err(anyhow!("unknown hook: {}", name)),
+    }
+}
+
+/// Draft a message for a plain `git commit` from the staged changes
+///
+/// Commits with a message from -m, a template, a merge, a squash or an
+/// amended commit are left alone.
+async fn prepare_commit_msg(client: &Client, file: &Path, source: Option<&String>) -> Result<()> {
+    if source.is_some() {
+        return Ok(());
+    }
+    let current = std::fs::read_to_string(file)?;
+    if !message_text(&current).is_empty() {
+        return Ok(());
+    }
+    let diff = staged_diff()?;
+    if diff.is_empty() {
+        return Ok(());
+    }
+
+    let system = "You write git commit messages. Reply with only the message: a summary \
+                  line in the imperative mood of at most 72 characters, then, if the change \
+                  needs explaining, a blank line and a short body wrapped at 72 characters."
+        .to_string();
+    let messages = Messages::new()
+        .push_user(format!(
+            "Write a commit message for this staged diff:\n\n{}",
+            diff
+        ))
+        .clone();
+    let message = client.send_message(messages, None, Some(system)).await?;
+
+    std::fs::write(file, with_message(&current, message.trim()))?;
+    Ok(())
+}
+
+/// Warn when the commit message doesn't describe the staged changes
+async fn commit_msg(client: &Client, file: &Path) -> Result<()> {
+    let message = message_text(&std::fs::read_to_string(file)?);
+    let diff = staged_diff()?;
+    if message.is_empty() || diff.is_empty() {
+        return Ok(());
+    }
+
+    let response = client
+        .ask_bool(format!(
+            "Does this commit message accurately describe the diff it commits?\n\n\
+             Commit message:\n{}\n\nDiff:\n{}",
+            message, diff
+        ))
+        .await?;
+    if response.outcome(0.7) == BoolAnswer::False {
+        eprintln!(
+            "ellm: the commit message may not match the change: {}",
+            response.explanation
+        );
+    }
+    Ok(())
+}
+
+/// The message in a commit message file, without git's comment lines
+fn message_text(contents: &str) -> String {
+    contents
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// `contents` of a commit message file with `message` put before git's comments
+fn with_message(contents: &str, message: &str) -> String {
+    let comments = contents.trim_start();
+    if comments.is_empty() {
+        format!("{}\n", message)
+    } else {
+        format!("{}\n\n{}", message, comments)
+    }
+}
+
+/// The staged changes, cut to [`MAX_DIFF_BYTES`]
+fn staged_diff() -> Result<String> {
+    let diff = git(&["diff", "--cached", "--no-color", "--no-ext-diff"])?;
+    Ok(truncated(diff))
+}
+
+/// The changes in a revision range such as `main..HEAD`
+pub fn range_diff(range: &str) -> Result<String> {
+    git(&["diff", "--no-color", "--no-ext-diff", range])
+}
+
+/// `diff` cut to [`MAX_DIFF_BYTES`]
+pub fn truncated(mut diff: String) -> String {
+    if diff.len() > MAX_DIFF_BYTES {
+        let mut end = MAX_DIFF_BYTES;
+        while !diff.is_char_boundary(end) {
+            end -= 1;
+        }
+        diff.truncate(end);
+        diff.push_str("\n[diff truncated]\n");
+    }
+    diff
+}
+
+/// A reviewer's summary of a diff
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DiffExplanation {
+    /// what the change is trying to achieve, in one or two sentences
+    pub intent: String,
+    /// changes in behavior users or callers can observe, one per item
+    pub behavioral_changes: Vec<String>,
+    /// parts of the change most likely to cause bugs or regressions, and why
+    pub risk_areas: Vec<String>,
+    /// tests that should be added or run to verify the change
+    pub suggested_tests: Vec<String>,
+    /// people from the provided list of recent authors best placed to review; empty if none was provided
+    pub suggested_reviewers: Vec<String>,
+}
+
+impl fmt::Display for DiffExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Intent: {}", self.intent)?;
+        for (heading, items) in [
+            ("Behavioral changes", &self.behavioral_changes),
+            ("Risk areas", &self.risk_areas),
+            ("Suggested tests", &self.suggested_tests),
+            ("Suggested reviewers", &self.suggested_reviewers),
+        ] {
+            if !items.is_empty() {
+                writeln!(f, "\n{}:", heading)?;
+                for item in items {
+                    writeln!(f, "- {}", item)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Explain `diff` for reviewers
+///
+/// When run in a repository, the recent authors of the changed files are
+/// offered as candidate reviewers.
+pub async fn explain_diff(client: &Client, diff: &str) -> Result<DiffExplanation> {
+    let mut request = format!(
+        "Explain this diff for reviewers:\n\n{}",
+        truncated(diff.into())
+    );
+    let authors = recent_authors(&changed_files(diff));
+    if !authors.is_empty() {
+        request.push_str(&format!(
+            "\n\nRecent authors of the changed files, most active first:\n{}",
+            authors.join("\n")
+        ));
+    }
+
+    let system = "You review code changes. Describe the change precisely and concisely, \
+                  based only on the diff."
+        .to_string();
+    let messages = Messages::new().push_user(request).clone();
+    Ok(client.send_structured(messages, Some(system), 3).await?)
+}
+
+/// The files a unified diff changes, by their new path (old path for deletions)
+fn changed_files(diff: &str) -> Vec<String> {
+    let mut files = Vec::new();
+    let mut old = None;
+    for line in diff.lines() {
+        if let Some(path) = line.strip_prefix("--- ") {
+            old = path.strip_prefix("a/");
+        } else if let Some(path) = line.strip_prefix("+++ ") {
+            let path = path.strip_prefix("b/").or(old);
+            files.extend(path.map(str::to_string));
+            old = None;
+        }
+    }
+    files.dedup();
+    files
+}
+
+/// The people who most often committed to `files` recently, if in a repository
+fn recent_authors(files: &[String]) -> Vec<String> {
+    if files.is_empty() {
+        return Vec::new();
+    }
+    let mut args = vec!["log", "-n", "100", "--format=%an <%ae>", "--"];
+    args.extend(files.iter().map(String::as_str));
+    let Ok(log) = git(&args) else {
+        return Vec::new();
+    };
+
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for author in log.lines().filter(|line| !line.is_empty()) {
+        *counts.entry(author).or_default() += 1;
+    }
+    let mut authors: Vec<(&str, usize)> = counts.into_iter().collect();
+    authors.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    authors
+        .into_iter()
+        .take(5)
+        .map(|(author, _)| author.to_string())
+        .collect()
+}
+
+pub fn git(args: &[&str]) -> Result<String> {
+    let output = std::process::Command::new("git")
+        .args(args)
+        .output()
+        .context("failed to run git")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_install_hooks() {
+        let dir = std::env::temp_dir().join(format!("ellm-hooks-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let paths = install_hooks(&dir, false).unwrap();
+        assert_eq!(
+            paths,
+            [dir.join("prepare-commit-msg"), dir.join("commit-msg")]
+        );
+        let script = std::fs::read_to_string(&paths[1]).unwrap();
+        assert!(script.starts_with("#!/bin/sh\n"));
+        assert!(script.contains("ellm git hook commit-msg \"$@\""));
+        assert!(script.ends_with("exit 0\n"));
+
+        // Reinstalling replaces ellm's own hooks but not others
+        install_hooks(&dir, false).unwrap();
+        std::fs::write(&paths[0], "#!/bin/sh\nmy-hook\n").unwrap();
+        assert!(install_hooks(&dir, false).is_err());
+        install_hooks(&dir, true).unwrap();
+        assert!(std::fs::read_to_string(&paths[0]).unwrap().contains(MARKER));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_changed_files() {
+        let diff = "diff --git a/src/lib.rs b/src/lib.rs\n\
+                    --- a/src/lib.rs\n\
+                    +++ b/src/lib.rs\n\
+                    @@ -1 +1 @@\n\
+                    -old\n\
+                    +new\n\
+                    diff --git a/gone.rs b/gone.rs\n\
+                    --- a/gone.rs\n\
+                    +++ /dev/null\n\
+                    diff --git a/new.rs b/new.rs\n\
+                    --- /dev/null\n\
+                    +++ b/new.rs\n";
+        assert_eq!(changed_files(diff), ["src/lib.rs", "gone.rs", "new.rs"]);
+    }
+
+    #[test]
+    fn test_explanation_display() {
+        let explanation = DiffExplanation {
+            intent: "Fix the parser.".into(),
+            behavioral_changes: vec!["The last line is no longer dropped.".into()],
+            risk_areas: vec![],
+            suggested_tests: vec!["A file without a trailing newline.".into()],
+            suggested_reviewers: vec![],
+        };
+        assert_eq!(
+            explanation.to_string(),
+            "Intent: Fix the parser.\n\n\
+             Behavioral changes:\n- The last line is no longer dropped.\n\n\
+             Suggested tests:\n- A file without a trailing newline.\n"
+        );
+    }
+
+    #[test]
+    fn test_commit_message_file() {
+        let contents = "\n# Please enter the commit message for your changes.\n# On branch main\n";
+        assert_eq!(message_text(contents), "");
+        assert_eq!(
+            with_message(contents, "Fix the parser\n\nIt dropped the last line."),
+            "Fix the parser\n\nIt dropped the last line.\n\n\
+             # Please enter the commit message for your changes.\n# On branch main\n"
+        );
+        assert_eq!(with_message("", "Fix the parser"), "Fix the parser\n");
+        assert_eq!(
+            message_text("Fix the parser\n# comment\n\nDetails\n"),
+            "Fix the parser\n\nDetails"
+        );
+    }
+}
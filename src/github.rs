@@ -0,0 +1,483 @@
+//! GitHub issue triage and pull request descriptions
+//!
+//! `ellm gh triage` fetches an issue and the repository's labels from the
+//! GitHub REST API, classifies the issue with a structured schema and drafts a
+//! first response, printing JSON a labeling bot can act on.
+//!
+//! `ellm gh pr-describe` writes a pull request title and description from the
+//! branch's commits and diff, and can set them on the pull request.
+//!
+//! `GITHUB_TOKEN` is sent when set, and `GITHUB_API_URL` overrides the API
+//! endpoint, e.g. for GitHub Enterprise.
+
+use crate::git;
+use anyhow::{anyhow, Context, Result};
+use ellm::{Client, Messages};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_API_URL: &str = "https://api.github.com";
+
+/// Issue bodies are cut to this many bytes before being sent
+const MAX_BODY_BYTES: usize = 20_000;
+
+/// An issue in a repository
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IssueRef {
+    pub owner: String,
+    pub repo: String,
+    pub number: u64,
+}
+
+impl IssueRef {
+    /// Parse an issue URL, `owner/repo#123`, or a number (or `#123`) in `repo`
+    pub fn parse(issue: &str, repo: Option<&str>) -> Result<Self> {
+        let invalid = || anyhow!("not an issue URL, owner/repo#number or number: {}", issue);
+
+        if let Some(path) = issue
+            .strip_prefix("https://")
+            .or_else(|| issue.strip_prefix("http://"))
+        {
+            // host/owner/repo/issues/123, also for pull requests
+            let parts: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+            return match parts[..] {
+                [_, owner, repo, "issues" | "pull", number, ..] => Ok(Self {
+                    owner: owner.into(),
+                    repo: repo.into(),
+                    number: number.parse().map_err(|_| invalid())?,
+                }),
+                _ => Err(invalid()),
+            };
+        }
+
+        let (repo, number) = match issue.split_once('#') {
+            Some(("", number)) => (repo, number),
+            Some((repo, number)) => (Some(repo), number),
+            None => (repo, issue),
+        };
+        let number = number.parse().map_err(|_| invalid())?;
+        let repo = repo.ok_or_else(|| {
+            anyhow!(
+                "give the issue as owner/repo#{}, or the repository with --repo",
+                number
+            )
+        })?;
+        let (owner, repo) = repo
+            .split_once('/')
+            .filter(|(owner, repo)| !owner.is_empty() && !repo.is_empty())
+            .ok_or_else(|| anyhow!("repository must be owner/name: {}", repo))?;
+        Ok(Self {
+            owner: owner.into(),
+            repo: repo.into(),
+            number,
+        })
+    }
+}
+
+/// `owner/repo` of the origin remote, if it is on GitHub
+pub fn origin_repo() -> Option<String> {
+    let url = git::git(&["remote", "get-url", "origin"]).ok()?;
+    repo_from_remote(url.trim())
+}
+
+/// `owner/repo` of a GitHub remote URL, over SSH or HTTPS
+pub fn repo_from_remote(url: &str) -> Option<String> {
+    let path = url
+        .strip_prefix("git@github.com:")
+        .or_else(|| url.strip_prefix("ssh://git@github.com/"))
+        .or_else(|| url.strip_prefix("https://github.com/"))?;
+    let path = path.trim_end_matches('/').trim_end_matches(".git");
+    path.split_once('/')
+        .filter(|(_, repo)| !repo.contains('/'))
+        .map(|_| path.to_string())
+}
+
+#[derive(Debug, Deserialize)]
+struct Issue {
+    title: String,
+    #[serde(default)]
+    body: Option<String>,
+    html_url: String,
+    #[serde(default)]
+    labels: Vec<Label>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Label {
+    name: String,
+    #[serde(default)]
+    description: Option<String>,
+}
+
+/// What kind of issue it is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum IssueKind {
+    Bug,
+    Feature,
+    Question,
+    Other,
+}
+
+/// How urgently the issue needs attention
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Critical,
+    High,
+    Medium,
+    Low,
+}
+
+/// A classification of an issue and a first response to it
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Triage {
+    /// bug report, feature request, question, or other
+    pub kind: IssueKind,
+    /// critical for data loss, security or a broken core feature; low for cosmetic issues
+    pub severity: Severity,
+    /// the module, component or area of the project the issue concerns, or "unknown"
+    pub affected_module: String,
+    /// labels to apply, chosen only from the repository's labels
+    pub labels: Vec<String>,
+    /// the issue in one sentence
+    pub summary: String,
+    /// a friendly first response to the reporter, in Markdown: acknowledge the issue and ask for any missing information
+    pub draft_response: String,
+}
+
+/// The triage of an issue, as printed by `ellm gh triage`
+#[derive(Debug, Serialize)]
+pub struct TriageReport {
+    #[serde(flatten)]
+    pub issue: IssueRef,
+    pub url: String,
+    pub title: String,
+    #[serde(flatten)]
+    pub triage: Triage,
+}
+
+/// The GitHub REST API, as configured by the environment
+struct Api {
+    http: reqwest::Client,
+    url: String,
+    token: Option<String>,
+}
+
+impl Api {
+    fn from_env() -> Self {
+        let url = std::env::var("GITHUB_API_URL").unwrap_or_else(|_| DEFAULT_API_URL.into());
+        Self {
+            http: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            token: std::env::var("GITHUB_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty()),
+        }
+    }
+
+    /// Send a request for `path` and parse the JSON response
+    async fn send<T: serde::de::DeserializeOwned>(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        body: Option<&serde_json::Value>,
+    ) -> Result<T> {
+        let mut request = self
+            .http
+            .request(method, format!("{}{}", self.url, path))
+            .header("accept", "application/vnd.github+json")
+            .header("user-agent", concat!("ellm/", env!("CARGO_PKG_VERSION")));
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(anyhow!(
+                "GitHub API returned {} for {}",
+                status,
+                response.url()
+            ));
+        }
+        response
+            .json()
+            .await
+            .with_context(|| format!("unexpected JSON from {}", path))
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<T> {
+        self.send(reqwest::Method::GET, path, None).await
+    }
+}
+
+/// Fetch `issue` and triage it
+pub async fn triage(client: &Client, issue: IssueRef) -> Result<TriageReport> {
+    let api = Api::from_env();
+    let repo = format!("/repos/{}/{}", issue.owner, issue.repo);
+    let fetched: Issue = api
+        .get(&format!("{}/issues/{}", repo, issue.number))
+        .await?;
+    let labels: Vec<Label> = api.get(&format!("{}/labels?per_page=100", repo)).await?;
+
+    let mut body = fetched.body.clone().unwrap_or_default();
+    if body.len() > MAX_BODY_BYTES {
+        let mut end = MAX_BODY_BYTES;
+        while !body.is_char_boundary(end) {
+            end -= 1;
+        }
+        body.truncate(end);
+        body.push_str("\n[truncated]");
+    }
+    let request = format!(
+        "Repository: {}/{}\n\nAvailable labels:\n{}\n\nCurrent labels: {}\n\n\
+         Issue #{}: {}\n\n{}",
+        issue.owner,
+        issue.repo,
+        label_list(&labels),
+        fetched
+            .labels
+            .iter()
+            .map(|label| label.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", "),
+        issue.number,
+        fetched.title,
+        body
+    );
+    let system = "You triage GitHub issues for the project's maintainers. Classify the \
+                  issue from its text alone and draft the maintainers' first response."
+        .to_string();
+
+    let triage: Triage = client
+        .send_structured(Messages::new().push_user(request).clone(), Some(system), 3)
+        .await?;
+    let triage = Triage {
+        // Labels the repository doesn't have can't be applied
+        labels: triage
+            .labels
+            .into_iter()
+            .filter(|name| labels.iter().any(|label| &label.name == name))
+            .collect(),
+        ..triage
+    };
+
+    Ok(TriageReport {
+        issue,
+        url: fetched.html_url,
+        title: fetched.title,
+        triage,
+    })
+}
+
+/// A pull request title and description
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PrDescription {
+    /// a concise title in the imperative mood, at most 72 characters
+    pub title: String,
+    /// what the change does and why, as a few sentences or Markdown bullets
+    pub summary: String,
+    /// how to verify the change, as Markdown bullets
+    pub test_plan: String,
+}
+
+impl PrDescription {
+    /// The description as a pull request body
+    pub fn body(&self) -> String {
+        format!(
+            "## Summary\n\n{}\n\n## Test plan\n\n{}\n",
+            self.summary.trim(),
+            self.test_plan.trim()
+        )
+    }
+}
+
+/// The branch that pull requests target: origin's default branch, or `main`
+pub fn default_base() -> String {
+    git::git(&["symbolic-ref", "--short", "refs/remotes/origin/HEAD"])
+        .map(|base| base.trim().to_string())
+        .ok()
+        .filter(|base| !base.is_empty())
+        .unwrap_or_else(|| "main".into())
+}
+
+/// Describe the changes of the current branch since `base`
+pub async fn describe_pr(client: &Client, base: &str) -> Result<PrDescription> {
+    let commits = git::git(&[
+        "log",
+        "--reverse",
+        "--format=%s%n%n%b%n---",
+        &format!("{}..HEAD", base),
+    ])?;
+    if commits.trim().is_empty() {
+        return Err(anyhow!("no commits on this branch since {}", base));
+    }
+    let diff = git::range_diff(&format!("{}...HEAD", base))?;
+
+    let request = format!(
+        "Commit messages:\n\n{}\n\nDiff:\n\n{}",
+        commits.trim(),
+        git::truncated(diff)
+    );
+    let system = "You write pull request descriptions for reviewers who have not seen \
+                  the change. Be concise and specific; describe only what the commits \
+                  and diff show."
+        .to_string();
+    Ok(client
+        .send_structured(Messages::new().push_user(request).clone(), Some(system), 3)
+        .await?)
+}
+
+#[derive(Debug, Deserialize)]
+struct PullRequest {
+    number: u64,
+    html_url: String,
+}
+
+/// Set the title and body of pull request `number` of `repo`, or of the open
+/// pull request for the current branch, returning its URL
+pub async fn update_pr(
+    description: &PrDescription,
+    repo: &str,
+    number: Option<u64>,
+) -> Result<String> {
+    let api = Api::from_env();
+    if api.token.is_none() {
+        return Err(anyhow!("updating a pull request needs GITHUB_TOKEN"));
+    }
+    let (owner, _) = repo
+        .split_once('/')
+        .ok_or_else(|| anyhow!("repository must be owner/name: {}", repo))?;
+
+    let number = match number {
+        Some(number) => number,
+        None => {
+            let branch = git::git(&["rev-parse", "--abbrev-ref", "HEAD"])?;
+            let pulls: Vec<PullRequest> = api
+                .get(&format!(
+                    "/repos/{}/pulls?state=open&head={}:{}",
+                    repo,
+                    owner,
+                    branch.trim()
+                ))
+                .await?;
+            pulls.first().map(|pull| pull.number).ok_or_else(|| {
+                anyhow!(
+                    "no open pull request for branch {}; give its number with --pr",
+                    branch.trim()
+                )
+            })?
+        }
+    };
+
+    let body = serde_json::json!({ "title": description.title, "body": description.body() });
+    let pull: PullRequest = api
+        .send(
+            reqwest::Method::PATCH,
+            &format!("/repos/{}/pulls/{}", repo, number),
+            Some(&body),
+        )
+        .await?;
+    Ok(pull.html_url)
+}
+
+fn label_list(labels: &[Label]) -> String {
+    if labels.is_empty() {
+        return "(none)".into();
+    }
+    labels
+        .iter()
+        .map(|label| match &label.description {
+            Some(description) if !description.is_empty() => {
+                format!("- {}: {}", label.name, description)
+            }
+            _ => format!("- {}", label.name),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_issue_ref() {
+        let expected = IssueRef {
+            owner: "altendky".into(),
+            repo: "ellm".into(),
+            number: 42,
+        };
+        for issue in [
+            "https://github.com/altendky/ellm/issues/42",
+            "https://github.com/altendky/ellm/pull/42/files",
+            "altendky/ellm#42",
+        ] {
+            assert_eq!(IssueRef::parse(issue, None).unwrap(), expected, "{}", issue);
+        }
+        assert_eq!(
+            IssueRef::parse("42", Some("altendky/ellm")).unwrap(),
+            expected
+        );
+        assert_eq!(
+            IssueRef::parse("#42", Some("altendky/ellm")).unwrap(),
+            expected
+        );
+
+        assert!(IssueRef::parse("42", None).is_err());
+        assert!(IssueRef::parse("42", Some("ellm")).is_err());
+        assert!(IssueRef::parse("https://github.com/altendky/ellm", None).is_err());
+        assert!(IssueRef::parse("altendky/ellm#x", None).is_err());
+    }
+
+    #[test]
+    fn test_repo_from_remote() {
+        for url in [
+            "git@github.com:altendky/ellm.git",
+            "https://github.com/altendky/ellm",
+            "https://github.com/altendky/ellm.git",
+            "ssh://git@github.com/altendky/ellm.git",
+        ] {
+            assert_eq!(
+                repo_from_remote(url).as_deref(),
+                Some("altendky/ellm"),
+                "{}",
+                url
+            );
+        }
+        assert_eq!(repo_from_remote("https://gitlab.com/altendky/ellm"), None);
+    }
+
+    #[test]
+    fn test_pr_body() {
+        let description = PrDescription {
+            title: "Fix the parser".into(),
+            summary: "Keep the last line of input.\n".into(),
+            test_plan: "- Parse a file without a trailing newline".into(),
+        };
+        assert_eq!(
+            description.body(),
+            "## Summary\n\nKeep the last line of input.\n\n\
+             ## Test plan\n\n- Parse a file without a trailing newline\n"
+        );
+    }
+
+    #[test]
+    fn test_triage_schema() {
+        let triage: Triage = serde_json::from_value(serde_json::json!({
+            "kind": "bug",
+            "severity": "high",
+            "affected_module": "stream",
+            "labels": ["bug"],
+            "summary": "Streaming drops the last event.",
+            "draft_response": "Thanks for the report!",
+        }))
+        .unwrap();
+        assert_eq!(triage.kind, IssueKind::Bug);
+        assert_eq!(triage.severity, Severity::High);
+    }
+}
@@ -0,0 +1,330 @@
+//! Golden-output regression tests
+//!
+//! Each case's input is sent, after a shared prompt when there is one, and
+//! the output is compared with the case's stored golden output, its
+//! `expected`. Outputs can be required to match exactly, to match once
+//! normalized (ignoring case, punctuation and how whitespace is laid out), or
+//! to be similar enough: the cosine similarity of the two texts' word counts
+//! must reach a threshold. The API has no embeddings endpoint, so similarity
+//! is measured on the words themselves, locally.
+
+use crate::client::{Client, Messages};
+use crate::error::{ClaudeError, Result};
+use crate::optimize::EvalCase;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// How an output is compared with its golden output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GoldenComparison {
+    /// Byte for byte
+    Exact,
+    /// Ignoring case, punctuation and whitespace layout
+    #[default]
+    Normalized,
+    /// Cosine similarity of the word counts, at least the threshold
+    Similarity,
+}
+
+impl GoldenComparison {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GoldenComparison::Exact => "exact",
+            GoldenComparison::Normalized => "normalized",
+            GoldenComparison::Similarity => "similarity",
+        }
+    }
+
+    /// How closely `output` matches `golden`, from 0 to 1; exact and
+    /// normalized comparisons only give 0 or 1
+    pub fn score(&self, golden: &str, output: &str) -> f64 {
+        let matched = match self {
+            GoldenComparison::Exact => golden == output,
+            GoldenComparison::Normalized => normalize(golden) == normalize(output),
+            GoldenComparison::Similarity => return similarity(golden, output),
+        };
+        if matched {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+impl fmt::Display for GoldenComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for GoldenComparison {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "exact" => Ok(GoldenComparison::Exact),
+            "normalized" => Ok(GoldenComparison::Normalized),
+            "similarity" => Ok(GoldenComparison::Similarity),
+            _ => Err(format!(
+                "unknown comparison {:?}; use exact, normalized or similarity",
+                s
+            )),
+        }
+    }
+}
+
+/// `text` lowercased, without punctuation, with words separated by single spaces
+fn normalize(text: &str) -> String {
+    let text: String = text
+        .chars()
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect();
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The cosine similarity of the word counts of `a` and `b`
+fn similarity(a: &str, b: &str) -> f64 {
+    let counts = |text: &str| {
+        let mut counts: HashMap<String, f64> = HashMap::new();
+        for word in normalize(text).split_whitespace() {
+            *counts.entry(word.to_string()).or_default() += 1.0;
+        }
+        counts
+    };
+    let (a, b) = (counts(a), counts(b));
+    if a.is_empty() || b.is_empty() {
+        return if a.is_empty() && b.is_empty() {
+            1.0
+        } else {
+            0.0
+        };
+    }
+    let dot: f64 = a
+        .iter()
+        .map(|(word, count)| count * b.get(word).unwrap_or(&0.0))
+        .sum();
+    let norm = |counts: &HashMap<String, f64>| counts.values().map(|c| c * c).sum::<f64>().sqrt();
+    dot / (norm(&a) * norm(&b))
+}
+
+/// How [`Client::run_golden`] produces and compares outputs
+#[derive(Debug, Clone, PartialEq)]
+pub struct GoldenOptions {
+    pub comparison: GoldenComparison,
+    /// The least similarity that passes, for [`GoldenComparison::Similarity`]
+    pub threshold: f64,
+    /// Prompt each case's input follows
+    pub prompt: Option<String>,
+    /// System prompt for every case
+    pub system: Option<String>,
+}
+
+impl Default for GoldenOptions {
+    fn default() -> Self {
+        Self {
+            comparison: GoldenComparison::default(),
+            threshold: 0.8,
+            prompt: None,
+            system: None,
+        }
+    }
+}
+
+impl GoldenOptions {
+    fn passes(&self, score: f64) -> bool {
+        match self.comparison {
+            GoldenComparison::Similarity => score >= self.threshold,
+            _ => score >= 1.0,
+        }
+    }
+}
+
+/// A case's output compared with its golden output
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GoldenResult {
+    pub input: String,
+    /// The stored output; a case without one fails
+    pub golden: Option<String>,
+    pub output: String,
+    /// How closely the output matches the golden output, from 0 to 1
+    pub score: f64,
+    pub passed: bool,
+}
+
+/// The result of [`Client::run_golden`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GoldenReport {
+    pub comparison: GoldenComparison,
+    pub threshold: f64,
+    pub cases: Vec<GoldenResult>,
+}
+
+impl GoldenReport {
+    /// The cases whose output no longer matches
+    pub fn failures(&self) -> impl Iterator<Item = &GoldenResult> {
+        self.cases.iter().filter(|case| !case.passed)
+    }
+
+    pub fn passed(&self) -> bool {
+        self.cases.iter().all(|case| case.passed)
+    }
+
+    /// The cases with their current outputs as the golden outputs, to store
+    /// after an intended change
+    pub fn updated_cases(&self, cases: &[EvalCase]) -> Vec<EvalCase> {
+        cases
+            .iter()
+            .zip(&self.cases)
+            .map(|(case, result)| EvalCase {
+                expected: Some(result.output.clone()),
+                ..case.clone()
+            })
+            .collect()
+    }
+}
+
+impl Client {
+    /// Send every case, all at once, and compare the outputs with the
+    /// cases' golden outputs
+    pub async fn run_golden(
+        &self,
+        cases: &[EvalCase],
+        options: &GoldenOptions,
+    ) -> Result<GoldenReport> {
+        if cases.is_empty() {
+            return Err(ClaudeError::Eval("there are no cases to run".into()));
+        }
+
+        let results = futures::future::join_all(cases.iter().map(|case| async move {
+            let message = match &options.prompt {
+                Some(prompt) => format!("{}\n\n{}", prompt, case.input),
+                None => case.input.clone(),
+            };
+            let output = self
+                .send_message(
+                    Messages::new().push_user(message).clone(),
+                    None,
+                    options.system.clone(),
+                )
+                .await?;
+            let score = case
+                .expected
+                .as_deref()
+                .map_or(0.0, |golden| options.comparison.score(golden, &output));
+            Ok(GoldenResult {
+                input: case.input.clone(),
+                golden: case.expected.clone(),
+                passed: case.expected.is_some() && options.passes(score),
+                output,
+                score,
+            })
+        }))
+        .await;
+
+        Ok(GoldenReport {
+            comparison: options.comparison,
+            threshold: options.threshold,
+            cases: results.into_iter().collect::<Result<_>>()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::stub_client;
+    use crate::middleware::{Middleware, Next, Request, Response};
+    use futures::future::BoxFuture;
+
+    /// Answers with the capital of the country a request asks about, since
+    /// the cases are sent at once and can arrive in any order
+    struct Capitals;
+
+    impl Middleware for Capitals {
+        fn handle<'a>(
+            &'a self,
+            request: Request,
+            _next: Next<'a>,
+        ) -> BoxFuture<'a, Result<Response>> {
+            let body = request.body.unwrap().to_string();
+            assert!(body.contains("Name the capital of:"));
+            let capital = [
+                ("France", "Paris."),
+                ("Spain", "Madrid."),
+                ("Italy", "Rome."),
+            ]
+            .into_iter()
+            .find(|(country, _)| body.contains(country))
+            .map(|(_, capital)| capital)
+            .unwrap();
+            Box::pin(async move { Ok(Response::new(200, Vec::new(), reply(capital))) })
+        }
+    }
+
+    fn reply(text: &str) -> &'static str {
+        Box::leak(
+            serde_json::json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-haiku-4-5",
+                "content": [{"type": "text", "text": text}],
+                "stop_reason": "end_turn",
+                "usage": {"input_tokens": 1, "output_tokens": 1}
+            })
+            .to_string()
+            .into_boxed_str(),
+        )
+    }
+
+    #[test]
+    fn test_comparisons() {
+        let golden = "The capital of France is Paris.";
+        assert_eq!(GoldenComparison::Exact.score(golden, golden), 1.0);
+        assert_eq!(
+            GoldenComparison::Exact.score(golden, "the capital of france is paris"),
+            0.0
+        );
+        assert_eq!(
+            GoldenComparison::Normalized.score(golden, "  the capital of\nFrance is Paris "),
+            1.0
+        );
+        let similar = GoldenComparison::Similarity.score(golden, "Paris is the capital of France.");
+        assert!((similar - 1.0).abs() < 1e-9);
+        let partly = GoldenComparison::Similarity.score(golden, "The capital of Spain is Madrid.");
+        assert!(partly > 0.5 && partly < 0.9, "{}", partly);
+        assert_eq!(GoldenComparison::Similarity.score("", ""), 1.0);
+        assert_eq!("similarity".parse(), Ok(GoldenComparison::Similarity));
+        assert!("embedding".parse::<GoldenComparison>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_golden() {
+        let client = stub_client("http://127.0.0.1:9".into()).with_middleware(Capitals);
+
+        let cases = EvalCase::parse_jsonl(
+            "{\"input\": \"France\", \"expected\": \"paris\"}\n\
+             {\"input\": \"Spain\", \"expected\": \"Barcelona\"}\n\
+             {\"input\": \"Italy\"}\n",
+        )
+        .unwrap();
+        let options = GoldenOptions {
+            prompt: Some("Name the capital of:".into()),
+            ..GoldenOptions::default()
+        };
+        let report = client.run_golden(&cases, &options).await.unwrap();
+
+        let passed: Vec<bool> = report.cases.iter().map(|case| case.passed).collect();
+        assert_eq!(passed, [true, false, false]);
+        assert!(!report.passed());
+        assert_eq!(report.failures().count(), 2);
+
+        let updated = report.updated_cases(&cases);
+        assert_eq!(updated[2].expected.as_deref(), Some("Rome."));
+        assert_eq!(updated[0].input, "France");
+    }
+}
@@ -0,0 +1,626 @@
+//! gRPC service exposing the client as an LLM gateway
+//!
+//! Mirrors `proto/ellm.proto`, so services in other languages can generate
+//! typed clients from that file. The message types are written out here and
+//! the service stubs are generated by `build.rs`, which keeps `protoc` out of
+//! the build.
+
+use crate::boolean::BoolAnswer;
+use crate::client::{Client, Messages, StopReason};
+use crate::error::{ApiError, ClaudeError};
+use crate::reload::ReloadingClient;
+use crate::stream::StreamEvent;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tonic::{Request, Response, Status};
+
+mod generated {
+    include!(concat!(env!("OUT_DIR"), "/ellm.v1.Ellm.rs"));
+}
+
+pub use generated::ellm_server::{Ellm, EllmServer};
+
+/// Attempts allowed for `Structured` when the request leaves it at zero
+const DEFAULT_ATTEMPTS: usize = 3;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Message {
+    #[prost(string, tag = "1")]
+    pub role: String,
+    #[prost(string, tag = "2")]
+    pub content: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SendRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub messages: Vec<Message>,
+    #[prost(string, optional, tag = "2")]
+    pub system: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SendReply {
+    #[prost(string, tag = "1")]
+    pub text: String,
+}
+
+/// One event of a reply streamed by `Stream`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StreamReply {
+    #[prost(oneof = "stream_reply::Event", tags = "1, 2, 3, 4, 5, 6, 7, 8")]
+    pub event: Option<stream_reply::Event>,
+}
+
+pub mod stream_reply {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Event {
+        #[prost(message, tag = "1")]
+        MessageStart(super::MessageStart),
+        #[prost(message, tag = "2")]
+        TextDelta(super::TextDelta),
+        #[prost(message, tag = "3")]
+        ThinkingDelta(super::ThinkingDelta),
+        #[prost(message, tag = "4")]
+        ToolUseStart(super::ToolUseStart),
+        #[prost(message, tag = "5")]
+        ToolInputDelta(super::ToolInputDelta),
+        #[prost(message, tag = "6")]
+        ToolUseStop(super::ToolUseStop),
+        #[prost(message, tag = "7")]
+        BlockStop(super::BlockStop),
+        #[prost(message, tag = "8")]
+        MessageStop(super::MessageStop),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MessageStart {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub model: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TextDelta {
+    #[prost(uint32, tag = "1")]
+    pub index: u32,
+    #[prost(string, tag = "2")]
+    pub text: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ThinkingDelta {
+    #[prost(uint32, tag = "1")]
+    pub index: u32,
+    #[prost(string, tag = "2")]
+    pub thinking: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ToolUseStart {
+    #[prost(uint32, tag = "1")]
+    pub index: u32,
+    #[prost(string, tag = "2")]
+    pub id: String,
+    #[prost(string, tag = "3")]
+    pub name: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ToolInputDelta {
+    #[prost(uint32, tag = "1")]
+    pub index: u32,
+    #[prost(string, tag = "2")]
+    pub partial_json: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ToolUseStop {
+    #[prost(uint32, tag = "1")]
+    pub index: u32,
+    #[prost(string, tag = "2")]
+    pub id: String,
+    #[prost(string, tag = "3")]
+    pub name: String,
+    #[prost(string, tag = "4")]
+    pub input_json: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct BlockStop {
+    #[prost(uint32, tag = "1")]
+    pub index: u32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MessageStop {
+    #[prost(string, tag = "1")]
+    pub stop_reason: String,
+    #[prost(string, tag = "2")]
+    pub text: String,
+}
+
+impl From<StreamEvent> for stream_reply::Event {
+    fn from(event: StreamEvent) -> Self {
+        use stream_reply::Event;
+
+        match event {
+            StreamEvent::MessageStart { id, model } => {
+                Event::MessageStart(MessageStart { id, model })
+            }
+            StreamEvent::TextDelta { index, text } => Event::TextDelta(TextDelta {
+                index: index as u32,
+                text,
+            }),
+            StreamEvent::ThinkingDelta { index, thinking } => Event::ThinkingDelta(ThinkingDelta {
+                index: index as u32,
+                thinking,
+            }),
+            StreamEvent::ToolUseStart { index, id, name } => Event::ToolUseStart(ToolUseStart {
+                index: index as u32,
+                id,
+                name,
+            }),
+            StreamEvent::ToolInputDelta {
+                index,
+                partial_json,
+                ..
+            } => Event::ToolInputDelta(ToolInputDelta {
+                index: index as u32,
+                partial_json,
+            }),
+            StreamEvent::ToolUseStop {
+                index,
+                id,
+                name,
+                input,
+            } => Event::ToolUseStop(ToolUseStop {
+                index: index as u32,
+                id,
+                name,
+                input_json: input.to_string(),
+            }),
+            StreamEvent::BlockStop { index } => Event::BlockStop(BlockStop {
+                index: index as u32,
+            }),
+            StreamEvent::MessageStop { stop_reason } => Event::MessageStop(MessageStop {
+                stop_reason: stop_reason_name(stop_reason.as_ref()),
+                text: String::new(),
+            }),
+        }
+    }
+}
+
+/// The API's name for a stop reason, e.g. `end_turn`
+fn stop_reason_name(stop_reason: Option<&StopReason>) -> String {
+    stop_reason
+        .and_then(|reason| serde_json::to_value(reason).ok())
+        .and_then(|reason| reason.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StructuredRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub messages: Vec<Message>,
+    #[prost(string, optional, tag = "2")]
+    pub system: Option<String>,
+    #[prost(string, tag = "3")]
+    pub schema_json: String,
+    #[prost(uint32, tag = "4")]
+    pub max_attempts: u32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StructuredReply {
+    #[prost(string, tag = "1")]
+    pub json: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct BoolRequest {
+    #[prost(string, tag = "1")]
+    pub question: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum ProtoBoolAnswer {
+    Unspecified = 0,
+    True = 1,
+    False = 2,
+    Unsure = 3,
+}
+
+impl From<BoolAnswer> for ProtoBoolAnswer {
+    fn from(answer: BoolAnswer) -> Self {
+        match answer {
+            BoolAnswer::True => ProtoBoolAnswer::True,
+            BoolAnswer::False => ProtoBoolAnswer::False,
+            BoolAnswer::Unsure => ProtoBoolAnswer::Unsure,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct BoolReply {
+    #[prost(enumeration = "ProtoBoolAnswer", tag = "1")]
+    pub answer: i32,
+    #[prost(double, tag = "2")]
+    pub confidence: f64,
+    #[prost(string, tag = "3")]
+    pub explanation: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TranslateRequest {
+    #[prost(string, tag = "1")]
+    pub text: String,
+    #[prost(string, tag = "2")]
+    pub to: String,
+    #[prost(string, optional, tag = "3")]
+    pub from: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TranslateReply {
+    #[prost(string, tag = "1")]
+    pub text: String,
+}
+
+/// Map library errors onto the closest gRPC status code
+fn status(error: ClaudeError) -> Status {
+    let message = error.to_string();
+    match error {
+        ClaudeError::Api(ApiError::InvalidRequest(_)) => Status::invalid_argument(message),
+        ClaudeError::Api(ApiError::AuthenticationFailed(_)) => Status::unauthenticated(message),
+        ClaudeError::Api(ApiError::RateLimitExceeded) => Status::resource_exhausted(message),
+        ClaudeError::Api(ApiError::Overloaded(_)) => Status::unavailable(message),
+        // Not unavailable, which gRPC clients treat as safe to retry
+        ClaudeError::Api(ApiError::OutcomeUnknown { .. }) => Status::unknown(message),
+        ClaudeError::Guardrail(_) => Status::permission_denied(message),
+        ClaudeError::Network(_) | ClaudeError::RelayedNetwork { .. } | ClaudeError::Daemon(_) => {
+            Status::unavailable(message)
+        }
+        ClaudeError::Config(_) => Status::failed_precondition(message),
+        _ => Status::internal(message),
+    }
+}
+
+fn messages(messages: Vec<Message>) -> Result<Messages, Status> {
+    if messages.is_empty() {
+        return Err(Status::invalid_argument("at least one message is required"));
+    }
+
+    let mut conversation = Messages::new();
+    for message in messages {
+        match message.role.as_str() {
+            "user" => conversation.push_user(message.content),
+            "assistant" => conversation.push_assistant(message.content),
+            role => {
+                return Err(Status::invalid_argument(format!(
+                    "unknown role {:?}, expected user or assistant",
+                    role
+                )))
+            }
+        };
+    }
+    Ok(conversation)
+}
+
+/// Stream the reply to `messages` into `replies` until it ends or the caller
+/// goes away
+///
+/// Text is only passed on as it arrives when nothing may change it afterwards;
+/// the final `MessageStop` always carries the whole, checked text.
+async fn forward_stream(
+    client: &Client,
+    messages: Messages,
+    system: Option<String>,
+    replies: &mpsc::Sender<Result<StreamReply, Status>>,
+) -> crate::error::Result<()> {
+    let live = !client.config().guardrails.filters_output() && !client.has_response_hooks();
+    let mut stream = client
+        .stream_message(messages, system, Vec::new(), None)
+        .await?;
+
+    while let Some(event) = stream.next_event().await {
+        let event = match event? {
+            StreamEvent::TextDelta { .. } if !live => continue,
+            // Sent below, with the text
+            StreamEvent::MessageStop { .. } => continue,
+            event => event.into(),
+        };
+        let reply = StreamReply { event: Some(event) };
+        // Dropping the stream closes the connection, ending the request
+        if replies.send(Ok(reply)).await.is_err() {
+            return Ok(());
+        }
+    }
+
+    let message = stream.finish().await?;
+    let stop = MessageStop {
+        stop_reason: stop_reason_name(message.stop_reason.as_ref()),
+        text: message.text(),
+    };
+    let _ = replies
+        .send(Ok(StreamReply {
+            event: Some(stream_reply::Event::MessageStop(stop)),
+        }))
+        .await;
+    Ok(())
+}
+
+/// The `Ellm` service backed by a shared [`Client`]
+pub struct Service {
+    client: Arc<ReloadingClient>,
+}
+
+impl Service {
+    pub fn new(client: Client) -> Self {
+        Self::reloading(Arc::new(ReloadingClient::new(client)))
+    }
+
+    /// A service that uses whatever client `client` currently holds
+    pub fn reloading(client: Arc<ReloadingClient>) -> Self {
+        Self { client }
+    }
+}
+
+#[tonic::async_trait]
+impl Ellm for Service {
+    async fn send(&self, request: Request<SendRequest>) -> Result<Response<SendReply>, Status> {
+        let request = request.into_inner();
+        let text = self
+            .client
+            .client()
+            .send_message(messages(request.messages)?, None, request.system)
+            .await
+            .map_err(status)?;
+
+        Ok(Response::new(SendReply { text }))
+    }
+
+    type StreamStream =
+        Pin<Box<dyn futures::Stream<Item = Result<StreamReply, Status>> + Send + 'static>>;
+
+    async fn stream(
+        &self,
+        request: Request<SendRequest>,
+    ) -> Result<Response<Self::StreamStream>, Status> {
+        let request = request.into_inner();
+        let messages = messages(request.messages)?;
+        let client = self.client.client();
+
+        let (sender, receiver) = mpsc::channel(16);
+        tokio::spawn(async move {
+            if let Err(error) = forward_stream(&client, messages, request.system, &sender).await {
+                let _ = sender.send(Err(status(error))).await;
+            }
+        });
+
+        let replies = futures::stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|reply| (reply, receiver))
+        });
+        Ok(Response::new(Box::pin(replies)))
+    }
+
+    async fn structured(
+        &self,
+        request: Request<StructuredRequest>,
+    ) -> Result<Response<StructuredReply>, Status> {
+        let request = request.into_inner();
+        let schema: serde_json::Value = serde_json::from_str(&request.schema_json)
+            .map_err(|e| Status::invalid_argument(format!("schema_json: {}", e)))?;
+        let attempts = match request.max_attempts {
+            0 => DEFAULT_ATTEMPTS,
+            attempts => attempts as usize,
+        };
+
+        let value = self
+            .client
+            .client()
+            .send_structured_value(
+                messages(request.messages)?,
+                request.system,
+                &schema,
+                attempts,
+            )
+            .await
+            .map_err(status)?;
+
+        Ok(Response::new(StructuredReply {
+            json: value.to_string(),
+        }))
+    }
+
+    async fn ask_bool(&self, request: Request<BoolRequest>) -> Result<Response<BoolReply>, Status> {
+        let response = self
+            .client
+            .client()
+            .ask_bool(request.into_inner().question)
+            .await
+            .map_err(status)?;
+
+        Ok(Response::new(BoolReply {
+            answer: ProtoBoolAnswer::from(response.answer) as i32,
+            confidence: response.confidence,
+            explanation: response.explanation,
+        }))
+    }
+
+    async fn translate(
+        &self,
+        request: Request<TranslateRequest>,
+    ) -> Result<Response<TranslateReply>, Status> {
+        let request = request.into_inner();
+        let text = self
+            .client
+            .client()
+            .translate(&request.text, &request.to, request.from.as_deref())
+            .await
+            .map_err(status)?;
+
+        Ok(Response::new(TranslateReply { text }))
+    }
+}
+
+/// Serve the `Ellm` service on `address` until the process is stopped
+///
+/// Configuration changes are picked up while serving if `client` is being
+/// [watched](ReloadingClient::watch).
+pub async fn serve(address: SocketAddr, client: Arc<ReloadingClient>) -> crate::error::Result<()> {
+    tonic::transport::Server::builder()
+        .add_service(EllmServer::new(Service::reloading(client)))
+        .serve(address)
+        .await
+        .map_err(|e| ClaudeError::Io(std::io::Error::other(e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message as _;
+
+    #[test]
+    fn test_messages_from_proto() {
+        let conversation = messages(vec![
+            Message {
+                role: "user".into(),
+                content: "hi".into(),
+            },
+            Message {
+                role: "assistant".into(),
+                content: "hello".into(),
+            },
+        ])
+        .unwrap();
+        let conversation: Vec<crate::Message> = conversation.into();
+        assert_eq!(conversation.len(), 2);
+        assert_eq!(conversation[1].role, "assistant");
+
+        assert!(messages(vec![]).is_err());
+        let error = messages(vec![Message {
+            role: "system".into(),
+            content: "x".into(),
+        }])
+        .unwrap_err();
+        assert_eq!(error.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[test]
+    fn test_wire_round_trip() {
+        let request = TranslateRequest {
+            text: "hello".into(),
+            to: "German".into(),
+            from: None,
+        };
+        let decoded = TranslateRequest::decode(request.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(decoded, request);
+    }
+
+    async fn stream_replies(client: Client) -> Vec<stream_reply::Event> {
+        use futures::StreamExt;
+
+        let request = SendRequest {
+            messages: vec![Message {
+                role: "user".into(),
+                content: "hi".into(),
+            }],
+            system: None,
+        };
+        let replies = Service::new(client)
+            .stream(Request::new(request))
+            .await
+            .unwrap()
+            .into_inner();
+        replies
+            .map(|reply| reply.unwrap().event.unwrap())
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_stream() {
+        use crate::client::tests::{http_stub, stub_client};
+        use stream_reply::Event;
+
+        let body: String = [
+            r#"{"type":"message_start","message":{"id":"msg_1","model":"claude-haiku-4-5","usage":{"input_tokens":5}}}"#,
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hel"}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"lo"}}"#,
+            r#"{"type":"content_block_stop","index":0}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":2}}"#,
+            r#"{"type":"message_stop"}"#,
+        ]
+        .iter()
+        .map(|data| format!("data: {}\n\n", data))
+        .collect();
+        let body: &'static str = Box::leak(body.into_boxed_str());
+        let (url, _) = http_stub(vec![Some((200, body)), Some((200, body))]).await;
+
+        let events = stream_replies(stub_client(url.clone())).await;
+        assert_eq!(
+            events,
+            vec![
+                Event::MessageStart(MessageStart {
+                    id: "msg_1".into(),
+                    model: "claude-haiku-4-5".into(),
+                }),
+                Event::TextDelta(TextDelta {
+                    index: 0,
+                    text: "Hel".into(),
+                }),
+                Event::TextDelta(TextDelta {
+                    index: 0,
+                    text: "lo".into(),
+                }),
+                Event::BlockStop(BlockStop { index: 0 }),
+                Event::MessageStop(MessageStop {
+                    stop_reason: "end_turn".into(),
+                    text: "Hello".into(),
+                }),
+            ]
+        );
+
+        // Text a response hook rewrites only arrives once it is final
+        let client = stub_client(url).with_response_hook(|text: String| Ok(text.to_uppercase()));
+        let events = stream_replies(client).await;
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event, Event::TextDelta(_))));
+        assert_eq!(
+            events.last(),
+            Some(&Event::MessageStop(MessageStop {
+                stop_reason: "end_turn".into(),
+                text: "HELLO".into(),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_error_status_codes() {
+        let code = |error| status(error).code();
+        assert_eq!(
+            code(ApiError::RateLimitExceeded.into()),
+            tonic::Code::ResourceExhausted
+        );
+        assert_eq!(
+            code(ApiError::Overloaded("Overloaded".into()).into()),
+            tonic::Code::Unavailable
+        );
+        assert_eq!(
+            code(ClaudeError::Guardrail("no".into())),
+            tonic::Code::PermissionDenied
+        );
+        assert_eq!(
+            code(ClaudeError::StructuredOutput("bad".into())),
+            tonic::Code::Internal
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Fitting images within the API's limits before they're uploaded
+//!
+//! The API rejects images over 5 MB, and scales down anything larger than
+//! about 1568 pixels on the long edge anyway, so sending a phone photo as it
+//! is fails or wastes upload time. Images over either limit are resized to
+//! the configured maximum dimension and re-encoded: as PNG when they have
+//! transparency, otherwise as JPEG at the configured quality. Images within
+//! the limits are sent untouched.
+
+use crate::error::{ClaudeError, Result};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, ImageReader};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+
+/// The largest image the API accepts
+pub const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+
+/// Times an image is shrunk further when it's still too large after resizing
+const SHRINK_ATTEMPTS: u32 = 4;
+
+/// Image settings from the config file's `[images]` section
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImageConfig {
+    /// Longest edge, in pixels, that images are scaled down to
+    pub max_dimension: u32,
+
+    /// JPEG quality, from 1 to 100, of re-encoded images
+    pub quality: u8,
+}
+
+impl Default for ImageConfig {
+    fn default() -> Self {
+        Self {
+            max_dimension: 1568,
+            quality: 85,
+        }
+    }
+}
+
+/// An image that was scaled down and re-encoded to fit
+#[derive(Debug, Clone)]
+pub struct FittedImage {
+    pub media_type: &'static str,
+    pub bytes: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    pub original_width: u32,
+    pub original_height: u32,
+}
+
+impl ImageConfig {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// `bytes` scaled down and re-encoded to fit the limits, or `None` if
+    /// they already fit
+    pub fn fit(&self, bytes: &[u8]) -> Result<Option<FittedImage>> {
+        if self.max_dimension == 0 || !(1..=100).contains(&self.quality) {
+            return Err(ClaudeError::Image(format!(
+                "max_dimension must be positive and quality from 1 to 100, not {} and {}",
+                self.max_dimension, self.quality
+            )));
+        }
+        let (width, height) = ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()?
+            .into_dimensions()
+            .map_err(|e| ClaudeError::Image(e.to_string()))?;
+        if width.max(height) <= self.max_dimension && bytes.len() <= MAX_IMAGE_BYTES {
+            return Ok(None);
+        }
+
+        let image =
+            image::load_from_memory(bytes).map_err(|e| ClaudeError::Image(e.to_string()))?;
+        let mut limit = self.max_dimension.min(width.max(height));
+        for _ in 0..SHRINK_ATTEMPTS {
+            let resized = image.resize(limit, limit, FilterType::Lanczos3);
+            let (media_type, encoded) = self.encode(&resized)?;
+            if encoded.len() <= MAX_IMAGE_BYTES {
+                return Ok(Some(FittedImage {
+                    media_type,
+                    bytes: encoded,
+                    width: resized.width(),
+                    height: resized.height(),
+                    original_width: width,
+                    original_height: height,
+                }));
+            }
+            limit = limit * 3 / 4;
+        }
+        Err(ClaudeError::Image(format!(
+            "a {}x{} image is still over {} MB after scaling it down; lower images.quality",
+            width,
+            height,
+            MAX_IMAGE_BYTES / (1024 * 1024)
+        )))
+    }
+
+    fn encode(&self, image: &DynamicImage) -> Result<(&'static str, Vec<u8>)> {
+        let mut encoded = Vec::new();
+        // JPEG has no transparency, so only opaque images can use it
+        let (media_type, result) = if image.color().has_alpha() {
+            let result = image.write_to(&mut Cursor::new(&mut encoded), ImageFormat::Png);
+            ("image/png", result)
+        } else {
+            let encoder = JpegEncoder::new_with_quality(&mut encoded, self.quality);
+            let result = DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(encoder);
+            ("image/jpeg", result)
+        };
+        result.map_err(|e| ClaudeError::Image(e.to_string()))?;
+        Ok((media_type, encoded))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage, Rgba, RgbaImage};
+
+    fn png(image: DynamicImage) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_scales_down_large_images() {
+        let photo = RgbImage::from_fn(3000, 2000, |x, y| Rgb([x as u8, y as u8, 128]));
+        let fitted = ImageConfig::default()
+            .fit(&png(DynamicImage::ImageRgb8(photo)))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(fitted.media_type, "image/jpeg");
+        assert_eq!((fitted.width, fitted.height), (1568, 1045));
+        assert_eq!(
+            (fitted.original_width, fitted.original_height),
+            (3000, 2000)
+        );
+        assert!(fitted.bytes.starts_with(&[0xFF, 0xD8, 0xFF]));
+    }
+
+    #[test]
+    fn test_keeps_transparency() {
+        let icon = RgbaImage::from_pixel(400, 200, Rgba([0, 0, 0, 0]));
+        let config = ImageConfig {
+            max_dimension: 100,
+            ..ImageConfig::default()
+        };
+        let fitted = config
+            .fit(&png(DynamicImage::ImageRgba8(icon)))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(fitted.media_type, "image/png");
+        assert_eq!((fitted.width, fitted.height), (100, 50));
+    }
+
+    #[test]
+    fn test_leaves_small_images_alone() {
+        let small = png(DynamicImage::ImageRgb8(RgbImage::new(800, 600)));
+        assert!(ImageConfig::default().fit(&small).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(matches!(
+            ImageConfig::default().fit(b"\x89PNG\r\n\x1A\nnot really"),
+            Err(ClaudeError::Image(_))
+        ));
+        let config = ImageConfig {
+            quality: 0,
+            ..ImageConfig::default()
+        };
+        assert!(config.fit(&[]).is_err());
+    }
+}
@@ -0,0 +1,388 @@
+//! Local record of API usage
+//!
+//! Every successful request made by the CLI appends one JSON line with its
+//! token counts and estimated cost, tagged with the configured workspace, so
+//! usage can be reported and charged back without access to the Admin API.
+
+use crate::error::{ClaudeError, Result};
+use crate::pricing;
+use chrono::{DateTime, Datelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// One API call in the ledger
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub timestamp: DateTime<Utc>,
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub cache_creation_input_tokens: u64,
+    #[serde(default)]
+    pub cache_read_input_tokens: u64,
+    /// Estimated cost in USD, when the model's price is known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+    /// Made through the Message Batches API, at a discount
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub batch: bool,
+}
+
+impl LedgerEntry {
+    /// An entry for a call made now, with its cost estimated from published prices
+    pub fn new(
+        model: impl Into<String>,
+        workspace: Option<String>,
+        input_tokens: u64,
+        output_tokens: u64,
+        cache_creation_input_tokens: u64,
+        cache_read_input_tokens: u64,
+    ) -> Self {
+        let model = model.into();
+        let cost_usd = pricing::price(&model).map(|price| {
+            price.cost(
+                input_tokens,
+                output_tokens,
+                cache_creation_input_tokens,
+                cache_read_input_tokens,
+            )
+        });
+
+        Self {
+            timestamp: Utc::now(),
+            model,
+            workspace,
+            input_tokens,
+            output_tokens,
+            cache_creation_input_tokens,
+            cache_read_input_tokens,
+            cost_usd,
+            batch: false,
+        }
+    }
+
+    /// Mark the entry as a batch request, applying the batch discount to its cost
+    pub fn batch(mut self) -> Self {
+        if !self.batch {
+            self.batch = true;
+            self.cost_usd = self.cost_usd.map(|cost| cost * pricing::BATCH_MULTIPLIER);
+        }
+        self
+    }
+}
+
+/// Summed usage for a group of ledger entries
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Totals {
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_creation_input_tokens: u64,
+    pub cache_read_input_tokens: u64,
+    pub cost_usd: f64,
+    /// Saved by prompt cache hits, net of the premium paid for cache writes
+    pub cache_savings_usd: f64,
+    /// Saved by using the Message Batches API
+    pub batch_savings_usd: f64,
+}
+
+impl Totals {
+    pub fn add(&mut self, entry: &LedgerEntry) {
+        self.requests += 1;
+        self.input_tokens += entry.input_tokens;
+        self.output_tokens += entry.output_tokens;
+        self.cache_creation_input_tokens += entry.cache_creation_input_tokens;
+        self.cache_read_input_tokens += entry.cache_read_input_tokens;
+
+        let cost = entry.cost_usd.unwrap_or(0.0);
+        self.cost_usd += cost;
+
+        let multiplier = if entry.batch {
+            // The regular price would have been cost / multiplier
+            self.batch_savings_usd += cost / pricing::BATCH_MULTIPLIER - cost;
+            pricing::BATCH_MULTIPLIER
+        } else {
+            1.0
+        };
+        if let Some(price) = pricing::price(&entry.model) {
+            self.cache_savings_usd += multiplier
+                * price.cache_savings(
+                    entry.cache_creation_input_tokens,
+                    entry.cache_read_input_tokens,
+                );
+        }
+    }
+}
+
+/// What to break a usage report down by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    Model,
+    Workspace,
+    None,
+}
+
+impl FromStr for GroupBy {
+    type Err = ClaudeError;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "model" => Ok(GroupBy::Model),
+            "workspace" => Ok(GroupBy::Workspace),
+            "none" => Ok(GroupBy::None),
+            _ => Err(ClaudeError::Ledger(format!(
+                "unknown grouping {:?} (expected model, workspace or none)",
+                value
+            ))),
+        }
+    }
+}
+
+/// The time period a usage report row covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Day,
+    Month,
+    All,
+}
+
+impl Period {
+    fn label(self, timestamp: &DateTime<Utc>) -> String {
+        match self {
+            Period::Day => timestamp.format("%Y-%m-%d").to_string(),
+            Period::Month => format!("{:04}-{:02}", timestamp.year(), timestamp.month()),
+            Period::All => "all".to_string(),
+        }
+    }
+}
+
+impl FromStr for Period {
+    type Err = ClaudeError;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "day" => Ok(Period::Day),
+            "month" => Ok(Period::Month),
+            "all" => Ok(Period::All),
+            _ => Err(ClaudeError::Ledger(format!(
+                "unknown period {:?} (expected day, month or all)",
+                value
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Period::Day => write!(f, "day"),
+            Period::Month => write!(f, "month"),
+            Period::All => write!(f, "all"),
+        }
+    }
+}
+
+/// One row of a usage report
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReportRow {
+    pub period: String,
+    pub group: String,
+    #[serde(flatten)]
+    pub totals: Totals,
+}
+
+/// Sum entries per period and group, ordered by period then group
+pub fn report(entries: &[LedgerEntry], group_by: GroupBy, period: Period) -> Vec<ReportRow> {
+    let mut rows: BTreeMap<(String, String), Totals> = BTreeMap::new();
+
+    for entry in entries {
+        let group = match group_by {
+            GroupBy::Model => entry.model.clone(),
+            GroupBy::Workspace => entry.workspace.clone().unwrap_or_default(),
+            GroupBy::None => String::new(),
+        };
+        rows.entry((period.label(&entry.timestamp), group))
+            .or_default()
+            .add(entry);
+    }
+
+    rows.into_iter()
+        .map(|((period, group), totals)| ReportRow {
+            period,
+            group,
+            totals,
+        })
+        .collect()
+}
+
+/// An append-only JSONL file of [`LedgerEntry`]s
+#[derive(Debug, Clone)]
+pub struct Ledger {
+    path: PathBuf,
+}
+
+impl Ledger {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The ledger in the user's data directory
+    pub fn open_default() -> Result<Self> {
+        let data_dir = dirs::data_dir()
+            .ok_or_else(|| ClaudeError::Ledger("could not determine data directory".into()))?;
+        Ok(Self::new(data_dir.join("ellm").join("usage.jsonl")))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, entry: &LedgerEntry) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(&line)?;
+        Ok(())
+    }
+
+    /// All entries, oldest first; an absent ledger is empty
+    pub fn entries(&self) -> Result<Vec<LedgerEntry>> {
+        let file = match std::fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error.into()),
+        };
+
+        std::io::BufReader::new(file)
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+            .map(|(number, line)| {
+                serde_json::from_str(&line?).map_err(|e| {
+                    ClaudeError::Ledger(format!(
+                        "{} line {}: {}",
+                        self.path.display(),
+                        number + 1,
+                        e
+                    ))
+                })
+            })
+            .collect()
+    }
+
+    /// Totals per workspace; untagged usage is grouped under `None`
+    pub fn totals_by_workspace(&self) -> Result<BTreeMap<Option<String>, Totals>> {
+        let mut totals: BTreeMap<Option<String>, Totals> = BTreeMap::new();
+        for entry in self.entries()? {
+            totals
+                .entry(entry.workspace.clone())
+                .or_default()
+                .add(&entry);
+        }
+        Ok(totals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ledger(name: &str) -> Ledger {
+        let path =
+            std::env::temp_dir().join(format!("ellm-ledger-{}-{}.jsonl", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        Ledger::new(path)
+    }
+
+    #[test]
+    fn test_append_and_read() {
+        let ledger = ledger("append");
+        assert!(ledger.entries().unwrap().is_empty());
+
+        let entry = LedgerEntry::new("claude-sonnet-4-5-20250929", None, 1000, 100, 0, 0);
+        ledger.append(&entry).unwrap();
+        ledger.append(&entry).unwrap();
+
+        let entries = ledger.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0], entry);
+        assert!((entry.cost_usd.unwrap() - 0.0045).abs() < 1e-9);
+
+        let _ = std::fs::remove_file(ledger.path());
+    }
+
+    #[test]
+    fn test_totals_by_workspace() {
+        let ledger = ledger("workspace");
+        for workspace in [Some("research"), Some("research"), None] {
+            let entry = LedgerEntry::new(
+                "claude-haiku-4-5",
+                workspace.map(str::to_string),
+                10,
+                5,
+                0,
+                0,
+            );
+            ledger.append(&entry).unwrap();
+        }
+
+        let totals = ledger.totals_by_workspace().unwrap();
+        assert_eq!(totals[&Some("research".to_string())].requests, 2);
+        assert_eq!(totals[&Some("research".to_string())].input_tokens, 20);
+        assert_eq!(totals[&None].requests, 1);
+
+        let _ = std::fs::remove_file(ledger.path());
+    }
+
+    #[test]
+    fn test_report_by_model_and_month() {
+        let at = |date: &str| format!("{}T12:00:00Z", date).parse().unwrap();
+        let entry = |date: &str, model: &str| LedgerEntry {
+            timestamp: at(date),
+            ..LedgerEntry::new(model, None, 1000, 100, 0, 1_000_000)
+        };
+        let entries = vec![
+            entry("2024-06-01", "claude-sonnet-4-5"),
+            entry("2024-06-20", "claude-sonnet-4-5"),
+            entry("2024-06-20", "claude-haiku-4-5").batch(),
+            entry("2024-07-02", "claude-sonnet-4-5"),
+        ];
+
+        let rows = report(&entries, GroupBy::Model, Period::Month);
+        let keys: Vec<(&str, &str)> = rows
+            .iter()
+            .map(|row| (row.period.as_str(), row.group.as_str()))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                ("2024-06", "claude-haiku-4-5"),
+                ("2024-06", "claude-sonnet-4-5"),
+                ("2024-07", "claude-sonnet-4-5"),
+            ]
+        );
+        assert_eq!(rows[1].totals.requests, 2);
+        // A million cache reads at $3/MTok save $2.70 per request
+        assert!((rows[1].totals.cache_savings_usd - 5.4).abs() < 1e-9);
+        assert!(rows[0].totals.batch_savings_usd > 0.0);
+        assert!((rows[0].totals.batch_savings_usd - rows[0].totals.cost_usd).abs() < 1e-9);
+
+        let rows = report(&entries, GroupBy::None, Period::All);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].totals.requests, 4);
+    }
+}
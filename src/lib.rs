@@ -30,14 +30,108 @@
 //! }
 //! ```
 
+#[cfg(feature = "admin")]
+pub mod admin;
+pub mod batch;
+mod boolean;
 mod client;
 mod config;
+mod consistency;
+mod constraints;
+mod critique;
+pub mod daemon;
+pub mod diagnostics;
+pub mod encoding;
 mod error;
+mod fanout;
+pub mod fixtures;
+mod golden;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod guardrails;
+mod images;
+pub mod ledger;
+pub mod longdoc;
+pub mod metrics;
+pub mod middleware;
+pub mod models;
+pub mod notify;
+pub mod observer;
+mod ocr;
+mod openai;
+mod optimize;
+pub mod policy;
+pub mod postprocess;
+pub mod pricing;
+mod pseudonym;
+mod ratelimit;
+mod raw;
+pub mod redact;
+mod regexgen;
+pub mod reload;
+mod repair;
+pub mod retry;
+pub mod safety;
+pub mod select;
+pub mod snippets;
+pub mod stats;
+pub mod stream;
+mod structured;
+mod sweep;
+mod table;
+mod tokens;
+pub mod tools;
+mod translate;
+mod verify;
+pub mod watch;
 
 // Re-export main types
-pub use client::{Client, Message, Messages};
-pub use config::Config;
-pub use error::{ApiError, ClaudeError, ConfigError, Result};
+pub use boolean::{BoolAnswer, BoolResponse};
+pub use client::{
+    CacheControl, Client, Content, ContentBlock, DocumentSource, ImageSource, Message,
+    MessageResponse, Messages, StopReason, Usage,
+};
+pub use config::{Config, HttpConfig, Provider};
+pub use consistency::{Consensus, Sampling};
+pub use constraints::Constraint;
+pub use critique::{CritiqueOptions, CritiqueRound, Critiqued, Review};
+pub use diagnostics::LogFormat;
+pub use error::{ApiError, ClaudeError, ConfigError, ErrorKind, Result};
+pub use fanout::{Aggregator, FanOut, ModelAnswer};
+pub use golden::{GoldenComparison, GoldenOptions, GoldenReport, GoldenResult};
+pub use guardrails::{Guardrail, GuardrailsConfig};
+pub use images::{FittedImage, ImageConfig};
+pub use ledger::{Ledger, LedgerEntry};
+pub use longdoc::LongDocOptions;
+pub use middleware::Middleware;
+pub use models::ModelInfo;
+pub use notify::{Notification, NotifyConfig};
+pub use observer::Observer;
+pub use ocr::OcrFormat;
+pub use optimize::{CaseResult, EvalCase, OptimizeOptions, PromptEval, PromptOptimization};
+pub use policy::Policy;
+pub use postprocess::{Pipeline, PostProcessor, ResponseHook};
+pub use pseudonym::Pseudonyms;
+pub use ratelimit::{RateLimit, RateLimitConfig, RateLimits};
+pub use raw::{RawMessageResponse, RequestBuilder};
+pub use redact::{RedactionConfig, Redactor};
+pub use regexgen::{GeneratedRegex, RegexSample};
+pub use retry::RetryConfig;
+pub use safety::SafetyPreset;
+pub use select::Selector;
+pub use snippets::{Snippet, SnippetStore};
+pub use stream::{MessageStream, StreamEvent, StreamedMessage};
+pub use structured::{FailedAttempt, StructuredRetry};
+pub use sweep::{Judgement, SweepPoint, SweepRun};
+pub use table::{
+    Aggregate, AggregateFunction, ColumnProfile, ColumnType, Filter, FilterOp, Table, TableAnswer,
+    TableOptions, TableQuery,
+};
+#[cfg(feature = "estimate")]
+pub use tokens::estimate_tokens;
+pub use tokens::TokenEstimate;
+pub use tools::{Tool, ToolCall, ToolChoice, ToolExecutor, ToolLoopOptions, ToolRun};
+pub use verify::{VerificationCheck, Verified};
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,337 @@
+//! Processing documents too long for one request
+//!
+//! A document that doesn't fit the model's context window is split into
+//! overlapping chunks at paragraph, line or word boundaries. Each chunk is
+//! processed on its own, several at a time, and the partial results are
+//! merged by a final request. [`Client::summarize`] and [`Client::extract`]
+//! work this way; [`Client::map_chunks`] is the building block for other
+//! kinds of processing.
+//!
+//! Documents that fit are sent whole, as a single request.
+
+use crate::client::{Client, Messages};
+use crate::error::Result;
+use crate::models;
+use crate::structured::StructuredRetry;
+use futures::{StreamExt, TryStreamExt};
+use std::future::Future;
+
+/// How a long document is split and processed
+#[derive(Debug, Clone, PartialEq)]
+pub struct LongDocOptions {
+    /// Bytes per chunk; when unset, as much as the model's context window
+    /// is sure to hold next to the prompt and the response
+    pub chunk_bytes: Option<usize>,
+    /// Bytes at the end of a chunk that are repeated at the start of the next,
+    /// so nothing split across a boundary loses its context
+    pub overlap_bytes: usize,
+    /// Chunks processed at the same time
+    pub concurrency: usize,
+}
+
+impl Default for LongDocOptions {
+    fn default() -> Self {
+        Self {
+            chunk_bytes: None,
+            overlap_bytes: 2_000,
+            concurrency: 4,
+        }
+    }
+}
+
+/// Room left in the context window for the instructions around a chunk
+const PROMPT_BYTES: u64 = 4_000;
+
+/// Chunk size for models whose context window is unknown
+const DEFAULT_CHUNK_BYTES: usize = 100_000;
+
+impl Client {
+    /// The chunk size `options` give for the configured model
+    fn chunk_bytes(&self, options: &LongDocOptions) -> usize {
+        if let Some(bytes) = options.chunk_bytes {
+            return bytes.max(1);
+        }
+        // A token always covers at least one byte, so this many bytes fit
+        match models::context_window(&self.config().model) {
+            Some(window) => {
+                let reserved = PROMPT_BYTES + u64::from(self.config().request_max_tokens());
+                window.saturating_sub(reserved).max(1_000) as usize
+            }
+            None => DEFAULT_CHUNK_BYTES,
+        }
+    }
+
+    /// Run `process` on every chunk of `text`, at most `options.concurrency`
+    /// at a time, returning the results in document order
+    ///
+    /// A text that fits in one chunk is processed whole.
+    pub async fn map_chunks<T, F, Fut>(
+        &self,
+        text: &str,
+        options: &LongDocOptions,
+        process: F,
+    ) -> Result<Vec<T>>
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let chunks: Vec<String> = chunks(text, self.chunk_bytes(options), options.overlap_bytes)
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        futures::stream::iter(chunks)
+            .map(process)
+            .buffered(options.concurrency.max(1))
+            .try_collect()
+            .await
+    }
+
+    /// Summarize `text`, however long
+    ///
+    /// Each chunk of a long document is summarized on its own, and the
+    /// summaries, in document order, are combined into one. `instructions`
+    /// say what to focus on or how to format the summary.
+    pub async fn summarize(
+        &self,
+        text: &str,
+        instructions: Option<&str>,
+        options: &LongDocOptions,
+    ) -> Result<String> {
+        let instructions = instructions
+            .map(|instructions| format!(" {}", instructions))
+            .unwrap_or_default();
+        let ask = |system: String, text: String| async move {
+            self.send_message(Messages::new().push_user(text).clone(), None, Some(system))
+                .await
+        };
+
+        let mut summaries = self
+            .map_chunks(text, options, |chunk| {
+                ask(
+                    format!(
+                        "Summarize the user's text. Respond with only the summary.{}",
+                        instructions
+                    ),
+                    chunk,
+                )
+            })
+            .await?;
+
+        // Summaries of a very long document may need summarizing themselves
+        while summaries.len() > 1 {
+            let combined = summaries.join("\n\n");
+            let parts = self
+                .map_chunks(&combined, options, |chunk| {
+                    ask(
+                        format!(
+                            "The user's text is a series of summaries of consecutive \
+                             parts of one document, which overlap slightly. Combine \
+                             them into a single summary of the whole document. \
+                             Respond with only the summary.{}",
+                            instructions
+                        ),
+                        chunk,
+                    )
+                })
+                .await?;
+            if parts.len() >= summaries.len() {
+                // Combining doesn't shrink them further; take what was combined
+                return Ok(parts.join("\n\n"));
+            }
+            summaries = parts;
+        }
+        Ok(summaries.pop().unwrap_or_default())
+    }
+
+    /// Extract a JSON object matching `schema` from `text`, however long
+    ///
+    /// Each chunk of a long document is extracted from on its own, and the
+    /// partial results are merged into one object matching the schema. Every
+    /// request is retried according to `retry`, as in
+    /// [`Client::send_structured_value`].
+    pub async fn extract(
+        &self,
+        text: &str,
+        schema: &serde_json::Value,
+        instructions: Option<String>,
+        retry: impl Into<StructuredRetry>,
+        options: &LongDocOptions,
+    ) -> Result<serde_json::Value> {
+        let retry = retry.into();
+        let mut partials = self
+            .map_chunks(text, options, |chunk| {
+                self.send_structured_value(
+                    Messages::new().push_user(chunk).clone(),
+                    instructions.clone(),
+                    schema,
+                    retry.clone(),
+                )
+            })
+            .await?;
+        if partials.len() == 1 {
+            return Ok(partials.remove(0));
+        }
+
+        let merge = format!(
+            "The user's message lists JSON objects extracted from consecutive parts \
+             of one document, which overlap slightly. Merge them into a single object \
+             for the whole document: combine lists without repeating items found in \
+             two parts, and prefer the most complete value of other fields.{}",
+            instructions
+                .map(|instructions| format!(" The extraction instructions were: {}", instructions))
+                .unwrap_or_default()
+        );
+        self.send_structured_value(
+            Messages::new()
+                .push_user(serde_json::to_string_pretty(&partials)?)
+                .clone(),
+            Some(merge),
+            schema,
+            retry,
+        )
+        .await
+    }
+}
+
+/// Split `text` into chunks of at most `size` bytes, each starting with about
+/// `overlap` bytes of the previous one
+///
+/// Chunks end at a paragraph break if there is one in their second half, else
+/// at a line break or space, and only split words that are longer than that.
+fn chunks(text: &str, size: usize, overlap: usize) -> Vec<&str> {
+    // Make sure every chunk moves past the previous one
+    let overlap = overlap.min(size / 4);
+    let mut chunks = Vec::new();
+    let mut start = 0;
+
+    while text.len() - start > size {
+        let limit = floor_boundary(text, start + size);
+        let window = &text[floor_boundary(text, start + size / 2)..limit];
+        let end = ["\n\n", "\n", " "]
+            .iter()
+            .find_map(|separator| {
+                window
+                    .rfind(separator)
+                    .map(|at| limit - window.len() + at + separator.len())
+            })
+            .unwrap_or(limit);
+        let end = match end > start {
+            true => end,
+            // Less room than one character; take the character anyway
+            false => start + text[start..].chars().next().map_or(1, char::len_utf8),
+        };
+        chunks.push(&text[start..end]);
+
+        // Start the next chunk at a word boundary within the overlap
+        let back = floor_boundary(text, end - overlap).max(start + 1);
+        let back = floor_boundary(text, back);
+        start = text[back..end]
+            .find(char::is_whitespace)
+            .map(|at| back + at + 1)
+            .filter(|&next| next < end)
+            .unwrap_or(back);
+    }
+    chunks.push(&text[start..]);
+    chunks
+}
+
+/// The largest char boundary of `text` at or before `index`
+fn floor_boundary(text: &str, index: usize) -> usize {
+    let mut index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::{http_stub, stub_client};
+
+    #[test]
+    fn test_chunks() {
+        let text = "one two three four five six seven eight nine ten";
+        let parts = chunks(text, 20, 8);
+        assert!(parts.len() > 2);
+        assert!(parts.iter().all(|part| part.len() <= 20));
+        assert_eq!(parts[0], "one two three four ");
+        // Each chunk repeats the end of the previous one
+        for pair in parts.windows(2) {
+            let first_word = pair[1].split_whitespace().next().unwrap();
+            assert!(pair[0].contains(first_word));
+        }
+        assert!(parts.last().unwrap().ends_with("ten"));
+
+        assert_eq!(chunks("short", 20, 8), ["short"]);
+        // Paragraph breaks are preferred
+        assert_eq!(
+            chunks("aaaa bbbb\n\ncccc dddd eeee", 16, 0)[0],
+            "aaaa bbbb\n\n"
+        );
+        // Multi-byte characters are never split
+        let accents = "é".repeat(30);
+        let parts = chunks(&accents, 7, 2);
+        assert!(parts.iter().all(|part| part.chars().all(|c| c == 'é')));
+    }
+
+    fn reply(text: &str) -> &'static str {
+        Box::leak(
+            serde_json::json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-haiku-4-5",
+                "content": [{"type": "text", "text": text}],
+                "stop_reason": "end_turn",
+                "usage": {"input_tokens": 1, "output_tokens": 1},
+            })
+            .to_string()
+            .into_boxed_str(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_summarize_long_document() {
+        let (url, requests) = http_stub(vec![
+            Some((200, reply("Part one."))),
+            Some((200, reply("Part two."))),
+            Some((200, reply("The whole story."))),
+        ])
+        .await;
+        let client = stub_client(url);
+        let options = LongDocOptions {
+            chunk_bytes: Some(40),
+            overlap_bytes: 0,
+            concurrency: 1,
+        };
+
+        let text = "The first half of the story is here.\n\nThe second half is here.";
+        let summary = client.summarize(text, None, &options).await.unwrap();
+        assert_eq!(summary, "The whole story.");
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[2].contains(r#""content":"part one.\n\npart two.""#));
+    }
+
+    #[tokio::test]
+    async fn test_short_document_is_sent_whole() {
+        let (url, requests) = http_stub(vec![Some((200, reply(r#""names": ["Ada"]}"#)))]).await;
+        let client = stub_client(url);
+
+        let schema = serde_json::json!({"type": "object", "required": ["names"]});
+        let value = client
+            .extract(
+                "Ada wrote it.",
+                &schema,
+                None,
+                1,
+                &LongDocOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(value["names"][0], "Ada");
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+}
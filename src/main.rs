@@ -1,71 +1,2287 @@
-use anyhow::{anyhow, Result};
-use clap::Parser;
-use ellm::{Client, Config, Messages};
+use anyhow::{anyhow, Context, Result};
+use clap::{CommandFactory, FromArgMatches, Parser};
+use ellm::diagnostics;
+use ellm::encoding::{self, Encoding};
+use ellm::fixtures::{Recorder, Replayer};
+use ellm::ledger::{self, GroupBy, Period};
+use ellm::stats::{self, Invocation, RequestTally, StatsLog};
+use ellm::watch::{line_diff, DiffLine, FileWatcher};
+use ellm::{
+    daemon, pricing, Aggregator, BoolAnswer, CacheControl, Client, Config, Consensus, Constraint,
+    Content, ContentBlock, CritiqueOptions, ErrorKind, EvalCase, GoldenOptions, ImageConfig,
+    Ledger, LongDocOptions, Message, MessageStream, Messages, Notification, NotifyConfig,
+    OcrFormat, OptimizeOptions, Pipeline, Policy, Redactor, RegexSample, Selector, Snippet,
+    SnippetStore, StopReason, StreamEvent, StreamedMessage, StructuredRetry, SweepPoint, Table,
+    TableOptions, Usage,
+};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{LazyLock, Mutex, Once, OnceLock};
 
 mod cli;
-use cli::{Cli, Commands};
-use schemars::JsonSchema;
-use serde::Deserialize;
+mod completions;
+mod digest;
+mod git;
+mod github;
+mod mcp;
+mod progress;
+mod screenshot;
+#[cfg(feature = "tui")]
+mod tui;
+use cli::{
+    Cli, Commands, EvalCommand, FixtureMode, FixturesCommand, GhCommand, GitCommand, MaxTokens,
+    SamplingArgs, SnippetCommand,
+};
+use progress::Progress;
+
+/// The requests made by every client of this run, for the local statistics
+static REQUESTS: LazyLock<RequestTally> = LazyLock::new(RequestTally::default);
+
+/// The part of the JSON output to print, from `--select`
+static SELECT: OnceLock<Selector> = OnceLock::new();
+
+/// Whether the command printed JSON, which `--select` needs
+static PRINTED_JSON: AtomicBool = AtomicBool::new(false);
+
+/// Where Ctrl-C goes while a response streams; outside of one it exits
+static INTERRUPT: Mutex<Option<tokio::sync::oneshot::Sender<()>>> = Mutex::new(None);
+
+/// A streamed response was interrupted with Ctrl-C
+///
+/// What arrived has been shown; `main` exits with 130 as the shell would.
+#[derive(Debug)]
+struct Interrupted;
+
+impl std::fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("interrupted; the response above is incomplete")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// Print `value` as pretty JSON, or only the parts of it `--select` picks
+///
+/// Selected strings are printed without quotes, like `jq -r`, one per line.
+fn print_json(value: &impl serde::Serialize) -> Result<()> {
+    PRINTED_JSON.store(true, Ordering::Relaxed);
+    let Some(selector) = SELECT.get() else {
+        println!("{}", serde_json::to_string_pretty(value)?);
+        return Ok(());
+    };
+    let value = serde_json::to_value(value)?;
+    for selected in selector.select(&value)? {
+        match selected {
+            serde_json::Value::String(text) => println!("{}", text),
+            other => println!("{}", serde_json::to_string_pretty(other)?),
+        }
+    }
+    Ok(())
+}
+
+/// Load the configuration with the `--max-tokens` setting applied
+fn load_config(cli: &Cli) -> Result<Config> {
+    // Replayed requests are never sent, so they need no key
+    let api_key = match (&cli.api_key, &cli.fixtures) {
+        (None, Some(FixtureMode::Replay(_))) => Some("sk-ant-replay".to_string()),
+        (api_key, _) => api_key.clone(),
+    };
+    let mut config =
+        Config::load_with_provider(cli.config.as_deref(), cli.provider.as_deref(), api_key)?;
+    if let Some(base_url) = cli.base_url.clone() {
+        config.base_url = base_url;
+    }
+    if let Some(model) = cli.model.clone() {
+        config = config.with_model(model);
+    }
+    if let Some(safety) = cli.safety {
+        config.safety = safety;
+    }
+    if let Some(language) = cli.respond_in.clone() {
+        config.respond_in = Some(language);
+    }
+    Ok(match cli.max_tokens {
+        Some(MaxTokens::Fixed(max_tokens)) => config.with_max_tokens(max_tokens),
+        Some(MaxTokens::Auto) => config.with_auto_max_tokens(),
+        None => config,
+    })
+}
 
 /// Helper function to build a Client from Cli struct
 fn build_client(cli: &Cli) -> Result<Client> {
-    Ok(Config::build_from_cli(
-        cli.api_key.clone(),
-        cli.model.clone(),
-        cli.max_tokens,
-    )?)
+    build_client_with(cli, client_config(cli)?)
+}
+
+/// The config with the command line's overrides of client settings applied
+fn client_config(cli: &Cli) -> Result<Config> {
+    let mut config = load_config(cli)?;
+
+    if cli.no_redact {
+        config.redaction.enabled = false;
+    }
+    if cli.workspace.is_some() {
+        config.workspace = cli.workspace.clone();
+    }
+    if cli.thinking.is_some() {
+        config.thinking_budget = cli.thinking;
+    }
+    if cli.cache_system {
+        config.cache_system = true;
+    }
+    if cli.auto_migrate {
+        config.auto_migrate = true;
+    }
+    if let Some(continuations) = cli.max_continuations {
+        config.max_continuations = continuations;
+    }
+    Ok(config)
+}
+
+/// A client for `config`, set up as the command line asks
+fn build_client_with(cli: &Cli, config: Config) -> Result<Client> {
+    // Fixtures are masked with the configured patterns even when prompts aren't
+    let redactor = Redactor::new(&config.redaction)?;
+    let mut client = Client::new(config)?.with_observer(REQUESTS.clone());
+
+    match &cli.fixtures {
+        Some(FixtureMode::Record(dir)) => {
+            client = client.with_middleware(Recorder::new(dir, redactor)?);
+        }
+        Some(FixtureMode::Replay(dir)) => {
+            client = client.with_middleware(Replayer::with_redactor(dir, redactor)?);
+        }
+        None => {}
+    }
+
+    // Replayed usage was never paid for
+    match Ledger::open_default() {
+        Ok(_) if matches!(cli.fixtures, Some(FixtureMode::Replay(_))) => {}
+        Ok(ledger) => client = client.with_ledger(ledger),
+        Err(error) => diagnostics::warn(
+            "ledger",
+            format!("usage will not be recorded: {}", error),
+            json!({}),
+        ),
+    }
+
+    if !cli.no_daemon {
+        if let Ok(socket) = daemon::default_socket_path() {
+            if socket.exists() {
+                client = client.with_daemon(socket);
+            }
+        }
+    }
+
+    Ok(client)
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    // Answers the shell's completion requests and exits when they are made
+    clap_complete::CompleteEnv::with_factory(Cli::command)
+        .var(completions::COMPLETE_VAR)
+        .complete();
+
+    let parsed = Cli::command()
+        .try_get_matches()
+        .and_then(|matches| Ok((Cli::from_arg_matches(&matches)?, matches)));
+    let (cli, matches) = match parsed {
+        Ok(parsed) => parsed,
+        // Usage errors keep clear of the codes `bool` answers with
+        Err(error) if error.use_stderr() => {
+            let _ = error.print();
+            return ExitCode::from(ErrorKind::Usage.exit_code());
+        }
+        Err(error) => error.exit(),
+    };
+    let log_format = match cli.log_format {
+        Some(format) => format,
+        // A config file that can't be read is reported by the command itself
+        None => load_config(&cli)
+            .map(|config| config.log_format)
+            .unwrap_or_default(),
+    };
+    diagnostics::set_format(log_format);
+    if let Some(selector) = cli.select.clone() {
+        let _ = SELECT.set(selector);
+    }
+    let json = cli.error_format == "json";
+    let recorded = !matches!(
+        cli.command,
+        Commands::Stats { .. } | Commands::Completions { .. }
+    );
+    let snippet = cli.snippet.clone();
+    let started = std::time::Instant::now();
+    let result = run_notifying(cli).await.and_then(|code| {
+        if SELECT.get().is_some() && !PRINTED_JSON.load(Ordering::Relaxed) {
+            return Err(anyhow!(
+                "--select only applies to JSON output; use --json or --output json"
+            ));
+        }
+        Ok(code)
+    });
+    if recorded {
+        record_invocation(&matches, snippet, started.elapsed(), result.is_ok());
+    }
+    match result {
+        Ok(code) => code,
+        Err(error) if error.is::<Interrupted>() => {
+            if json {
+                let report = serde_json::json!({
+                    "error": {"kind": "interrupted", "exit_code": 130, "message": error.to_string()}
+                });
+                eprintln!("{}", report);
+            } else {
+                eprintln!("{}", error);
+            }
+            ExitCode::from(130)
+        }
+        Err(error) => {
+            let kind = error_kind(&error);
+            if json {
+                let report = serde_json::json!({
+                    "error": {
+                        "kind": kind.as_str(),
+                        "exit_code": kind.exit_code(),
+                        "message": error_message(&error),
+                    }
+                });
+                eprintln!("{}", report);
+            } else {
+                eprintln!("Error: {:?}", error);
+            }
+            ExitCode::from(kind.exit_code())
+        }
+    }
+}
+
+/// Add the run of the command in `matches` to the local statistics
+fn record_invocation(
+    matches: &clap::ArgMatches,
+    snippet: Option<String>,
+    duration: std::time::Duration,
+    success: bool,
+) {
+    let mut command = Vec::new();
+    let mut matches = matches;
+    while let Some((name, subcommand)) = matches.subcommand() {
+        command.push(name);
+        matches = subcommand;
+    }
+    let invocation = Invocation::new(
+        command.join(" "),
+        snippet,
+        duration,
+        success,
+        REQUESTS.totals(),
+    );
+    // The command already ran, so don't fail it over bookkeeping
+    if let Err(error) = StatsLog::open_default().and_then(|log| log.append(&invocation)) {
+        diagnostics::warn(
+            "stats",
+            format!("could not record statistics: {}", error),
+            json!({}),
+        );
+    }
+}
+
+/// `error` and its causes on one line, leaving out causes that their wrapping
+/// error already quotes
+fn error_message(error: &anyhow::Error) -> String {
+    let mut message = String::new();
+    for cause in error.chain() {
+        let text = cause.to_string();
+        if !message.contains(&text) {
+            if !message.is_empty() {
+                message.push_str(": ");
+            }
+            message.push_str(&text);
+        }
+    }
+    message
+}
+
+/// The kind of the first library or HTTP error behind `error`
+fn error_kind(error: &anyhow::Error) -> ErrorKind {
+    error
+        .chain()
+        .find_map(|cause| {
+            if let Some(error) = cause.downcast_ref::<ellm::ClaudeError>() {
+                Some(error.kind())
+            } else {
+                cause
+                    .downcast_ref::<reqwest::Error>()
+                    .map(|_| ErrorKind::Network)
+            }
+        })
+        .unwrap_or(ErrorKind::Other)
+}
+
+/// Run the command, notifying about long operations when they finish
+async fn run_notifying(cli: Cli) -> Result<ExitCode> {
+    let Some(operation) = long_operation(&cli.command) else {
+        return run(cli).await;
+    };
+    let notify = notify_config(&cli)?;
+    let started = std::time::Instant::now();
+    let result = run(cli).await;
+    if !notify.is_empty() {
+        let error = result.as_ref().err().map(|error| format!("{:#}", error));
+        let notification = Notification::new(operation, error, started.elapsed());
+        if let Err(error) = notify.send(&notification).await {
+            diagnostics::warn(
+                "notify",
+                format!("notification not sent: {}", error),
+                json!({"operation": operation}),
+            );
+        }
+    }
+    result
+}
+
+/// The name of the operation `command` runs, if it may take long enough to notify about
+fn long_operation(command: &Commands) -> Option<&'static str> {
+    match command {
+        Commands::Batch {
+            command: cli::BatchCommand::Wait { .. },
+        } => Some("batch wait"),
+        Commands::Translate { jsonl: true, .. } => Some("translate"),
+        Commands::Summarize { .. } => Some("summarize"),
+        Commands::Extract { .. } => Some("extract"),
+        Commands::Digest { .. } => Some("digest"),
+        Commands::Sweep { .. } => Some("sweep"),
+        Commands::OptimizePrompt { .. } => Some("optimize-prompt"),
+        _ => None,
+    }
+}
+
+/// The configured notification targets, with the command line's taking precedence
+fn notify_config(cli: &Cli) -> Result<NotifyConfig> {
+    let mut notify = load_config(cli)?.notify;
+    if cli.notify_webhook.is_some() {
+        notify.webhook = cli.notify_webhook.clone();
+    }
+    if cli.notify_command.is_some() {
+        notify.command = cli.notify_command.clone();
+    }
+    Ok(notify)
+}
+
+async fn run(cli: Cli) -> Result<ExitCode> {
+    let snippet = match &cli.snippet {
+        Some(name) => Some(SnippetStore::open_default()?.load(name)?),
+        None => None,
+    };
+    if snippet.is_some()
+        && !matches!(
+            cli.command,
+            Commands::Send { .. }
+                | Commands::Bool { .. }
+                | Commands::Tokens { .. }
+                | Commands::Summarize { .. }
+                | Commands::Extract { .. }
+        )
+    {
+        return Err(anyhow!("--snippet can't be used with this command"));
+    }
+    let snippet = snippet.as_ref();
+    let snippet_system = |system: Option<String>| {
+        system.or_else(|| snippet.and_then(|snippet| snippet.system.clone()))
+    };
+
+    match cli.command.clone() {
+        Commands::Send {
+            message,
+            system,
+            files,
+            cache_files,
+            mut images,
+            screenshot,
+            show_thinking,
+            output,
+            verify,
+            constraints,
+            import_openai,
+            export_openai,
+        } => {
+            let message = with_snippet(snippet, message);
+            let (imported_system, mut messages) = match &import_openai {
+                Some(path) => {
+                    let json = std::fs::read_to_string(path)
+                        .with_context(|| format!("reading {}", path.display()))?;
+                    Messages::from_openai(&json)
+                        .with_context(|| format!("importing {}", path.display()))?
+                }
+                None => (None, Messages::new()),
+            };
+            let system = snippet_system(system).or(imported_system);
+            let limits = load_config(&cli)?.images;
+            let screenshot = screenshot
+                .map(|area| screenshot::capture(area == "region"))
+                .transpose()?;
+            images.extend(screenshot.as_ref().map(|shot| shot.path().to_path_buf()));
+            messages.push(with_attachments(
+                message,
+                &files,
+                cache_files,
+                &images,
+                &limits,
+            )?);
+            let response = send_message(
+                cli,
+                messages.clone(),
+                system.clone(),
+                show_thinking,
+                &output,
+                verify,
+                constraints,
+            )
+            .await?;
+            if let Some(path) = export_openai {
+                messages.push_assistant(response);
+                let conversation = messages.to_openai(system.as_deref());
+                std::fs::write(&path, serde_json::to_string_pretty(&conversation)? + "\n")
+                    .with_context(|| format!("writing {}", path.display()))?;
+            }
+        }
+        Commands::Config => {
+            show_config(cli)?;
+        }
+        Commands::Bool {
+            question,
+            min_confidence,
+            quiet,
+            json,
+            sampling,
+        } => {
+            let question = with_snippet(snippet, question);
+            let outcome = bool(cli, question, min_confidence, quiet, json, sampling).await?;
+            if outcome != BoolAnswer::True {
+                return Ok(ExitCode::from(outcome.exit_code() as u8));
+            }
+        }
+        Commands::Translate {
+            text,
+            to,
+            from,
+            file,
+            jsonl,
+            field,
+        } => {
+            let input = read_input(text, file.as_deref())?;
+            if jsonl {
+                translate_jsonl(cli, input, to, from, field).await?;
+            } else {
+                translate(cli, input, to, from).await?;
+            }
+        }
+        Commands::Council {
+            question,
+            models,
+            aggregate,
+            synthesizer,
+            system,
+            json,
+        } => {
+            council(cli, question, models, aggregate, synthesizer, system, json).await?;
+        }
+        Commands::Sweep {
+            prompt,
+            temperature,
+            models,
+            system,
+            judge,
+            json,
+        } => {
+            sweep(cli, prompt, temperature, models, system, judge, json).await?;
+        }
+        Commands::Critique {
+            prompt,
+            file,
+            criteria,
+            rounds,
+            critic,
+            system,
+            json,
+        } => {
+            let prompt = read_input(prompt, file.as_deref())?;
+            let options = CritiqueOptions {
+                rounds,
+                critic,
+                ..CritiqueOptions::new(criteria)
+            };
+            critique(cli, prompt, system, options, json).await?;
+        }
+        Commands::OptimizePrompt {
+            prompt,
+            cases,
+            rounds,
+            criteria,
+            optimizer,
+            system,
+            json,
+        } => {
+            let options = OptimizeOptions {
+                rounds,
+                criteria,
+                optimizer,
+                system,
+            };
+            optimize_prompt(cli, prompt, cases, options, json).await?;
+        }
+        Commands::Eval {
+            command:
+                EvalCommand::Golden {
+                    cases,
+                    prompt,
+                    system,
+                    compare,
+                    threshold,
+                    update,
+                    json,
+                },
+        } => {
+            let prompt = prompt
+                .map(|path| {
+                    std::fs::read_to_string(&path)
+                        .with_context(|| format!("failed to read {}", path.display()))
+                })
+                .transpose()?;
+            let options = GoldenOptions {
+                comparison: compare,
+                threshold,
+                prompt: prompt.map(|prompt| prompt.trim().to_string()),
+                system,
+            };
+            eval_golden(cli, cases, options, update, json).await?;
+        }
+        Commands::Summarize {
+            text,
+            file,
+            instructions,
+            chunking,
+        } => {
+            let input = read_input(text, file.as_deref())?;
+            let instructions = snippet_instructions(snippet, instructions);
+            summarize(cli, input, instructions, chunking.into()).await?;
+        }
+        Commands::Extract {
+            text,
+            file,
+            schema,
+            instructions,
+            chunking,
+            sampling,
+        } => {
+            let input = read_input(text, file.as_deref())?;
+            let instructions = snippet_instructions(snippet, instructions);
+            extract(cli, input, schema, instructions, chunking.into(), sampling).await?;
+        }
+        Commands::Ocr {
+            image,
+            format,
+            layout,
+        } => {
+            ocr(cli, image, format, layout).await?;
+        }
+        Commands::Table {
+            path,
+            question,
+            verify,
+            budget,
+            pseudonymize,
+            pseudonymize_columns,
+            json,
+        } => {
+            let options = TableOptions {
+                budget_bytes: budget,
+                verify,
+                pseudonymize: pseudonymize || !pseudonymize_columns.is_empty(),
+                identifier_columns: pseudonymize_columns,
+                ..TableOptions::default()
+            };
+            ask_table(cli, path, question, options, json).await?;
+        }
+        Commands::McpServe => {
+            mcp::serve(build_client(&cli)?).await?;
+        }
+        Commands::Daemon { socket, metrics } => {
+            run_daemon(cli, socket, metrics).await?;
+        }
+        Commands::Usage { command } => match command {
+            cli::UsageCommand::Workspaces { json } => usage_by_workspace(json)?,
+            cli::UsageCommand::Report {
+                group_by,
+                period,
+                format,
+            } => usage_report(group_by, period, &format)?,
+        },
+        Commands::Batch { command } => {
+            batch(cli, command).await?;
+        }
+        Commands::Regex {
+            description,
+            samples,
+            full_match,
+            attempts,
+            json,
+        } => {
+            if !regex(cli, description, samples, full_match, attempts, json).await? {
+                // The pattern never matched its samples, much as output can miss a schema
+                return Ok(ExitCode::from(ErrorKind::Schema.exit_code()));
+            }
+        }
+        Commands::Watch {
+            paths,
+            template,
+            prompt,
+            system,
+            debounce,
+            no_diff,
+        } => {
+            let options = WatchOptions {
+                template,
+                prompt,
+                system,
+                debounce: std::time::Duration::from_millis(debounce),
+                diff: !no_diff,
+            };
+            watch(cli, paths, options).await?;
+        }
+        Commands::Digest {
+            feeds,
+            since,
+            chunking,
+        } => {
+            let since = chrono::Utc::now() - digest::parse_since(&since)?;
+            let urls = digest::feed_urls(
+                &std::fs::read_to_string(&feeds)
+                    .with_context(|| format!("failed to read {}", feeds.display()))?,
+            );
+            if urls.is_empty() {
+                return Err(anyhow!("{} lists no feeds", feeds.display()));
+            }
+            let client = build_client(&cli)?;
+            let sections = digest::digest(&client, &urls, since, &chunking.into()).await?;
+            print!("{}", digest::markdown(&sections, since));
+        }
+        Commands::DiffExplain { range, json } => {
+            let diff = match range {
+                Some(range) => git::range_diff(&range)?,
+                None => read_input(None, None)?,
+            };
+            if diff.trim().is_empty() {
+                return Err(anyhow!("the diff is empty"));
+            }
+            let explanation = git::explain_diff(&build_client(&cli)?, &diff).await?;
+            if json {
+                print_json(&explanation)?;
+            } else {
+                print!("{}", explanation);
+            }
+        }
+        Commands::Gh { command } => match command {
+            GhCommand::Triage { issue, repo } => {
+                let repo = match repo {
+                    Some(repo) => Some(repo),
+                    // Only needed, and only looked up, for an issue given by number
+                    None if !issue.contains('/') => github::origin_repo(),
+                    None => None,
+                };
+                let issue = github::IssueRef::parse(&issue, repo.as_deref())?;
+                let report = github::triage(&build_client(&cli)?, issue).await?;
+                print_json(&report)?;
+            }
+            GhCommand::PrDescribe {
+                base,
+                update,
+                pr,
+                repo,
+                json,
+            } => {
+                let base = base.unwrap_or_else(github::default_base);
+                let description = github::describe_pr(&build_client(&cli)?, &base).await?;
+                if json {
+                    print_json(&description)?;
+                } else {
+                    println!("{}\n\n{}", description.title, description.body());
+                }
+                if update {
+                    let repo = repo.or_else(github::origin_repo).ok_or_else(|| {
+                        anyhow!("origin is not a GitHub repository; give it with --repo")
+                    })?;
+                    let url = github::update_pr(&description, &repo, pr).await?;
+                    eprintln!("Updated {}", url);
+                }
+            }
+        },
+        Commands::Git { command } => match command {
+            GitCommand::InstallHooks { force } => {
+                for path in git::install_hooks(&git::hooks_dir()?, force)? {
+                    eprintln!("Installed {}", path.display());
+                }
+            }
+            GitCommand::Hook {
+                name,
+                args,
+                timeout,
+            } => {
+                // Hooks must never block a commit, so failures and timeouts are silent
+                let run = async {
+                    let client = build_client(&cli)?;
+                    git::run_hook(&client, &name, &args).await
+                };
+                let _ = tokio::time::timeout(std::time::Duration::from_secs(timeout), run).await;
+            }
+        },
+        Commands::Snippets { command } => {
+            snippets(cli, command).await?;
+        }
+        Commands::Fixtures { command } => {
+            let (mode, args) = match command {
+                FixturesCommand::Record { dir, args } => (FixtureMode::Record(dir), args),
+                FixturesCommand::Replay { dir, args } => (FixtureMode::Replay(dir), args),
+            };
+            let mut inner = Cli::try_parse_from(std::iter::once("ellm".to_string()).chain(args))
+                .unwrap_or_else(|error| error.exit());
+            if let Some(selector) = inner.select.clone() {
+                let _ = SELECT.set(selector);
+            }
+            inner.fixtures = Some(mode);
+            return Box::pin(run(inner)).await;
+        }
+        Commands::Tokens {
+            text,
+            file,
+            system,
+            offline,
+            json,
+        } => {
+            let input = with_snippet(snippet, read_input(text, file.as_deref())?);
+            let system = snippet_system(system);
+            tokens(cli, input, system, offline, json).await?;
+        }
+        Commands::Limits { json } => {
+            limits(cli, json).await?;
+        }
+        Commands::Completions { shell } => {
+            completions::write_registration(&shell, &mut std::io::stdout())?;
+        }
+        #[cfg(feature = "admin")]
+        Commands::Admin { admin_key, command } => {
+            admin(admin_key, command).await?;
+        }
+        #[cfg(feature = "grpc")]
+        Commands::GrpcServe { addr } => {
+            let client =
+                std::sync::Arc::new(ellm::reload::ReloadingClient::new(build_client(&cli)?));
+            let config_path = match &cli.config {
+                Some(path) => path.clone(),
+                None => Config::config_path()?,
+            };
+            client.clone().watch(
+                vec![config_path, Policy::system_path()],
+                std::time::Duration::from_secs(2),
+                move || build_client(&cli),
+            );
+            eprintln!("ellm gRPC service listening on {}", addr);
+            ellm::grpc::serve(addr, client).await?;
+        }
+        #[cfg(feature = "tui")]
+        Commands::Tui { system } => tui::run(build_client(&cli)?, system).await?,
+        Commands::Stats { json, clear } => stats(json, clear)?,
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn council(
+    cli: Cli,
+    question: String,
+    models: Vec<String>,
+    aggregate: String,
+    synthesizer: Option<String>,
+    system: Option<String>,
+    json: bool,
+) -> Result<()> {
+    let client = build_client(&cli)?;
+    let aggregator = match aggregate.parse().map_err(|error: String| anyhow!(error))? {
+        Aggregator::Synthesize { .. } => Aggregator::Synthesize { model: synthesizer },
+        aggregator => aggregator,
+    };
+    let messages = Messages::new().push_user(question).clone();
+    let fan_out = client
+        .fan_out(messages, system, &models, aggregator)
+        .await?;
+
+    if json {
+        print_json(&fan_out)?;
+        return Ok(());
+    }
+    for answer in &fan_out.answers {
+        if let Some(error) = &answer.error {
+            diagnostics::warn(
+                "council",
+                format!("{} did not answer: {}", answer.model, error),
+                json!({"model": answer.model}),
+            );
+        }
+    }
+    println!("{}", fan_out.answer);
+    Ok(())
+}
+
+async fn sweep(
+    cli: Cli,
+    prompt: PathBuf,
+    temperatures: Vec<f32>,
+    mut models: Vec<String>,
+    system: Option<String>,
+    judge: Option<String>,
+    json: bool,
+) -> Result<()> {
+    let prompt = std::fs::read_to_string(&prompt)
+        .with_context(|| format!("failed to read {}", prompt.display()))?;
+    if models.is_empty() {
+        models.push(load_config(&cli)?.model);
+    }
+
+    let points = SweepPoint::grid(&models, &temperatures);
+    let requests = points.len() * if judge.is_some() { 2 } else { 1 };
+    let progress = Progress::of_requests(requests as u64);
+    let client = build_client(&cli)?.with_observer(progress.clone());
+    let messages = Messages::new().push_user(prompt).clone();
+    let runs = client
+        .sweep(messages, system, &points, judge.as_deref())
+        .await;
+    progress.finish();
+    let runs = runs?;
+
+    if json {
+        print_json(&runs)?;
+        return Ok(());
+    }
+
+    println!(
+        "{:>3}  {:<32}  {:>11}  {:>8}  {:>6}  {:>6}",
+        "#", "model", "temperature", "latency", "output", "score"
+    );
+    for (index, run) in runs.iter().enumerate() {
+        let score = match (&run.judgement, &run.error) {
+            (Some(judgement), _) => format!("{:.1}", judgement.score),
+            (None, Some(_)) => "failed".to_string(),
+            (None, None) => "-".to_string(),
+        };
+        println!(
+            "{:>3}  {:<32}  {:>11}  {:>7.1}s  {:>6}  {:>6}",
+            index + 1,
+            run.point.model,
+            run.point.temperature,
+            run.latency_ms as f64 / 1000.0,
+            run.output_tokens,
+            score
+        );
+    }
+    for (index, run) in runs.iter().enumerate() {
+        println!(
+            "\n## {}. {} at temperature {}\n",
+            index + 1,
+            run.point.model,
+            run.point.temperature
+        );
+        match (&run.output, &run.error) {
+            (Some(output), _) => println!("{}", output.trim()),
+            (None, error) => println!("Failed: {}", error.as_deref().unwrap_or_default()),
+        }
+        if let Some(judgement) = &run.judgement {
+            println!("\nScore {:.1}: {}", judgement.score, judgement.reason);
+        }
+    }
+    Ok(())
+}
+
+async fn critique(
+    cli: Cli,
+    prompt: String,
+    system: Option<String>,
+    options: CritiqueOptions,
+    json: bool,
+) -> Result<()> {
+    let client = build_client(&cli)?;
+    let messages = Messages::new().push_user(prompt).clone();
+    let critiqued = client.critique(messages, system, &options).await?;
+
+    if json {
+        print_json(&critiqued)?;
+        return Ok(());
+    }
+    for (index, round) in critiqued.rounds.iter().enumerate() {
+        let verdict = if round.review.satisfied {
+            "satisfied"
+        } else {
+            "revising"
+        };
+        eprintln!(
+            "Round {} ({}): {}\n",
+            index + 1,
+            verdict,
+            round.review.critique.trim()
+        );
+    }
+    println!("{}", critiqued.answer);
+    Ok(())
+}
+
+/// Run the cases of `path` and compare their outputs with the golden ones,
+/// or store the outputs as the golden ones with `update`
+async fn eval_golden(
+    cli: Cli,
+    path: PathBuf,
+    options: GoldenOptions,
+    update: bool,
+    json: bool,
+) -> Result<()> {
+    let cases = EvalCase::parse_jsonl(
+        &std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?,
+    )?;
+
+    let progress = Progress::of_requests(cases.len() as u64);
+    let client = build_client(&cli)?.with_observer(progress.clone());
+    let report = client.run_golden(&cases, &options).await;
+    progress.finish();
+    let report = report?;
+
+    if update {
+        let mut jsonl = String::new();
+        for case in report.updated_cases(&cases) {
+            jsonl.push_str(&serde_json::to_string(&case)?);
+            jsonl.push('\n');
+        }
+        std::fs::write(&path, jsonl)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        eprintln!(
+            "Stored {} golden outputs in {}",
+            cases.len(),
+            path.display()
+        );
+        return Ok(());
+    }
+
+    if json {
+        print_json(&report)?;
+    } else {
+        for case in &report.cases {
+            let input: String = case
+                .input
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .chars()
+                .take(60)
+                .collect();
+            let verdict = if case.passed { "pass" } else { "FAIL" };
+            println!("{} {:.2}  {}", verdict, case.score, input);
+            match (&case.golden, case.passed) {
+                (None, _) => println!("    no golden output; store one with --update"),
+                (Some(golden), false) => {
+                    for line in line_diff(golden, &case.output) {
+                        println!("    {}", line);
+                    }
+                }
+                (Some(_), true) => {}
+            }
+        }
+    }
+
+    let failed = report.failures().count();
+    if failed > 0 {
+        return Err(anyhow!(
+            "{} of {} cases no longer match their golden outputs ({} comparison)",
+            failed,
+            report.cases.len(),
+            report.comparison
+        ));
+    }
+    eprintln!(
+        "All {} cases match their golden outputs",
+        report.cases.len()
+    );
+    Ok(())
+}
+
+async fn optimize_prompt(
+    cli: Cli,
+    prompt: PathBuf,
+    cases: PathBuf,
+    options: OptimizeOptions,
+    json: bool,
+) -> Result<()> {
+    let prompt = std::fs::read_to_string(&prompt)
+        .with_context(|| format!("failed to read {}", prompt.display()))?;
+    let cases = EvalCase::parse_jsonl(
+        &std::fs::read_to_string(&cases)
+            .with_context(|| format!("failed to read {}", cases.display()))?,
+    )?;
+
+    // Each prompt answers and judges every case, and each rewrite is a request
+    let requests = (options.rounds + 1) * cases.len() * 2 + options.rounds;
+    let progress = Progress::of_requests(requests as u64);
+    let client = build_client(&cli)?.with_observer(progress.clone());
+    let optimization = client
+        .optimize_prompt(prompt.trim(), &cases, &options)
+        .await;
+    progress.finish();
+    let optimization = optimization?;
+
+    if json {
+        print_json(&optimization)?;
+        return Ok(());
+    }
+    eprintln!("original: {:.2}", optimization.original.score);
+    for (index, variant) in optimization.variants.iter().enumerate() {
+        eprintln!(
+            "variant {}: {:.2} ({})",
+            index + 1,
+            variant.score,
+            variant.changes.as_deref().unwrap_or_default().trim()
+        );
+    }
+    let best = optimization.best();
+    eprintln!(
+        "\nBest: {:.2}, {:+.2} over the original\n",
+        best.score,
+        optimization.improvement()
+    );
+    println!("{}", best.prompt);
+    Ok(())
+}
+
+/// Read command input from an argument, a file, or stdin, in that order
+fn read_input(text: Option<String>, file: Option<&Path>) -> Result<String> {
+    if let Some(text) = text {
+        return Ok(text);
+    }
+
+    if let Some(file) = file {
+        let bytes =
+            std::fs::read(file).with_context(|| format!("failed to read {}", file.display()))?;
+        return decode_file(file, &bytes)
+            .ok_or_else(|| anyhow!("{} looks like a binary file, not text", file.display()));
+    }
+
+    let mut input = String::new();
+    std::io::stdin().read_to_string(&mut input)?;
+    Ok(input)
+}
+
+/// Decode a text file, warning when it isn't UTF-8; `None` if it looks binary
+fn decode_file(path: &Path, bytes: &[u8]) -> Option<String> {
+    let (text, encoding) = encoding::decode(bytes)?;
+    if encoding != Encoding::Utf8 {
+        diagnostics::warn(
+            "encoding",
+            format!("{} is not UTF-8; read it as {}", path.display(), encoding),
+            json!({"path": path, "encoding": encoding.as_str()}),
+        );
+    }
+    Some(text)
+}
+
+/// Generate and print a regex, returning whether it passed every sample
+async fn regex(
+    cli: Cli,
+    description: String,
+    samples: PathBuf,
+    full_match: bool,
+    attempts: usize,
+    json: bool,
+) -> Result<bool> {
+    let client = build_client(&cli)?;
+    let samples = RegexSample::parse_lines(
+        &std::fs::read_to_string(&samples)
+            .with_context(|| format!("failed to read {}", samples.display()))?,
+    );
+    if samples.is_empty() {
+        return Err(anyhow!("the samples file has no samples"));
+    }
+
+    let generated = client
+        .generate_regex(&description, &samples, full_match, attempts)
+        .await?;
+
+    if json {
+        print_json(&generated)?;
+    } else {
+        println!("{}", generated.pattern);
+        eprintln!("{}", generated.explanation);
+        if !generated.is_success() {
+            eprintln!(
+                "Warning: {} of {} samples still fail after {} attempts:",
+                generated.failures.len(),
+                samples.len(),
+                generated.attempts
+            );
+            for failure in &generated.failures {
+                let expected = if failure.should_match {
+                    "should match"
+                } else {
+                    "should not match"
+                };
+                eprintln!("  {:?} ({})", failure.text, expected);
+            }
+        }
+    }
+
+    Ok(generated.is_success())
+}
+
+struct WatchOptions {
+    template: Option<PathBuf>,
+    prompt: Option<String>,
+    system: Option<String>,
+    debounce: std::time::Duration,
+    diff: bool,
+}
+
+/// Send the prompt with the files attached every time they or the template change
+async fn watch(cli: Cli, paths: Vec<PathBuf>, options: WatchOptions) -> Result<()> {
+    let client = build_client(&cli)?;
+    let mut watched = paths.clone();
+    watched.extend(options.template.clone());
+    let mut watcher = FileWatcher::new(watched);
+    let mut previous: Option<String> = None;
+
+    for run in 1.. {
+        eprintln!(
+            "--- run {} at {} ---",
+            run,
+            chrono::Local::now().format("%H:%M:%S")
+        );
+        match watch_run(&client, &paths, &options).await {
+            Ok(response) => {
+                match previous.as_deref() {
+                    Some(previous) if options.diff => {
+                        let diff = line_diff(previous, &response);
+                        if diff.iter().all(|line| matches!(line, DiffLine::Same(_))) {
+                            eprintln!("(response unchanged)");
+                        } else {
+                            for line in diff {
+                                println!("{}", line);
+                            }
+                        }
+                    }
+                    _ => println!("{}", response),
+                }
+                previous = Some(response);
+            }
+            // Keep watching, e.g. while a file is being fixed
+            Err(error) => eprintln!("Error: {:#}", error),
+        }
+        watcher
+            .changed(std::time::Duration::from_millis(200), options.debounce)
+            .await;
+    }
+
+    Ok(())
+}
+
+async fn watch_run(client: &Client, paths: &[PathBuf], options: &WatchOptions) -> Result<String> {
+    let prompt = match &options.template {
+        Some(template) => std::fs::read_to_string(template)
+            .with_context(|| format!("failed to read {}", template.display()))?,
+        None => options.prompt.clone().unwrap_or_default(),
+    };
+    let message = with_attachments(prompt, paths, false, &[], &client.config().images)?;
+    let messages = Messages::new().push(message).clone();
+    Ok(client
+        .send_message(messages, None, options.system.clone())
+        .await?)
+}
+
+/// `text` preceded by the prompt of the `--snippet`, if any
+fn with_snippet(snippet: Option<&Snippet>, text: String) -> String {
+    match snippet {
+        Some(snippet) => snippet.apply(&text),
+        None => text,
+    }
+}
+
+/// `--instructions` preceded by the prompt of the `--snippet`, if any
+fn snippet_instructions(snippet: Option<&Snippet>, instructions: Option<String>) -> Option<String> {
+    Some(with_snippet(snippet, instructions.unwrap_or_default())).filter(|text| !text.is_empty())
+}
+
+async fn snippets(cli: Cli, command: SnippetCommand) -> Result<()> {
+    let store = SnippetStore::open_default()?;
+
+    match command {
+        SnippetCommand::List => {
+            let names = store.list()?;
+            if names.is_empty() {
+                eprintln!("No snippets saved in {}", store.dir().display());
+            }
+            for name in names {
+                println!("{}", name);
+            }
+        }
+        SnippetCommand::Show { name } => {
+            store.load(&name)?;
+            print!("{}", std::fs::read_to_string(store.path(&name)?)?);
+        }
+        SnippetCommand::Save {
+            name,
+            prompt,
+            system,
+            from,
+        } => {
+            let snippet = match from {
+                Some(path) => Snippet::from_path(&path)?,
+                None => Snippet { prompt, system },
+            };
+            let path = store.save(&name, &snippet)?;
+            eprintln!("Saved {}", path.display());
+        }
+        SnippetCommand::Remove { name } => {
+            store.remove(&name)?;
+        }
+        SnippetCommand::Use { name, text } => {
+            let snippet = store.load(&name)?;
+            let message = with_attachments(
+                snippet.apply(&text.unwrap_or_default()),
+                &[],
+                false,
+                &[],
+                &ImageConfig::default(),
+            )?;
+            send_message(
+                cli,
+                Messages::new().push(message).clone(),
+                snippet.system,
+                false,
+                "text",
+                false,
+                Vec::new(),
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The user's message, preceded by the given files
+fn with_attachments(
+    message: String,
+    files: &[PathBuf],
+    cache: bool,
+    images: &[PathBuf],
+    limits: &ImageConfig,
+) -> Result<Message> {
+    let mut blocks = files
+        .iter()
+        .chain(images)
+        .map(|path| attachment(path, limits))
+        .collect::<Result<Vec<_>>>()?;
+    // Caching the last document caches everything before it too
+    let last_document = blocks.iter_mut().rev().find_map(|block| match block {
+        ContentBlock::Document { cache_control, .. } => Some(cache_control),
+        _ => None,
+    });
+    if let (Some(cache_control), true) = (last_document, cache) {
+        *cache_control = Some(CacheControl::Ephemeral);
+    }
+
+    let content = if blocks.is_empty() {
+        Content::Text(message)
+    } else {
+        blocks.push(ContentBlock::Text { text: message });
+        Content::Blocks(blocks)
+    };
+    Ok(Message {
+        role: "user".into(),
+        content,
+    })
+}
+
+/// A file as the block for what it contains: an image, scaled down if it's
+/// too large, a PDF document, or a text document
+fn attachment(path: &Path, limits: &ImageConfig) -> Result<ContentBlock> {
+    let bytes = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    let title = path.file_name().map(|name| name.to_string_lossy().into());
+    match encoding::media_type(&bytes) {
+        Some("application/pdf") => Ok(ContentBlock::pdf_document(title, &bytes)),
+        Some(media_type) => match limits
+            .fit(&bytes)
+            .with_context(|| format!("fitting {}", path.display()))?
+        {
+            Some(fitted) => {
+                diagnostics::info(
+                    "image",
+                    format!(
+                        "scaled {} down from {}x{} to {}x{} ({} KB to {} KB)",
+                        path.display(),
+                        fitted.original_width,
+                        fitted.original_height,
+                        fitted.width,
+                        fitted.height,
+                        bytes.len() / 1024,
+                        fitted.bytes.len() / 1024
+                    ),
+                    json!({
+                        "path": path,
+                        "original_width": fitted.original_width,
+                        "original_height": fitted.original_height,
+                        "width": fitted.width,
+                        "height": fitted.height,
+                        "original_bytes": bytes.len(),
+                        "bytes": fitted.bytes.len(),
+                        "media_type": fitted.media_type,
+                    }),
+                );
+                Ok(ContentBlock::image(fitted.media_type, &fitted.bytes))
+            }
+            None => Ok(ContentBlock::image(media_type, &bytes)),
+        },
+        None => match decode_file(path, &bytes) {
+            Some(text) => Ok(ContentBlock::text_document(title, text)),
+            None => Err(anyhow!(
+                "{} can't be attached: only text, PDF and PNG, JPEG, GIF or WebP image files can",
+                path.display()
+            )),
+        },
+    }
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let cli = Cli::parse();
+async fn send_message(
+    cli: Cli,
+    messages: Messages,
+    system: Option<String>,
+    show_thinking: bool,
+    output: &str,
+    verify: bool,
+    mut constraints: Vec<Constraint>,
+) -> Result<String> {
+    let caching = cli.cache_system
+        || messages.iter().any(|message| {
+            matches!(&message.content, Content::Blocks(blocks) if blocks.iter().any(|block| {
+                matches!(block, ContentBlock::Document { cache_control: Some(_), .. })
+            }))
+        });
+    let client = build_client(&cli)?;
+    let pipeline = client.config().pipeline_for("send", &cli.post);
+    let json = output == "json";
+    if let (Some(language), false) = (&client.config().respond_in, verify) {
+        constraints.push(Constraint::Language(language.clone()));
+    }
+    if !constraints.is_empty() {
+        return send_constrained(&client, messages, system, &constraints, &pipeline, json).await;
+    }
+    // Text can only be printed as it arrives when nothing has to be done to it first
+    let live = !json
+        && pipeline.is_empty()
+        && !verify
+        && !client.config().guardrails.filters_output()
+        && !client.has_response_hooks();
 
-    match cli.command.clone() {
-        Commands::Send { message } => {
-            send_message(cli, message).await?;
+    eprintln!("Sending message to Claude...\n");
+
+    let mut thinking_shown = false;
+    let stream = client
+        .stream_message(messages.clone(), system.clone(), Vec::new(), None)
+        .await?;
+    let (response, interrupted) = stream_interruptibly(stream, |event| {
+        match event {
+            StreamEvent::ThinkingDelta { thinking, .. } if show_thinking && !json => {
+                eprint!("{}", dimmed(&thinking));
+                thinking_shown = true;
+            }
+            StreamEvent::TextDelta { text, .. } => {
+                if std::mem::take(&mut thinking_shown) {
+                    eprintln!("\n");
+                }
+                if live {
+                    print!("{}", text);
+                    std::io::stdout().flush()?;
+                }
+            }
+            _ => {}
         }
-        Commands::Config => {
-            show_config(cli)?;
+        Ok(())
+    })
+    .await?;
+    let mut text = response.text();
+    let usage = &response.usage;
+
+    let verified = if verify && !interrupted {
+        eprintln!("Verifying the response...\n");
+        let verified = client.verify(messages, system, &text).await?;
+        if !json {
+            for check in &verified.checks {
+                eprintln!("Q: {}\nA: {}\n", check.question, check.answer);
+            }
+        }
+        text = verified.answer.clone();
+        Some(verified)
+    } else {
+        None
+    };
+
+    if json {
+        let thinking = response.thinking();
+        let output = serde_json::json!({
+            "text": pipeline.apply(&text)?,
+            "thinking": (!thinking.is_empty()).then_some(thinking),
+            "model": response.model,
+            "stop_reason": response.stop_reason,
+            "usage": usage,
+            "interrupted": interrupted,
+            "verification": verified,
+        });
+        print_json(&output)?;
+    } else if live {
+        println!();
+    } else {
+        println!("{}", pipeline.apply(&text)?);
+    }
+
+    match response.stop_reason {
+        Some(StopReason::MaxTokens) => diagnostics::warn(
+            "truncated",
+            format!(
+                "the response was cut off at max_tokens after {} output tokens; raise --max-tokens or --max-continuations to get all of it",
+                usage.output_tokens
+            ),
+            json!({"stop_reason": "max_tokens", "output_tokens": usage.output_tokens}),
+        ),
+        Some(StopReason::ModelContextWindowExceeded) => diagnostics::warn(
+            "truncated",
+            "the response was cut off at the end of the model's context window",
+            json!({"stop_reason": "model_context_window_exceeded"}),
+        ),
+        _ => {}
+    }
+
+    if caching || usage.cache_read_input_tokens > 0 {
+        report_cache(&response.model, usage);
+    }
+
+    if interrupted {
+        return Err(Interrupted.into());
+    }
+
+    Ok(text)
+}
+
+/// Send a message, re-asking until the response meets `constraints`
+///
+/// The response can't be streamed, since it may be replaced.
+async fn send_constrained(
+    client: &Client,
+    messages: Messages,
+    system: Option<String>,
+    constraints: &[Constraint],
+    pipeline: &Pipeline,
+    json: bool,
+) -> Result<String> {
+    eprintln!("Sending message to Claude...\n");
+
+    let retry = StructuredRetry::default().with_failure_hook(|failed| {
+        diagnostics::warn(
+            "constraint",
+            format!(
+                "attempt {} broke the constraints: {}",
+                failed.attempt, failed.error
+            ),
+            json!({"attempt": failed.attempt, "violations": failed.error}),
+        )
+    });
+    let text = client
+        .send_constrained(messages, system, constraints, retry)
+        .await?;
+
+    if json {
+        let output = serde_json::json!({
+            "text": pipeline.apply(&text)?,
+            "model": client.config().model,
+            "stop_reason": client.last_stop_reason(),
+            "usage": client.last_usage(),
+        });
+        print_json(&output)?;
+    } else {
+        println!("{}", pipeline.apply(&text)?);
+    }
+
+    Ok(text)
+}
+
+/// Stream a response into `handle` until it completes or Ctrl-C is pressed
+///
+/// Ctrl-C abandons only the response: what arrived so far is returned along
+/// with `true`, so it can still be shown. Outside of a stream, Ctrl-C exits
+/// immediately as usual.
+async fn stream_interruptibly(
+    mut stream: MessageStream<'_>,
+    mut handle: impl FnMut(StreamEvent) -> Result<()>,
+) -> Result<(StreamedMessage, bool)> {
+    let (sender, mut interrupt) = tokio::sync::oneshot::channel();
+    catch_interrupts();
+    *INTERRUPT.lock().unwrap() = Some(sender);
+
+    let result = async {
+        loop {
+            tokio::select! {
+                event = stream.next_event() => match event {
+                    Some(event) => handle(event?)?,
+                    None => return Ok((stream.finish().await?, false)),
+                },
+                _ = &mut interrupt => return Ok((stream.abort()?, true)),
+            }
+        }
+    }
+    .await;
+    INTERRUPT.lock().unwrap().take();
+    result
+}
+
+/// Take over Ctrl-C, sending it to the streaming response if there is one
+///
+/// Once Ctrl-C is caught it stays caught for the rest of the process, so
+/// outside of a stream this exits the way the signal would have.
+fn catch_interrupts() {
+    static CAUGHT: Once = Once::new();
+    CAUGHT.call_once(|| {
+        tokio::spawn(async {
+            while tokio::signal::ctrl_c().await.is_ok() {
+                match INTERRUPT.lock().unwrap().take() {
+                    Some(stream) => {
+                        let _ = stream.send(());
+                    }
+                    None => std::process::exit(130),
+                }
+            }
+        });
+    });
+}
+
+/// Print the prompt cache activity of a request and what it saved
+fn report_cache(model: &str, usage: &Usage) {
+    let written = usage.cache_creation_input_tokens;
+    let read = usage.cache_read_input_tokens;
+    let mut message = format!("Cache: {} tokens written, {} read", written, read);
+    let savings = pricing::price(model).map(|price| price.cache_savings(written, read));
+    match savings {
+        Some(savings) if savings < 0.0 => message.push_str(&format!(
+            " (${:.4} extra until the cache is read)",
+            -savings
+        )),
+        Some(savings) => message.push_str(&format!(" (saved ${:.4})", savings)),
+        None => {}
+    }
+    diagnostics::info(
+        "cache",
+        message,
+        json!({"written_tokens": written, "read_tokens": read, "savings_usd": savings}),
+    );
+    if written == 0 && read == 0 {
+        diagnostics::info(
+            "cache",
+            "The cached prefix may be shorter than the model's minimum cacheable length",
+            json!({}),
+        );
+    }
+}
+
+/// Render text faintly on a terminal, to set it apart from the response
+fn dimmed(text: &str) -> String {
+    if std::io::stderr().is_terminal() {
+        format!("\x1b[2m{}\x1b[0m", text)
+    } else {
+        text.to_string()
+    }
+}
+
+fn usage_by_workspace(json: bool) -> Result<()> {
+    let ledger = Ledger::open_default()?;
+    let totals = ledger.totals_by_workspace()?;
+
+    if json {
+        let totals: std::collections::BTreeMap<&str, _> = totals
+            .iter()
+            .map(|(workspace, totals)| (workspace.as_deref().unwrap_or(""), totals))
+            .collect();
+        print_json(&totals)?;
+        return Ok(());
+    }
+
+    if totals.is_empty() {
+        eprintln!("No usage recorded yet in {}", ledger.path().display());
+        return Ok(());
+    }
+
+    println!(
+        "{:<20}  {:>8}  {:>12}  {:>12}  {:>10}",
+        "workspace", "requests", "input", "output", "cost (USD)"
+    );
+    for (workspace, totals) in &totals {
+        println!(
+            "{:<20}  {:>8}  {:>12}  {:>12}  {:>10.4}",
+            workspace.as_deref().unwrap_or("(none)"),
+            totals.requests,
+            totals.input_tokens
+                + totals.cache_creation_input_tokens
+                + totals.cache_read_input_tokens,
+            totals.output_tokens,
+            totals.cost_usd
+        );
+    }
+
+    Ok(())
+}
+
+fn usage_report(group_by: GroupBy, period: Period, format: &str) -> Result<()> {
+    let ledger = Ledger::open_default()?;
+    let rows = ledger::report(&ledger.entries()?, group_by, period);
+
+    match format {
+        "json" => print_json(&rows)?,
+        "csv" => {
+            println!(
+                "period,group,requests,input_tokens,output_tokens,cache_creation_input_tokens,\
+                 cache_read_input_tokens,cost_usd,cache_savings_usd,batch_savings_usd"
+            );
+            for row in &rows {
+                let totals = &row.totals;
+                println!(
+                    "{},{},{},{},{},{},{},{:.6},{:.6},{:.6}",
+                    csv_field(&row.period),
+                    csv_field(&row.group),
+                    totals.requests,
+                    totals.input_tokens,
+                    totals.output_tokens,
+                    totals.cache_creation_input_tokens,
+                    totals.cache_read_input_tokens,
+                    totals.cost_usd,
+                    totals.cache_savings_usd,
+                    totals.batch_savings_usd
+                );
+            }
+        }
+        _ => {
+            if rows.is_empty() {
+                eprintln!("No usage recorded yet in {}", ledger.path().display());
+                return Ok(());
+            }
+
+            println!(
+                "{:<10}  {:<28}  {:>8}  {:>12}  {:>12}  {:>10}  {:>12}  {:>12}",
+                period.to_string(),
+                "group",
+                "requests",
+                "input",
+                "output",
+                "cost (USD)",
+                "cache saved",
+                "batch saved"
+            );
+            for row in &rows {
+                let totals = &row.totals;
+                println!(
+                    "{:<10}  {:<28}  {:>8}  {:>12}  {:>12}  {:>10.4}  {:>12.4}  {:>12.4}",
+                    row.period,
+                    if row.group.is_empty() {
+                        "(none)"
+                    } else {
+                        &row.group
+                    },
+                    totals.requests,
+                    totals.input_tokens
+                        + totals.cache_creation_input_tokens
+                        + totals.cache_read_input_tokens,
+                    totals.output_tokens,
+                    totals.cost_usd,
+                    totals.cache_savings_usd,
+                    totals.batch_savings_usd
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the local statistics per command and snippet, or forget them
+fn stats(json: bool, clear: bool) -> Result<()> {
+    let log = StatsLog::open_default()?;
+    if clear {
+        log.clear()?;
+        eprintln!("Cleared {}", log.path().display());
+        return Ok(());
+    }
+
+    let rows = stats::by_command(&log.invocations()?);
+    if json {
+        print_json(&rows)?;
+        return Ok(());
+    }
+    if rows.is_empty() {
+        eprintln!("No invocations recorded yet in {}", log.path().display());
+        return Ok(());
+    }
+
+    println!(
+        "{:<28}  {:>6}  {:>6}  {:>9}  {:>8}  {:>12}  {:>12}  {:>10}",
+        "command", "runs", "failed", "mean time", "requests", "input", "output", "cost (USD)"
+    );
+    for row in &rows {
+        let name = match &row.snippet {
+            Some(snippet) => format!("{} ({})", row.command, snippet),
+            None => row.command.clone(),
+        };
+        println!(
+            "{:<28}  {:>6}  {:>6}  {:>8.1}s  {:>8}  {:>12}  {:>12}  {:>10.4}",
+            name,
+            row.invocations,
+            row.failures,
+            row.mean_duration().as_secs_f64(),
+            row.totals.requests,
+            row.totals.input_tokens,
+            row.totals.output_tokens,
+            row.totals.cost_usd
+        );
+    }
+    Ok(())
+}
+
+/// Quote a CSV field when it contains a separator, quote or newline
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+async fn batch(cli: Cli, command: cli::BatchCommand) -> Result<()> {
+    use cli::BatchCommand;
+    use ellm::batch::{self, Batch};
+
+    let client = build_client(&cli)?;
+    let print_status = |batch: &Batch| {
+        let counts = &batch.request_counts;
+        println!("{}  {}", batch.id, batch.processing_status);
+        println!(
+            "  processing {}, succeeded {}, errored {}, canceled {}, expired {}",
+            counts.processing, counts.succeeded, counts.errored, counts.canceled, counts.expired
+        );
+    };
+
+    match command {
+        BatchCommand::Submit {
+            requests,
+            template,
+            input,
+            failed_in,
+        } => {
+            let mut requests = read_batch_requests(requests, template, input)?;
+            if let Some(previous) = failed_in {
+                let mut results = Vec::new();
+                client
+                    .stream_batch_results(&previous, |result| {
+                        results.push(result);
+                        Ok(())
+                    })
+                    .await?;
+                requests = batch::unsucceeded(requests, &results);
+                if requests.is_empty() {
+                    eprintln!("Every request succeeded in {}", previous);
+                    return Ok(());
+                }
+            }
+
+            eprintln!("Submitting {} requests", requests.len());
+            let batch = client.create_batch(requests).await?;
+            println!("{}", batch.id);
+        }
+        BatchCommand::Status { id, json } => {
+            let batch = client.batch(&id).await?;
+            if json {
+                print_json(&batch)?;
+            } else {
+                print_status(&batch);
+            }
         }
-        Commands::Bool { question } => {
-            match bool(cli, question).await?.answer {
-                true => (),
-                // TODO: is this actually kind with tokio?
-                false => std::process::exit(1),
+        BatchCommand::Wait { id, interval } => {
+            let progress = Progress::new(0, "requests");
+            let mut last = String::new();
+            loop {
+                let batch = client.batch(&id).await?;
+                let counts = &batch.request_counts;
+                let done = counts.succeeded + counts.errored + counts.canceled + counts.expired;
+                progress.set_length(done + counts.processing);
+                progress.set_position(done);
+                progress.set_message(&batch.processing_status);
+                let status = format!(
+                    "{}: processing {}, succeeded {}, errored {}, canceled {}, expired {}",
+                    batch.processing_status,
+                    counts.processing,
+                    counts.succeeded,
+                    counts.errored,
+                    counts.canceled,
+                    counts.expired
+                );
+                if status != last {
+                    progress.suspend(|| {
+                        diagnostics::info(
+                            "batch_progress",
+                            &status,
+                            json!({"batch": batch.id, "processing_status": batch.processing_status}),
+                        )
+                    });
+                    last = status;
+                }
+                if batch.processing_status == "ended" {
+                    progress.finish();
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_secs(interval.max(1))).await;
+            }
+        }
+        BatchCommand::Results { id, output, split } => {
+            let create = |path: &Path| -> Result<Box<dyn Write>> {
+                let file = std::fs::File::create(path)
+                    .with_context(|| format!("creating {}", path.display()))?;
+                Ok(Box::new(std::io::BufWriter::new(file)))
             };
+            let mut outputs: BTreeMap<&str, Box<dyn Write>> = BTreeMap::new();
+            match (&output, &split) {
+                (_, Some(dir)) => {
+                    std::fs::create_dir_all(dir)
+                        .with_context(|| format!("creating {}", dir.display()))?;
+                    for kind in ["succeeded", "errored", "expired", "canceled"] {
+                        outputs.insert(kind, create(&dir.join(format!("{}.jsonl", kind)))?);
+                    }
+                }
+                (Some(path), None) => {
+                    outputs.insert("", create(path)?);
+                }
+                (None, None) => {
+                    outputs.insert("", Box::new(std::io::stdout().lock()));
+                }
+            }
+
+            let mut counts: BTreeMap<&str, u64> = BTreeMap::new();
+            client
+                .stream_batch_results(&id, |result| {
+                    client.record_batch_usage(&result);
+                    let kind = result.result.kind();
+                    *counts.entry(kind).or_default() += 1;
+                    let out = match outputs.get_mut(kind) {
+                        Some(out) => out,
+                        None => outputs.get_mut("").expect("an output for every result"),
+                    };
+                    writeln!(out, "{}", serde_json::to_string(&result)?)?;
+                    Ok(())
+                })
+                .await?;
+            for out in outputs.values_mut() {
+                out.flush()?;
+            }
+
+            let summary: Vec<String> = counts
+                .iter()
+                .map(|(kind, count)| format!("{} {}", count, kind))
+                .collect();
+            eprintln!("{}", summary.join(", "));
+        }
+        BatchCommand::Cancel { id } => {
+            print_status(&client.cancel_batch(&id).await?);
         }
     }
 
     Ok(())
 }
 
-async fn send_message(cli: Cli, message: String) -> Result<()> {
+/// Batch requests from a requests file, or from a template filled from an input file
+fn read_batch_requests(
+    requests: Option<PathBuf>,
+    template: Option<PathBuf>,
+    input: Option<PathBuf>,
+) -> Result<Vec<ellm::batch::BatchRequest>> {
+    match (requests, template, input) {
+        (_, Some(template), Some(input)) => {
+            let template: serde_json::Value = serde_json::from_str(
+                &std::fs::read_to_string(&template)
+                    .with_context(|| format!("reading {}", template.display()))?,
+            )
+            .with_context(|| format!("{}: invalid JSON", template.display()))?;
+            let inputs = std::fs::read_to_string(&input)
+                .with_context(|| format!("reading {}", input.display()))?;
+            Ok(ellm::batch::from_template(&template, &inputs)?)
+        }
+        (Some(requests), _, _) => {
+            let lines = std::fs::read_to_string(&requests)
+                .with_context(|| format!("reading {}", requests.display()))?;
+            lines
+                .lines()
+                .enumerate()
+                .filter(|(_, line)| !line.trim().is_empty())
+                .map(|(index, line)| {
+                    serde_json::from_str(line)
+                        .with_context(|| format!("line {}: invalid request", index + 1))
+                })
+                .collect()
+        }
+        _ => Err(anyhow!("pass a requests file or --template with --input")),
+    }
+}
+
+async fn tokens(
+    cli: Cli,
+    input: String,
+    system: Option<String>,
+    offline: bool,
+    json: bool,
+) -> Result<()> {
     let client = build_client(&cli)?;
+    let model = client.config().model.clone();
+
+    let counted = if offline {
+        None
+    } else {
+        let messages = Messages::new().push_user(input.clone()).clone();
+        match client.count_tokens(messages, system.clone()).await {
+            Ok(count) => Some(count),
+            Err(
+                error @ (ellm::ClaudeError::Network(_) | ellm::ClaudeError::RelayedNetwork { .. }),
+            ) if cfg!(feature = "estimate") => {
+                diagnostics::warn(
+                    "estimate",
+                    format!("{}; estimating locally instead", error),
+                    json!({}),
+                );
+                None
+            }
+            Err(error) => return Err(error.into()),
+        }
+    };
+    let (input_tokens, estimate) = match counted {
+        Some(count) => (count, None),
+        None => {
+            let estimate = estimate_offline(&input, system.as_deref(), &model)?;
+            (estimate.tokens, Some(estimate))
+        }
+    };
+    let context_window = ellm::models::context_window(&model);
+    let max_tokens = if client.config().auto_max_tokens {
+        let ceiling = u64::from(client.config().auto_max_tokens_ceiling());
+        context_window.map_or(ceiling, |window| {
+            ceiling.min(window.saturating_sub(input_tokens))
+        })
+    } else {
+        u64::from(client.config().max_tokens)
+    };
+    let remaining = context_window.map(|window| window as i64 - (input_tokens + max_tokens) as i64);
+    let cost = pricing::price(&model).map(|price| price.cost(input_tokens, 0, 0, 0));
 
-    println!("Sending message to Claude...\n");
+    if json {
+        let output = serde_json::json!({
+            "model": model,
+            "input_tokens": input_tokens,
+            "estimate": estimate,
+            "max_tokens": max_tokens,
+            "context_window": context_window,
+            "remaining": remaining,
+            "input_cost_usd": cost,
+        });
+        print_json(&output)?;
+        return Ok(());
+    }
 
-    let response = client
-        .send_message(Messages::new().push_user(message).clone(), None, None)
-        .await?;
+    println!("{:>14}: {}", "model", model);
+    match &estimate {
+        Some(estimate) => println!(
+            "{:>14}: ~{} (likely {}-{})",
+            "input tokens", estimate.tokens, estimate.low, estimate.high
+        ),
+        None => println!("{:>14}: {}", "input tokens", input_tokens),
+    }
+    if let (Some(window), Some(remaining)) = (context_window, remaining) {
+        println!("{:>14}: {}", "context window", window);
+        println!(
+            "{:>14}: {} after {} max tokens",
+            "remaining", remaining, max_tokens
+        );
+        if remaining < 0 {
+            diagnostics::warn(
+                "context_window",
+                "the prompt and --max-tokens don't fit in the context window",
+                json!({"remaining": remaining}),
+            );
+        }
+    }
+    if let Some(cost) = cost {
+        println!("{:>14}: ${:.4}", "input cost", cost);
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "estimate")]
+fn estimate_offline(input: &str, system: Option<&str>, model: &str) -> Result<ellm::TokenEstimate> {
+    let text = format!("{}\n{}", system.unwrap_or_default(), input);
+    Ok(ellm::estimate_tokens(&text, model))
+}
+
+#[cfg(not(feature = "estimate"))]
+fn estimate_offline(
+    _input: &str,
+    _system: Option<&str>,
+    _model: &str,
+) -> Result<ellm::TokenEstimate> {
+    Err(anyhow!(
+        "offline token estimates require building ellm with --features estimate"
+    ))
+}
+
+async fn limits(mut cli: Cli, json: bool) -> Result<()> {
+    // The limits only arrive as response headers, so make the cheapest request possible
+    cli.max_tokens = Some(MaxTokens::Fixed(1));
+    let mut config = client_config(&cli)?;
+    // A cut off response is the point, and thinking needs more than one token
+    config.max_continuations = 0;
+    config.thinking_budget = None;
+    let client = build_client_with(&cli, config)?;
+
+    eprintln!("Checking rate limits...");
+    let sent = client
+        .send_message(Messages::new().push_user("hi".into()).clone(), None, None)
+        .await;
+
+    // A rate limited request still reports the limits it ran into
+    let limits = match (client.rate_limits(), sent) {
+        (Some(limits), Ok(_)) => limits,
+        (Some(limits), Err(error)) => {
+            diagnostics::warn(
+                "limits",
+                format!("the request failed: {}", error),
+                json!({"error": error.to_string()}),
+            );
+            limits
+        }
+        (None, Err(error)) => return Err(error.into()),
+        (None, Ok(_)) => {
+            return Err(anyhow!(
+                "the API response did not include rate limit headers"
+            ))
+        }
+    };
+
+    if json {
+        print_json(&limits)?;
+        return Ok(());
+    }
+
+    for (name, limit) in limits.entries() {
+        println!("{:>14}: {}", name, limit);
+    }
+    if let Some(seconds) = limits.retry_after {
+        println!("{:>14}: {}s", "retry after", seconds);
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "admin")]
+async fn admin(admin_key: Option<String>, command: cli::AdminCommand) -> Result<()> {
+    use cli::{AdminCommand, WorkspaceCommand};
+    use ellm::admin::{AdminClient, ReportQuery};
+
+    let client = match admin_key {
+        Some(key) => AdminClient::new(key)?,
+        None => AdminClient::from_env()?,
+    };
+    let query = |report: &cli::ReportArgs| ReportQuery {
+        since: report.since.clone(),
+        until: report.until.clone(),
+        group_by: report.group_by.clone(),
+    };
+    // Reports identify workspaces by ID; show their names when grouping by them
+    let workspace_names: std::collections::HashMap<String, String> = match &command {
+        AdminCommand::Usage { report } | AdminCommand::Cost { report }
+            if !report.json && report.group_by.iter().any(|g| g == "workspace_id") =>
+        {
+            client
+                .workspaces()
+                .await?
+                .into_iter()
+                .map(|workspace| (workspace.id, workspace.name))
+                .collect()
+        }
+        _ => Default::default(),
+    };
+    let workspace = |id: &Option<String>| -> Option<String> {
+        id.as_ref()
+            .map(|id| workspace_names.get(id).unwrap_or(id).clone())
+    };
+    let group = |fields: [Option<String>; 2]| {
+        let labels: Vec<String> = fields.into_iter().flatten().collect();
+        labels.join(" ")
+    };
+
+    match command {
+        AdminCommand::Usage { report } => {
+            let buckets = client.usage(&query(&report)).await?;
+            if report.json {
+                print_json(&buckets)?;
+                return Ok(());
+            }
+            println!(
+                "{:<10}  {:>12}  {:>12}  {:>12}  {:>12}  group",
+                "date", "input", "output", "cache write", "cache read"
+            );
+            for bucket in &buckets {
+                for result in &bucket.results {
+                    println!(
+                        "{:<10}  {:>12}  {:>12}  {:>12}  {:>12}  {}",
+                        &bucket.starting_at[..10.min(bucket.starting_at.len())],
+                        result.uncached_input_tokens,
+                        result.output_tokens,
+                        result.cache_write_tokens(),
+                        result.cache_read_input_tokens,
+                        group([result.model.clone(), workspace(&result.workspace_id)]),
+                    );
+                }
+            }
+        }
+        AdminCommand::Cost { report } => {
+            let buckets = client.cost(&query(&report)).await?;
+            if report.json {
+                print_json(&buckets)?;
+                return Ok(());
+            }
+            let mut total = 0.0;
+            for bucket in &buckets {
+                for result in &bucket.results {
+                    total += result.amount();
+                    println!(
+                        "{:<10}  {:>10.2} {}  {}",
+                        &bucket.starting_at[..10.min(bucket.starting_at.len())],
+                        result.amount(),
+                        result.currency,
+                        group([workspace(&result.workspace_id), result.description.clone()]),
+                    );
+                }
+            }
+            println!("{:<10}  {:>10.2}", "total", total);
+        }
+        AdminCommand::Keys { json } => {
+            let keys = client.api_keys().await?;
+            if json {
+                print_json(&keys)?;
+                return Ok(());
+            }
+            for key in keys {
+                println!(
+                    "{}  {:<8}  {}  {}",
+                    key.id,
+                    key.status,
+                    key.name,
+                    key.workspace_id.as_deref().unwrap_or("default workspace")
+                );
+            }
+        }
+        AdminCommand::Workspaces { command } => match command.unwrap_or(WorkspaceCommand::List) {
+            WorkspaceCommand::List => {
+                for workspace in client.workspaces().await? {
+                    let archived = if workspace.archived_at.is_some() {
+                        "  (archived)"
+                    } else {
+                        ""
+                    };
+                    println!("{}  {}{}", workspace.id, workspace.name, archived);
+                }
+            }
+            WorkspaceCommand::Create { name } => {
+                let workspace = client.create_workspace(&name).await?;
+                println!("{}", workspace.id);
+            }
+            WorkspaceCommand::Archive { id } => {
+                client.archive_workspace(&id).await?;
+                eprintln!("Archived workspace {}", id);
+            }
+        },
+    }
+
+    Ok(())
+}
+
+async fn run_daemon(
+    cli: Cli,
+    socket: Option<PathBuf>,
+    metrics: Option<std::net::SocketAddr>,
+) -> Result<()> {
+    let config = load_config(&cli)?;
+    let socket = match socket {
+        Some(socket) => socket,
+        None => daemon::default_socket_path()?,
+    };
 
-    println!("{}", response);
+    eprintln!("ellm daemon listening on {}", socket.display());
+    if let Some(address) = metrics {
+        eprintln!("Serving metrics on http://{}/metrics", address);
+    }
+    daemon::serve(&socket, &config, metrics).await?;
 
     Ok(())
 }
 
 fn show_config(cli: Cli) -> Result<()> {
-    let config = Config::load(cli.api_key)?;
+    let config = load_config(&cli)?;
 
     println!("Current Configuration:");
     println!(
         "  API Key: {}***",
         &config.api_key[..10.min(config.api_key.len())]
     );
+    if let Some(provider) = &config.provider {
+        println!("  Provider: {}", provider);
+    }
     println!("  Base URL: {}", config.base_url);
     println!("  Model: {}", config.model);
-    println!("  Max Tokens: {}", config.max_tokens);
+    if config.auto_max_tokens {
+        println!(
+            "  Max Tokens: auto (up to {})",
+            config.auto_max_tokens_ceiling()
+        );
+    } else {
+        println!("  Max Tokens: {}", config.max_tokens);
+    }
+    if let Some(budget) = config.thinking_budget {
+        println!("  Thinking Budget: {}", budget);
+    }
+    if config.cache_system {
+        println!("  Cache System Prompt: yes");
+    }
+    if let Some(workspace) = &config.workspace {
+        println!("  Workspace: {}", workspace);
+    }
+    if !config.safety.is_off() {
+        println!("  Safety: {}", config.safety);
+    }
+    if let Some(language) = &config.respond_in {
+        println!("  Respond In: {}", language);
+    }
 
-    if let Ok(config_path) = Config::config_path() {
+    let config_path = match cli.config {
+        Some(path) => Ok(path),
+        None => Config::config_path(),
+    };
+    if let Ok(config_path) = config_path {
         println!("\nConfig file location: {}", config_path.display());
         if config_path.exists() {
             println!("  Status: Found");
@@ -74,100 +2290,237 @@ fn show_config(cli: Cli) -> Result<()> {
         }
     }
 
+    let policy_path = Policy::system_path();
+    println!("\nOrganization policy: {}", policy_path.display());
+    if policy_path.exists() {
+        Policy::load_system()?;
+        println!("  Status: Enforced");
+    } else {
+        println!("  Status: Not installed");
+    }
+
     Ok(())
 }
 
-#[derive(Debug, Deserialize, JsonSchema)]
-#[allow(dead_code)]
-struct BoolResponse {
-    /// when unable to assess the input clearly, default to false
-    answer: bool,
-    /// provide an explanation of how you reached the answer
-    explanation: String,
-}
+async fn bool(
+    cli: Cli,
+    question: String,
+    min_confidence: f64,
+    quiet: bool,
+    json: bool,
+    sampling: SamplingArgs,
+) -> Result<BoolAnswer> {
+    let client = build_client(&cli)?;
 
-/// Sends a message to the Claude API with retry logic for JSON responses.
-///
-/// This function attempts to get a valid JSON response of type `T` from the API,
-/// retrying up to `max_retries` times if parsing fails. Each failed attempt
-/// includes the error in the conversation to help the model correct its response.
-///
-/// # Arguments
-/// * `client` - The API client to use for sending messages
-/// * `messages` - The conversation messages to send
-/// * `system` - Optional system prompt to guide the model's behavior
-/// * `max_retries` - Maximum number of retry attempts (default: 3)
-///
-/// # Returns
-/// * `Ok(T)` - Successfully parsed response of type T
-/// * `Err` - If all retry attempts fail or an API error occurs
-async fn send_with_json_retry<T>(
-    client: &Client,
-    mut messages: Messages,
-    system: Option<String>,
-    max_retries: usize,
-) -> Result<T>
-where
-    T: serde::de::DeserializeOwned + JsonSchema,
-{
-    let schema = schemars::schema_for!(T);
-    let schema_json = serde_json::to_string_pretty(&schema)?;
-    let jsonschema_system = format!(
-        "encode the result to a json object that matches the following JSON schema:\n\n{}",
-        schema_json
-    );
-    let system = if let Some(system) = system {
-        format!("{}\n\n{}", system, jsonschema_system)
-    } else {
-        jsonschema_system
+    if !quiet {
+        eprintln!("Sending message to Claude...\n");
+    }
+
+    // With several samples, the confidence is the share that agree
+    let (response, samples) = match sampling.sampling() {
+        Some(sampling) => {
+            let consensus = client.ask_bool_consistent(question, &sampling).await?;
+            (consensus.answer, Some(consensus.samples))
+        }
+        None => (client.ask_bool(question).await?, None),
     };
+    let outcome = response.outcome(min_confidence);
 
-    let mut result: Option<T> = None;
+    if json {
+        let mut value = serde_json::to_value(&response)?;
+        value["outcome"] = serde_json::to_value(outcome)?;
+        if let Some(samples) = samples {
+            value["samples"] = samples.into();
+        }
+        print_json(&value)?;
+    } else if !quiet {
+        println!("{} (confidence {:.2})", outcome, response.confidence);
+        println!("{}", response.explanation);
+    }
 
-    'retry: for _retry in 0..max_retries {
-        // https://github.com/anthropics/claude-cookbooks/blob/main/misc/how_to_enable_json_mode.ipynb
-        let lead = "{";
-        let mut response = client
-            .send_message(messages.clone(), Some(lead.into()), Some(system.clone()))
-            .await?;
-        response.insert_str(0, lead);
+    Ok(outcome)
+}
 
-        println!("{}", response);
+async fn translate(cli: Cli, text: String, to: String, from: Option<String>) -> Result<()> {
+    let client = build_client(&cli)?;
+    let pipeline = client.config().pipeline_for("translate", &cli.post);
 
-        // First validate as generic JSON
-        if let Err(error) = json::parse(&response) {
-            println!("{}", error);
-            messages.push_assistant(response);
-            messages.push_user(error.to_string());
-            continue 'retry;
-        }
+    let translation = client.translate(&text, &to, from.as_deref()).await?;
 
-        // Then try to parse into the specific type
-        match serde_json::from_str::<T>(&response) {
-            Ok(r) => {
-                result = Some(r);
-                break 'retry;
-            }
-            Err(error) => {
-                println!("{}", error);
-                messages.push_assistant(response);
-                messages.push_user(format!("response did not match schema: {}", error));
-                continue 'retry;
-            }
+    println!("{}", pipeline.apply(&translation)?);
+
+    Ok(())
+}
+
+async fn summarize(
+    cli: Cli,
+    text: String,
+    instructions: Option<String>,
+    options: LongDocOptions,
+) -> Result<()> {
+    let client = build_client(&cli)?;
+    let pipeline = client.config().pipeline_for("summarize", &cli.post);
+
+    let summary = client
+        .summarize(&text, instructions.as_deref(), &options)
+        .await?;
+
+    println!("{}", pipeline.apply(&summary)?);
+
+    Ok(())
+}
+
+async fn ocr(cli: Cli, path: PathBuf, format: OcrFormat, layout: bool) -> Result<()> {
+    let client = build_client(&cli)?;
+    let pipeline = client.config().pipeline_for("ocr", &cli.post);
+    let bytes = std::fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+    if encoding::media_type(&bytes).is_none() {
+        return Err(anyhow!(
+            "{} isn't a PNG, JPEG, GIF or WebP image or a PDF",
+            path.display()
+        ));
+    }
+    let image = attachment(&path, &client.config().images)?;
+
+    let text = client.ocr(image, format, layout).await?;
+    if text.is_empty() {
+        eprintln!("No text found in {}", path.display());
+        return Ok(());
+    }
+    println!("{}", pipeline.apply(&text)?);
+
+    Ok(())
+}
+
+async fn ask_table(
+    cli: Cli,
+    path: PathBuf,
+    question: String,
+    options: TableOptions,
+    json: bool,
+) -> Result<()> {
+    let client = build_client(&cli)?;
+    let pipeline = client.config().pipeline_for("table", &cli.post);
+    let bytes = std::fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+    let text = decode_file(&path, &bytes)
+        .ok_or_else(|| anyhow!("{} is a binary file, not a table", path.display()))?;
+    let table = Table::parse(&text).with_context(|| format!("parsing {}", path.display()))?;
+
+    let mut answer = client.ask_table(&table, &question, &options).await?;
+    answer.answer = pipeline.apply(&answer.answer)?;
+    if json {
+        print_json(&answer)?;
+        return Ok(());
+    }
+    if let (Some(query), Some(result)) = (&answer.query, &answer.result) {
+        eprintln!(
+            "Query: {}
+
+{}",
+            query,
+            result.to_csv(20)
+        );
+        if result.rows.len() > 20 {
+            eprintln!("({} more rows)", result.rows.len() - 20);
         }
+        eprintln!();
     }
+    println!("{}", answer.answer);
 
-    result.ok_or_else(|| anyhow!("failed to get valid response despite retries"))
+    Ok(())
 }
 
-async fn bool(cli: Cli, message: String) -> Result<BoolResponse> {
+async fn extract(
+    cli: Cli,
+    text: String,
+    schema: PathBuf,
+    instructions: Option<String>,
+    options: LongDocOptions,
+    sampling: SamplingArgs,
+) -> Result<()> {
     let client = build_client(&cli)?;
+    let schema: serde_json::Value = serde_json::from_str(
+        &std::fs::read_to_string(&schema)
+            .with_context(|| format!("failed to read {}", schema.display()))?,
+    )
+    .with_context(|| format!("{}: invalid JSON schema", schema.display()))?;
+
+    let value = match sampling.sampling() {
+        None => {
+            client
+                .extract(
+                    &text,
+                    &schema,
+                    instructions,
+                    StructuredRetry::default(),
+                    &options,
+                )
+                .await?
+        }
+        Some(sampling) => {
+            let sampler = client.with_temperature(sampling.temperature)?;
+            let results = futures::future::join_all((0..sampling.samples).map(|_| {
+                sampler.extract(
+                    &text,
+                    &schema,
+                    instructions.clone(),
+                    StructuredRetry::default(),
+                    &options,
+                )
+            }))
+            .await;
+            // serde_json orders object keys, so equal values serialize alike
+            let consensus = Consensus::of(results, |value| value.to_string())?;
+            diagnostics::info(
+                "consensus",
+                format!("{} of {} samples agree", consensus.votes, consensus.samples),
+                json!({"votes": consensus.votes, "samples": consensus.samples}),
+            );
+            consensus.answer
+        }
+    };
+
+    print_json(&value)?;
 
-    println!("Sending message to Claude...\n");
+    Ok(())
+}
+
+/// Translate one field of every line of a JSONL document, leaving the rest of
+/// each object untouched so localization files keep their keys and metadata
+async fn translate_jsonl(
+    cli: Cli,
+    input: String,
+    to: String,
+    from: Option<String>,
+    field: String,
+) -> Result<()> {
+    let lines = input.lines().filter(|line| !line.trim().is_empty()).count();
+    let progress = Progress::new(lines as u64, "lines");
+    let client = build_client(&cli)?.with_observer(progress.clone());
+
+    for (index, line) in input.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut entry: serde_json::Value = serde_json::from_str(line)
+            .with_context(|| format!("line {}: invalid JSON", index + 1))?;
+        let text = entry
+            .get(&field)
+            .and_then(|value| value.as_str())
+            .ok_or_else(|| anyhow!("line {}: missing string field {:?}", index + 1, field))?;
 
-    let system = "consider the question or statement and answer with a true or false.".into();
+        let translation = client
+            .translate(text, &to, from.as_deref())
+            .await
+            .with_context(|| format!("line {}", index + 1))?;
+        entry[&field] = serde_json::Value::String(translation);
 
-    let messages = Messages::new().push_user(message).clone();
+        let entry = serde_json::to_string(&entry)?;
+        progress.suspend(|| println!("{}", entry));
+        progress.inc();
+    }
+    progress.finish();
 
-    send_with_json_retry::<BoolResponse>(&client, messages, Some(system), 3).await
+    Ok(())
 }
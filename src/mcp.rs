@@ -0,0 +1,295 @@
+//! Model Context Protocol server over stdio
+//!
+//! Speaks newline-delimited JSON-RPC 2.0 on stdin/stdout and exposes ellm's
+//! commands as MCP tools. Diagnostics go to stderr so stdout stays a clean
+//! protocol channel.
+
+use anyhow::Result;
+use ellm::{Client, LongDocOptions, Messages};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Serve MCP requests on stdin/stdout until stdin is closed
+pub async fn serve(client: Client) -> Result<()> {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut stdout = tokio::io::stdout();
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(request) => handle(&client, request).await,
+            Err(error) => Some(error_response(Value::Null, PARSE_ERROR, error.to_string())),
+        };
+
+        if let Some(response) = response {
+            let mut out = serde_json::to_vec(&response)?;
+            out.push(b'\n');
+            stdout.write_all(&out).await?;
+            stdout.flush().await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Handle one JSON-RPC message, returning the response for requests
+async fn handle(client: &Client, request: Value) -> Option<Value> {
+    // Notifications carry no id and get no response
+    let id = request.get("id")?.clone();
+    let method = request.get("method").and_then(Value::as_str).unwrap_or("");
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+
+    let result = match method {
+        "initialize" => Ok(initialize_result()),
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({ "tools": tools() })),
+        "tools/call" => call_tool(client, &params).await,
+        _ => Err((METHOD_NOT_FOUND, format!("method not found: {}", method))),
+    };
+
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => error_response(id, code, message),
+    })
+}
+
+fn error_response(id: Value, code: i64, message: String) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+fn initialize_result() -> Value {
+    json!({
+        "protocolVersion": PROTOCOL_VERSION,
+        "capabilities": { "tools": {} },
+        "serverInfo": {
+            "name": "ellm",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+    })
+}
+
+fn tools() -> Value {
+    json!([
+        {
+            "name": "send",
+            "description": "Send a prompt to the configured model and return its reply.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "prompt": { "type": "string", "description": "The message to send" },
+                    "system": { "type": "string", "description": "Optional system prompt" },
+                },
+                "required": ["prompt"],
+            },
+        },
+        {
+            "name": "bool",
+            "description": "Answer a yes/no question with true, false, or unsure, plus confidence and explanation.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "question": { "type": "string", "description": "The question or statement to assess" },
+                },
+                "required": ["question"],
+            },
+        },
+        {
+            "name": "translate",
+            "description": "Translate text, preserving code blocks and placeholders.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "text": { "type": "string", "description": "The text to translate" },
+                    "to": { "type": "string", "description": "Target language" },
+                    "from": { "type": "string", "description": "Source language (detected when omitted)" },
+                },
+                "required": ["text", "to"],
+            },
+        },
+        {
+            "name": "summarize",
+            "description": "Summarize text of any length, including documents longer than the model's context window.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "text": { "type": "string", "description": "The text to summarize" },
+                    "instructions": { "type": "string", "description": "What to focus on or how to format the summary" },
+                },
+                "required": ["text"],
+            },
+        },
+        {
+            "name": "extract",
+            "description": "Extract structured data from text of any length as a JSON object matching the given JSON schema.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "text": { "type": "string", "description": "The text to extract from" },
+                    "schema": { "type": "object", "description": "JSON schema for the result" },
+                    "instructions": { "type": "string", "description": "Optional extraction instructions" },
+                },
+                "required": ["text", "schema"],
+            },
+        },
+    ])
+}
+
+fn string_arg<'a>(
+    arguments: &'a Value,
+    name: &str,
+) -> std::result::Result<Option<&'a str>, (i64, String)> {
+    match arguments.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(value)) => Ok(Some(value)),
+        Some(_) => Err((
+            INVALID_PARAMS,
+            format!("argument {} must be a string", name),
+        )),
+    }
+}
+
+fn required_arg<'a>(
+    arguments: &'a Value,
+    name: &str,
+) -> std::result::Result<&'a str, (i64, String)> {
+    string_arg(arguments, name)?
+        .ok_or_else(|| (INVALID_PARAMS, format!("missing argument {}", name)))
+}
+
+async fn call_tool(client: &Client, params: &Value) -> std::result::Result<Value, (i64, String)> {
+    let name = params.get("name").and_then(Value::as_str).unwrap_or("");
+    let arguments = params.get("arguments").cloned().unwrap_or(json!({}));
+
+    let outcome: ellm::Result<String> = match name {
+        "send" => {
+            let prompt = required_arg(&arguments, "prompt")?;
+            let system = string_arg(&arguments, "system")?.map(str::to_string);
+            client
+                .send_message(
+                    Messages::new().push_user(prompt.into()).clone(),
+                    None,
+                    system,
+                )
+                .await
+        }
+        "bool" => {
+            let question = required_arg(&arguments, "question")?;
+            client
+                .ask_bool(question)
+                .await
+                .and_then(|response| serde_json::to_string_pretty(&response).map_err(Into::into))
+        }
+        "translate" => {
+            let text = required_arg(&arguments, "text")?;
+            let to = required_arg(&arguments, "to")?;
+            let from = string_arg(&arguments, "from")?;
+            client.translate(text, to, from).await
+        }
+        "summarize" => {
+            let text = required_arg(&arguments, "text")?;
+            let instructions = string_arg(&arguments, "instructions")?;
+            client
+                .summarize(text, instructions, &LongDocOptions::default())
+                .await
+        }
+        "extract" => {
+            let text = required_arg(&arguments, "text")?;
+            let schema = arguments
+                .get("schema")
+                .filter(|schema| schema.is_object())
+                .ok_or_else(|| {
+                    (
+                        INVALID_PARAMS,
+                        "argument schema must be an object".to_string(),
+                    )
+                })?;
+            let instructions = string_arg(&arguments, "instructions")?.map(str::to_string);
+            client
+                .extract(text, schema, instructions, 3, &LongDocOptions::default())
+                .await
+                .and_then(|value| serde_json::to_string_pretty(&value).map_err(Into::into))
+        }
+        _ => return Err((INVALID_PARAMS, format!("unknown tool: {}", name))),
+    };
+
+    // Tool failures are reported in the result so the calling model can see them
+    Ok(match outcome {
+        Ok(text) => json!({ "content": [{ "type": "text", "text": text }], "isError": false }),
+        Err(error) => json!({
+            "content": [{ "type": "text", "text": error.to_string() }],
+            "isError": true,
+        }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ellm::Config;
+
+    fn client() -> Client {
+        Client::new(Config::new("sk-ant-test-key")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_initialize() {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} });
+        let response = handle(&client(), request).await.unwrap();
+
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["result"]["protocolVersion"], PROTOCOL_VERSION);
+        assert_eq!(response["result"]["serverInfo"]["name"], "ellm");
+    }
+
+    #[tokio::test]
+    async fn test_notification_has_no_response() {
+        let request = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+        assert!(handle(&client(), request).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_tools_list() {
+        let request = json!({ "jsonrpc": "2.0", "id": "a", "method": "tools/list" });
+        let response = handle(&client(), request).await.unwrap();
+
+        let names: Vec<&str> = response["result"]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            vec!["send", "bool", "translate", "summarize", "extract"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_calls() {
+        let request = json!({ "jsonrpc": "2.0", "id": 2, "method": "bogus" });
+        let response = handle(&client(), request).await.unwrap();
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "method": "tools/call",
+            "params": { "name": "send", "arguments": {} },
+        });
+        let response = handle(&client(), request).await.unwrap();
+        assert_eq!(response["error"]["code"], INVALID_PARAMS);
+    }
+}
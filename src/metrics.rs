@@ -0,0 +1,330 @@
+//! Prometheus metrics for requests relayed by the daemon
+//!
+//! Counters are kept in memory and rendered in the Prometheus text exposition
+//! format by a small `/metrics` HTTP endpoint.
+
+use crate::pricing;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// Upper bounds of the latency histogram buckets, in seconds
+const LATENCY_BUCKETS: &[f64] = &[0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+
+const TOKEN_KINDS: &[&str] = &[
+    "input_tokens",
+    "output_tokens",
+    "cache_creation_input_tokens",
+    "cache_read_input_tokens",
+];
+
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: Vec<u64>,
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; LATENCY_BUCKETS.len()];
+        }
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if value <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    requests: BTreeMap<(String, u16), u64>,
+    errors: BTreeMap<(String, String), u64>,
+    tokens: BTreeMap<(String, &'static str), u64>,
+    cost: BTreeMap<String, f64>,
+    latency: BTreeMap<String, Histogram>,
+}
+
+/// Request, token, cost, latency and error counters broken down by model
+#[derive(Debug, Default)]
+pub struct Metrics {
+    state: Mutex<State>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record one API call from its request body and raw response
+    ///
+    /// `status` is `None` when no response was received at all.
+    pub fn record(
+        &self,
+        request: &serde_json::Value,
+        status: Option<u16>,
+        response: &str,
+        latency: Duration,
+    ) {
+        let model = request
+            .get("model")
+            .and_then(|model| model.as_str())
+            .unwrap_or("unknown")
+            .to_string();
+        let response: serde_json::Value = serde_json::from_str(response).unwrap_or_default();
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        *state
+            .requests
+            .entry((model.clone(), status.unwrap_or(0)))
+            .or_default() += 1;
+        state
+            .latency
+            .entry(model.clone())
+            .or_default()
+            .observe(latency.as_secs_f64());
+
+        let error_type = match status {
+            None => Some("network_error"),
+            Some(200..=299) => None,
+            Some(_) => Some(
+                response
+                    .pointer("/error/type")
+                    .and_then(|kind| kind.as_str())
+                    .unwrap_or("http_error"),
+            ),
+        };
+        if let Some(error_type) = error_type {
+            *state
+                .errors
+                .entry((model.clone(), error_type.to_string()))
+                .or_default() += 1;
+        }
+
+        let Some(usage) = response.get("usage") else {
+            return;
+        };
+        let count = |kind: &str| usage.get(kind).and_then(|n| n.as_u64()).unwrap_or(0);
+        for kind in TOKEN_KINDS {
+            *state.tokens.entry((model.clone(), kind)).or_default() += count(kind);
+        }
+        if let Some(price) = pricing::price(&model) {
+            *state.cost.entry(model).or_default() += price.cost(
+                count("input_tokens"),
+                count("output_tokens"),
+                count("cache_creation_input_tokens"),
+                count("cache_read_input_tokens"),
+            );
+        }
+    }
+
+    /// Render all metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let mut out = String::new();
+        // Writing to a String cannot fail
+        let _ = write_metrics(&state, &mut out);
+        out
+    }
+}
+
+fn write_metrics(state: &State, out: &mut String) -> std::fmt::Result {
+    let header = |out: &mut String, name: &str, kind: &str, help: &str| {
+        writeln!(out, "# HELP {} {}", name, help)?;
+        writeln!(out, "# TYPE {} {}", name, kind)
+    };
+
+    header(
+        out,
+        "ellm_requests_total",
+        "counter",
+        "API requests by model and HTTP status (0 when no response was received).",
+    )?;
+    for ((model, status), count) in &state.requests {
+        writeln!(
+            out,
+            "ellm_requests_total{{model=\"{}\",status=\"{}\"}} {}",
+            escape(model),
+            status,
+            count
+        )?;
+    }
+
+    header(
+        out,
+        "ellm_errors_total",
+        "counter",
+        "Failed API requests by model and error type.",
+    )?;
+    for ((model, kind), count) in &state.errors {
+        writeln!(
+            out,
+            "ellm_errors_total{{model=\"{}\",type=\"{}\"}} {}",
+            escape(model),
+            escape(kind),
+            count
+        )?;
+    }
+
+    header(
+        out,
+        "ellm_tokens_total",
+        "counter",
+        "Tokens used by model and kind.",
+    )?;
+    for ((model, kind), count) in &state.tokens {
+        writeln!(
+            out,
+            "ellm_tokens_total{{model=\"{}\",kind=\"{}\"}} {}",
+            escape(model),
+            kind,
+            count
+        )?;
+    }
+
+    header(
+        out,
+        "ellm_cost_usd_total",
+        "counter",
+        "Estimated cost in USD by model, from published prices.",
+    )?;
+    for (model, cost) in &state.cost {
+        writeln!(
+            out,
+            "ellm_cost_usd_total{{model=\"{}\"}} {}",
+            escape(model),
+            cost
+        )?;
+    }
+
+    header(
+        out,
+        "ellm_request_duration_seconds",
+        "histogram",
+        "API request latency by model.",
+    )?;
+    for (model, histogram) in &state.latency {
+        let model = escape(model);
+        let bounds = LATENCY_BUCKETS.iter().map(|bound| bound.to_string());
+        let counts = histogram.buckets.iter();
+        for (bound, count) in bounds
+            .chain(["+Inf".to_string()])
+            .zip(counts.chain([&histogram.count]))
+        {
+            writeln!(
+                out,
+                "ellm_request_duration_seconds_bucket{{model=\"{}\",le=\"{}\"}} {}",
+                model, bound, count
+            )?;
+        }
+        writeln!(
+            out,
+            "ellm_request_duration_seconds_sum{{model=\"{}\"}} {}",
+            model, histogram.sum
+        )?;
+        writeln!(
+            out,
+            "ellm_request_duration_seconds_count{{model=\"{}\"}} {}",
+            model, histogram.count
+        )?;
+    }
+
+    Ok(())
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Answer `GET /metrics` on `listener` until the process is stopped
+pub async fn serve(listener: TcpListener, metrics: Arc<Metrics>) -> crate::error::Result<()> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let metrics = metrics.clone();
+
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 1024];
+            let Ok(read) = stream.read(&mut buffer).await else {
+                return;
+            };
+            let request = String::from_utf8_lossy(&buffer[..read]);
+
+            let response = if request.starts_with("GET /metrics ") {
+                let body = metrics.render();
+                format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: text/plain; version=0.0.4\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            } else {
+                "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+                    .to_string()
+            };
+            let _ = stream.write_all(response.as_bytes()).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_records_success_and_errors() {
+        let metrics = Metrics::new();
+        let request = json!({ "model": "claude-sonnet-4-5-20250929" });
+
+        metrics.record(
+            &request,
+            Some(200),
+            r#"{"usage": {"input_tokens": 1000, "output_tokens": 200}}"#,
+            Duration::from_millis(800),
+        );
+        metrics.record(
+            &request,
+            Some(529),
+            r#"{"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}}"#,
+            Duration::from_secs(3),
+        );
+
+        let text = metrics.render();
+        assert!(text.contains(
+            "ellm_requests_total{model=\"claude-sonnet-4-5-20250929\",status=\"200\"} 1"
+        ));
+        assert!(text.contains(
+            "ellm_errors_total{model=\"claude-sonnet-4-5-20250929\",type=\"overloaded_error\"} 1"
+        ));
+        assert!(text.contains(
+            "ellm_tokens_total{model=\"claude-sonnet-4-5-20250929\",kind=\"output_tokens\"} 200"
+        ));
+        assert!(text.contains("ellm_cost_usd_total{model=\"claude-sonnet-4-5-20250929\"} 0.006"));
+        assert!(text.contains(
+            "ellm_request_duration_seconds_bucket{model=\"claude-sonnet-4-5-20250929\",le=\"1\"} 1"
+        ));
+        assert!(text.contains(
+            "ellm_request_duration_seconds_count{model=\"claude-sonnet-4-5-20250929\"} 2"
+        ));
+    }
+
+    #[test]
+    fn test_network_failure_counted() {
+        let metrics = Metrics::new();
+        metrics.record(&json!({}), None, "", Duration::from_secs(1));
+
+        let text = metrics.render();
+        assert!(text.contains("ellm_requests_total{model=\"unknown\",status=\"0\"} 1"));
+        assert!(text.contains("ellm_errors_total{model=\"unknown\",type=\"network_error\"} 1"));
+    }
+}
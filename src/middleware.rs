@@ -0,0 +1,269 @@
+//! Code run around every API request
+//!
+//! A [`Middleware`] added with [`Client::with_middleware`](crate::Client::with_middleware)
+//! receives each HTTP request the client makes to the API, together with a
+//! [`Next`] that sends it on. It can change the request (custom auth, extra
+//! headers, a rewritten body), inspect or replace the response, time the call
+//! or answer without sending anything. Middleware runs in the order it was
+//! added, the first being outermost, and once per attempt when requests are
+//! retried.
+//!
+//! ```no_run
+//! use ellm::middleware::{Middleware, Next, Request, Response};
+//! use futures::future::BoxFuture;
+//!
+//! struct Gateway;
+//!
+//! impl Middleware for Gateway {
+//!     fn handle<'a>(
+//!         &'a self,
+//!         mut request: Request,
+//!         next: Next<'a>,
+//!     ) -> BoxFuture<'a, ellm::Result<Response>> {
+//!         request.set_header("x-gateway-token", "secret");
+//!         next.run(request)
+//!     }
+//! }
+//! ```
+
+use crate::error::{ApiError, Result};
+use futures::future::BoxFuture;
+use std::sync::Arc;
+
+pub use reqwest::Method;
+
+/// An API request about to be sent
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    /// The JSON body, if the request has one
+    pub body: Option<serde_json::Value>,
+}
+
+impl Request {
+    /// The value of the header `name`, compared case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        header(&self.headers, name)
+    }
+
+    /// Set the header `name`, replacing any existing value
+    pub fn set_header(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let name = name.into();
+        self.headers
+            .retain(|(existing, _)| !existing.eq_ignore_ascii_case(&name));
+        self.headers.push((name, value.into()));
+    }
+}
+
+/// The API's response to a [`Request`]
+///
+/// The body of a streamed response is read as it arrives, after the
+/// middleware has run, so only its status and headers are available.
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    body: Body,
+}
+
+#[derive(Debug)]
+enum Body {
+    Text(String),
+    Stream(reqwest::Response),
+}
+
+impl Response {
+    /// A response with a complete body, e.g. to answer a request without sending it
+    pub fn new(status: u16, headers: Vec<(String, String)>, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            headers,
+            body: Body::Text(body.into()),
+        }
+    }
+
+    /// The value of the header `name`, compared case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        header(&self.headers, name)
+    }
+
+    /// The body, unless the response is being streamed
+    pub fn text(&self) -> Option<&str> {
+        match &self.body {
+            Body::Text(text) => Some(text),
+            Body::Stream(_) => None,
+        }
+    }
+
+    /// Replace the body, including that of a streamed response
+    pub fn set_text(&mut self, text: impl Into<String>) {
+        self.body = Body::Text(text.into());
+    }
+
+    pub(crate) fn streaming(response: reqwest::Response) -> Self {
+        let headers = response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| {
+                Some((name.as_str().to_string(), value.to_str().ok()?.to_string()))
+            })
+            .collect();
+        Self {
+            status: response.status().as_u16(),
+            headers,
+            body: Body::Stream(response),
+        }
+    }
+
+    /// The complete body, reading a streamed one to the end
+    pub(crate) async fn into_text(self) -> Result<String> {
+        match self.body {
+            Body::Text(text) => Ok(text),
+            Body::Stream(response) => Ok(response.text().await?),
+        }
+    }
+
+    /// The body as an HTTP response that can be read incrementally
+    pub(crate) fn into_reqwest(self) -> Result<reqwest::Response> {
+        match self.body {
+            Body::Stream(response) => Ok(response),
+            Body::Text(text) => {
+                let mut response = http::Response::builder().status(self.status);
+                for (name, value) in &self.headers {
+                    response = response.header(name, value);
+                }
+                let response = response
+                    .body(text)
+                    .map_err(|e| ApiError::UnexpectedResponse(e.to_string()))?;
+                Ok(response.into())
+            }
+        }
+    }
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(existing, _)| existing.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+/// Wraps the API requests made by a [`Client`](crate::Client)
+pub trait Middleware: Send + Sync {
+    /// Handle `request`, usually by passing it on with [`Next::run`]
+    fn handle<'a>(&'a self, request: Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>>;
+}
+
+type Transport<'a> = dyn Fn(Request) -> BoxFuture<'a, Result<Response>> + Send + Sync + 'a;
+
+/// The rest of the middleware chain, ending with the request being sent
+pub struct Next<'a> {
+    middleware: &'a [Arc<dyn Middleware>],
+    send: &'a Transport<'a>,
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(middleware: &'a [Arc<dyn Middleware>], send: &'a Transport<'a>) -> Self {
+        Self { middleware, send }
+    }
+
+    /// Pass `request` to the next middleware, or send it if there is none
+    pub fn run(self, request: Request) -> BoxFuture<'a, Result<Response>> {
+        match self.middleware.split_first() {
+            Some((first, rest)) => first.handle(
+                request,
+                Next {
+                    middleware: rest,
+                    send: self.send,
+                },
+            ),
+            None => (self.send)(request),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::{http_stub, stub_client, REPLY};
+    use crate::Messages;
+    use std::sync::Mutex;
+
+    /// Adds a header naming itself and records the responses it sees
+    struct Tag(&'static str, Arc<Mutex<Vec<String>>>);
+
+    impl Middleware for Tag {
+        fn handle<'a>(
+            &'a self,
+            mut request: Request,
+            next: Next<'a>,
+        ) -> BoxFuture<'a, Result<Response>> {
+            Box::pin(async move {
+                self.1.lock().unwrap().push(format!("{} before", self.0));
+                request.set_header(format!("x-{}", self.0), "1");
+                let response = next.run(request).await?;
+                self.1
+                    .lock()
+                    .unwrap()
+                    .push(format!("{} after {}", self.0, response.status));
+                Ok(response)
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_middleware_order_and_headers() {
+        let (url, requests) = http_stub(vec![Some((200, REPLY))]).await;
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let client = stub_client(url)
+            .with_middleware(Tag("outer", log.clone()))
+            .with_middleware(Tag("inner", log.clone()));
+
+        let text = client
+            .send_message(Messages::new().push_user("hi".into()).clone(), None, None)
+            .await
+            .unwrap();
+
+        assert_eq!(text, "hi");
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                "outer before",
+                "inner before",
+                "inner after 200",
+                "outer after 200"
+            ]
+        );
+        let request = &requests.lock().unwrap()[0];
+        assert!(request.contains("x-outer: 1") && request.contains("x-inner: 1"));
+    }
+
+    #[tokio::test]
+    async fn test_middleware_can_answer() {
+        struct Canned;
+        impl Middleware for Canned {
+            fn handle<'a>(
+                &'a self,
+                request: Request,
+                _next: Next<'a>,
+            ) -> BoxFuture<'a, Result<Response>> {
+                assert_eq!(request.method, Method::POST);
+                assert!(request.header("X-API-Key").is_some());
+                let reply = REPLY.replace("\"hi\"", "\"canned\"");
+                Box::pin(async move { Ok(Response::new(200, Vec::new(), reply)) })
+            }
+        }
+
+        let (url, requests) = http_stub(vec![]).await;
+        let client = stub_client(url).with_middleware(Canned);
+
+        let text = client
+            .send_message(Messages::new().push_user("hi".into()).clone(), None, None)
+            .await
+            .unwrap();
+        assert_eq!(text, "canned");
+        assert!(requests.lock().unwrap().is_empty());
+    }
+}
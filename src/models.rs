@@ -0,0 +1,401 @@
+//! What is known about each model
+//!
+//! [`model_info`] describes a model's limits and the features it supports.
+//! Requests are checked against it before they are sent, so that e.g. an image
+//! sent to a text-only model fails with a clear error instead of the API's `400`.
+//!
+//! Old snapshots are regularly retired, which breaks saved configurations.
+//! [`replacement`] suggests the closest current model, and with
+//! [`Config::auto_migrate`] deprecated models are swapped for it.
+
+use crate::client::{Content, ContentBlock, DocumentSource, MessageRequest};
+use crate::config::Config;
+use crate::diagnostics;
+use crate::error::{ApiError, ClaudeError, Result};
+use serde::Serialize;
+
+/// Limits and supported features of a model family
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ModelInfo {
+    /// Tokens of input and output the model can handle in one request
+    pub context_window: u64,
+    /// The largest `max_tokens` the model accepts
+    pub max_output_tokens: u32,
+    /// Accepts images in user messages
+    pub vision: bool,
+    /// Accepts PDF documents in user messages
+    pub pdf: bool,
+    /// Accepts tool definitions
+    pub tools: bool,
+    /// Supports extended thinking
+    pub thinking: bool,
+    /// Supports prompt caching
+    pub caching: bool,
+}
+
+impl ModelInfo {
+    const CURRENT: ModelInfo = ModelInfo {
+        context_window: 200_000,
+        max_output_tokens: 64_000,
+        vision: true,
+        pdf: true,
+        tools: true,
+        thinking: true,
+        caching: true,
+    };
+
+    /// Claude 3 and 3.5 models, which predate extended thinking
+    const LEGACY: ModelInfo = ModelInfo {
+        max_output_tokens: 4_096,
+        pdf: false,
+        thinking: false,
+        ..ModelInfo::CURRENT
+    };
+
+    /// Reject a request that uses a feature the model doesn't support
+    pub(crate) fn check(&self, request: &MessageRequest) -> Result<()> {
+        let unsupported = |feature: &str| {
+            Err(ClaudeError::Unsupported(format!(
+                "{} does not support {}",
+                request.model, feature
+            )))
+        };
+
+        if !self.thinking && request.thinking.is_some() {
+            return unsupported("extended thinking");
+        }
+        if !self.tools && !request.tools.is_empty() {
+            return unsupported("tools");
+        }
+        if !self.vision
+            && request
+                .messages
+                .iter()
+                .any(|message| has_image(&message.content))
+        {
+            return unsupported("images");
+        }
+        if !self.pdf
+            && request
+                .messages
+                .iter()
+                .any(|message| has_pdf(&message.content))
+        {
+            return unsupported("PDF documents");
+        }
+        if !self.caching && request.uses_cache() {
+            return unsupported("prompt caching");
+        }
+        if request.max_tokens > self.max_output_tokens {
+            return Err(ClaudeError::Unsupported(format!(
+                "{} generates at most {} tokens, but max_tokens is {}",
+                request.model, self.max_output_tokens, request.max_tokens
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn has_image(content: &Content) -> bool {
+    matches!(content, Content::Blocks(blocks)
+        if blocks.iter().any(|block| matches!(block, ContentBlock::Image { .. })))
+}
+
+fn has_pdf(content: &Content) -> bool {
+    matches!(content, Content::Blocks(blocks)
+        if blocks.iter().any(|block| matches!(block, ContentBlock::Document {
+            source: DocumentSource::Base64 { media_type, .. }, ..
+        } if media_type == "application/pdf")))
+}
+
+/// Models by ID prefix; the first match wins, so list specific IDs first
+const MODELS: &[(&str, ModelInfo)] = &[
+    ("claude-opus-4-5", ModelInfo::CURRENT),
+    (
+        "claude-opus-4",
+        ModelInfo {
+            max_output_tokens: 32_000,
+            ..ModelInfo::CURRENT
+        },
+    ),
+    ("claude-sonnet-4", ModelInfo::CURRENT),
+    ("claude-haiku-4", ModelInfo::CURRENT),
+    ("claude-3-7-sonnet", ModelInfo::CURRENT),
+    (
+        "claude-3-5-haiku",
+        ModelInfo {
+            max_output_tokens: 8_192,
+            vision: false,
+            pdf: true,
+            ..ModelInfo::LEGACY
+        },
+    ),
+    (
+        "claude-3-5",
+        ModelInfo {
+            max_output_tokens: 8_192,
+            pdf: true,
+            ..ModelInfo::LEGACY
+        },
+    ),
+    ("claude-3", ModelInfo::LEGACY),
+];
+
+const OPUS: &str = "claude-opus-4-5-20251101";
+const SONNET: &str = "claude-sonnet-4-5-20250929";
+const HAIKU: &str = "claude-haiku-4-5-20251001";
+
+/// Deprecated and retired models by ID prefix, with their closest current model
+const DEPRECATED: &[(&str, &str)] = &[
+    ("claude-3-opus", OPUS),
+    ("claude-3-7-sonnet", SONNET),
+    ("claude-3-5-sonnet", SONNET),
+    ("claude-3-sonnet", SONNET),
+    ("claude-3-5-haiku", HAIKU),
+    ("claude-3-haiku", HAIKU),
+    ("claude-2", SONNET),
+    ("claude-instant", HAIKU),
+];
+
+/// Whether `model` is deprecated or already retired
+pub fn is_deprecated(model: &str) -> bool {
+    DEPRECATED
+        .iter()
+        .any(|(prefix, _)| model.starts_with(prefix))
+}
+
+/// The closest current model to `model`, if it isn't current itself
+///
+/// Deprecated models map to their successor; other IDs, e.g. ones the API
+/// doesn't know, to the current model of the same family.
+pub fn replacement(model: &str) -> Option<&'static str> {
+    let replacement = DEPRECATED
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, replacement)| *replacement)
+        .or_else(|| {
+            [OPUS, SONNET, HAIKU]
+                .into_iter()
+                .find(|current| model.contains(current.split('-').nth(1).unwrap_or_default()))
+        })?;
+    (replacement != model).then_some(replacement)
+}
+
+/// Warn about a deprecated model, or switch to its replacement with `auto_migrate`
+pub(crate) fn migrate(mut config: Config) -> Config {
+    if !is_deprecated(&config.model) {
+        return config;
+    }
+    let Some(replacement) = replacement(&config.model) else {
+        return config;
+    };
+
+    if config.auto_migrate {
+        diagnostics::warn(
+            "model_deprecated",
+            format!(
+                "{} is deprecated, using {} instead",
+                config.model, replacement
+            ),
+            serde_json::json!({"model": config.model, "replacement": replacement}),
+        );
+        config.model = replacement.to_string();
+    } else {
+        diagnostics::warn(
+            "model_deprecated",
+            format!(
+                "{} is deprecated and will stop working; {} is its closest current model (set auto_migrate to switch automatically)",
+                config.model, replacement
+            ),
+            serde_json::json!({"model": config.model, "replacement": replacement}),
+        );
+    }
+    config
+}
+
+/// [`ClaudeError::ModelNotFound`] with a suggestion if `error` says the API
+/// doesn't know `model`, otherwise `error` as is
+pub(crate) fn not_found(model: &str, error: ClaudeError) -> ClaudeError {
+    match error {
+        ClaudeError::Api(ApiError::ApiError {
+            status: 404,
+            ref message,
+        }) if message.contains("model") => ClaudeError::ModelNotFound {
+            model: model.to_string(),
+            suggestion: replacement(model).map(String::from),
+        },
+        error => error,
+    }
+}
+
+/// What is known about `model`, if anything
+pub fn model_info(model: &str) -> Option<ModelInfo> {
+    MODELS
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, info)| *info)
+}
+
+/// Tokens of input and output the model can handle in one request, if known
+pub fn context_window(model: &str) -> Option<u64> {
+    model_info(model).map(|info| info.context_window)
+}
+
+/// The largest `max_tokens` the model accepts, if known
+pub fn max_output_tokens(model: &str) -> Option<u32> {
+    model_info(model).map(|info| info.max_output_tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::http_stub;
+    use crate::client::{Client, Message, Messages};
+    use crate::config::Config;
+
+    #[test]
+    fn test_context_window() {
+        assert_eq!(context_window("claude-sonnet-4-5-20250929"), Some(200_000));
+        assert_eq!(context_window("claude-3-5-haiku-latest"), Some(200_000));
+        assert_eq!(context_window("gpt-4o"), None);
+    }
+
+    #[test]
+    fn test_max_output_tokens() {
+        assert_eq!(max_output_tokens("claude-opus-4-5-20251101"), Some(64_000));
+        assert_eq!(max_output_tokens("claude-opus-4-1-20250805"), Some(32_000));
+        assert_eq!(max_output_tokens("claude-3-5-haiku-latest"), Some(8_192));
+        assert_eq!(max_output_tokens("gpt-4o"), None);
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let haiku = model_info("claude-3-5-haiku-20241022").unwrap();
+        assert!(!haiku.vision && !haiku.thinking && haiku.tools);
+        assert!(model_info("claude-3-5-sonnet-20241022").unwrap().vision);
+        assert!(haiku.pdf && !model_info("claude-3-haiku-20240307").unwrap().pdf);
+        assert!(model_info("claude-sonnet-4-5").unwrap().thinking);
+    }
+
+    #[test]
+    fn test_replacement() {
+        assert_eq!(
+            replacement("claude-3-5-sonnet-20241022"),
+            Some("claude-sonnet-4-5-20250929")
+        );
+        assert_eq!(
+            replacement("claude-3-opus-latest"),
+            Some("claude-opus-4-5-20251101")
+        );
+        assert_eq!(
+            replacement("claude-haiku-4-7-typo"),
+            Some("claude-haiku-4-5-20251001")
+        );
+        assert_eq!(replacement("claude-sonnet-4-5-20250929"), None);
+        assert_eq!(replacement("gpt-4o"), None);
+        assert!(is_deprecated("claude-3-5-haiku-20241022"));
+        assert!(!is_deprecated("claude-haiku-4-5"));
+    }
+
+    #[test]
+    fn test_auto_migrate() {
+        let mut config = Config::new("sk-ant-test-key").with_model("claude-3-7-sonnet-latest");
+        assert_eq!(migrate(config.clone()).model, "claude-3-7-sonnet-latest");
+
+        config.auto_migrate = true;
+        assert_eq!(migrate(config).model, "claude-sonnet-4-5-20250929");
+    }
+
+    #[tokio::test]
+    async fn test_model_not_found() {
+        const NOT_FOUND: &str = r#"{"type":"error","error":{"type":"not_found_error","message":"model: claude-sonnet-4-9"}}"#;
+        const REPLY: &str = r#"{"id":"msg_1","type":"message","role":"assistant","model":"claude-sonnet-4-5-20250929","content":[{"type":"text","text":"hi"}],"stop_reason":"end_turn","usage":{"input_tokens":1,"output_tokens":1}}"#;
+        let (url, requests) = http_stub(vec![
+            Some((404, NOT_FOUND)),
+            Some((404, NOT_FOUND)),
+            Some((200, REPLY)),
+        ])
+        .await;
+        let mut config = Config::new("sk-ant-test-key").with_model("claude-sonnet-4-9");
+        config.base_url = url;
+        let messages = Messages::new().push_user("hi".into()).clone();
+
+        let client = Client::new(config.clone()).unwrap();
+        let error = client
+            .send_message(messages.clone(), None, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ClaudeError::ModelNotFound { ref suggestion, .. }
+                if suggestion.as_deref() == Some("claude-sonnet-4-5-20250929")
+        ));
+
+        config.auto_migrate = true;
+        let client = Client::new(config).unwrap();
+        assert_eq!(
+            client.send_message(messages, None, None).await.unwrap(),
+            "hi"
+        );
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[2].contains("claude-sonnet-4-5-20250929"));
+    }
+
+    #[tokio::test]
+    async fn test_image_rejected_before_sending() {
+        let (url, requests) = http_stub(vec![]).await;
+        let mut config = Config::new("sk-ant-test-key").with_model("claude-3-5-haiku-20241022");
+        config.base_url = url;
+        let client = Client::new(config).unwrap();
+        assert!(!client.model_info().unwrap().vision);
+
+        let message = Message {
+            role: "user".into(),
+            content: Content::Blocks(vec![
+                ContentBlock::image("image/png", b"not really a png"),
+                ContentBlock::Text {
+                    text: "What is this?".into(),
+                },
+            ]),
+        };
+        let error = client
+            .send_message(Messages::new().push(message).clone(), None, None)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error, ClaudeError::Unsupported(ref message) if message.contains("images")),
+            "{}",
+            error
+        );
+        assert!(requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pdf_rejected_before_sending() {
+        let (url, requests) = http_stub(vec![]).await;
+        let mut config = Config::new("sk-ant-test-key").with_model("claude-3-haiku-20240307");
+        config.base_url = url;
+        let client = Client::new(config).unwrap();
+
+        let message = Message {
+            role: "user".into(),
+            content: Content::Blocks(vec![
+                ContentBlock::pdf_document(Some("report.pdf".into()), b"%PDF-1.7"),
+                ContentBlock::Text {
+                    text: "Summarize this".into(),
+                },
+            ]),
+        };
+        let error = client
+            .send_message(Messages::new().push(message).clone(), None, None)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error, ClaudeError::Unsupported(ref message) if message.contains("PDF")),
+            "{}",
+            error
+        );
+        assert!(requests.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,227 @@
+//! Notifications when long operations finish
+//!
+//! A [`Notification`] is POSTed as JSON to a webhook and/or piped to a shell
+//! command, so a batch left running overnight can ping a chat channel. The
+//! payload has a `text` field, which is what Slack and compatible incoming
+//! webhooks display.
+
+use crate::error::{ClaudeError, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// Where to send notifications, from the config file's `[notify]` section
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotifyConfig {
+    /// URL the notification is POSTed to as JSON
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<String>,
+
+    /// Shell command run with the notification as JSON on stdin, and its
+    /// operation, status and text in `ELLM_NOTIFY_OPERATION`,
+    /// `ELLM_NOTIFY_STATUS` and `ELLM_NOTIFY_TEXT`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+}
+
+/// How a long operation ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationStatus {
+    Succeeded,
+    Failed,
+}
+
+impl NotificationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationStatus::Succeeded => "succeeded",
+            NotificationStatus::Failed => "failed",
+        }
+    }
+}
+
+/// The payload sent when a long operation finishes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Notification {
+    /// The operation that finished, e.g. `batch wait`
+    pub operation: String,
+    pub status: NotificationStatus,
+    /// A one-line description for people
+    pub text: String,
+    pub elapsed_secs: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Notification {
+    /// A notification that `operation` finished after `elapsed`, failing with `error` if any
+    pub fn new(operation: impl Into<String>, error: Option<String>, elapsed: Duration) -> Self {
+        let operation = operation.into();
+        let status = match error {
+            None => NotificationStatus::Succeeded,
+            Some(_) => NotificationStatus::Failed,
+        };
+        let mut text = format!(
+            "ellm {} {} after {}",
+            operation,
+            status.as_str(),
+            format_elapsed(elapsed)
+        );
+        if let Some(error) = &error {
+            text.push_str(&format!(": {}", error));
+        }
+        Self {
+            operation,
+            status,
+            text,
+            elapsed_secs: elapsed.as_secs_f64(),
+            error,
+        }
+    }
+}
+
+/// `elapsed` as e.g. `3h 2m`, `4m 10s` or `12s`
+fn format_elapsed(elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs();
+    match (seconds / 3600, seconds / 60 % 60, seconds % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, m, _) => format!("{}h {}m", h, m),
+    }
+}
+
+impl NotifyConfig {
+    pub fn is_empty(&self) -> bool {
+        self.webhook.is_none() && self.command.is_none()
+    }
+
+    /// Send `notification` to the webhook and run the command, whichever are set
+    ///
+    /// Both are attempted even if the first fails; the first error is returned.
+    pub async fn send(&self, notification: &Notification) -> Result<()> {
+        let webhook = match &self.webhook {
+            Some(url) => post(url, notification).await,
+            None => Ok(()),
+        };
+        let command = match &self.command {
+            Some(command) => run(command, notification),
+            None => Ok(()),
+        };
+        webhook.and(command)
+    }
+}
+
+async fn post(url: &str, notification: &Notification) -> Result<()> {
+    let response = reqwest::Client::new()
+        .post(url)
+        .json(notification)
+        .timeout(Duration::from_secs(30))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(ClaudeError::Notify(format!(
+            "webhook responded with {}",
+            response.status()
+        )));
+    }
+    Ok(())
+}
+
+fn run(command: &str, notification: &Notification) -> Result<()> {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut child = Command::new(shell)
+        .args([flag, command])
+        .env("ELLM_NOTIFY_OPERATION", &notification.operation)
+        .env("ELLM_NOTIFY_STATUS", notification.status.as_str())
+        .env("ELLM_NOTIFY_TEXT", &notification.text)
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A command that ignores its input may exit before reading it
+        let _ = stdin.write_all(serde_json::to_string(notification)?.as_bytes());
+    }
+    let exit = child.wait()?;
+    if !exit.success() {
+        return Err(ClaudeError::Notify(format!(
+            "notification command exited with {}",
+            exit
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::http_stub;
+
+    #[test]
+    fn test_notification() {
+        let done = Notification::new("batch wait", None, Duration::from_secs(3 * 3600 + 125));
+        assert_eq!(done.status, NotificationStatus::Succeeded);
+        assert_eq!(done.text, "ellm batch wait succeeded after 3h 2m");
+
+        let failed = Notification::new("digest", Some("no feeds".into()), Duration::from_secs(250));
+        assert_eq!(failed.text, "ellm digest failed after 4m 10s: no feeds");
+        assert_eq!(
+            serde_json::to_value(&failed).unwrap()["status"],
+            serde_json::json!("failed")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_webhook() {
+        let (url, requests) = http_stub(vec![Some((200, "ok")), Some((500, "down"))]).await;
+        let config = NotifyConfig {
+            webhook: Some(url),
+            command: None,
+        };
+        let notification = Notification::new("summarize", None, Duration::from_secs(1));
+
+        config.send(&notification).await.unwrap();
+        assert!(
+            requests.lock().unwrap()[0].contains(r#""text":"ellm summarize succeeded after 1s""#)
+        );
+        assert!(matches!(
+            config.send(&notification).await,
+            Err(ClaudeError::Notify(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_command() {
+        let path = std::env::temp_dir().join(format!("ellm-notify-{}.txt", std::process::id()));
+        let config = NotifyConfig {
+            webhook: None,
+            command: Some(format!(
+                r#"echo "$ELLM_NOTIFY_STATUS" > "{0}"; cat >> "{0}""#,
+                path.display()
+            )),
+        };
+        let notification = Notification::new("extract", None, Duration::from_secs(1));
+        config.send(&notification).await.unwrap();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let (status, payload) = written.split_once('\n').unwrap();
+        assert_eq!(status, "succeeded");
+        assert_eq!(
+            serde_json::from_str::<Notification>(payload).unwrap(),
+            notification
+        );
+
+        let failing = NotifyConfig {
+            webhook: None,
+            command: Some("exit 3".into()),
+        };
+        assert!(failing.send(&notification).await.is_err());
+    }
+}
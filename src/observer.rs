@@ -0,0 +1,119 @@
+//! Callbacks for following requests as they happen
+//!
+//! An [`Observer`] added with [`Client::with_observer`](crate::Client::with_observer)
+//! is told when each request starts, when the first token of the response
+//! arrives, about every piece of text as it is generated, and how the request
+//! ended. That is enough to drive progress bars, spinners and logging without
+//! wrapping every call site.
+//!
+//! Buffered requests such as [`Client::send_message`](crate::Client::send_message)
+//! receive their whole response at once, so `on_first_token` and `on_token`
+//! fire once with the complete text.
+
+use crate::client::StopReason;
+use crate::error::ClaudeError;
+use std::time::{Duration, Instant};
+
+/// A request being observed
+#[derive(Debug, Clone)]
+pub struct RequestInfo {
+    /// The idempotency key the request is sent with, for correlation
+    pub idempotency_key: String,
+    pub model: String,
+    /// Whether the response is streamed
+    pub stream: bool,
+    pub started: Instant,
+}
+
+impl RequestInfo {
+    pub(crate) fn new(idempotency_key: String, model: String, stream: bool) -> Self {
+        Self {
+            idempotency_key,
+            model,
+            stream,
+            started: Instant::now(),
+        }
+    }
+
+    /// Time since the request started
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+/// How a successful request ended
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_creation_input_tokens: u64,
+    pub cache_read_input_tokens: u64,
+    pub stop_reason: Option<StopReason>,
+    pub elapsed: Duration,
+}
+
+/// Receives request lifecycle events; every method does nothing by default
+///
+/// Callbacks run on the task making the request, so they should return quickly.
+pub trait Observer: Send + Sync {
+    /// The request is about to be sent
+    fn on_request(&self, _request: &RequestInfo) {}
+
+    /// The first output of the response arrived
+    fn on_first_token(&self, _request: &RequestInfo) {}
+
+    /// Response text arrived
+    fn on_token(&self, _request: &RequestInfo, _text: &str) {}
+
+    /// The response is complete
+    fn on_complete(&self, _request: &RequestInfo, _completion: &Completion) {}
+
+    /// The request failed
+    fn on_error(&self, _request: &RequestInfo, _error: &ClaudeError) {}
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Records the callbacks it receives, by name
+    #[derive(Clone, Default)]
+    pub(crate) struct Recorder(pub(crate) Arc<Mutex<Vec<String>>>);
+
+    impl Observer for Recorder {
+        fn on_request(&self, _request: &RequestInfo) {
+            self.0.lock().unwrap().push("request".into());
+        }
+
+        fn on_first_token(&self, _request: &RequestInfo) {
+            self.0.lock().unwrap().push("first_token".into());
+        }
+
+        fn on_token(&self, _request: &RequestInfo, text: &str) {
+            self.0.lock().unwrap().push(format!("token:{}", text));
+        }
+
+        fn on_complete(&self, _request: &RequestInfo, completion: &Completion) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("complete:{}", completion.output_tokens));
+        }
+
+        fn on_error(&self, _request: &RequestInfo, _error: &ClaudeError) {
+            self.0.lock().unwrap().push("error".into());
+        }
+    }
+
+    #[test]
+    fn test_default_methods_do_nothing() {
+        struct Quiet;
+        impl Observer for Quiet {}
+
+        let request = RequestInfo::new("key".into(), "claude-haiku-4-5".into(), false);
+        Quiet.on_request(&request);
+        Quiet.on_error(&request, &ClaudeError::Daemon("gone".into()));
+        assert!(request.elapsed() < Duration::from_secs(60));
+    }
+}
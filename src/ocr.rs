@@ -0,0 +1,195 @@
+//! Transcribing the text and tables in images
+//!
+//! The image is read with vision, not character recognition, so handwriting,
+//! photos of screens and rotated text work too. Text is transcribed as plain
+//! text, as Markdown with headings, lists and tables, or as CSV of the tables
+//! only. With the layout preserved, line breaks, indentation and the
+//! alignment of columns follow the image; otherwise lines are joined into
+//! paragraphs.
+
+use crate::client::{Client, Content, ContentBlock, Message, Messages};
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// What the transcription of an image is written as
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OcrFormat {
+    /// Plain text
+    #[default]
+    Text,
+    /// Markdown, with tables as Markdown tables
+    Markdown,
+    /// The tables only, as CSV
+    Csv,
+}
+
+impl OcrFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OcrFormat::Text => "text",
+            OcrFormat::Markdown => "markdown",
+            OcrFormat::Csv => "csv",
+        }
+    }
+
+    fn instructions(&self, preserve_layout: bool) -> &'static str {
+        match (self, preserve_layout) {
+            (OcrFormat::Text, true) => {
+                "Write it as plain text, keeping the line breaks, indentation and blank \
+                 lines of the image, and aligning columns and tables with spaces."
+            }
+            (OcrFormat::Text, false) => {
+                "Write it as plain text, joining lines that wrap within a paragraph and \
+                 separating paragraphs with a blank line. Write tables as one row per \
+                 line with cells separated by tabs."
+            }
+            (OcrFormat::Markdown, true) => {
+                "Write it as Markdown: headings as headings, lists as lists and tables as \
+                 Markdown tables, keeping every line break of the image, and keeping \
+                 emphasis such as bold and italic."
+            }
+            (OcrFormat::Markdown, false) => {
+                "Write it as Markdown: headings as headings, lists as lists and tables as \
+                 Markdown tables, joining lines that wrap within a paragraph, and keeping \
+                 emphasis such as bold and italic."
+            }
+            (OcrFormat::Csv, _) => {
+                "Transcribe only the tables, as CSV with a header row when the table has \
+                 one, quoting cells as CSV requires. Separate tables with a blank line, \
+                 and leave merged cells empty after their first column."
+            }
+        }
+    }
+}
+
+impl fmt::Display for OcrFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for OcrFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OcrFormat::Text),
+            "markdown" => Ok(OcrFormat::Markdown),
+            "csv" => Ok(OcrFormat::Csv),
+            _ => Err(format!(
+                "unknown OCR format {:?}; use text, markdown or csv",
+                s
+            )),
+        }
+    }
+}
+
+/// `text` without a code fence wrapped around all of it
+fn unfenced(text: &str) -> &str {
+    let trimmed = text.trim();
+    match trimmed
+        .strip_prefix("```")
+        .and_then(|rest| rest.strip_suffix("```"))
+    {
+        // Skip the language tag
+        Some(inner) if !inner.contains("```") => match inner.split_once('\n') {
+            Some((_, body)) => body.trim_end_matches('\n'),
+            None => inner,
+        },
+        _ => trimmed,
+    }
+}
+
+impl Client {
+    /// Transcribe the text in `image`, an image or PDF content block
+    ///
+    /// Returns an empty string when there is no text to transcribe, or no
+    /// tables for [`OcrFormat::Csv`].
+    pub async fn ocr(
+        &self,
+        image: ContentBlock,
+        format: OcrFormat,
+        preserve_layout: bool,
+    ) -> Result<String> {
+        let system = format!(
+            "Transcribe the text in the image exactly as written, in its reading order, \
+             without correcting, translating or summarizing it. {} Mark text you can't \
+             read as [illegible]. Respond with only the transcription, without a code \
+             fence or commentary; if there is nothing to transcribe, respond with \
+             nothing.",
+            format.instructions(preserve_layout)
+        );
+        let message = Message {
+            role: "user".into(),
+            content: Content::Blocks(vec![
+                image,
+                ContentBlock::Text {
+                    text: format!("Transcribe this image as {}.", format),
+                },
+            ]),
+        };
+        let response = self
+            .send_message(Messages::new().push(message).clone(), None, Some(system))
+            .await?;
+        Ok(unfenced(&response).to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::http_stub;
+    use crate::config::Config;
+
+    fn reply(text: &str) -> &'static str {
+        Box::leak(
+            serde_json::json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-haiku-4-5",
+                "content": [{"type": "text", "text": text}],
+                "stop_reason": "end_turn",
+                "usage": {"input_tokens": 1, "output_tokens": 1}
+            })
+            .to_string()
+            .into_boxed_str(),
+        )
+    }
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!("csv".parse(), Ok(OcrFormat::Csv));
+        assert_eq!(OcrFormat::Markdown.to_string(), "markdown");
+        assert!("html".parse::<OcrFormat>().is_err());
+    }
+
+    #[test]
+    fn test_unfenced() {
+        assert_eq!(unfenced("```csv\na,b\n1,2\n```\n"), "a,b\n1,2");
+        assert_eq!(unfenced("  Hello\n"), "Hello");
+        let code = "```rust\nfn main() {}\n```\ntext\n```\nmore\n```";
+        assert_eq!(unfenced(code), code);
+    }
+
+    #[tokio::test]
+    async fn test_ocr() {
+        let (url, requests) =
+            http_stub(vec![Some((200, reply("```csv\nname,qty\nbolt,4\n```")))]).await;
+        let mut config = Config::new("sk-ant-test-key");
+        config.base_url = url;
+        let client = Client::new(config).unwrap();
+
+        let image = ContentBlock::image("image/png", b"\x89PNG");
+        let csv = client.ocr(image, OcrFormat::Csv, false).await.unwrap();
+        assert_eq!(csv, "name,qty\nbolt,4");
+
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].contains("\"type\":\"image\""));
+        assert!(requests[0].contains("transcribe only the tables, as csv"));
+        assert!(requests[0].contains("transcribe this image as csv."));
+    }
+}
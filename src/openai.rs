@@ -0,0 +1,389 @@
+//! Conversations in the OpenAI chat format
+//!
+//! Prompt datasets and most other tools store a conversation as OpenAI chat
+//! `messages`: objects with a `role` of `system`, `user`, `assistant` or
+//! `tool`, and `content` that is a string or a list of parts. Reading one
+//! turns its system messages into the system prompt, assistant `tool_calls`
+//! into tool use blocks and `tool` messages into tool results; writing one
+//! does the reverse. Images and PDFs travel as `data:` URLs, and thinking is
+//! left out, since the format has no place for it.
+
+use crate::client::{Content, ContentBlock, DocumentSource, ImageSource, Message, Messages};
+use crate::error::{ClaudeError, Result};
+use serde_json::{json, Map, Value};
+
+fn invalid(problem: String) -> ClaudeError {
+    ClaudeError::Conversation(format!("not an OpenAI chat conversation: {}", problem))
+}
+
+impl Messages {
+    /// Read a conversation in the OpenAI chat format: a list of messages, or
+    /// an object with a `messages` list as in fine-tuning datasets
+    ///
+    /// Returns the system prompt the `system` and `developer` messages make
+    /// up, if there are any, along with the other messages.
+    pub fn from_openai(json: &str) -> Result<(Option<String>, Messages)> {
+        let value: Value = serde_json::from_str(json)?;
+        let list = match &value {
+            Value::Array(list) => list,
+            Value::Object(map) => match map.get("messages") {
+                Some(Value::Array(list)) => list,
+                _ => return Err(invalid("the object has no \"messages\" list".into())),
+            },
+            _ => return Err(invalid("expected a list of messages".into())),
+        };
+
+        let mut system = Vec::new();
+        let mut messages = Messages::new();
+        // Consecutive tool messages answer one assistant turn together
+        let mut results = Vec::new();
+        for (index, message) in list.iter().enumerate() {
+            let number = index + 1;
+            let role = message
+                .get("role")
+                .and_then(Value::as_str)
+                .ok_or_else(|| invalid(format!("message {} has no role", number)))?;
+            if role != "tool" && !results.is_empty() {
+                messages.push(user(std::mem::take(&mut results)));
+            }
+            let content = message.get("content").unwrap_or(&Value::Null);
+            match role {
+                "system" | "developer" => system.push(text_of(content, number)?),
+                "user" => {
+                    messages.push(Message {
+                        role: "user".into(),
+                        content: user_content(content, number)?,
+                    });
+                }
+                "assistant" => {
+                    messages.push(Message {
+                        role: "assistant".into(),
+                        content: assistant_content(message, number)?,
+                    });
+                }
+                "tool" => results.push(ContentBlock::ToolResult {
+                    tool_use_id: string_field(message, "tool_call_id", number)?,
+                    content: text_of(content, number)?,
+                    is_error: false,
+                }),
+                other => {
+                    return Err(invalid(format!(
+                        "message {} has the unknown role {:?}",
+                        number, other
+                    )))
+                }
+            }
+        }
+        if !results.is_empty() {
+            messages.push(user(results));
+        }
+        Ok(((!system.is_empty()).then(|| system.join("\n\n")), messages))
+    }
+
+    /// The conversation in the OpenAI chat format, as an object with a
+    /// `messages` list that starts with `system` when there is one
+    pub fn to_openai(&self, system: Option<&str>) -> Value {
+        let mut list: Vec<Value> = system
+            .map(|system| json!({"role": "system", "content": system}))
+            .into_iter()
+            .collect();
+        for message in self.iter() {
+            match (&message.content, message.role.as_str()) {
+                (Content::Text(text), role) => list.push(json!({"role": role, "content": text})),
+                (Content::Blocks(blocks), "assistant") => list.push(assistant_message(blocks)),
+                (Content::Blocks(blocks), role) => {
+                    let mut parts = Vec::new();
+                    for block in blocks {
+                        match block {
+                            ContentBlock::ToolResult {
+                                tool_use_id,
+                                content,
+                                ..
+                            } => list.push(json!({
+                                "role": "tool",
+                                "tool_call_id": tool_use_id,
+                                "content": content,
+                            })),
+                            block => parts.extend(part(block)),
+                        }
+                    }
+                    match parts.as_slice() {
+                        [] => {}
+                        [part] if part["type"] == "text" => {
+                            list.push(json!({"role": role, "content": part["text"]}))
+                        }
+                        _ => list.push(json!({"role": role, "content": parts})),
+                    }
+                }
+            }
+        }
+        json!({ "messages": list })
+    }
+}
+
+fn user(blocks: Vec<ContentBlock>) -> Message {
+    Message {
+        role: "user".into(),
+        content: Content::Blocks(blocks),
+    }
+}
+
+fn string_field(value: &Value, field: &str, number: usize) -> Result<String> {
+    value
+        .get(field)
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| invalid(format!("message {} has no {}", number, field)))
+}
+
+/// The text of `content`, a string or a list of text parts
+fn text_of(content: &Value, number: usize) -> Result<String> {
+    match content {
+        Value::Null => Ok(String::new()),
+        Value::String(text) => Ok(text.clone()),
+        Value::Array(parts) => parts
+            .iter()
+            .map(|part| match part.get("text").and_then(Value::as_str) {
+                Some(text) => Ok(text),
+                None => Err(invalid(format!(
+                    "message {} can only have text parts",
+                    number
+                ))),
+            })
+            .collect(),
+        _ => Err(invalid(format!("message {} has invalid content", number))),
+    }
+}
+
+/// The media type and base64 data of a `data:` URL
+fn data_url(url: &str) -> Option<(&str, &str)> {
+    let (media_type, data) = url.strip_prefix("data:")?.split_once(";base64,")?;
+    Some((media_type, data))
+}
+
+fn user_content(content: &Value, number: usize) -> Result<Content> {
+    let Value::Array(parts) = content else {
+        return text_of(content, number).map(Content::Text);
+    };
+    let unsupported = |kind: &str| invalid(format!("message {} has {}", number, kind));
+    let blocks = parts
+        .iter()
+        .map(|part| match part.get("type").and_then(Value::as_str) {
+            Some("text") => match part.get("text").and_then(Value::as_str) {
+                Some(text) => Ok(ContentBlock::Text { text: text.into() }),
+                None => Err(unsupported("a text part without text")),
+            },
+            Some("image_url") => {
+                // The URL may be given directly, or under "url" as the API has it
+                let image = &part["image_url"];
+                let url = image
+                    .get("url")
+                    .unwrap_or(image)
+                    .as_str()
+                    .unwrap_or_default();
+                let (media_type, data) = data_url(url)
+                    .ok_or_else(|| unsupported("an image that isn't a base64 data: URL"))?;
+                Ok(ContentBlock::Image {
+                    source: ImageSource::Base64 {
+                        media_type: media_type.into(),
+                        data: data.into(),
+                    },
+                })
+            }
+            Some("file") => {
+                let file = &part["file"];
+                let (media_type, data) = file["file_data"]
+                    .as_str()
+                    .and_then(data_url)
+                    .ok_or_else(|| unsupported("a file that isn't a base64 data: URL"))?;
+                Ok(ContentBlock::Document {
+                    source: DocumentSource::Base64 {
+                        media_type: media_type.into(),
+                        data: data.into(),
+                    },
+                    title: file["filename"].as_str().map(str::to_string),
+                    cache_control: None,
+                })
+            }
+            Some(other) => Err(unsupported(&format!(
+                "a part of unsupported type {:?}",
+                other
+            ))),
+            None => Err(unsupported("a part without a type")),
+        })
+        .collect::<Result<_>>()?;
+    Ok(Content::Blocks(blocks))
+}
+
+fn assistant_content(message: &Value, number: usize) -> Result<Content> {
+    let text = text_of(message.get("content").unwrap_or(&Value::Null), number)?;
+    let calls = match message.get("tool_calls") {
+        Some(Value::Array(calls)) if !calls.is_empty() => calls,
+        _ => return Ok(Content::Text(text)),
+    };
+    let mut blocks: Vec<ContentBlock> = (!text.is_empty())
+        .then_some(ContentBlock::Text { text })
+        .into_iter()
+        .collect();
+    for call in calls {
+        let function = &call["function"];
+        let name = string_field(function, "name", number)?;
+        // Arguments are a JSON string, though some tools write the object
+        let input = match &function["arguments"] {
+            Value::String(arguments) if arguments.trim().is_empty() => json!({}),
+            Value::String(arguments) => serde_json::from_str(arguments).map_err(|e| {
+                invalid(format!(
+                    "message {} calls {} with invalid arguments: {}",
+                    number, name, e
+                ))
+            })?,
+            Value::Null => json!({}),
+            arguments => arguments.clone(),
+        };
+        blocks.push(ContentBlock::ToolUse {
+            id: string_field(call, "id", number)?,
+            name,
+            input,
+        });
+    }
+    Ok(Content::Blocks(blocks))
+}
+
+/// An assistant message, with its tool use blocks as `tool_calls`
+fn assistant_message(blocks: &[ContentBlock]) -> Value {
+    let mut text = String::new();
+    let mut calls = Vec::new();
+    for block in blocks {
+        match block {
+            ContentBlock::Text { text: part } => text.push_str(part),
+            ContentBlock::ToolUse { id, name, input } => calls.push(json!({
+                "id": id,
+                "type": "function",
+                "function": {"name": name, "arguments": input.to_string()},
+            })),
+            _ => {}
+        }
+    }
+    let mut message = Map::new();
+    message.insert("role".into(), "assistant".into());
+    let content = if text.is_empty() && !calls.is_empty() {
+        Value::Null
+    } else {
+        text.into()
+    };
+    message.insert("content".into(), content);
+    if !calls.is_empty() {
+        message.insert("tool_calls".into(), calls.into());
+    }
+    Value::Object(message)
+}
+
+/// A user content block as a content part, if the format has one for it
+fn part(block: &ContentBlock) -> Option<Value> {
+    match block {
+        ContentBlock::Text { text } => Some(json!({"type": "text", "text": text})),
+        ContentBlock::Image {
+            source: ImageSource::Base64 { media_type, data },
+        } => Some(json!({
+            "type": "image_url",
+            "image_url": {"url": format!("data:{};base64,{}", media_type, data)},
+        })),
+        ContentBlock::Document {
+            source: DocumentSource::Text { data, .. },
+            ..
+        } => Some(json!({"type": "text", "text": data})),
+        ContentBlock::Document {
+            source: DocumentSource::Base64 { media_type, data },
+            title,
+            ..
+        } => Some(json!({
+            "type": "file",
+            "file": {
+                "filename": title.as_deref().unwrap_or("document.pdf"),
+                "file_data": format!("data:{};base64,{}", media_type, data),
+            },
+        })),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONVERSATION: &str = r#"{"messages": [
+        {"role": "system", "content": "Be brief."},
+        {"role": "user", "content": "Weather in Oslo?"},
+        {"role": "assistant", "content": null, "tool_calls": [
+            {"id": "call_1", "type": "function",
+             "function": {"name": "weather", "arguments": "{\"city\":\"Oslo\"}"}}
+        ]},
+        {"role": "tool", "tool_call_id": "call_1", "content": "4C, rain"},
+        {"role": "assistant", "content": "4C and raining."},
+        {"role": "user", "content": [
+            {"type": "text", "text": "And this?"},
+            {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBO"}}
+        ]}
+    ]}"#;
+
+    #[test]
+    fn test_from_openai() {
+        let (system, messages) = Messages::from_openai(CONVERSATION).unwrap();
+        assert_eq!(system.as_deref(), Some("Be brief."));
+        let messages: Vec<Message> = messages.into();
+        assert_eq!(messages.len(), 5);
+        assert_eq!(messages[0].content, "Weather in Oslo?");
+        assert_eq!(
+            messages[1].content,
+            Content::Blocks(vec![ContentBlock::ToolUse {
+                id: "call_1".into(),
+                name: "weather".into(),
+                input: json!({"city": "Oslo"}),
+            }])
+        );
+        assert_eq!(messages[2].role, "user");
+        assert!(matches!(
+            &messages[2].content,
+            Content::Blocks(blocks) if matches!(&blocks[0], ContentBlock::ToolResult { content, .. } if content == "4C, rain")
+        ));
+        assert_eq!(
+            messages[4].content,
+            Content::Blocks(vec![
+                ContentBlock::Text {
+                    text: "And this?".into()
+                },
+                ContentBlock::Image {
+                    source: ImageSource::Base64 {
+                        media_type: "image/png".into(),
+                        data: "iVBO".into(),
+                    }
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let (system, messages) = Messages::from_openai(CONVERSATION).unwrap();
+        let exported = messages.to_openai(system.as_deref());
+        let original: Value = serde_json::from_str(CONVERSATION).unwrap();
+        assert_eq!(exported, original);
+
+        // A bare list of messages reads the same
+        let list = exported["messages"].to_string();
+        assert_eq!(Messages::from_openai(&list).unwrap().1.len(), 5);
+    }
+
+    #[test]
+    fn test_invalid() {
+        for json in [
+            r#"{"conversation": []}"#,
+            r#"[{"content": "hi"}]"#,
+            r#"[{"role": "narrator", "content": "hi"}]"#,
+            r#"[{"role": "user", "content": [{"type": "image_url", "image_url": {"url": "https://example.com/a.png"}}]}]"#,
+        ] {
+            let error = Messages::from_openai(json).unwrap_err();
+            assert!(matches!(error, ClaudeError::Conversation(_)), "{}", error);
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! Prompt optimization against evaluation cases
+//!
+//! A prompt is evaluated by sending it followed by each case's input, as a
+//! snippet's prompt precedes text, and having the client judge each output
+//! from 0 to 10 against the case's criteria and expected answer. An optimizer
+//! model then rewrites the best prompt so far from the weakest outputs and
+//! the judge's reasons, and the rewrite is evaluated in turn.
+
+use crate::client::{Client, Messages};
+use crate::error::{ClaudeError, Result};
+use crate::structured::StructuredRetry;
+use crate::sweep::Judgement;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Lowest-scoring cases shown to the optimizer per rewrite
+const FEEDBACK_CASES: usize = 5;
+
+/// One input to evaluate a prompt on, a line of a cases file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalCase {
+    pub input: String,
+    /// The ideal output, which the judge compares outputs to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected: Option<String>,
+    /// What a good output is for this case, instead of the options' criteria
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub criteria: Option<String>,
+}
+
+impl EvalCase {
+    /// Parse JSONL with one case per line, skipping blank lines
+    pub fn parse_jsonl(jsonl: &str) -> Result<Vec<EvalCase>> {
+        jsonl
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(number, line)| {
+                serde_json::from_str(line)
+                    .map_err(|e| ClaudeError::Eval(format!("case on line {}: {}", number + 1, e)))
+            })
+            .collect()
+    }
+}
+
+/// How [`Client::optimize_prompt`] evaluates and rewrites prompts
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptimizeOptions {
+    /// Rewrites to try after evaluating the original prompt
+    pub rounds: usize,
+    /// What a good output is, for cases without their own criteria
+    pub criteria: String,
+    /// Model that rewrites the prompt (the client's own when `None`)
+    pub optimizer: Option<String>,
+    /// System prompt for every case
+    pub system: Option<String>,
+}
+
+impl Default for OptimizeOptions {
+    fn default() -> Self {
+        Self {
+            rounds: 3,
+            criteria: "correct, complete and clear".into(),
+            optimizer: None,
+            system: None,
+        }
+    }
+}
+
+/// A case's output for a prompt and its score
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CaseResult {
+    pub input: String,
+    pub output: String,
+    pub judgement: Judgement,
+}
+
+/// A prompt evaluated on every case
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PromptEval {
+    pub prompt: String,
+    /// How the prompt differs from the one it was rewritten from; `None` for
+    /// the original
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changes: Option<String>,
+    /// The mean score of the cases, from 0 to 10
+    pub score: f64,
+    pub cases: Vec<CaseResult>,
+}
+
+/// The result of [`Client::optimize_prompt`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PromptOptimization {
+    pub original: PromptEval,
+    /// Each rewrite, in the order they were tried
+    pub variants: Vec<PromptEval>,
+}
+
+impl PromptOptimization {
+    /// The highest-scoring prompt, preferring the earliest on a tie
+    pub fn best(&self) -> &PromptEval {
+        self.variants.iter().fold(&self.original, |best, variant| {
+            if variant.score > best.score {
+                variant
+            } else {
+                best
+            }
+        })
+    }
+
+    /// How much higher the best prompt scores than the original
+    pub fn improvement(&self) -> f64 {
+        self.best().score - self.original.score
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct Rewrite {
+    /// the complete rewritten prompt, which is followed by each input as before
+    prompt: String,
+    /// briefly, what you changed and why
+    changes: String,
+}
+
+impl Client {
+    /// Evaluate `prompt` on every case, all at once
+    pub async fn evaluate_prompt(
+        &self,
+        prompt: &str,
+        cases: &[EvalCase],
+        options: &OptimizeOptions,
+    ) -> Result<PromptEval> {
+        if cases.is_empty() {
+            return Err(ClaudeError::Eval("there are no cases to evaluate".into()));
+        }
+
+        let results = futures::future::join_all(cases.iter().map(|case| async move {
+            let messages = Messages::new()
+                .push_user(format!("{}\n\n{}", prompt, case.input))
+                .clone();
+            let output = self
+                .send_message(messages.clone(), None, options.system.clone())
+                .await?;
+            let mut criteria = case
+                .criteria
+                .clone()
+                .unwrap_or_else(|| options.criteria.clone());
+            if let Some(expected) = &case.expected {
+                criteria.push_str(&format!(
+                    "\n\nIt should agree with this expected output:\n{}",
+                    expected
+                ));
+            }
+            let judgement = self.judge(&messages, &output, &criteria).await?;
+            Ok(CaseResult {
+                input: case.input.clone(),
+                output,
+                judgement,
+            })
+        }))
+        .await;
+        let cases = results.into_iter().collect::<Result<Vec<_>>>()?;
+
+        let score = cases.iter().map(|case| case.judgement.score).sum::<f64>() / cases.len() as f64;
+        Ok(PromptEval {
+            prompt: prompt.to_string(),
+            changes: None,
+            score,
+            cases,
+        })
+    }
+
+    /// Rewrite `prompt` in rounds to score better on `cases`
+    ///
+    /// Each round rewrites the best prompt so far, showing the optimizer its
+    /// weakest outputs with the judge's reasons and the prompts already tried.
+    pub async fn optimize_prompt(
+        &self,
+        prompt: &str,
+        cases: &[EvalCase],
+        options: &OptimizeOptions,
+    ) -> Result<PromptOptimization> {
+        let optimizer = match &options.optimizer {
+            Some(model) => self.for_model(model.as_str())?,
+            None => self.for_model(self.config().model.clone())?,
+        };
+        let mut optimization = PromptOptimization {
+            original: self.evaluate_prompt(prompt, cases, options).await?,
+            variants: Vec::with_capacity(options.rounds),
+        };
+
+        for _ in 0..options.rounds {
+            let best = optimization.best();
+            let mut weakest: Vec<&CaseResult> = best.cases.iter().collect();
+            weakest.sort_by(|a, b| a.judgement.score.total_cmp(&b.judgement.score));
+            let feedback = weakest
+                .iter()
+                .take(FEEDBACK_CASES)
+                .map(|case| {
+                    format!(
+                        "<case score=\"{}\">\n<input>\n{}\n</input>\n<output>\n{}\n</output>\n\
+                         <reason>{}</reason>\n</case>",
+                        case.judgement.score, case.input, case.output, case.judgement.reason
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            let tried = std::iter::once(&optimization.original)
+                .chain(&optimization.variants)
+                .map(|tried| {
+                    format!(
+                        "<tried score=\"{:.2}\">\n{}\n</tried>",
+                        tried.score, tried.prompt
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            let request = format!(
+                "This prompt is sent followed by each case's input, and its outputs are \
+                 scored from 0 to 10:\n<prompt>\n{}\n</prompt>\n\nIts lowest-scoring \
+                 cases:\n{}\n\nPrompts tried so far:\n{}\n\nRewrite the prompt so that it \
+                 scores higher on cases like these, without overfitting to them.",
+                best.prompt, feedback, tried
+            );
+
+            let rewrite: Rewrite = optimizer
+                .send_structured(
+                    Messages::new().push_user(request).clone(),
+                    Some("You improve prompts for language models.".into()),
+                    StructuredRetry::default(),
+                )
+                .await?;
+            let mut variant = self
+                .evaluate_prompt(rewrite.prompt.trim(), cases, options)
+                .await?;
+            variant.changes = Some(rewrite.changes);
+            optimization.variants.push(variant);
+        }
+        Ok(optimization)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::{http_stub, stub_client};
+
+    #[test]
+    fn test_parse_cases() {
+        let cases = EvalCase::parse_jsonl(
+            "{\"input\": \"2+2\", \"expected\": \"4\"}\n\n{\"input\": \"hi\", \"criteria\": \"polite\"}\n",
+        )
+        .unwrap();
+        assert_eq!(cases.len(), 2);
+        assert_eq!(cases[0].expected.as_deref(), Some("4"));
+        assert_eq!(cases[1].criteria.as_deref(), Some("polite"));
+
+        let error = EvalCase::parse_jsonl("{\"input\": \"a\"}\n{\"expected\": \"b\"}").unwrap_err();
+        assert!(error.to_string().contains("line 2"), "{}", error);
+    }
+
+    fn reply(text: &str) -> &'static str {
+        Box::leak(
+            serde_json::json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-haiku-4-5",
+                "content": [{"type": "text", "text": text}],
+                "stop_reason": "end_turn",
+                "usage": {"input_tokens": 1, "output_tokens": 1},
+            })
+            .to_string()
+            .into_boxed_str(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_optimize_prompt() {
+        // Structured output prefills "{", so judgements and rewrites continue from there
+        let (url, requests) = http_stub(vec![
+            Some((200, reply("four"))),
+            Some((200, reply(r#""score": 4, "reason": "Not a digit."}"#))),
+            Some((
+                200,
+                reply(r#""prompt": "Answer with digits only.", "changes": "Ask for digits."}"#),
+            )),
+            Some((200, reply("4"))),
+            Some((200, reply(r#""score": 10, "reason": "Exact."}"#))),
+        ])
+        .await;
+        let client = stub_client(url);
+        let cases = vec![EvalCase {
+            input: "2+2".into(),
+            expected: Some("4".into()),
+            criteria: None,
+        }];
+        let options = OptimizeOptions {
+            rounds: 1,
+            ..OptimizeOptions::default()
+        };
+
+        let optimization = client
+            .optimize_prompt("Answer the question.", &cases, &options)
+            .await
+            .unwrap();
+        assert_eq!(optimization.original.score, 4.0);
+        assert_eq!(optimization.variants.len(), 1);
+        let best = optimization.best();
+        assert_eq!(best.prompt, "Answer with digits only.");
+        assert_eq!(best.changes.as_deref(), Some("Ask for digits."));
+        assert_eq!(optimization.improvement(), 6.0);
+
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].contains("answer the question.\\n\\n2+2"));
+        assert!(requests[1].contains("expected output:\\n4"));
+        assert!(requests[2].contains("<reason>not a digit.</reason>"));
+        assert!(requests[3].contains("answer with digits only.\\n\\n2+2"));
+    }
+}
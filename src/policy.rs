@@ -0,0 +1,338 @@
+use crate::config::Config;
+use crate::diagnostics;
+use crate::error::{ConfigError, Result};
+use crate::ledger::Ledger;
+use crate::safety::SafetyPreset;
+use chrono::{Datelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Organization-wide restrictions loaded from a system-level policy file
+///
+/// The policy is read-only for users: it lives outside the user's config
+/// directory and is applied on top of whatever the user configured. Unknown
+/// keys are rejected so that a policy written for a newer version fails
+/// closed instead of silently not being enforced.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    /// Models that may be used; a trailing `*` matches any suffix
+    #[serde(default)]
+    pub allowed_models: Option<Vec<String>>,
+
+    /// Entries of the config file's `providers` that may be used; when set,
+    /// one of them must be chosen
+    #[serde(default)]
+    pub allowed_providers: Option<Vec<String>>,
+
+    /// Base URLs requests may be sent to, e.g. `https://api.anthropic.com/v1`;
+    /// a trailing `*` matches any suffix
+    #[serde(default)]
+    pub allowed_base_urls: Option<Vec<String>>,
+
+    /// Most that may be spent in a calendar month (UTC), in USD, as estimated
+    /// by the local usage ledger; requests are refused once it is reached
+    #[serde(default)]
+    pub max_spend: Option<f64>,
+
+    /// Upper bound on max_tokens; larger requests are lowered to this value
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+
+    /// Always redact outgoing prompts, ignoring config and `--no-redact`
+    #[serde(default)]
+    pub force_redaction: bool,
+
+    /// The least strict safety preset allowed; weaker presets are raised to it
+    #[serde(default)]
+    pub min_safety: Option<SafetyPreset>,
+}
+
+impl Policy {
+    /// Location of the system policy file
+    ///
+    /// `/etc/ellm/policy.toml` on Unix, `%ProgramData%\ellm\policy.toml` on Windows.
+    pub fn system_path() -> PathBuf {
+        if cfg!(windows) {
+            let program_data =
+                std::env::var_os("ProgramData").unwrap_or_else(|| "C:\\ProgramData".into());
+            PathBuf::from(program_data).join("ellm").join("policy.toml")
+        } else {
+            PathBuf::from("/etc/ellm/policy.toml")
+        }
+    }
+
+    /// Load the system policy, or an empty policy when no file is installed
+    pub fn load_system() -> Result<Self> {
+        let path = Self::system_path();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Self::from_file(&path)
+    }
+
+    /// Load a policy from a specific file
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        toml::from_str(&contents).map_err(|e| {
+            ConfigError::ParseError(format!("policy file {}: {}", path.display(), e)).into()
+        })
+    }
+
+    /// Whether the model is permitted by this policy
+    pub fn allows_model(&self, model: &str) -> bool {
+        matches_any(self.allowed_models.as_deref(), model)
+    }
+
+    /// Whether the provider, if any was chosen, is permitted by this policy
+    pub fn allows_provider(&self, provider: Option<&str>) -> bool {
+        match (&self.allowed_providers, provider) {
+            (None, _) => true,
+            (Some(allowed), Some(provider)) => allowed.iter().any(|name| name == provider),
+            (Some(_), None) => false,
+        }
+    }
+
+    /// Whether requests may be sent to `base_url`, ignoring a trailing slash
+    pub fn allows_base_url(&self, base_url: &str) -> bool {
+        let Some(allowed) = &self.allowed_base_urls else {
+            return true;
+        };
+        let trimmed: Vec<String> = allowed
+            .iter()
+            .map(|url| url.trim_end_matches('/').to_string())
+            .collect();
+        matches_any(Some(&trimmed), base_url.trim_end_matches('/'))
+    }
+
+    /// Refuse further requests once this month's spend in `ledger` reaches
+    /// the policy's limit
+    pub fn check_spend(&self, ledger: &Ledger) -> Result<()> {
+        let Some(limit) = self.max_spend else {
+            return Ok(());
+        };
+
+        let now = Utc::now();
+        let spent: f64 = ledger
+            .entries()?
+            .iter()
+            .filter(|entry| {
+                (entry.timestamp.year(), entry.timestamp.month()) == (now.year(), now.month())
+            })
+            .filter_map(|entry| entry.cost_usd)
+            .sum();
+        if spent >= limit {
+            return Err(ConfigError::PolicyViolation(format!(
+                "${:.2} spent this month reaches the organization policy's limit of ${:.2}",
+                spent, limit
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Check and adjust a configuration so it complies with this policy
+    pub fn apply(&self, mut config: Config) -> Result<Config> {
+        if !self.allows_model(&config.model) {
+            return Err(ConfigError::PolicyViolation(format!(
+                "model {} is not allowed by the organization policy",
+                config.model
+            ))
+            .into());
+        }
+
+        if !self.allows_provider(config.provider.as_deref()) {
+            return Err(ConfigError::PolicyViolation(match &config.provider {
+                Some(provider) => format!(
+                    "provider {} is not allowed by the organization policy",
+                    provider
+                ),
+                None => "the organization policy requires choosing one of its providers".into(),
+            })
+            .into());
+        }
+
+        if !self.allows_base_url(&config.base_url) {
+            return Err(ConfigError::PolicyViolation(format!(
+                "base URL {} is not allowed by the organization policy",
+                config.base_url
+            ))
+            .into());
+        }
+
+        if let Some(cap) = self.max_tokens {
+            if config.max_tokens > cap {
+                diagnostics::warn(
+                    "policy",
+                    format!(
+                        "max_tokens lowered from {} to {} by the organization policy",
+                        config.max_tokens, cap
+                    ),
+                    serde_json::json!({"max_tokens": config.max_tokens, "cap": cap}),
+                );
+                config.max_tokens = cap;
+            }
+            if config.auto_max_tokens {
+                config.max_tokens_ceiling = Some(config.auto_max_tokens_ceiling().min(cap));
+            }
+        }
+
+        if self.force_redaction && !config.redaction.enabled {
+            diagnostics::warn(
+                "policy",
+                "redaction is required by the organization policy",
+                serde_json::json!({}),
+            );
+            config.redaction.enabled = true;
+        }
+
+        if let Some(min_safety) = self.min_safety {
+            if config.safety < min_safety {
+                diagnostics::warn(
+                    "policy",
+                    format!(
+                        "safety preset raised from {} to {} by the organization policy",
+                        config.safety, min_safety
+                    ),
+                    serde_json::json!({"safety": config.safety, "min_safety": min_safety}),
+                );
+                config.safety = min_safety;
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Whether `value` matches one of the `allowed` patterns, where a trailing
+/// `*` matches any suffix; no list allows everything
+fn matches_any(allowed: Option<&[String]>, value: &str) -> bool {
+    let Some(allowed) = allowed else {
+        return true;
+    };
+
+    allowed
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => value.starts_with(prefix),
+            None => value == pattern,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(toml_str: &str) -> Policy {
+        toml::from_str(toml_str).unwrap()
+    }
+
+    #[test]
+    fn test_empty_policy_allows_everything() {
+        let config = Config::new("sk-ant-test-key").with_max_tokens(100_000);
+        let config = Policy::default().apply(config).unwrap();
+        assert_eq!(config.max_tokens, 100_000);
+    }
+
+    #[test]
+    fn test_allowed_models() {
+        let policy = policy(r#"allowed_models = ["claude-sonnet-4-5*", "claude-haiku-4-5"]"#);
+        assert!(policy.allows_model("claude-sonnet-4-5-20250929"));
+        assert!(policy.allows_model("claude-haiku-4-5"));
+        assert!(!policy.allows_model("claude-opus-4-1"));
+
+        let config = Config::new("sk-ant-test-key").with_model("claude-opus-4-1");
+        assert!(policy.apply(config).is_err());
+    }
+
+    #[test]
+    fn test_allowed_providers() {
+        let policy = policy(r#"allowed_providers = ["work"]"#);
+        let mut config = Config::new("sk-ant-test-key");
+        assert!(policy.apply(config.clone()).is_err());
+
+        config.provider = Some("personal".into());
+        assert!(policy.apply(config.clone()).is_err());
+
+        config.provider = Some("work".into());
+        assert!(policy.apply(config).is_ok());
+    }
+
+    #[test]
+    fn test_allowed_base_urls() {
+        let policy = policy(
+            r#"allowed_base_urls = ["https://api.anthropic.com/v1/", "https://gateway.example.com/*"]"#,
+        );
+        assert!(policy.allows_base_url("https://api.anthropic.com/v1"));
+        assert!(policy.allows_base_url("https://gateway.example.com/anthropic/v1"));
+        assert!(!policy.allows_base_url("https://api.anthropic.com.evil.example/v1"));
+        assert!(policy.apply(Config::new("sk-ant-test-key")).is_ok());
+
+        let mut config = Config::new("sk-ant-test-key");
+        config.base_url = "http://localhost:8080/v1".into();
+        let error = policy.apply(config).unwrap_err();
+        assert!(error.to_string().contains("localhost:8080"), "{}", error);
+    }
+
+    #[test]
+    fn test_max_spend() {
+        use crate::ledger::LedgerEntry;
+
+        let path =
+            std::env::temp_dir().join(format!("ellm-policy-ledger-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let ledger = Ledger::new(&path);
+        let mut entry = LedgerEntry::new("claude-sonnet-4-5", None, 0, 0, 0, 0);
+        entry.cost_usd = Some(6.0);
+
+        let policy = policy("max_spend = 10.0");
+        assert!(policy.check_spend(&ledger).is_ok());
+        ledger.append(&entry).unwrap();
+        assert!(policy.check_spend(&ledger).is_ok());
+
+        // Last month's spend doesn't count
+        let mut old = entry.clone();
+        old.timestamp -= chrono::Duration::days(62);
+        ledger.append(&old).unwrap();
+        assert!(policy.check_spend(&ledger).is_ok());
+
+        ledger.append(&entry).unwrap();
+        assert!(policy.check_spend(&ledger).is_err());
+        assert!(Policy::default().check_spend(&ledger).is_ok());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_caps_max_tokens_and_forces_redaction() {
+        let policy = policy(
+            r#"
+            max_tokens = 2048
+            force_redaction = true
+            "#,
+        );
+        let mut config = Config::new("sk-ant-test-key").with_max_tokens(8192);
+        config.redaction.enabled = false;
+
+        let config = policy.apply(config).unwrap();
+        assert_eq!(config.max_tokens, 2048);
+        assert!(config.redaction.enabled);
+    }
+
+    #[test]
+    fn test_raises_safety() {
+        let policy = policy(r#"min_safety = "standard""#);
+        let config = policy.apply(Config::new("sk-ant-test-key")).unwrap();
+        assert_eq!(config.safety, SafetyPreset::Standard);
+
+        let mut config = Config::new("sk-ant-test-key");
+        config.safety = SafetyPreset::Strict;
+        let config = policy.apply(config).unwrap();
+        assert_eq!(config.safety, SafetyPreset::Strict);
+    }
+
+    #[test]
+    fn test_unknown_keys_rejected() {
+        assert!(toml::from_str::<Policy>("max_spend_usd = 100").is_err());
+        assert!(toml::from_str::<Policy>("max_spend = 100").is_ok());
+    }
+}
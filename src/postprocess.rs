@@ -0,0 +1,280 @@
+use crate::error::{ClaudeError, Result};
+use crate::select::Selector;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+/// A single step in a response post-processing pipeline
+///
+/// Steps are written as short specs so they can be given on the command line
+/// or in the config file:
+///
+/// * `trim` - trim leading and trailing whitespace
+/// * `strip-markdown` - remove code fences, emphasis, headings and link syntax
+/// * `regex:<pattern>` - keep the first capture group (or the whole match)
+/// * `json:<path>` - select a field from a JSON response, e.g. `json:items.0.name`
+///   or `json:items[?done].name`, with the syntax of [`Selector`]; several
+///   selected values are written one per line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum PostProcessor {
+    Trim,
+    StripMarkdown,
+    Regex(String),
+    JsonField(String),
+}
+
+impl PostProcessor {
+    /// Apply this step to the given text
+    pub fn apply(&self, text: &str) -> Result<String> {
+        match self {
+            PostProcessor::Trim => Ok(text.trim().to_string()),
+            PostProcessor::StripMarkdown => Ok(strip_markdown(text)),
+            PostProcessor::Regex(pattern) => {
+                let regex = compile(pattern)?;
+                let captures = regex.captures(text).ok_or_else(|| {
+                    ClaudeError::PostProcess(format!("pattern {:?} did not match", pattern))
+                })?;
+                let matched = captures
+                    .get(1)
+                    .or_else(|| captures.get(0))
+                    .map(|m| m.as_str())
+                    .unwrap_or_default();
+                Ok(matched.to_string())
+            }
+            PostProcessor::JsonField(path) => {
+                let selector: Selector = path.parse()?;
+                let value: serde_json::Value = serde_json::from_str(text.trim())?;
+                let selected: Vec<String> = selector
+                    .select(&value)?
+                    .into_iter()
+                    .map(|selected| match selected {
+                        serde_json::Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    })
+                    .collect();
+                Ok(selected.join("\n"))
+            }
+        }
+    }
+}
+
+impl FromStr for PostProcessor {
+    type Err = ClaudeError;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let (name, arg) = match spec.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (spec, None),
+        };
+
+        match (name, arg) {
+            ("trim", None) => Ok(PostProcessor::Trim),
+            ("strip-markdown", None) => Ok(PostProcessor::StripMarkdown),
+            ("regex", Some(pattern)) => {
+                compile(pattern)?;
+                Ok(PostProcessor::Regex(pattern.to_string()))
+            }
+            ("json", Some(path)) => {
+                path.parse::<Selector>()?;
+                Ok(PostProcessor::JsonField(path.to_string()))
+            }
+            _ => Err(ClaudeError::PostProcess(format!(
+                "unknown post-processor {:?} (expected trim, strip-markdown, regex:<pattern> or json:<path>)",
+                spec
+            ))),
+        }
+    }
+}
+
+impl TryFrom<String> for PostProcessor {
+    type Error = ClaudeError;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<PostProcessor> for String {
+    fn from(value: PostProcessor) -> Self {
+        value.to_string()
+    }
+}
+
+impl fmt::Display for PostProcessor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PostProcessor::Trim => write!(f, "trim"),
+            PostProcessor::StripMarkdown => write!(f, "strip-markdown"),
+            PostProcessor::Regex(pattern) => write!(f, "regex:{}", pattern),
+            PostProcessor::JsonField(path) => write!(f, "json:{}", path),
+        }
+    }
+}
+
+/// An ordered chain of post-processing steps
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    steps: Vec<PostProcessor>,
+}
+
+impl Pipeline {
+    pub fn new(steps: Vec<PostProcessor>) -> Self {
+        Self { steps }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Run the text through every step in order
+    pub fn apply(&self, text: &str) -> Result<String> {
+        self.steps
+            .iter()
+            .try_fold(text.to_string(), |text, step| step.apply(&text))
+    }
+}
+
+/// Rewrites the text of a response before it is returned to the caller
+///
+/// Hooks added with [`Client::with_response_hook`](crate::Client::with_response_hook)
+/// run in the order added, after the output guardrails have passed the text,
+/// on every response and the text of every tool run round. Streamed text is
+/// delivered as it arrives, so only the finished message passes through them.
+/// Returning an error fails the request. Any `Fn(String) -> Result<String>` is a hook,
+/// as are post-processors and pipelines.
+pub trait ResponseHook: Send + Sync {
+    fn transform(&self, text: String) -> Result<String>;
+}
+
+impl<F> ResponseHook for F
+where
+    F: Fn(String) -> Result<String> + Send + Sync,
+{
+    fn transform(&self, text: String) -> Result<String> {
+        self(text)
+    }
+}
+
+impl ResponseHook for PostProcessor {
+    fn transform(&self, text: String) -> Result<String> {
+        self.apply(&text)
+    }
+}
+
+impl ResponseHook for Pipeline {
+    fn transform(&self, text: String) -> Result<String> {
+        self.apply(&text)
+    }
+}
+
+fn compile(pattern: &str) -> Result<Regex> {
+    Regex::new(pattern).map_err(|e| ClaudeError::PostProcess(e.to_string()))
+}
+
+/// Select a value from JSON using a dotted path, where numeric segments index arrays
+pub fn select_json<'a>(value: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    path.split('.')
+        .filter(|segment| !segment.is_empty())
+        .try_fold(value, |value, segment| match value {
+            serde_json::Value::Array(items) => items.get(segment.parse::<usize>().ok()?),
+            serde_json::Value::Object(map) => map.get(segment),
+            _ => None,
+        })
+}
+
+fn strip_markdown(text: &str) -> String {
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        // Drop fence lines but keep the code they contain
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            continue;
+        }
+        let line = if trimmed.starts_with('#') {
+            trimmed.trim_start_matches('#').trim_start()
+        } else if let Some(quoted) = trimmed.strip_prefix('>') {
+            quoted.trim_start()
+        } else {
+            line
+        };
+        lines.push(line);
+    }
+    let mut text = lines.join("\n");
+
+    for regex in INLINE_MARKDOWN.iter() {
+        text = regex.replace_all(&text, "$1").into_owned();
+    }
+    text
+}
+
+/// Inline markdown whose first group is the text it marks up: links and
+/// images, bold, italics and code
+static INLINE_MARKDOWN: LazyLock<[Regex; 5]> = LazyLock::new(|| {
+    [
+        r"!?\[([^\]]*)\]\([^)]*\)",
+        r"\*\*(.+?)\*\*",
+        r"__(.+?)__",
+        r"\*(\S(?:[^*]*\S)?)\*",
+        r"`([^`]+)`",
+    ]
+    .map(|pattern| Regex::new(pattern).unwrap())
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_specs() {
+        assert!(matches!("trim".parse(), Ok(PostProcessor::Trim)));
+        assert!(matches!(
+            "strip-markdown".parse(),
+            Ok(PostProcessor::StripMarkdown)
+        ));
+        assert!(matches!(
+            "json:a.b".parse(),
+            Ok(PostProcessor::JsonField(path)) if path == "a.b"
+        ));
+        assert!("regex:(".parse::<PostProcessor>().is_err());
+        assert!("bogus".parse::<PostProcessor>().is_err());
+    }
+
+    #[test]
+    fn test_regex_capture() {
+        let step: PostProcessor = r"regex:version (\d+\.\d+)".parse().unwrap();
+        assert_eq!(step.apply("the version 1.2 is out").unwrap(), "1.2");
+        assert!(step.apply("nothing here").is_err());
+    }
+
+    #[test]
+    fn test_json_field() {
+        let step: PostProcessor = "json:items.1.name".parse().unwrap();
+        let text = r#"{"items": [{"name": "a"}, {"name": "b"}]}"#;
+        assert_eq!(step.apply(text).unwrap(), "b");
+
+        let step: PostProcessor = "json:items[?name!=b].name".parse().unwrap();
+        assert_eq!(step.apply(text).unwrap(), "a");
+        assert!("json:items[".parse::<PostProcessor>().is_err());
+    }
+
+    #[test]
+    fn test_strip_markdown() {
+        let text = "# Title\n\n```rust\nlet x = 1;\n```\nSome **bold** and [a link](http://x).";
+        assert_eq!(
+            strip_markdown(text),
+            "Title\n\nlet x = 1;\nSome bold and a link."
+        );
+    }
+
+    #[test]
+    fn test_pipeline_order() {
+        let pipeline = Pipeline::new(vec![
+            "strip-markdown".parse().unwrap(),
+            "trim".parse().unwrap(),
+        ]);
+        assert_eq!(pipeline.apply("  `42`  \n").unwrap(), "42");
+    }
+}
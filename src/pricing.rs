@@ -0,0 +1,133 @@
+//! Published per-token prices, used to estimate what requests cost
+
+/// USD per million tokens for one model family
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Price {
+    pub input: f64,
+    pub output: f64,
+}
+
+/// Prices by model ID prefix; the first match wins, so list specific IDs first
+const PRICES: &[(&str, Price)] = &[
+    (
+        "claude-opus-4-5",
+        Price {
+            input: 5.0,
+            output: 25.0,
+        },
+    ),
+    (
+        "claude-opus-4",
+        Price {
+            input: 15.0,
+            output: 75.0,
+        },
+    ),
+    (
+        "claude-3-opus",
+        Price {
+            input: 15.0,
+            output: 75.0,
+        },
+    ),
+    (
+        "claude-sonnet-4",
+        Price {
+            input: 3.0,
+            output: 15.0,
+        },
+    ),
+    (
+        "claude-3-7-sonnet",
+        Price {
+            input: 3.0,
+            output: 15.0,
+        },
+    ),
+    (
+        "claude-3-5-sonnet",
+        Price {
+            input: 3.0,
+            output: 15.0,
+        },
+    ),
+    (
+        "claude-haiku-4-5",
+        Price {
+            input: 1.0,
+            output: 5.0,
+        },
+    ),
+    (
+        "claude-3-5-haiku",
+        Price {
+            input: 0.8,
+            output: 4.0,
+        },
+    ),
+    (
+        "claude-3-haiku",
+        Price {
+            input: 0.25,
+            output: 1.25,
+        },
+    ),
+];
+
+/// Cache writes cost more than regular input, cache reads much less
+const CACHE_WRITE_MULTIPLIER: f64 = 1.25;
+const CACHE_READ_MULTIPLIER: f64 = 0.1;
+
+/// Fraction of the regular price charged for Message Batches requests
+pub const BATCH_MULTIPLIER: f64 = 0.5;
+
+/// The price of a model, if it is known
+pub fn price(model: &str) -> Option<Price> {
+    PRICES
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, price)| *price)
+}
+
+impl Price {
+    /// Cost in USD of the given token counts
+    pub fn cost(&self, input: u64, output: u64, cache_write: u64, cache_read: u64) -> f64 {
+        let input = input as f64
+            + cache_write as f64 * CACHE_WRITE_MULTIPLIER
+            + cache_read as f64 * CACHE_READ_MULTIPLIER;
+        (input * self.input + output as f64 * self.output) / 1_000_000.0
+    }
+
+    /// USD saved by prompt caching compared to sending the same tokens uncached
+    ///
+    /// Negative when cache writes have not (yet) been paid back by reads.
+    pub fn cache_savings(&self, cache_write: u64, cache_read: u64) -> f64 {
+        let saved = cache_read as f64 * (1.0 - CACHE_READ_MULTIPLIER)
+            - cache_write as f64 * (CACHE_WRITE_MULTIPLIER - 1.0);
+        saved * self.input / 1_000_000.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_price_lookup() {
+        assert_eq!(price("claude-sonnet-4-5-20250929").unwrap().input, 3.0);
+        assert_eq!(price("claude-opus-4-5-20251101").unwrap().output, 25.0);
+        assert_eq!(price("claude-opus-4-1-20250805").unwrap().output, 75.0);
+        assert!(price("gpt-4o").is_none());
+    }
+
+    #[test]
+    fn test_cost() {
+        let price = price("claude-sonnet-4-5").unwrap();
+        let cost = price.cost(1_000_000, 100_000, 0, 1_000_000);
+        assert!((cost - (3.0 + 1.5 + 0.3)).abs() < 1e-9);
+
+        // A million cached reads at $3/MTok save 90% of $3
+        assert!((price.cache_savings(0, 1_000_000) - 2.7).abs() < 1e-9);
+        assert!(price.cache_savings(1_000_000, 0) < 0.0);
+    }
+}
@@ -0,0 +1,156 @@
+//! Progress bars for long operations
+//!
+//! A [`Progress`] shows the items processed out of the total, the tokens and
+//! estimated cost of the requests made so far, and the time left. Added to a
+//! client as an observer, it sees every request; commands that can't see
+//! their items complete one by one, because a single library call makes all
+//! the requests, count the requests themselves as the items instead.
+//!
+//! Bars are hidden when stdout or stderr isn't a terminal, so piped output
+//! and logs stay clean.
+
+use ellm::observer::{Completion, Observer, RequestInfo};
+use ellm::Usage;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::io::IsTerminal;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const TEMPLATE: &str = "{spinner} [{elapsed}] {wide_bar} {pos}/{len} {prefix} · {msg} · {eta} left";
+
+#[derive(Debug, Default)]
+struct Totals {
+    tokens: u64,
+    cost_usd: f64,
+}
+
+#[derive(Clone)]
+pub struct Progress {
+    bar: ProgressBar,
+    totals: Arc<Mutex<Totals>>,
+    count_requests: bool,
+}
+
+impl Progress {
+    /// A bar for `len` items, counted with [`Progress::inc`]
+    pub fn new(len: u64, unit: &str) -> Self {
+        let target = if std::io::stdout().is_terminal() && std::io::stderr().is_terminal() {
+            ProgressDrawTarget::stderr()
+        } else {
+            ProgressDrawTarget::hidden()
+        };
+        let bar = ProgressBar::with_draw_target(Some(len), target);
+        bar.set_style(
+            ProgressStyle::with_template(TEMPLATE)
+                .expect("valid progress template")
+                .progress_chars("=> "),
+        );
+        bar.set_prefix(unit.to_string());
+        bar.set_message("0 tokens");
+        bar.enable_steady_tick(Duration::from_millis(200));
+        Self {
+            bar,
+            totals: Arc::default(),
+            count_requests: false,
+        }
+    }
+
+    /// A bar for about `len` requests, counted as they complete
+    pub fn of_requests(len: u64) -> Self {
+        Self {
+            count_requests: true,
+            ..Self::new(len, "requests")
+        }
+    }
+
+    pub fn inc(&self) {
+        self.bar.inc(1);
+    }
+
+    pub fn set_position(&self, position: u64) {
+        self.bar.set_position(position);
+    }
+
+    pub fn set_length(&self, len: u64) {
+        self.bar.set_length(len);
+    }
+
+    /// Show a message instead of the tokens and cost, e.g. for batches
+    pub fn set_message(&self, message: impl Into<String>) {
+        self.bar.set_message(message.into());
+    }
+
+    /// Hide the bar while `f` writes to the terminal
+    pub fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.bar.suspend(f)
+    }
+
+    /// Remove the bar
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+impl Observer for Progress {
+    fn on_complete(&self, request: &RequestInfo, completion: &Completion) {
+        let usage = Usage {
+            input_tokens: completion.input_tokens,
+            output_tokens: completion.output_tokens,
+            cache_creation_input_tokens: completion.cache_creation_input_tokens,
+            cache_read_input_tokens: completion.cache_read_input_tokens,
+        };
+        let mut totals = self.totals.lock().unwrap();
+        totals.tokens += usage.input_tokens
+            + usage.output_tokens
+            + usage.cache_creation_input_tokens
+            + usage.cache_read_input_tokens;
+        totals.cost_usd += usage.cost(&request.model).unwrap_or(0.0);
+        self.bar.set_message(summary(&totals));
+        // More requests than expected, e.g. retries, mustn't overrun the bar
+        if self.count_requests && self.bar.position() + 1 < self.bar.length().unwrap_or(0) {
+            self.bar.inc(1);
+        }
+    }
+}
+
+fn summary(totals: &Totals) -> String {
+    format!("{} tokens, ${:.4}", totals.tokens, totals.cost_usd)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn completion(input_tokens: u64, output_tokens: u64) -> Completion {
+        Completion {
+            input_tokens,
+            output_tokens,
+            cache_creation_input_tokens: 0,
+            cache_read_input_tokens: 0,
+            stop_reason: None,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_observes_requests() {
+        let progress = Progress::of_requests(3);
+        let request = RequestInfo {
+            idempotency_key: "key".into(),
+            model: "claude-haiku-4-5".into(),
+            stream: false,
+            started: Instant::now(),
+        };
+        for _ in 0..4 {
+            progress.on_complete(&request, &completion(1000, 500));
+        }
+
+        let totals = progress.totals.lock().unwrap();
+        assert_eq!(totals.tokens, 6000);
+        assert!(totals.cost_usd > 0.0);
+        // The last step is left for the command to finish
+        assert_eq!(progress.bar.position(), 2);
+        assert!(summary(&totals).starts_with("6000 tokens, $0.0"));
+    }
+}
@@ -0,0 +1,276 @@
+//! Pseudonymizing the identifiers in tables before they're sent
+//!
+//! Identifier-like columns, such as customer IDs, names and email addresses,
+//! have each of their values replaced by a consistent fake one: every
+//! occurrence of a value, in any column, gets the same pseudonym, so
+//! grouping, counting and joining still work. The mapping never leaves the
+//! machine, and pseudonyms in the model's answer are turned back into the
+//! real values locally.
+
+use crate::error::{ClaudeError, Result};
+use crate::redact;
+use crate::table::{ColumnType, Table};
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+/// Header words that mark a column as identifiers whatever its values are
+const IDENTIFIER_WORDS: &[&str] = &[
+    "id", "uuid", "guid", "email", "mail", "phone", "mobile", "tel", "ssn", "iban", "ip",
+    "username", "login",
+];
+
+/// Header words that mark a column of text as identifiers, e.g. `customer`
+/// but not `customer_count`
+const PERSON_WORDS: &[&str] = &[
+    "name",
+    "firstname",
+    "lastname",
+    "fullname",
+    "surname",
+    "customer",
+    "client",
+    "user",
+    "account",
+    "contact",
+    "owner",
+    "employee",
+    "patient",
+    "address",
+    "street",
+];
+
+/// Share of a column's values that must look like personal data, such as
+/// email addresses, for the column to count as identifiers
+const PERSONAL_SHARE: f64 = 0.8;
+
+/// The fake values that stand in for real ones, and the way back
+#[derive(Debug, Clone, Default)]
+pub struct Pseudonyms {
+    /// Real value to pseudonym
+    fakes: HashMap<String, String>,
+    /// Pseudonym to real value
+    reals: HashMap<String, String>,
+}
+
+impl Pseudonyms {
+    pub fn is_empty(&self) -> bool {
+        self.fakes.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.fakes.len()
+    }
+
+    /// `text` with the real values in it written as their pseudonyms
+    pub fn conceal(&self, text: &str) -> String {
+        replace_words(text, &self.fakes)
+    }
+
+    /// `text` with the pseudonyms in it written as the real values
+    pub fn reveal(&self, text: &str) -> String {
+        replace_words(text, &self.reals)
+    }
+
+    /// `table` with the pseudonyms in its cells replaced by the real values
+    pub fn reveal_table(&self, table: &Table) -> Table {
+        let reveal = |cell: &String| self.reals.get(cell).unwrap_or(cell).clone();
+        Table {
+            headers: table.headers.clone(),
+            rows: table
+                .rows
+                .iter()
+                .map(|row| row.iter().map(reveal).collect())
+                .collect(),
+        }
+    }
+
+    /// The pseudonym for `value`, first seen in the column `prefix` names
+    fn pseudonym(&mut self, value: &str, prefix: &str, email: &Regex) -> String {
+        if let Some(fake) = self.fakes.get(value) {
+            return fake.clone();
+        }
+        let number = self.fakes.len() + 1;
+        let fake = if email.is_match(value) {
+            format!("{}_{}@example.com", prefix, number)
+        } else {
+            format!("{}_{}", prefix, number)
+        };
+        self.fakes.insert(value.to_string(), fake.clone());
+        self.reals.insert(fake.clone(), value.to_string());
+        fake
+    }
+}
+
+/// Values that are personal data in full, e.g. an email address
+static PERSONAL: LazyLock<Vec<Regex>> = LazyLock::new(|| {
+    redact::pii_patterns()
+        .map(|pattern| Regex::new(&format!("^(?:{})$", pattern)).unwrap())
+        .collect()
+});
+static EMAIL: LazyLock<Regex> = LazyLock::new(|| Regex::new(redact::EMAIL_PATTERN).unwrap());
+
+/// `text` with each key of `replacements` in it, as a whole word, replaced
+/// by its value, longest keys first
+fn replace_words(text: &str, replacements: &HashMap<String, String>) -> String {
+    let mut found: Vec<(&String, &String)> = replacements
+        .iter()
+        .filter(|(from, _)| text.contains(from.as_str()))
+        .collect();
+    found.sort_by_key(|(from, _)| std::cmp::Reverse(from.len()));
+
+    let mut text = text.to_string();
+    for (from, to) in found {
+        let word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+        let start = if word(from.chars().next()) { r"\b" } else { "" };
+        let end = if word(from.chars().last()) { r"\b" } else { "" };
+        let pattern = format!("{}{}{}", start, regex::escape(from), end);
+        let regex = Regex::new(&pattern).expect("escaped pattern");
+        text = regex.replace_all(&text, regex::NoExpand(to)).into_owned();
+    }
+    text
+}
+
+/// The lowercase words of a header, split at punctuation and camelCase
+fn header_words(header: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut previous_lower = false;
+    for c in header.chars() {
+        if !c.is_alphanumeric() || (c.is_uppercase() && previous_lower) {
+            words.extend((!word.is_empty()).then(|| std::mem::take(&mut word)));
+        }
+        if c.is_alphanumeric() {
+            word.extend(c.to_lowercase());
+        }
+        previous_lower = c.is_lowercase();
+    }
+    words.extend((!word.is_empty()).then_some(word));
+    words
+}
+
+/// A column's header as a pseudonym prefix, e.g. `customer_id`
+fn prefix(header: &str) -> String {
+    match header_words(header).join("_") {
+        prefix if prefix.is_empty() => "value".to_string(),
+        prefix => prefix,
+    }
+}
+
+impl Table {
+    /// The columns that look like they identify people or accounts
+    ///
+    /// A column counts by its header, e.g. `customer_id`, `email` or
+    /// `lastName` (words like `name` and `customer` only for text columns),
+    /// or when most of its values look like personal data such as email
+    /// addresses.
+    pub fn identifier_columns(&self) -> Vec<String> {
+        self.profile()
+            .into_iter()
+            .enumerate()
+            .filter(|(index, profile)| {
+                let words = header_words(&profile.name);
+                let has = |list: &[&str]| words.iter().any(|word| list.contains(&word.as_str()));
+                if has(IDENTIFIER_WORDS) || (profile.kind == ColumnType::Text && has(PERSON_WORDS))
+                {
+                    return true;
+                }
+                let matching = self
+                    .rows
+                    .iter()
+                    .map(|row| row[*index].trim())
+                    .filter(|cell| PERSONAL.iter().any(|regex| regex.is_match(cell)))
+                    .count();
+                profile.values > 0 && matching as f64 >= profile.values as f64 * PERSONAL_SHARE
+            })
+            .map(|(_, profile)| profile.name)
+            .collect()
+    }
+
+    /// The table with every value in `columns` replaced by a pseudonym
+    pub fn pseudonymize(&self, columns: &[String]) -> Result<(Table, Pseudonyms)> {
+        let indexes = columns
+            .iter()
+            .map(|name| {
+                self.headers
+                    .iter()
+                    .position(|header| header == name)
+                    .ok_or_else(|| {
+                        ClaudeError::Table(format!(
+                            "no column named {:?} to pseudonymize; the columns are {}",
+                            name,
+                            self.headers.join(", ")
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut pseudonyms = Pseudonyms::default();
+        let mut table = self.clone();
+        for row in &mut table.rows {
+            for &index in &indexes {
+                let cell = row[index].trim();
+                if !cell.is_empty() {
+                    row[index] = pseudonyms.pseudonym(cell, &prefix(&self.headers[index]), &EMAIL);
+                }
+            }
+        }
+        Ok((table, pseudonyms))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CUSTOMERS: &str = "customerId,contact,email,region,user_count,amount\n\
+                             C-17,Ada Lovelace,ada@example.org,North,3,100\n\
+                             C-18,Alan Turing,alan@example.org,South,5,250\n\
+                             C-17,Ada Lovelace,ada@example.org,North,3,50\n";
+
+    #[test]
+    fn test_identifier_columns() {
+        let table = Table::parse(CUSTOMERS).unwrap();
+        assert_eq!(
+            table.identifier_columns(),
+            ["customerId", "contact", "email"]
+        );
+
+        let emails = Table::parse("who,n\nada@example.org,1\nalan@example.org,2\n").unwrap();
+        assert_eq!(emails.identifier_columns(), ["who"]);
+    }
+
+    #[test]
+    fn test_pseudonymize_and_reveal() {
+        let table = Table::parse(CUSTOMERS).unwrap();
+        let columns = table.identifier_columns();
+        let (shown, pseudonyms) = table.pseudonymize(&columns).unwrap();
+
+        assert_eq!(
+            shown.rows[0][..4],
+            ["customer_id_1", "contact_2", "email_3@example.com", "North"]
+        );
+        // The same value always gets the same pseudonym
+        assert_eq!(shown.rows[2][..3], shown.rows[0][..3]);
+        assert_eq!(pseudonyms.len(), 6);
+        assert_eq!(pseudonyms.reveal_table(&shown), table);
+
+        assert_eq!(
+            pseudonyms.conceal("How much did Ada Lovelace (C-17) spend?"),
+            "How much did contact_2 (customer_id_1) spend?"
+        );
+        assert_eq!(
+            pseudonyms.reveal("contact_2 spent 150; contact_5 spent 250."),
+            "Ada Lovelace spent 150; Alan Turing spent 250."
+        );
+
+        assert!(table.pseudonymize(&["phone".into()]).is_err());
+    }
+
+    #[test]
+    fn test_header_words() {
+        assert_eq!(header_words("customerID"), ["customer", "id"]);
+        assert_eq!(header_words("Last Name"), ["last", "name"]);
+        assert_eq!(prefix("e-mail"), "e_mail");
+    }
+}
@@ -0,0 +1,364 @@
+use crate::client::Usage;
+use crate::diagnostics;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+const HEADER_PREFIX: &str = "anthropic-ratelimit-";
+
+/// One rate limit as reported by the API
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RateLimit {
+    /// Maximum allowed in the current window
+    pub limit: Option<u64>,
+    /// How much is left in the current window
+    pub remaining: Option<u64>,
+    /// When the limit is fully replenished (RFC 3339)
+    pub reset: Option<String>,
+}
+
+impl RateLimit {
+    fn is_empty(&self) -> bool {
+        self.limit.is_none() && self.remaining.is_none() && self.reset.is_none()
+    }
+}
+
+impl fmt::Display for RateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let number = |n: Option<u64>| n.map_or("?".to_string(), |n| n.to_string());
+        write!(
+            f,
+            "{}/{} remaining",
+            number(self.remaining),
+            number(self.limit)
+        )?;
+        if let Some(reset) = &self.reset {
+            write!(f, ", resets {}", reset)?;
+        }
+        Ok(())
+    }
+}
+
+/// The `anthropic-ratelimit-*` values from the most recent response
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RateLimits {
+    pub requests: RateLimit,
+    pub tokens: RateLimit,
+    pub input_tokens: RateLimit,
+    pub output_tokens: RateLimit,
+    /// Seconds to wait before retrying, sent with 429 responses
+    pub retry_after: Option<u64>,
+}
+
+impl RateLimits {
+    /// Read the rate limit headers, or `None` when the response had none
+    pub fn from_headers(headers: &[(String, String)]) -> Option<Self> {
+        let mut limits = Self::default();
+
+        for (name, value) in headers {
+            let name = name.to_ascii_lowercase();
+            if name == "retry-after" {
+                limits.retry_after = value.trim().parse().ok();
+                continue;
+            }
+
+            let Some(rest) = name.strip_prefix(HEADER_PREFIX) else {
+                continue;
+            };
+            let (limit, field) = match rest.rsplit_once('-') {
+                Some(parts) => parts,
+                None => continue,
+            };
+            let limit = match limit {
+                "requests" => &mut limits.requests,
+                "tokens" => &mut limits.tokens,
+                "input-tokens" => &mut limits.input_tokens,
+                "output-tokens" => &mut limits.output_tokens,
+                _ => continue,
+            };
+            match field {
+                "limit" => limit.limit = value.trim().parse().ok(),
+                "remaining" => limit.remaining = value.trim().parse().ok(),
+                "reset" => limit.reset = Some(value.trim().to_string()),
+                _ => {}
+            }
+        }
+
+        let found = [
+            &limits.requests,
+            &limits.tokens,
+            &limits.input_tokens,
+            &limits.output_tokens,
+        ]
+        .iter()
+        .any(|limit| !limit.is_empty());
+
+        (found || limits.retry_after.is_some()).then_some(limits)
+    }
+
+    /// The named limits that were reported, for display
+    pub fn entries(&self) -> impl Iterator<Item = (&'static str, &RateLimit)> {
+        [
+            ("requests", &self.requests),
+            ("tokens", &self.tokens),
+            ("input tokens", &self.input_tokens),
+            ("output tokens", &self.output_tokens),
+        ]
+        .into_iter()
+        .filter(|(_, limit)| !limit.is_empty())
+    }
+}
+
+/// Limits to keep requests under, so the client waits instead of being
+/// rejected with 429s
+///
+/// Every client in the process using the same API key shares the limits, as
+/// do all processes relaying through the daemon.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requests_per_minute: Option<u32>,
+
+    /// Input tokens not read from the prompt cache plus output tokens
+    ///
+    /// A response's tokens are only known once it arrives, so requests wait
+    /// while earlier responses have used up the allowance rather than
+    /// reserving their own tokens in advance.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tokens_per_minute: Option<u32>,
+}
+
+impl RateLimitConfig {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Refills continuously at the per-minute rate, holding at most one minute's worth
+#[derive(Debug)]
+struct Bucket {
+    capacity: f64,
+    level: f64,
+    per_second: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(per_minute: u32, now: Instant) -> Self {
+        let capacity = f64::from(per_minute);
+        Self {
+            capacity,
+            level: capacity,
+            per_second: capacity / 60.0,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.level = (self.level + elapsed * self.per_second).min(self.capacity);
+        self.updated = now;
+    }
+
+    /// How long until the bucket holds at least `amount`
+    fn wait_for(&self, amount: f64) -> Duration {
+        if self.level >= amount {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64((amount - self.level) / self.per_second)
+    }
+}
+
+#[derive(Debug)]
+struct Buckets {
+    requests: Option<Bucket>,
+    tokens: Option<Bucket>,
+}
+
+/// Token buckets enforcing a [`RateLimitConfig`]
+#[derive(Debug)]
+pub(crate) struct Limiter {
+    buckets: Mutex<Buckets>,
+}
+
+impl Limiter {
+    fn new(limits: &RateLimitConfig) -> Self {
+        let now = Instant::now();
+        Self {
+            buckets: Mutex::new(Buckets {
+                requests: limits.requests_per_minute.map(|n| Bucket::new(n, now)),
+                tokens: limits.tokens_per_minute.map(|n| Bucket::new(n, now)),
+            }),
+        }
+    }
+
+    /// How long to wait before a request may be sent, taking its place if it
+    /// may be sent now
+    fn try_acquire(&self, now: Instant) -> Duration {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let Buckets { requests, tokens } = &mut *buckets;
+        let mut wait = Duration::ZERO;
+        if let Some(requests) = requests.as_mut() {
+            requests.refill(now);
+            wait = wait.max(requests.wait_for(1.0));
+        }
+        if let Some(tokens) = tokens.as_mut() {
+            tokens.refill(now);
+            // Enough for at least one token
+            wait = wait.max(tokens.wait_for(1.0));
+        }
+        if wait.is_zero() {
+            if let Some(requests) = requests.as_mut() {
+                requests.level -= 1.0;
+            }
+        }
+        wait
+    }
+
+    /// Wait until a request may be sent without exceeding the limits
+    pub(crate) async fn acquire(&self) {
+        loop {
+            let wait = self.try_acquire(Instant::now());
+            if wait.is_zero() {
+                return;
+            }
+            diagnostics::info(
+                "rate_limit",
+                format!("waiting {:?} to stay under the configured rate limit", wait),
+                json!({"delay_ms": wait.as_millis() as u64}),
+            );
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Count the tokens of a response against the limits
+    pub(crate) fn charge(&self, usage: &Usage) {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(tokens) = buckets.tokens.as_mut() {
+            tokens.refill(Instant::now());
+            tokens.level -= (usage.input_tokens
+                + usage.cache_creation_input_tokens
+                + usage.output_tokens) as f64;
+        }
+    }
+}
+
+/// Limiters by API key and limits
+type Limiters = HashMap<(String, RateLimitConfig), Arc<Limiter>>;
+
+/// The process's limiter for `api_key`, or `None` without limits
+///
+/// Clients with the same key and limits share a limiter, so together they
+/// stay under the limits.
+pub(crate) fn limiter(api_key: &str, limits: &RateLimitConfig) -> Option<Arc<Limiter>> {
+    static LIMITERS: OnceLock<Mutex<Limiters>> = OnceLock::new();
+
+    if limits.is_default() {
+        return None;
+    }
+    let mut limiters = LIMITERS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    let limiter = limiters
+        .entry((api_key.to_string(), *limits))
+        .or_insert_with(|| Arc::new(Limiter::new(limits)));
+    Some(limiter.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_from_headers() {
+        let limits = RateLimits::from_headers(&headers(&[
+            ("anthropic-ratelimit-requests-limit", "50"),
+            ("anthropic-ratelimit-requests-remaining", "49"),
+            ("anthropic-ratelimit-requests-reset", "2025-01-01T00:00:01Z"),
+            ("anthropic-ratelimit-input-tokens-remaining", "39000"),
+            ("content-type", "application/json"),
+        ]))
+        .unwrap();
+
+        assert_eq!(limits.requests.limit, Some(50));
+        assert_eq!(limits.requests.remaining, Some(49));
+        assert_eq!(limits.input_tokens.remaining, Some(39000));
+        assert_eq!(
+            limits.requests.to_string(),
+            "49/50 remaining, resets 2025-01-01T00:00:01Z"
+        );
+        let names: Vec<&str> = limits.entries().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["requests", "input tokens"]);
+    }
+
+    #[test]
+    fn test_bucket() {
+        let start = Instant::now();
+        let mut bucket = Bucket::new(60, start);
+        assert_eq!(bucket.wait_for(60.0), Duration::ZERO);
+        bucket.level = -2.0;
+        assert_eq!(bucket.wait_for(1.0), Duration::from_secs(3));
+
+        bucket.refill(start + Duration::from_secs(2));
+        assert_eq!(bucket.level, 0.0);
+        bucket.refill(start + Duration::from_secs(600));
+        assert_eq!(bucket.level, 60.0);
+    }
+
+    #[test]
+    fn test_limiter() {
+        let limits = RateLimitConfig {
+            requests_per_minute: Some(2),
+            tokens_per_minute: Some(600),
+        };
+        let limiter = Limiter::new(&limits);
+        let now = Instant::now();
+        assert_eq!(limiter.try_acquire(now), Duration::ZERO);
+        assert_eq!(limiter.try_acquire(now), Duration::ZERO);
+        assert_eq!(limiter.try_acquire(now), Duration::from_secs(30));
+
+        // Tokens over the allowance hold back requests until they are paid off
+        let limiter = Limiter::new(&limits);
+        limiter.charge(&Usage {
+            input_tokens: 500,
+            output_tokens: 200,
+            cache_read_input_tokens: 10_000,
+            ..Default::default()
+        });
+        let wait = limiter.try_acquire(Instant::now());
+        assert!(
+            wait > Duration::from_secs(10) && wait <= Duration::from_secs(11),
+            "{:?}",
+            wait
+        );
+    }
+
+    #[test]
+    fn test_shared_limiters() {
+        let limits = RateLimitConfig {
+            requests_per_minute: Some(50),
+            ..Default::default()
+        };
+        assert!(limiter("sk-ant-a", &RateLimitConfig::default()).is_none());
+        let a = limiter("sk-ant-a", &limits).unwrap();
+        assert!(Arc::ptr_eq(&a, &limiter("sk-ant-a", &limits).unwrap()));
+        assert!(!Arc::ptr_eq(&a, &limiter("sk-ant-b", &limits).unwrap()));
+    }
+
+    #[test]
+    fn test_no_rate_limit_headers() {
+        assert!(RateLimits::from_headers(&headers(&[("x-test", "yes")])).is_none());
+    }
+}
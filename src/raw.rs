@@ -0,0 +1,238 @@
+//! Requests beyond the typed surface
+//!
+//! API parameters the client doesn't know about yet can still be sent: build
+//! the request body with a [`RequestBuilder`], add them with
+//! [`RequestBuilder::param`], and send it with [`Client::send_raw`]. The
+//! response keeps every field the API returned, known or not.
+//!
+//! ```no_run
+//! use ellm::{Client, Config, Messages};
+//!
+//! # async fn example() -> ellm::Result<()> {
+//! let client = Client::new(Config::load(None)?)?;
+//! let body = client
+//!     .request(Messages::new().push_user("Hello".into()).clone(), None)?
+//!     .param("service_tier", "auto")
+//!     .build();
+//! let response = client.send_raw(body).await?;
+//! println!("{}", response.raw_json()["usage"]);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::{api_error, Client, MessageResponse, Messages};
+use crate::error::{ApiError, Result};
+use crate::tools::{Tool, ToolChoice};
+use serde_json::{json, Map, Value};
+
+/// Builds the JSON body of a Messages API request
+///
+/// Typed setters cover the parameters the client knows; [`param`](Self::param)
+/// sets any other, replacing what a setter set under the same name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestBuilder {
+    body: Map<String, Value>,
+}
+
+impl RequestBuilder {
+    /// A request to `model` with no messages yet
+    pub fn new(model: impl Into<String>, max_tokens: u32) -> Self {
+        Self::from_json(json!({"model": model.into(), "max_tokens": max_tokens, "messages": []}))
+    }
+
+    /// Start from an existing body, e.g. one built by [`Client::request`]
+    fn from_json(body: Value) -> Self {
+        match body {
+            Value::Object(body) => Self { body },
+            _ => unreachable!("a request body is an object"),
+        }
+    }
+
+    pub fn model(self, model: impl Into<String>) -> Self {
+        self.param("model", model.into())
+    }
+
+    pub fn max_tokens(self, max_tokens: u32) -> Self {
+        self.param("max_tokens", max_tokens)
+    }
+
+    pub fn system(self, system: impl Into<String>) -> Self {
+        self.param("system", system.into())
+    }
+
+    pub fn temperature(self, temperature: f32) -> Self {
+        self.param("temperature", temperature)
+    }
+
+    pub fn messages(self, messages: Messages) -> Self {
+        let messages: Vec<_> = messages.into();
+        self.param("messages", json!(messages))
+    }
+
+    pub fn tools(self, tools: Vec<Tool>) -> Self {
+        self.param("tools", json!(tools))
+    }
+
+    pub fn tool_choice(self, tool_choice: ToolChoice) -> Self {
+        self.param("tool_choice", json!(tool_choice))
+    }
+
+    /// Set any parameter, or remove it with [`Value::Null`]
+    pub fn param(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+        match value.into() {
+            Value::Null => self.body.remove(&name.into()),
+            value => self.body.insert(name.into(), value),
+        };
+        self
+    }
+
+    pub fn build(self) -> Value {
+        Value::Object(self.body)
+    }
+}
+
+/// A response to [`Client::send_raw`], with every field the API returned
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawMessageResponse {
+    json: Value,
+}
+
+impl RawMessageResponse {
+    /// The response exactly as the API returned it
+    pub fn raw_json(&self) -> &Value {
+        &self.json
+    }
+
+    pub fn into_raw_json(self) -> Value {
+        self.json
+    }
+
+    /// The fields the client knows, without the rest
+    pub fn parse(&self) -> Result<MessageResponse> {
+        serde_json::from_value(self.json.clone())
+            .map_err(|e| ApiError::UnexpectedResponse(e.to_string()).into())
+    }
+}
+
+impl Client {
+    /// A request for `messages` as [`Client::send_message`] would send it,
+    /// with the input guardrails applied, to extend before [`Client::send_raw`]
+    pub fn request(&self, messages: Messages, system: Option<String>) -> Result<RequestBuilder> {
+        let request = self.message_request(messages, None, system)?;
+        Ok(RequestBuilder::from_json(serde_json::to_value(&request)?))
+    }
+
+    /// Send a request body as it is and get the API's response as JSON
+    ///
+    /// Nothing is checked or changed on the way: the guardrails, model checks
+    /// and output hooks aren't applied, and a cut off response isn't
+    /// continued. Retries, middleware, observers and the usage ledger work as
+    /// for other requests. Streaming isn't supported.
+    pub async fn send_raw(&self, body: Value) -> Result<RawMessageResponse> {
+        if !body.is_object() {
+            return Err(
+                ApiError::InvalidRequest("the request body must be an object".into()).into(),
+            );
+        }
+        if body["stream"] == true {
+            return Err(ApiError::InvalidRequest(
+                "send_raw doesn't stream; remove \"stream\" from the request".into(),
+            )
+            .into());
+        }
+        let model = body["model"].as_str().unwrap_or(&self.config().model);
+        let info = self.start_request(model, false);
+
+        let result = async {
+            let response = self
+                .post("messages", &body, info.idempotency_key.clone())
+                .await?;
+            if !(200..300).contains(&response.status) {
+                return Err(api_error(response.status, response.body));
+            }
+            let json: Value = serde_json::from_str(&response.body)
+                .map_err(|e| ApiError::UnexpectedResponse(e.to_string()))?;
+            Ok(RawMessageResponse { json })
+        }
+        .await;
+
+        match &result {
+            Ok(response) => {
+                // A response the client can't parse is still returned, just not tallied
+                if let Ok(parsed) = response.parse() {
+                    self.record_usage(&parsed.model, &parsed.usage);
+                    self.set_last_usage(parsed.usage.clone());
+                    self.observe_response(&info, &parsed, Some(&parsed.text()));
+                }
+            }
+            Err(error) => self.notify(|observer| observer.on_error(&info, error)),
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::{http_stub, stub_client, REPLY};
+
+    #[test]
+    fn test_builder() {
+        let body = RequestBuilder::new("claude-haiku-4-5", 100)
+            .system("Be brief")
+            .messages(Messages::new().push_user("Hi".into()).clone())
+            .param("top_k", 5)
+            .param("metadata", json!({"user_id": "u1"}))
+            .max_tokens(200)
+            .build();
+        assert_eq!(
+            body,
+            json!({
+                "model": "claude-haiku-4-5",
+                "max_tokens": 200,
+                "system": "Be brief",
+                "messages": [{"role": "user", "content": "Hi"}],
+                "top_k": 5,
+                "metadata": {"user_id": "u1"}
+            })
+        );
+
+        let body = RequestBuilder::new("claude-haiku-4-5", 100)
+            .temperature(0.5)
+            .param("temperature", Value::Null)
+            .build();
+        assert!(body.get("temperature").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_send_raw() {
+        let reply = Box::leak(
+            REPLY
+                .replace(
+                    "\"stop_reason\"",
+                    "\"future_field\": [1, 2], \"stop_reason\"",
+                )
+                .into_boxed_str(),
+        );
+        let (url, requests) = http_stub(vec![Some((200, reply))]).await;
+        let client = stub_client(url);
+
+        let body = client
+            .request(Messages::new().push_user("Hi".into()).clone(), None)
+            .unwrap()
+            .param("new_parameter", true)
+            .build();
+        let response = client.send_raw(body).await.unwrap();
+        assert_eq!(response.raw_json()["future_field"], json!([1, 2]));
+        assert_eq!(response.parse().unwrap().text(), "hi");
+        assert!(client.last_usage().is_some());
+
+        let sent = requests.lock().unwrap()[0].clone();
+        assert!(sent.contains("\"new_parameter\":true"), "{}", sent);
+        assert!(sent.contains("\"model\""), "{}", sent);
+
+        let error = client.send_raw(json!({"stream": true})).await.unwrap_err();
+        assert!(error.to_string().contains("doesn't stream"), "{}", error);
+        assert!(client.send_raw(json!([])).await.is_err());
+    }
+}
@@ -0,0 +1,281 @@
+use crate::client::{Client, Messages};
+use crate::error::{ClaudeError, Result};
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// A line a generated regex must match, or must not match
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegexSample {
+    pub text: String,
+    pub should_match: bool,
+}
+
+impl RegexSample {
+    /// Samples from the lines of a samples file
+    ///
+    /// Lines starting with `- ` must not match; all others must, with an
+    /// optional `+ ` prefix for symmetry. Blank lines are skipped.
+    pub fn parse_lines(text: &str) -> Vec<RegexSample> {
+        text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(
+                |line| match (line.strip_prefix("- "), line.strip_prefix("+ ")) {
+                    (Some(text), _) => RegexSample {
+                        text: text.to_string(),
+                        should_match: false,
+                    },
+                    (None, Some(text)) => RegexSample {
+                        text: text.to_string(),
+                        should_match: true,
+                    },
+                    (None, None) => RegexSample {
+                        text: line.to_string(),
+                        should_match: true,
+                    },
+                },
+            )
+            .collect()
+    }
+}
+
+/// The model's answer to a request for a regex
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+struct RegexAnswer {
+    /// the regular expression, in Rust regex crate syntax, without delimiters or flags outside the pattern
+    pattern: String,
+    /// a short explanation of how the pattern works
+    explanation: String,
+}
+
+/// The result of [`Client::generate_regex`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GeneratedRegex {
+    pub pattern: String,
+    pub explanation: String,
+    /// Requests made to arrive at the pattern
+    pub attempts: usize,
+    /// Samples the pattern still classifies wrongly; empty on success
+    pub failures: Vec<RegexSample>,
+}
+
+impl GeneratedRegex {
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// The samples `pattern` classifies wrongly, or why it doesn't compile
+fn check(
+    pattern: &str,
+    samples: &[RegexSample],
+    full_match: bool,
+) -> std::result::Result<Vec<RegexSample>, String> {
+    let anchored;
+    let pattern = if full_match {
+        anchored = format!("^(?:{})$", pattern);
+        &anchored
+    } else {
+        pattern
+    };
+    let regex = Regex::new(pattern).map_err(|e| e.to_string())?;
+    Ok(samples
+        .iter()
+        .filter(|sample| regex.is_match(&sample.text) != sample.should_match)
+        .cloned()
+        .collect())
+}
+
+fn feedback(failures: &[RegexSample]) -> String {
+    let list = |should_match: bool| {
+        failures
+            .iter()
+            .filter(|sample| sample.should_match == should_match)
+            .map(|sample| format!("  {:?}", sample.text))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let mut feedback = String::from("The pattern classifies some samples wrongly.");
+    let missed = list(true);
+    if !missed.is_empty() {
+        feedback.push_str(&format!("\nIt should match, but doesn't:\n{}", missed));
+    }
+    let wrong = list(false);
+    if !wrong.is_empty() {
+        feedback.push_str(&format!("\nIt matches, but shouldn't:\n{}", wrong));
+    }
+    feedback + "\nCorrect the pattern."
+}
+
+impl Client {
+    /// Generate a regex for `description` and test it against `samples`
+    ///
+    /// The pattern is compiled and tested locally; while it fails to compile or
+    /// classifies any sample wrongly, the model is shown what went wrong and
+    /// asked again, for up to `max_attempts` requests. With `full_match` the
+    /// pattern must match whole lines, otherwise anywhere in them. If no
+    /// attempt succeeds, the pattern with the fewest failures is returned.
+    pub async fn generate_regex(
+        &self,
+        description: &str,
+        samples: &[RegexSample],
+        full_match: bool,
+        max_attempts: usize,
+    ) -> Result<GeneratedRegex> {
+        let mode = if full_match {
+            "The pattern is wrapped in ^(?:...)$ and must match whole lines."
+        } else {
+            "The pattern is tested with a search, so it may match anywhere in a line."
+        };
+        let system = format!(
+            "Write a regular expression in Rust regex crate syntax (no lookaround or \
+             backreferences) for the user's description. {}",
+            mode
+        );
+
+        let samples_text = |should_match: bool| {
+            samples
+                .iter()
+                .filter(|sample| sample.should_match == should_match)
+                .map(|sample| format!("  {:?}", sample.text))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let mut request = description.to_string();
+        for (should_match, heading) in [(true, "Must match"), (false, "Must not match")] {
+            let text = samples_text(should_match);
+            if !text.is_empty() {
+                request.push_str(&format!("\n\n{}:\n{}", heading, text));
+            }
+        }
+        let mut messages = Messages::new().push_user(request).clone();
+
+        let mut best: Option<GeneratedRegex> = None;
+        for attempt in 1..=max_attempts.max(1) {
+            let answer: RegexAnswer = self
+                .send_structured(messages.clone(), Some(system.clone()), 3)
+                .await?;
+
+            let reply = match check(&answer.pattern, samples, full_match) {
+                Ok(failures) => {
+                    let candidate = GeneratedRegex {
+                        pattern: answer.pattern.clone(),
+                        explanation: answer.explanation.clone(),
+                        attempts: attempt,
+                        failures,
+                    };
+                    if candidate.is_success() {
+                        return Ok(candidate);
+                    }
+                    let reply = feedback(&candidate.failures);
+                    if best
+                        .as_ref()
+                        .is_none_or(|best| candidate.failures.len() < best.failures.len())
+                    {
+                        best = Some(candidate);
+                    }
+                    reply
+                }
+                Err(error) => format!(
+                    "The pattern doesn't compile: {}\nCorrect the pattern.",
+                    error
+                ),
+            };
+            messages
+                .push_assistant(serde_json::to_string(&answer)?)
+                .push_user(reply);
+        }
+
+        match best {
+            Some(mut best) => {
+                best.attempts = max_attempts.max(1);
+                Ok(best)
+            }
+            None => Err(ClaudeError::Regex(format!(
+                "no valid pattern after {} attempts",
+                max_attempts.max(1)
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::{http_stub, stub_client};
+
+    #[test]
+    fn test_parse_samples() {
+        let samples = RegexSample::parse_lines("2024-01-31\n+ 1999-12-01\n\n- 2024/01/31\n");
+        assert_eq!(
+            samples,
+            [
+                RegexSample {
+                    text: "2024-01-31".into(),
+                    should_match: true
+                },
+                RegexSample {
+                    text: "1999-12-01".into(),
+                    should_match: true
+                },
+                RegexSample {
+                    text: "2024/01/31".into(),
+                    should_match: false
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let samples = RegexSample::parse_lines("2024-01-31\n- on 2024-01-31\n");
+        assert_eq!(check(r"\d{4}-\d{2}-\d{2}", &samples, true).unwrap(), []);
+        assert_eq!(
+            check(r"\d{4}-\d{2}-\d{2}", &samples, false).unwrap(),
+            [samples[1].clone()]
+        );
+        assert!(check("(", &samples, false).is_err());
+    }
+
+    fn reply(pattern: &str) -> &'static str {
+        let answer = serde_json::json!({"pattern": pattern, "explanation": "dates"});
+        // The response continues the "{" prefilled for structured output
+        let answer = answer.to_string()[1..].to_string();
+        Box::leak(
+            serde_json::json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-haiku-4-5",
+                "content": [{"type": "text", "text": answer}],
+                "stop_reason": "end_turn",
+                "usage": {"input_tokens": 1, "output_tokens": 1},
+            })
+            .to_string()
+            .into_boxed_str(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_generate_regex_retries_with_failures() {
+        let (url, requests) = http_stub(vec![
+            Some((200, reply(r"\d+-\d+-\d+"))),
+            Some((200, reply(r"\d{4}-\d{2}-\d{2}"))),
+        ])
+        .await;
+        let client = stub_client(url);
+        let samples = RegexSample::parse_lines("2024-01-31\n- 1-2-3\n");
+
+        let generated = client
+            .generate_regex("ISO dates", &samples, true, 3)
+            .await
+            .unwrap();
+        assert!(generated.is_success());
+        assert_eq!(generated.pattern, r"\d{4}-\d{2}-\d{2}");
+        assert_eq!(generated.attempts, 2);
+
+        let requests = requests.lock().unwrap();
+        assert!(requests[1].contains("it matches, but shouldn't"));
+        assert!(requests[1].contains(r#"\"1-2-3\""#));
+    }
+}
@@ -0,0 +1,206 @@
+//! Picking up configuration changes in long-running services
+//!
+//! A [`ReloadingClient`] holds the current [`Client`] and, once watching,
+//! rebuilds it when the config or policy file changes. A running
+//! `ellm grpc-serve` thereby applies a new model, limits or policy without a
+//! restart. Requests in flight finish with the client they started with.
+
+use crate::client::Client;
+use crate::config::Config;
+use crate::diagnostics;
+use crate::watch::FileWatcher;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// A [`Client`] that can be replaced while it is shared
+pub struct ReloadingClient {
+    current: RwLock<Arc<Client>>,
+}
+
+impl ReloadingClient {
+    pub fn new(client: Client) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(client)),
+        }
+    }
+
+    /// The current client
+    pub fn client(&self) -> Arc<Client> {
+        self.current
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Switch to `client`, returning how its configuration differs from the previous one
+    pub fn replace(&self, client: Client) -> Vec<String> {
+        let mut current = self.current.write().unwrap_or_else(|e| e.into_inner());
+        let changes = config_diff(current.config(), client.config());
+        *current = Arc::new(client);
+        changes
+    }
+
+    /// Check `paths` every `interval` and rebuild the client with `build` when
+    /// any of them is created, modified or removed
+    ///
+    /// Each reload is logged with the settings it changed. If `build` fails,
+    /// e.g. because the new file doesn't parse, the previous client is kept.
+    pub fn watch<F, E>(
+        self: Arc<Self>,
+        paths: Vec<PathBuf>,
+        interval: Duration,
+        build: F,
+    ) -> tokio::task::JoinHandle<()>
+    where
+        F: Fn() -> Result<Client, E> + Send + 'static,
+        E: Display,
+    {
+        let mut watcher = FileWatcher::new(paths);
+
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                if !watcher.poll() {
+                    continue;
+                }
+
+                match build() {
+                    Ok(client) => {
+                        let changes = self.replace(client);
+                        let summary = match changes.is_empty() {
+                            true => "no changes".to_string(),
+                            false => changes.join(", "),
+                        };
+                        diagnostics::info(
+                            "config_reload",
+                            format!("Reloaded configuration: {}", summary),
+                            serde_json::json!({"changes": changes}),
+                        );
+                    }
+                    Err(error) => diagnostics::warn(
+                        "config_reload",
+                        format!("keeping the previous configuration: {}", error),
+                        serde_json::json!({}),
+                    ),
+                }
+            }
+        })
+    }
+}
+
+/// The top-level settings that differ between `old` and `new`, as `key: old -> new`
+///
+/// The API key itself is never shown.
+pub fn config_diff(old: &Config, new: &Config) -> Vec<String> {
+    let as_map = |config: &Config| match serde_json::to_value(config) {
+        Ok(serde_json::Value::Object(map)) => map,
+        _ => serde_json::Map::new(),
+    };
+    let (old, new) = (as_map(old), as_map(new));
+
+    let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    let show = |value: Option<&serde_json::Value>| match value {
+        Some(value) => without_keys(value.clone()).to_string(),
+        None => "unset".to_string(),
+    };
+    keys.into_iter()
+        .filter(|key| old.get(*key) != new.get(*key))
+        .map(|key| match key.as_str() {
+            "api_key" => "api_key changed".to_string(),
+            _ => format!("{}: {} -> {}", key, show(old.get(key)), show(new.get(key))),
+        })
+        .collect()
+}
+
+/// `value` with the API keys nested in it, e.g. those of providers, hidden
+fn without_keys(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => map
+            .into_iter()
+            .map(|(key, value)| match key.as_str() {
+                "api_key" => (key, "***".into()),
+                _ => (key, without_keys(value)),
+            })
+            .collect(),
+        value => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    #[test]
+    fn test_config_diff() {
+        let old = Config::new("sk-ant-old");
+        let mut new = Config::new("sk-ant-new")
+            .with_model("claude-haiku-4-5")
+            .with_max_tokens(1000);
+        new.workspace = Some("research".into());
+        new.providers.insert(
+            "work".into(),
+            crate::config::Provider {
+                api_key: Some("sk-ant-secret".into()),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(
+            config_diff(&old, &new),
+            [
+                "api_key changed",
+                "max_tokens: 4096 -> 1000",
+                r#"model: "claude-sonnet-4-5-20250929" -> "claude-haiku-4-5""#,
+                r#"providers: unset -> {"work":{"api_key":"***"}}"#,
+                r#"workspace: unset -> "research""#,
+            ]
+        );
+        assert!(config_diff(&old, &old).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_watch_reloads_on_change() {
+        let path = std::env::temp_dir().join(format!("ellm-reload-{}.toml", std::process::id()));
+        std::fs::write(&path, "model = \"claude-haiku-4-5\"\n").unwrap();
+
+        let build = {
+            let path = path.clone();
+            move || {
+                let mut config = Config::from_path(&path)?;
+                config.api_key = "sk-ant-test-key".into();
+                Client::new(config)
+            }
+        };
+        let shared = Arc::new(ReloadingClient::new(build().unwrap()));
+        let watcher = shared
+            .clone()
+            .watch(vec![path.clone()], Duration::from_millis(10), build);
+
+        // Make sure the modification time moves even on coarse filesystems
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let later = SystemTime::now() + Duration::from_secs(5);
+        std::fs::write(&path, "model = \"claude-opus-4-5\"\n").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+
+        for _ in 0..100 {
+            if shared.client().config().model == "claude-opus-4-5" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        watcher.abort();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(shared.client().config().model, "claude-opus-4-5");
+    }
+}
@@ -0,0 +1,131 @@
+//! Local fixes for almost-valid JSON responses
+//!
+//! Most structured responses that fail to parse are close: wrapped in a
+//! markdown fence, followed by an explanation, left with a trailing comma or
+//! cut off by `max_tokens`. Repairing them locally is free, unlike another
+//! request.
+
+/// A repaired copy of `text`, if any of the fixes changed it
+///
+/// The result is not guaranteed to be valid JSON; it still has to be parsed.
+pub(crate) fn repair_json(text: &str) -> Option<String> {
+    let body = fenced(text).unwrap_or(text);
+    let start = body.find(['{', '['])?;
+
+    let mut out = String::with_capacity(body.len());
+    let mut closers = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for c in body[start..].chars() {
+        if in_string {
+            out.push(c);
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+
+        match c {
+            '"' => {
+                in_string = true;
+                out.push(c);
+            }
+            '{' => {
+                closers.push('}');
+                out.push(c);
+            }
+            '[' => {
+                closers.push(']');
+                out.push(c);
+            }
+            '}' | ']' => {
+                trim_dangling(&mut out);
+                closers.pop();
+                out.push(c);
+                // Anything after the outermost value is prose
+                if closers.is_empty() {
+                    break;
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+
+    // Close whatever was cut off
+    if in_string {
+        if escaped {
+            out.pop();
+        }
+        out.push('"');
+    }
+    while let Some(closer) = closers.pop() {
+        trim_dangling(&mut out);
+        out.push(closer);
+    }
+
+    (out != text).then_some(out)
+}
+
+/// The contents of the first markdown code fence in `text`
+fn fenced(text: &str) -> Option<&str> {
+    let open = text.find("```")?;
+    let rest = &text[open + 3..];
+    // Skip the language tag
+    let rest = &rest[rest.find('\n').map_or(0, |newline| newline + 1)..];
+    Some(rest.find("```").map_or(rest, |close| &rest[..close]))
+}
+
+/// Remove a trailing comma before a closing bracket, or complete a key without a value
+fn trim_dangling(out: &mut String) {
+    let trimmed = out.trim_end().len();
+    out.truncate(trimmed);
+    if out.ends_with(',') {
+        out.pop();
+    } else if out.ends_with(':') {
+        out.push_str("null");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repaired(text: &str) -> serde_json::Value {
+        serde_json::from_str(&repair_json(text).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_fences_and_prose() {
+        let value = repaired("Here you go:\n```json\n{\"a\": [1, 2]}\n```\nAnything else?");
+        assert_eq!(value, serde_json::json!({"a": [1, 2]}));
+
+        let value = repaired("{\"a\": \"}\"} is the answer because...");
+        assert_eq!(value, serde_json::json!({"a": "}"}));
+    }
+
+    #[test]
+    fn test_trailing_commas() {
+        let value = repaired("{\"a\": [1, 2, ], \"b\": 3,\n}");
+        assert_eq!(value, serde_json::json!({"a": [1, 2], "b": 3}));
+    }
+
+    #[test]
+    fn test_truncated() {
+        let value = repaired("{\"a\": [{\"b\": \"unfinished");
+        assert_eq!(value, serde_json::json!({"a": [{"b": "unfinished"}]}));
+
+        let value = repaired("{\"a\": 1, \"b\":");
+        assert_eq!(value, serde_json::json!({"a": 1, "b": null}));
+    }
+
+    #[test]
+    fn test_valid_json_is_unchanged() {
+        assert_eq!(repair_json("{\"a\": 1}"), None);
+        assert_eq!(repair_json("no json here"), None);
+    }
+}
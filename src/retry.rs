@@ -0,0 +1,159 @@
+//! Retrying of API requests without risking duplicates
+//!
+//! Every logical request gets an idempotency key that is sent with each of
+//! its attempts. A request is only retried when it certainly was not
+//! processed: the connection could not be established, or the API rejected it
+//! as rate limited or overloaded. Any other failure after the request was
+//! sent, such as a dropped connection or a timeout, may have happened after
+//! the API acted on it, so it is reported with its key instead of resent.
+
+use crate::error::ClaudeError;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Header carrying the idempotency key of a request
+pub const IDEMPOTENCY_HEADER: &str = "idempotency-key";
+
+/// Status of Anthropic's `overloaded_error`
+pub const OVERLOADED: u16 = 529;
+
+/// Retry settings from the config file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// Attempts after the first one; 0 disables retries
+    pub max_retries: u32,
+
+    /// Delay before the first retry, doubled for each retry after it
+    pub initial_backoff_ms: u64,
+
+    /// Upper bound on the delay between attempts
+    pub max_backoff_ms: u64,
+
+    /// Delay before the first retry of an overloaded request
+    ///
+    /// Overload lasts longer than a rate limit window, and every client sees
+    /// it at once, so these retries back off further and are jittered to
+    /// avoid retrying in lockstep.
+    pub overloaded_initial_backoff_ms: u64,
+
+    /// Upper bound on the delay between attempts of an overloaded request
+    pub overloaded_max_backoff_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 8000,
+            overloaded_initial_backoff_ms: 2000,
+            overloaded_max_backoff_ms: 30000,
+        }
+    }
+}
+
+impl RetryConfig {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// How long to wait before retry number `retry` (starting at 0)
+    ///
+    /// A delay requested by the API with `retry-after` takes precedence.
+    pub fn backoff(&self, retry: u32, retry_after: Option<u64>) -> Duration {
+        if let Some(seconds) = retry_after {
+            return Duration::from_secs(seconds);
+        }
+        Duration::from_millis(exponential(
+            self.initial_backoff_ms,
+            self.max_backoff_ms,
+            retry,
+        ))
+    }
+
+    /// How long to wait before retry number `retry` of an overloaded request
+    ///
+    /// Somewhere between half and all of the exponential delay, at random.
+    pub fn overloaded_backoff(&self, retry: u32, retry_after: Option<u64>) -> Duration {
+        if let Some(seconds) = retry_after {
+            return Duration::from_secs(seconds);
+        }
+        let delay = exponential(
+            self.overloaded_initial_backoff_ms,
+            self.overloaded_max_backoff_ms,
+            retry,
+        );
+        Duration::from_millis(delay / 2 + fastrand::u64(0..=delay - delay / 2))
+    }
+}
+
+fn exponential(initial: u64, max: u64, retry: u32) -> u64 {
+    initial.saturating_mul(1u64 << retry.min(32)).min(max)
+}
+
+/// A new key identifying one logical request across its attempts
+pub fn idempotency_key() -> String {
+    format!("ellm-{}", uuid::Uuid::new_v4())
+}
+
+/// Whether the API rejected a request without processing it
+pub(crate) fn is_retryable_status(status: u16) -> bool {
+    // Rate limited, unavailable and overloaded
+    matches!(status, 429 | 503 | OVERLOADED)
+}
+
+/// Whether a failed request never reached the API
+pub(crate) fn is_unsent(error: &ClaudeError) -> bool {
+    match error {
+        ClaudeError::Network(error) => error.is_connect(),
+        ClaudeError::RelayedNetwork { sent, .. } => !sent,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_limit() {
+        let config = RetryConfig::default();
+        assert_eq!(config.backoff(0, None), Duration::from_millis(500));
+        assert_eq!(config.backoff(1, None), Duration::from_millis(1000));
+        assert_eq!(config.backoff(10, None), Duration::from_millis(8000));
+        assert_eq!(config.backoff(63, None), Duration::from_millis(8000));
+        assert_eq!(config.backoff(0, Some(3)), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_overloaded_backoff_is_longer_and_jittered() {
+        let config = RetryConfig::default();
+        for retry in 0..8 {
+            let delay = config.overloaded_backoff(retry, None);
+            let full = Duration::from_millis(exponential(2000, 30000, retry));
+            assert!(delay >= full / 2 && delay <= full, "{:?}", delay);
+            assert!(delay >= config.backoff(retry, None));
+        }
+        assert_eq!(
+            config.overloaded_backoff(0, Some(1)),
+            Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn test_retryable_statuses() {
+        assert!(is_retryable_status(429));
+        assert!(is_retryable_status(529));
+        // A 500 may have been raised after the model already ran
+        assert!(!is_retryable_status(500));
+        assert!(!is_retryable_status(400));
+    }
+
+    #[test]
+    fn test_idempotency_keys_are_unique() {
+        let key = idempotency_key();
+        assert!(key.starts_with("ellm-"));
+        assert_ne!(key, idempotency_key());
+    }
+}
@@ -0,0 +1,189 @@
+//! Screenshots for `send --screenshot`
+//!
+//! The screen is captured with the platform's own tool rather than a capture
+//! library, which on Linux would need PipeWire and D-Bus to build: macOS's
+//! `screencapture`, PowerShell on Windows, and on Linux `grim` (with `slurp`
+//! to select a region) under Wayland or `maim`, `gnome-screenshot` or
+//! ImageMagick's `import` under X11, whichever is installed first.
+
+use anyhow::{anyhow, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Exit status of a shell that couldn't find the command it was asked to run
+const SHELL_NOT_FOUND: i32 = 127;
+
+/// A captured PNG in a temporary file, removed when dropped
+pub struct Screenshot {
+    path: PathBuf,
+}
+
+impl Screenshot {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Screenshot {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Capture the whole screen, or a region the user selects with `region`
+pub fn capture(region: bool) -> Result<Screenshot> {
+    let path = std::env::temp_dir().join(format!("ellm-screenshot-{}.png", std::process::id()));
+    let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+    // A leftover capture would hide a cancelled one
+    let _ = std::fs::remove_file(&path);
+    let screenshot = Screenshot { path };
+    run(&tools(region, wayland, &screenshot.path)?, &screenshot.path)?;
+    Ok(screenshot)
+}
+
+/// A way to capture the screen: the tool's name, to suggest installing it,
+/// and the command line that runs it
+type Tool = (&'static str, Vec<String>);
+
+/// Run the first of `tools` that's installed, which writes to `path`
+fn run(tools: &[Tool], path: &Path) -> Result<()> {
+    for (name, command) in tools {
+        let status = match Command::new(&command[0]).args(&command[1..]).status() {
+            Ok(status) => status,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(error) => return Err(error).with_context(|| format!("running {}", name)),
+        };
+        if status.code() == Some(SHELL_NOT_FOUND) {
+            continue;
+        }
+        // Cancelling a region selection leaves no file behind
+        if !status.success() || !path.exists() {
+            return Err(anyhow!("the screenshot was cancelled or {} failed", name));
+        }
+        return Ok(());
+    }
+    Err(anyhow!(
+        "no screenshot tool found; install one of: {}",
+        tools
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(", ")
+    ))
+}
+
+/// The tools that capture to `path`, in order of preference
+#[allow(unused_variables)]
+fn tools(region: bool, wayland: bool, path: &Path) -> Result<Vec<Tool>> {
+    let path = path.display().to_string();
+    let tool = |name: &'static str, args: &[&str]| -> Tool {
+        let command = args
+            .iter()
+            .map(|arg| arg.to_string())
+            .chain([path.clone()])
+            .collect();
+        (name, command)
+    };
+
+    #[cfg(target_os = "macos")]
+    return Ok(vec![tool(
+        "screencapture",
+        if region {
+            &["screencapture", "-x", "-i"]
+        } else {
+            &["screencapture", "-x"]
+        },
+    )]);
+
+    #[cfg(windows)]
+    {
+        if region {
+            return Err(anyhow!(
+                "selecting a region isn't supported on Windows; capture the whole screen or attach an image with --image"
+            ));
+        }
+        let script = "Add-Type -AssemblyName System.Windows.Forms,System.Drawing; \
+             $b = [System.Windows.Forms.SystemInformation]::VirtualScreen; \
+             $i = New-Object System.Drawing.Bitmap $b.Width, $b.Height; \
+             [System.Drawing.Graphics]::FromImage($i).CopyFromScreen($b.Left, $b.Top, 0, 0, $i.Size); \
+             $i.Save($args[0], [System.Drawing.Imaging.ImageFormat]::Png)";
+        return Ok(vec![tool(
+            "PowerShell",
+            &["powershell", "-NoProfile", "-Command", script],
+        )]);
+    }
+
+    #[cfg(not(any(target_os = "macos", windows)))]
+    {
+        // The shell reports grim or slurp missing as not found, like a missing tool
+        let grim_region = "command -v grim >/dev/null && command -v slurp >/dev/null || exit 127; \
+             geometry=$(slurp) || exit 1; grim -g \"$geometry\" \"$0\"";
+        let tools = match (wayland, region) {
+            (true, false) => vec![
+                tool("grim", &["grim"]),
+                tool("gnome-screenshot", &["gnome-screenshot", "-f"]),
+            ],
+            (true, true) => vec![
+                tool("grim and slurp", &["sh", "-c", grim_region]),
+                tool("gnome-screenshot", &["gnome-screenshot", "-a", "-f"]),
+            ],
+            (false, false) => vec![
+                tool("maim", &["maim"]),
+                tool("gnome-screenshot", &["gnome-screenshot", "-f"]),
+                tool("ImageMagick", &["import", "-window", "root"]),
+            ],
+            (false, true) => vec![
+                tool("maim", &["maim", "-s"]),
+                tool("gnome-screenshot", &["gnome-screenshot", "-a", "-f"]),
+                tool("ImageMagick", &["import"]),
+            ],
+        };
+        Ok(tools)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(not(any(target_os = "macos", windows)))]
+    #[test]
+    fn test_tools() {
+        let path = Path::new("/tmp/shot.png");
+        let x11 = tools(true, false, path).unwrap();
+        assert_eq!(x11[0].1, ["maim", "-s", "/tmp/shot.png"]);
+        assert_eq!(
+            x11[2],
+            ("ImageMagick", vec!["import".into(), "/tmp/shot.png".into()])
+        );
+
+        let wayland = tools(false, true, path).unwrap();
+        assert_eq!(wayland[0].1, ["grim", "/tmp/shot.png"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_first_installed() {
+        let path = std::env::temp_dir().join(format!("ellm-shot-{}.png", std::process::id()));
+        let shell = |name: &'static str, script: &str| -> Tool {
+            let command = ["sh", "-c", script, &path.display().to_string()]
+                .map(String::from)
+                .to_vec();
+            (name, command)
+        };
+        let missing: Tool = ("missing", vec!["ellm-no-such-tool".into()]);
+        let unknown_to_shell = shell("unknown", "ellm-no-such-tool");
+
+        let first = shell("first", "printf first > \"$0\"");
+        run(&[missing.clone(), unknown_to_shell, first.clone()], &path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "first");
+        std::fs::remove_file(&path).unwrap();
+
+        let cancelled = shell("cancelled", "exit 1");
+        let error = run(&[cancelled, first], &path).unwrap_err().to_string();
+        assert!(error.contains("cancelled or cancelled failed"));
+
+        let error = run(&[missing], &path).unwrap_err().to_string();
+        assert!(error.ends_with("install one of: missing"));
+    }
+}
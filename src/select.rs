@@ -0,0 +1,384 @@
+//! Picking values out of JSON with a path, like a small subset of jq
+//!
+//! A path is a chain of steps, each applied to every value the previous
+//! steps selected:
+//!
+//! * `name` or `.name` - a field of an object; a number indexes an array,
+//!   so `items.0` works too
+//! * `["a key"]` - a field whose name needs quoting
+//! * `[2]`, `[-1]` - an array element, counting from the end when negative
+//! * `[]` - every element of an array, or every value of an object
+//! * `[?field]`, `[?score>0.5]`, `[?status=="done"]` - the array elements
+//!   whose field is truthy, or compares as given with `==`, `!=`, `<`, `<=`,
+//!   `>` or `>=`; the field may itself be a dotted path
+//!
+//! For example `results[?score>=0.8].name` selects the names of the
+//! high-scoring results.
+
+use crate::error::{ClaudeError, Result};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Field(String),
+    Index(i64),
+    Each,
+    Filter(Condition),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Condition {
+    field: Vec<String>,
+    comparison: Option<(Comparison, Value)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A parsed path into JSON values
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+    path: String,
+    steps: Vec<Step>,
+}
+
+impl Selector {
+    /// The values the path selects from `value`, in order
+    ///
+    /// A field or index that doesn't exist is an error, except in filters,
+    /// where it just doesn't match.
+    pub fn select<'a>(&self, value: &'a Value) -> Result<Vec<&'a Value>> {
+        let mut current = vec![value];
+        for (index, step) in self.steps.iter().enumerate() {
+            let mut next = Vec::new();
+            for value in current {
+                match (step, value) {
+                    (Step::Field(name), Value::Object(map)) => match map.get(name) {
+                        Some(value) => next.push(value),
+                        None => {
+                            let fields: Vec<&str> = map.keys().map(String::as_str).collect();
+                            return Err(self.error(
+                                index,
+                                format!(
+                                    "has no field {:?}; its fields are {}",
+                                    name,
+                                    fields.join(", ")
+                                ),
+                            ));
+                        }
+                    },
+                    (Step::Field(name), Value::Array(items)) if name.parse::<usize>().is_ok() => {
+                        next.push(self.element(index, items, name.parse().unwrap_or_default())?)
+                    }
+                    (Step::Index(position), Value::Array(items)) => {
+                        next.push(self.element(index, items, *position)?)
+                    }
+                    (Step::Each, Value::Array(items)) => next.extend(items),
+                    (Step::Each, Value::Object(map)) => next.extend(map.values()),
+                    (Step::Filter(condition), Value::Array(items)) => {
+                        next.extend(items.iter().filter(|item| condition.matches(item)))
+                    }
+                    (_, value) => {
+                        return Err(self.error(index, format!("is {}", kind(value))));
+                    }
+                }
+            }
+            current = next;
+        }
+        Ok(current)
+    }
+
+    fn element<'a>(&self, step: usize, items: &'a [Value], position: i64) -> Result<&'a Value> {
+        let resolved = match position {
+            position if position < 0 => items.len().checked_sub(position.unsigned_abs() as usize),
+            position => Some(position as usize),
+        };
+        resolved
+            .and_then(|position| items.get(position))
+            .ok_or_else(|| {
+                self.error(
+                    step,
+                    format!("has {} elements, so no element {}", items.len(), position),
+                )
+            })
+    }
+
+    /// An error about the value selected by the steps before `step`
+    fn error(&self, step: usize, problem: String) -> ClaudeError {
+        let prefix: Vec<String> = self.steps[..step].iter().map(Step::to_string).collect();
+        let location = match prefix.concat() {
+            location if location.is_empty() => "the output".to_string(),
+            location => location.trim_start_matches('.').to_string(),
+        };
+        ClaudeError::PostProcess(format!("{} {}", location, problem))
+    }
+}
+
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+impl Condition {
+    fn matches(&self, item: &Value) -> bool {
+        let field = self.field.iter().try_fold(item, |value, name| match value {
+            Value::Object(map) => map.get(name),
+            Value::Array(items) => items.get(name.parse::<usize>().ok()?),
+            _ => None,
+        });
+        let Some(field) = field else {
+            return false;
+        };
+        let Some((comparison, expected)) = &self.comparison else {
+            return !matches!(field, Value::Null | Value::Bool(false));
+        };
+        let ordering = match (field, expected) {
+            (Value::Number(a), Value::Number(b)) => a
+                .as_f64()
+                .zip(b.as_f64())
+                .and_then(|(a, b)| a.partial_cmp(&b)),
+            (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+            (a, b) => (a == b).then_some(Ordering::Equal),
+        };
+        match comparison {
+            Comparison::Eq => ordering == Some(Ordering::Equal),
+            Comparison::Ne => ordering != Some(Ordering::Equal),
+            Comparison::Lt => ordering == Some(Ordering::Less),
+            Comparison::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+            Comparison::Gt => ordering == Some(Ordering::Greater),
+            Comparison::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+        }
+    }
+}
+
+impl Comparison {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        }
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Field(name) => write!(f, ".{}", name),
+            Step::Index(position) => write!(f, "[{}]", position),
+            Step::Each => write!(f, "[]"),
+            Step::Filter(condition) => {
+                write!(f, "[?{}", condition.field.join("."))?;
+                if let Some((comparison, value)) = &condition.comparison {
+                    write!(f, "{}{}", comparison.as_str(), value)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.path)
+    }
+}
+
+impl FromStr for Selector {
+    type Err = ClaudeError;
+
+    fn from_str(path: &str) -> Result<Self> {
+        let invalid = |problem: &str| {
+            ClaudeError::PostProcess(format!("invalid JSON path {:?}: {}", path, problem))
+        };
+        let mut steps = Vec::new();
+        let mut rest = path.trim();
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('[') {
+                let close = closing_bracket(after).ok_or_else(|| invalid("unclosed ["))?;
+                let inner = after[..close].trim();
+                rest = &after[close + 1..];
+                steps.push(if inner.is_empty() {
+                    Step::Each
+                } else if let Some(condition) = inner.strip_prefix('?') {
+                    Step::Filter(parse_condition(condition).map_err(|e| invalid(&e))?)
+                } else if inner.starts_with('"') {
+                    match serde_json::from_str(inner) {
+                        Ok(Value::String(name)) => Step::Field(name),
+                        _ => return Err(invalid("bad quoted field name")),
+                    }
+                } else {
+                    Step::Index(inner.parse().map_err(|_| {
+                        invalid("brackets hold an index, a quoted name, ? and a filter, or nothing")
+                    })?)
+                });
+                continue;
+            }
+            rest = rest.strip_prefix('.').unwrap_or(rest);
+            let end = rest.find(['.', '[']).unwrap_or(rest.len());
+            match rest[..end].trim() {
+                "" if end < rest.len() && rest[end..].starts_with('[') => {}
+                "" => return Err(invalid("empty field name")),
+                name => steps.push(Step::Field(name.to_string())),
+            }
+            rest = &rest[end..];
+        }
+        Ok(Selector {
+            path: path.to_string(),
+            steps,
+        })
+    }
+}
+
+/// The position of the `]` closing a bracket whose contents start `text`,
+/// skipping brackets inside quoted strings
+fn closing_bracket(text: &str) -> Option<usize> {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (index, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ']' if !quoted => return Some(index),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_condition(text: &str) -> std::result::Result<Condition, String> {
+    const OPERATORS: [(&str, Comparison); 6] = [
+        ("==", Comparison::Eq),
+        ("!=", Comparison::Ne),
+        ("<=", Comparison::Le),
+        (">=", Comparison::Ge),
+        ("<", Comparison::Lt),
+        (">", Comparison::Gt),
+    ];
+    let found = OPERATORS
+        .iter()
+        .filter_map(|(symbol, comparison)| Some((text.find(symbol)?, *symbol, *comparison)))
+        .min_by_key(|(position, symbol, _)| (*position, std::cmp::Reverse(symbol.len())));
+    let (field, comparison) = match found {
+        Some((position, symbol, comparison)) => {
+            let literal = text[position + symbol.len()..].trim();
+            // Anything that isn't JSON, such as done, is a string
+            let value = serde_json::from_str(literal)
+                .unwrap_or_else(|_| Value::String(literal.to_string()));
+            (&text[..position], Some((comparison, value)))
+        }
+        None => (text, None),
+    };
+    let field: Vec<String> = field
+        .trim()
+        .trim_start_matches('.')
+        .split('.')
+        .map(|name| name.trim().to_string())
+        .collect();
+    if field.iter().any(String::is_empty) {
+        return Err("a filter needs a field, e.g. [?score>0.5]".to_string());
+    }
+    Ok(Condition { field, comparison })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn select(path: &str, value: &Value) -> Result<Vec<Value>> {
+        let selector: Selector = path.parse()?;
+        Ok(selector.select(value)?.into_iter().cloned().collect())
+    }
+
+    fn results() -> Value {
+        json!({
+            "answer": "yes",
+            "results": [
+                {"name": "a", "score": 0.9, "status": "done", "tags": ["x"]},
+                {"name": "b", "score": 0.4, "status": "open", "tags": []},
+                {"name": "c", "score": 0.8, "status": "done", "meta": {"ok": true}}
+            ],
+            "odd key": 1
+        })
+    }
+
+    #[test]
+    fn test_fields_and_indexes() {
+        let value = results();
+        assert_eq!(select("answer", &value).unwrap(), [json!("yes")]);
+        assert_eq!(select(".results[1].name", &value).unwrap(), [json!("b")]);
+        assert_eq!(select("results.1.name", &value).unwrap(), [json!("b")]);
+        assert_eq!(select("results[-1].name", &value).unwrap(), [json!("c")]);
+        assert_eq!(select("[\"odd key\"]", &value).unwrap(), [json!(1)]);
+        assert_eq!(select("", &value).unwrap(), vec![value.clone()]);
+    }
+
+    #[test]
+    fn test_each_and_filters() {
+        let value = results();
+        assert_eq!(
+            select("results[].name", &value).unwrap(),
+            [json!("a"), json!("b"), json!("c")]
+        );
+        assert_eq!(
+            select("results[?score>=0.8].name", &value).unwrap(),
+            [json!("a"), json!("c")]
+        );
+        assert_eq!(
+            select("results[?status==\"open\"].name", &value).unwrap(),
+            [json!("b")]
+        );
+        assert_eq!(
+            select("results[?status!=done].name", &value).unwrap(),
+            [json!("b")]
+        );
+        assert_eq!(
+            select("results[?meta.ok].name", &value).unwrap(),
+            [json!("c")]
+        );
+        assert!(select("results[?score>2]", &value).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_errors() {
+        let value = results();
+        let error = select("results[0].nme", &value).unwrap_err().to_string();
+        assert!(
+            error.contains("results[0] has no field \"nme\""),
+            "{}",
+            error
+        );
+        assert!(select("results[5]", &value)
+            .unwrap_err()
+            .to_string()
+            .contains("has 3 elements"));
+        assert!(select("answer.text", &value)
+            .unwrap_err()
+            .to_string()
+            .contains("answer is a string"));
+        assert!("results[".parse::<Selector>().is_err());
+        assert!("results[x]".parse::<Selector>().is_err());
+        assert!("a..b".parse::<Selector>().is_err());
+    }
+}
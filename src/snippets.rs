@@ -0,0 +1,189 @@
+//! Saved prompts and system prompts
+//!
+//! A [`Snippet`] is a prompt and/or system prompt stored under a name, one
+//! TOML file per snippet in the `snippets` directory next to the config file.
+//! The files can be shared as they are: copy one into the directory, or
+//! `ellm snippets save --from` it, and it is available by its file name.
+
+use crate::config::Config;
+use crate::error::{ClaudeError, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// A saved prompt and system prompt
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Snippet {
+    /// Text placed before the message it is used with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    /// System prompt used when none is given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+}
+
+impl Snippet {
+    /// `text` preceded by the snippet's prompt, if it has one
+    pub fn apply(&self, text: &str) -> String {
+        match (self.prompt.as_deref(), text.is_empty()) {
+            (Some(prompt), true) => prompt.to_string(),
+            (Some(prompt), false) => format!("{}\n\n{}", prompt, text),
+            (None, _) => text.to_string(),
+        }
+    }
+
+    /// Parse a snippet file
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        toml::from_str(&contents)
+            .map_err(|e| ClaudeError::Snippet(format!("{}: {}", path.display(), e)))
+    }
+}
+
+/// The directory snippets are stored in
+#[derive(Debug, Clone)]
+pub struct SnippetStore {
+    dir: PathBuf,
+}
+
+impl SnippetStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The `snippets` directory next to the config file
+    pub fn open_default() -> Result<Self> {
+        let config = Config::config_path()?;
+        let dir = config.parent().unwrap_or(Path::new("."));
+        Ok(Self::new(dir.join("snippets")))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The file the snippet `name` is stored in
+    pub fn path(&self, name: &str) -> Result<PathBuf> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            && !name.starts_with('.');
+        if !valid {
+            return Err(ClaudeError::Snippet(format!(
+                "invalid snippet name {:?}: use letters, digits, '-', '_' and '.'",
+                name
+            )));
+        }
+        Ok(self.dir.join(format!("{}.toml", name)))
+    }
+
+    /// Store `snippet` as `name`, replacing any snippet of that name
+    pub fn save(&self, name: &str, snippet: &Snippet) -> Result<PathBuf> {
+        if snippet.prompt.is_none() && snippet.system.is_none() {
+            return Err(ClaudeError::Snippet(
+                "a snippet needs a prompt or a system prompt".into(),
+            ));
+        }
+        let path = self.path(name)?;
+        std::fs::create_dir_all(&self.dir)?;
+        let contents = toml::to_string(snippet).map_err(|e| ClaudeError::Snippet(e.to_string()))?;
+        std::fs::write(&path, contents)?;
+        Ok(path)
+    }
+
+    /// The snippet `name`
+    pub fn load(&self, name: &str) -> Result<Snippet> {
+        let path = self.path(name)?;
+        if !path.exists() {
+            return Err(ClaudeError::Snippet(format!("no snippet named {}", name)));
+        }
+        Snippet::from_path(&path)
+    }
+
+    /// Remove the snippet `name`
+    pub fn remove(&self, name: &str) -> Result<()> {
+        let path = self.path(name)?;
+        if !path.exists() {
+            return Err(ClaudeError::Snippet(format!("no snippet named {}", name)));
+        }
+        Ok(std::fs::remove_file(path)?)
+    }
+
+    /// The names of all stored snippets, sorted
+    pub fn list(&self) -> Result<Vec<String>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut names = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "toml")
+            {
+                if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let snippet = Snippet {
+            prompt: Some("Review this code:".into()),
+            system: None,
+        };
+        assert_eq!(
+            snippet.apply("fn main() {}"),
+            "Review this code:\n\nfn main() {}"
+        );
+        assert_eq!(snippet.apply(""), "Review this code:");
+        assert_eq!(Snippet::default().apply("as is"), "as is");
+    }
+
+    #[test]
+    fn test_store() {
+        let dir = std::env::temp_dir().join(format!("ellm-snippets-{}", std::process::id()));
+        let store = SnippetStore::new(&dir);
+        assert!(store.list().unwrap().is_empty());
+
+        let review = Snippet {
+            prompt: Some("Review this code:".into()),
+            system: Some("You are a careful reviewer.".into()),
+        };
+        let path = store.save("review", &review).unwrap();
+        assert_eq!(path, dir.join("review.toml"));
+        store
+            .save(
+                "terse",
+                &Snippet {
+                    prompt: None,
+                    system: Some("Answer in one sentence.".into()),
+                },
+            )
+            .unwrap();
+
+        assert_eq!(store.list().unwrap(), ["review", "terse"]);
+        assert_eq!(store.load("review").unwrap(), review);
+        assert_eq!(Snippet::from_path(&path).unwrap(), review);
+
+        assert!(store.save("empty", &Snippet::default()).is_err());
+        assert!(store.load("../config").is_err());
+        assert!(store.load("missing").is_err());
+
+        store.remove("terse").unwrap();
+        assert_eq!(store.list().unwrap(), ["review"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,299 @@
+//! Local statistics of CLI invocations
+//!
+//! Each command run appends one JSON line with how long it took and the
+//! requests, tokens and estimated cost it used, tagged with the snippet it
+//! ran with, so `ellm stats` can show which commands and prompts are the
+//! expensive ones. The file stays on this machine and is never uploaded.
+
+use crate::error::{ClaudeError, Result};
+use crate::observer::{Completion, Observer, RequestInfo};
+use crate::pricing;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Requests, tokens and estimated cost summed over some requests
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RequestTotals {
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: f64,
+}
+
+impl RequestTotals {
+    fn add(&mut self, other: &RequestTotals) {
+        self.requests += other.requests;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cost_usd += other.cost_usd;
+    }
+}
+
+/// An [`Observer`] summing up the requests a client completes
+#[derive(Debug, Clone, Default)]
+pub struct RequestTally(Arc<Mutex<RequestTotals>>);
+
+impl RequestTally {
+    pub fn totals(&self) -> RequestTotals {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl Observer for RequestTally {
+    fn on_complete(&self, request: &RequestInfo, completion: &Completion) {
+        let cost = pricing::price(&request.model).map(|price| {
+            price.cost(
+                completion.input_tokens,
+                completion.output_tokens,
+                completion.cache_creation_input_tokens,
+                completion.cache_read_input_tokens,
+            )
+        });
+        let mut totals = self.0.lock().unwrap();
+        totals.requests += 1;
+        totals.input_tokens += completion.input_tokens
+            + completion.cache_creation_input_tokens
+            + completion.cache_read_input_tokens;
+        totals.output_tokens += completion.output_tokens;
+        totals.cost_usd += cost.unwrap_or(0.0);
+    }
+}
+
+/// One run of a CLI command
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Invocation {
+    pub timestamp: DateTime<Utc>,
+    /// The command and any subcommand, e.g. `batch submit`
+    pub command: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+    pub duration_ms: u64,
+    pub success: bool,
+    #[serde(flatten)]
+    pub totals: RequestTotals,
+}
+
+impl Invocation {
+    /// An invocation that finished now, after `duration`
+    pub fn new(
+        command: impl Into<String>,
+        snippet: Option<String>,
+        duration: Duration,
+        success: bool,
+        totals: RequestTotals,
+    ) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            command: command.into(),
+            snippet,
+            duration_ms: duration.as_millis() as u64,
+            success,
+            totals,
+        }
+    }
+}
+
+/// Summed invocations of a command with a snippet
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CommandStats {
+    pub command: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+    pub invocations: u64,
+    pub failures: u64,
+    pub total_duration_ms: u64,
+    #[serde(flatten)]
+    pub totals: RequestTotals,
+}
+
+impl CommandStats {
+    pub fn mean_duration(&self) -> Duration {
+        Duration::from_millis(self.total_duration_ms / self.invocations.max(1))
+    }
+}
+
+/// Invocations summed per command and snippet, most expensive first
+pub fn by_command(invocations: &[Invocation]) -> Vec<CommandStats> {
+    let mut stats: BTreeMap<(&str, Option<&str>), CommandStats> = BTreeMap::new();
+    for invocation in invocations {
+        let key = (invocation.command.as_str(), invocation.snippet.as_deref());
+        let entry = stats.entry(key).or_insert_with(|| CommandStats {
+            command: invocation.command.clone(),
+            snippet: invocation.snippet.clone(),
+            ..CommandStats::default()
+        });
+        entry.invocations += 1;
+        entry.failures += u64::from(!invocation.success);
+        entry.total_duration_ms += invocation.duration_ms;
+        entry.totals.add(&invocation.totals);
+    }
+
+    let mut stats: Vec<CommandStats> = stats.into_values().collect();
+    stats.sort_by(|a, b| {
+        b.totals
+            .cost_usd
+            .total_cmp(&a.totals.cost_usd)
+            .then(b.invocations.cmp(&a.invocations))
+    });
+    stats
+}
+
+/// An append-only JSONL file of [`Invocation`]s
+#[derive(Debug, Clone)]
+pub struct StatsLog {
+    path: PathBuf,
+}
+
+impl StatsLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The statistics in the user's data directory, next to the usage ledger
+    pub fn open_default() -> Result<Self> {
+        let data_dir = dirs::data_dir()
+            .ok_or_else(|| ClaudeError::Stats("could not determine data directory".into()))?;
+        Ok(Self::new(data_dir.join("ellm").join("stats.jsonl")))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, invocation: &Invocation) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut line = serde_json::to_vec(invocation)?;
+        line.push(b'\n');
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(&line)?;
+        Ok(())
+    }
+
+    /// All invocations, oldest first; an absent file is empty
+    pub fn invocations(&self) -> Result<Vec<Invocation>> {
+        let file = match std::fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error.into()),
+        };
+
+        std::io::BufReader::new(file)
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+            .map(|(number, line)| {
+                serde_json::from_str(&line?).map_err(|e| {
+                    ClaudeError::Stats(format!(
+                        "{} line {}: {}",
+                        self.path.display(),
+                        number + 1,
+                        e
+                    ))
+                })
+            })
+            .collect()
+    }
+
+    /// Forget all invocations
+    pub fn clear(&self) -> Result<()> {
+        match std::fs::remove_file(&self.path) {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn totals(requests: u64, cost_usd: f64) -> RequestTotals {
+        RequestTotals {
+            requests,
+            input_tokens: requests * 1000,
+            output_tokens: requests * 100,
+            cost_usd,
+        }
+    }
+
+    #[test]
+    fn test_tally() {
+        let tally = RequestTally::default();
+        let request = RequestInfo {
+            idempotency_key: "key".into(),
+            model: "claude-sonnet-4-5-20250929".into(),
+            stream: false,
+            started: Instant::now(),
+        };
+        let completion = Completion {
+            input_tokens: 1000,
+            output_tokens: 100,
+            cache_creation_input_tokens: 0,
+            cache_read_input_tokens: 0,
+            stop_reason: None,
+            elapsed: Duration::from_secs(1),
+        };
+        tally.on_complete(&request, &completion);
+        tally.clone().on_complete(&request, &completion);
+
+        let totals = tally.totals();
+        assert_eq!(totals.requests, 2);
+        assert_eq!(totals.input_tokens, 2000);
+        assert!((totals.cost_usd - 0.009).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_log_and_by_command() {
+        let log = StatsLog::new(
+            std::env::temp_dir().join(format!("ellm-stats-{}.jsonl", std::process::id())),
+        );
+        log.clear().unwrap();
+        assert!(log.invocations().unwrap().is_empty());
+
+        let review = Some("review".to_string());
+        let second = Duration::from_secs(1);
+        for invocation in [
+            Invocation::new("send", None, second, true, totals(1, 0.01)),
+            Invocation::new("send", review.clone(), second * 3, true, totals(1, 0.05)),
+            Invocation::new("send", review.clone(), second, false, totals(0, 0.0)),
+            Invocation::new("summarize", None, second * 10, true, totals(6, 0.03)),
+        ] {
+            log.append(&invocation).unwrap();
+        }
+
+        let invocations = log.invocations().unwrap();
+        assert_eq!(invocations.len(), 4);
+        let stats = by_command(&invocations);
+        log.clear().unwrap();
+
+        let keys: Vec<_> = stats
+            .iter()
+            .map(|stats| (stats.command.as_str(), stats.snippet.as_deref()))
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                ("send", Some("review")),
+                ("summarize", None),
+                ("send", None)
+            ]
+        );
+        assert_eq!(stats[0].invocations, 2);
+        assert_eq!(stats[0].failures, 1);
+        assert_eq!(stats[0].mean_duration(), second * 2);
+        assert_eq!(stats[1].totals.requests, 6);
+    }
+}
@@ -0,0 +1,952 @@
+//! Streaming responses from the Messages API
+//!
+//! [`Client::stream_message`] returns a [`MessageStream`] that yields typed
+//! [`StreamEvent`]s as server-sent events arrive. Tool calls are reported as
+//! they are built: [`StreamEvent::ToolUseStart`] as soon as the model picks a
+//! tool, [`StreamEvent::ToolInputDelta`] for each fragment of its JSON input,
+//! and [`StreamEvent::ToolUseStop`] with the parsed input once it is complete.
+//!
+//! A response cut off at max_tokens is continued, up to the configured
+//! `max_continuations` times, by sending what it said so far back as a prefill
+//! to pick up from. The continuation's events follow on in the same stream,
+//! starting with another [`StreamEvent::MessageStart`] and numbering its
+//! blocks from 0; only the last request's [`StreamEvent::MessageStop`] is
+//! reported.
+//!
+//! Streamed requests go directly to the API rather than through the daemon.
+//! Output guardrails and response hooks run once the complete text has
+//! arrived, so they can fail the stream but cannot change text that was
+//! already delivered; redactions and rewrites show in the finished message.
+
+use crate::client::{
+    api_error, Client, ContentBlock, ErrorDetail, MessageRequest, MessageResponse, Messages,
+    StopReason, Usage,
+};
+use crate::error::{ApiError, Result};
+use crate::middleware::{Method, Request};
+use crate::models;
+use crate::observer::{Completion, RequestInfo};
+use crate::tools::{Tool, ToolChoice};
+use serde::Deserialize;
+use std::collections::{BTreeMap, VecDeque};
+
+/// Something that happened while a response was streamed
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamEvent {
+    /// The response has started
+    MessageStart { id: String, model: String },
+    /// More text for the text block at `index`
+    TextDelta { index: usize, text: String },
+    /// More of the model's reasoning for the thinking block at `index`
+    ThinkingDelta { index: usize, thinking: String },
+    /// The model started calling a tool; its input follows as deltas
+    ToolUseStart {
+        index: usize,
+        id: String,
+        name: String,
+    },
+    /// A fragment of a tool's JSON input, with all of the input received so far
+    ToolInputDelta {
+        index: usize,
+        partial_json: String,
+        input_so_far: String,
+    },
+    /// A tool call is complete
+    ToolUseStop {
+        index: usize,
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    /// A block other than a tool call is complete
+    BlockStop { index: usize },
+    /// The response is complete
+    MessageStop { stop_reason: Option<StopReason> },
+}
+
+/// A streamed response, as assembled so far
+///
+/// The same type as a buffered response; while the message streams, its
+/// output tokens are only final once it has stopped.
+pub type StreamedMessage = MessageResponse;
+
+/// Server-sent event payloads
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum WireEvent {
+    MessageStart {
+        message: WireMessage,
+    },
+    ContentBlockStart {
+        index: usize,
+        content_block: ContentBlock,
+    },
+    ContentBlockDelta {
+        index: usize,
+        delta: WireDelta,
+    },
+    ContentBlockStop {
+        index: usize,
+    },
+    MessageDelta {
+        delta: WireMessageDelta,
+        #[serde(default)]
+        usage: Usage,
+    },
+    MessageStop,
+    Error {
+        error: ErrorDetail,
+    },
+    /// `ping` and event types added after this version
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct WireMessage {
+    id: String,
+    model: String,
+    #[serde(default)]
+    usage: Usage,
+}
+
+#[derive(Debug, Deserialize)]
+struct WireMessageDelta {
+    stop_reason: Option<StopReason>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum WireDelta {
+    TextDelta {
+        text: String,
+    },
+    InputJsonDelta {
+        partial_json: String,
+    },
+    ThinkingDelta {
+        thinking: String,
+    },
+    SignatureDelta {
+        signature: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// Turns server-sent event bytes into [`StreamEvent`]s and the assembled message
+#[derive(Debug, Default)]
+struct Assembler {
+    buffer: Vec<u8>,
+    message: StreamedMessage,
+    /// Tool input received so far, by block index
+    inputs: BTreeMap<usize, String>,
+    complete: bool,
+}
+
+impl Assembler {
+    /// Process a chunk of the response body
+    fn push(&mut self, chunk: &[u8], events: &mut VecDeque<StreamEvent>) -> Result<()> {
+        self.buffer.extend_from_slice(chunk);
+        // Lines may end in CRLF; a CR at the end of a chunk waits for its LF
+        if self.buffer.contains(&b'\r') {
+            let mut kept = Vec::with_capacity(self.buffer.len());
+            for (index, &byte) in self.buffer.iter().enumerate() {
+                if byte != b'\r' || self.buffer.get(index + 1) != Some(&b'\n') {
+                    kept.push(byte);
+                }
+            }
+            self.buffer = kept;
+        }
+
+        while let Some(end) = self.buffer.windows(2).position(|pair| pair == b"\n\n") {
+            let raw: Vec<u8> = self.buffer.drain(..end + 2).collect();
+            let raw = String::from_utf8_lossy(&raw);
+            let data: Vec<&str> = raw
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(|data| data.strip_prefix(' ').unwrap_or(data))
+                .collect();
+            if !data.is_empty() {
+                self.handle(&data.join("\n"), events)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle(&mut self, data: &str, events: &mut VecDeque<StreamEvent>) -> Result<()> {
+        let event: WireEvent = serde_json::from_str(data)
+            .map_err(|e| ApiError::UnexpectedResponse(format!("bad stream event: {}", e)))?;
+
+        match event {
+            WireEvent::MessageStart { message } => {
+                self.message.id = message.id.clone();
+                self.message.model = message.model.clone();
+                self.message.usage = message.usage;
+                events.push_back(StreamEvent::MessageStart {
+                    id: message.id,
+                    model: message.model,
+                });
+            }
+            WireEvent::ContentBlockStart {
+                index,
+                content_block,
+            } => {
+                match &content_block {
+                    ContentBlock::ToolUse { id, name, .. } => {
+                        self.inputs.insert(index, String::new());
+                        events.push_back(StreamEvent::ToolUseStart {
+                            index,
+                            id: id.clone(),
+                            name: name.clone(),
+                        });
+                    }
+                    ContentBlock::Text { text } if !text.is_empty() => {
+                        events.push_back(StreamEvent::TextDelta {
+                            index,
+                            text: text.clone(),
+                        });
+                    }
+                    ContentBlock::Thinking { thinking, .. } if !thinking.is_empty() => {
+                        events.push_back(StreamEvent::ThinkingDelta {
+                            index,
+                            thinking: thinking.clone(),
+                        });
+                    }
+                    _ => {}
+                }
+
+                let content = &mut self.message.content;
+                if content.len() <= index {
+                    content.resize(index + 1, ContentBlock::Other(serde_json::Value::Null));
+                }
+                content[index] = content_block;
+            }
+            WireEvent::ContentBlockDelta { index, delta } => match delta {
+                WireDelta::TextDelta { text } => {
+                    if let Some(ContentBlock::Text { text: block }) =
+                        self.message.content.get_mut(index)
+                    {
+                        block.push_str(&text);
+                    }
+                    events.push_back(StreamEvent::TextDelta { index, text });
+                }
+                WireDelta::InputJsonDelta { partial_json } => {
+                    let input = self.inputs.entry(index).or_default();
+                    input.push_str(&partial_json);
+                    events.push_back(StreamEvent::ToolInputDelta {
+                        index,
+                        partial_json,
+                        input_so_far: input.clone(),
+                    });
+                }
+                WireDelta::ThinkingDelta { thinking } => {
+                    if let Some(ContentBlock::Thinking {
+                        thinking: block, ..
+                    }) = self.message.content.get_mut(index)
+                    {
+                        block.push_str(&thinking);
+                    }
+                    events.push_back(StreamEvent::ThinkingDelta { index, thinking });
+                }
+                WireDelta::SignatureDelta { signature } => {
+                    if let Some(ContentBlock::Thinking {
+                        signature: block, ..
+                    }) = self.message.content.get_mut(index)
+                    {
+                        block.push_str(&signature);
+                    }
+                }
+                WireDelta::Other => {}
+            },
+            WireEvent::ContentBlockStop { index } => match self.inputs.remove(&index) {
+                Some(json) => {
+                    let input = if json.trim().is_empty() {
+                        serde_json::json!({})
+                    } else {
+                        serde_json::from_str(&json).map_err(|e| {
+                            ApiError::UnexpectedResponse(format!("bad tool input: {}", e))
+                        })?
+                    };
+                    if let Some(ContentBlock::ToolUse {
+                        id,
+                        name,
+                        input: block,
+                    }) = self.message.content.get_mut(index)
+                    {
+                        *block = input.clone();
+                        events.push_back(StreamEvent::ToolUseStop {
+                            index,
+                            id: id.clone(),
+                            name: name.clone(),
+                            input,
+                        });
+                    }
+                }
+                None => events.push_back(StreamEvent::BlockStop { index }),
+            },
+            WireEvent::MessageDelta { delta, usage } => {
+                self.message.stop_reason = delta.stop_reason;
+                self.message.usage.output_tokens = usage.output_tokens;
+            }
+            WireEvent::MessageStop => {
+                self.complete = true;
+                events.push_back(StreamEvent::MessageStop {
+                    stop_reason: self.message.stop_reason.clone(),
+                });
+            }
+            // Errors after the response started arrive in the stream, not as a status
+            WireEvent::Error { error } => return Err(error.into_error(200)),
+            WireEvent::Other => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// A response being streamed from the API
+pub struct MessageStream<'a> {
+    client: &'a Client,
+    response: reqwest::Response,
+    assembler: Assembler,
+    events: VecDeque<StreamEvent>,
+    done: bool,
+    info: RequestInfo,
+    first_token: bool,
+    /// The request, to continue a response cut off at max_tokens
+    request: MessageRequest,
+    continuations: u32,
+    /// What arrived of a continued response before the current request
+    earlier: Option<StreamedMessage>,
+}
+
+impl<'a> MessageStream<'a> {
+    /// The next event, or `None` once the response is complete
+    ///
+    /// After an error no further events are returned.
+    pub async fn next_event(&mut self) -> Option<Result<StreamEvent>> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                self.observe(&event);
+                return Some(Ok(event));
+            }
+            if self.done {
+                return None;
+            }
+
+            let result = match self.response.chunk().await {
+                Ok(Some(chunk)) => self.assembler.push(&chunk, &mut self.events),
+                Ok(None) => Err(ApiError::UnexpectedResponse(
+                    "stream ended before the message was complete".to_string(),
+                )
+                .into()),
+                Err(error) => Err(error.into()),
+            };
+            let result = match result {
+                Ok(()) if self.assembler.complete => self.complete().await,
+                result => result,
+            };
+
+            if let Err(error) = result {
+                self.events.clear();
+                self.done = true;
+                self.client
+                    .notify(|observer| observer.on_error(&self.info, &error));
+                return Some(Err(error));
+            }
+        }
+    }
+
+    /// Consume the rest of the stream and return the complete message
+    pub async fn finish(mut self) -> Result<StreamedMessage> {
+        while let Some(event) = self.next_event().await {
+            event?;
+        }
+        Ok(self.assembler.message)
+    }
+
+    /// The message as assembled from the events so far
+    pub fn message(&self) -> StreamedMessage {
+        match &self.earlier {
+            Some(earlier) => {
+                let mut message = earlier.clone();
+                message.extend(self.assembler.message.clone());
+                message
+            }
+            None => self.assembler.message.clone(),
+        }
+    }
+
+    fn observe(&mut self, event: &StreamEvent) {
+        let client = self.client;
+        let info = &self.info;
+
+        let output = matches!(
+            event,
+            StreamEvent::TextDelta { .. }
+                | StreamEvent::ThinkingDelta { .. }
+                | StreamEvent::ToolUseStart { .. }
+        );
+        if output && !std::mem::replace(&mut self.first_token, true) {
+            client.notify(|observer| observer.on_first_token(info));
+        }
+
+        match event {
+            StreamEvent::TextDelta { text, .. } => {
+                client.notify(|observer| observer.on_token(info, text));
+            }
+            StreamEvent::MessageStop { stop_reason } => {
+                let completion = Completion {
+                    input_tokens: self.assembler.message.usage.input_tokens,
+                    output_tokens: self.assembler.message.usage.output_tokens,
+                    cache_creation_input_tokens: self
+                        .assembler
+                        .message
+                        .usage
+                        .cache_creation_input_tokens,
+                    cache_read_input_tokens: self.assembler.message.usage.cache_read_input_tokens,
+                    stop_reason: stop_reason.clone(),
+                    elapsed: info.elapsed(),
+                };
+                client.notify(|observer| observer.on_complete(info, &completion));
+            }
+            _ => {}
+        }
+    }
+
+    /// Handle the end of a request's response: continue it if it was cut
+    /// off, or finish the message
+    async fn complete(&mut self) -> Result<()> {
+        let piece = &self.assembler.message;
+        self.client.record_usage(&piece.model, &piece.usage);
+        if piece.stop_reason == Some(StopReason::MaxTokens)
+            && self.continuations < self.client.config().max_continuations
+            && self.request.thinking.is_none()
+            && self.request.tools.is_empty()
+        {
+            return self.continue_response().await;
+        }
+
+        self.done = true;
+        self.join_pieces();
+        self.client
+            .check_refusal(self.assembler.message.stop_reason.as_ref())?;
+        self.finish_output()
+    }
+
+    /// Stop reading the response and keep what arrived so far
+    ///
+    /// The partial message's usage is recorded and its text goes through the
+    /// output guardrails and response hooks, as a finished one's would.
+    /// Dropping the stream closes the connection, ending the request.
+    pub fn abort(mut self) -> Result<StreamedMessage> {
+        if !self.done {
+            let piece = &self.assembler.message;
+            self.client.record_usage(&piece.model, &piece.usage);
+            self.join_pieces();
+            self.finish_output()?;
+        }
+        Ok(self.assembler.message)
+    }
+
+    /// Put the pieces of a continued response back together
+    fn join_pieces(&mut self) {
+        if let Some(mut earlier) = self.earlier.take() {
+            earlier.extend(std::mem::take(&mut self.assembler.message));
+            self.assembler.message = earlier;
+        }
+    }
+
+    /// Run the message's text through the output guardrails and response hooks
+    fn finish_output(&mut self) -> Result<()> {
+        let text = self.assembler.message.text();
+        let checked = self.client.finish_output(text.clone())?;
+        if checked != text {
+            // The redacted or rewritten text takes the place of every text block
+            let mut checked = Some(checked);
+            for block in &mut self.assembler.message.content {
+                if let ContentBlock::Text { text } = block {
+                    *text = checked.take().unwrap_or_default();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Ask for the rest of a response cut off at max_tokens, prefilling what
+    /// it said so far
+    async fn continue_response(&mut self) -> Result<()> {
+        // The cut off response's stop isn't the end of the message
+        self.events
+            .retain(|event| !matches!(event, StreamEvent::MessageStop { .. }));
+
+        let piece = std::mem::take(&mut self.assembler).message;
+        let mut message = match self.earlier.take() {
+            Some(mut earlier) => {
+                earlier.extend(piece);
+                earlier
+            }
+            None => piece,
+        };
+        // Trimmed here too, so the pieces join up
+        message.trim_end();
+        self.request.set_prefill(&message.text());
+        self.earlier = Some(message);
+
+        self.request.max_tokens = self.client.fit_context(&self.request).await?;
+        let body = serde_json::to_value(&self.request)?;
+        // A different body, so a new key: the same one could get the first answer again
+        let key = self.client.new_idempotency_key();
+        self.response = self.client.open_stream(&body, key).await?;
+        self.continuations += 1;
+        Ok(())
+    }
+}
+
+/// A response whose body has not been read yet
+struct Opened {
+    status: u16,
+    headers: Vec<(String, String)>,
+    response: reqwest::Response,
+}
+
+impl Client {
+    /// Send a message and stream the response as it is generated
+    ///
+    /// `tools` are offered to the model; see [`StreamEvent`] for how their
+    /// calls are reported.
+    pub async fn stream_message(
+        &self,
+        messages: Messages,
+        system: Option<String>,
+        tools: Vec<Tool>,
+        tool_choice: Option<ToolChoice>,
+    ) -> Result<MessageStream<'_>> {
+        let mut request = self.message_request(messages, None, system)?;
+        request.set_tools(tools, tool_choice)?;
+        request.stream = true;
+        let info = self.start_request(&request.model, true);
+
+        let fitted = match self.check_model(&request) {
+            Ok(()) => self.fit_context(&request).await,
+            Err(error) => Err(error),
+        };
+        let opened = match fitted {
+            Ok(max_tokens) => {
+                request.max_tokens = max_tokens;
+                let body = serde_json::to_value(&request)?;
+                self.open_stream(&body, info.idempotency_key.clone()).await
+            }
+            Err(error) => Err(error),
+        };
+        let response = match opened {
+            Ok(response) => response,
+            Err(error) => {
+                self.notify(|observer| observer.on_error(&info, &error));
+                return Err(error);
+            }
+        };
+
+        Ok(MessageStream {
+            client: self,
+            response,
+            assembler: Assembler::default(),
+            events: VecDeque::new(),
+            done: false,
+            info,
+            first_token: false,
+            request,
+            continuations: 0,
+            earlier: None,
+        })
+    }
+
+    /// Send the request, returning the response once it is known to be streaming
+    async fn open_stream(
+        &self,
+        body: &serde_json::Value,
+        idempotency_key: String,
+    ) -> Result<reqwest::Response> {
+        let opened = self
+            .with_retries(
+                idempotency_key,
+                |key| self.send_stream_request(body, key),
+                |opened: &Opened| (opened.status, &opened.headers),
+            )
+            .await?;
+
+        if !(200..300).contains(&opened.status) {
+            let model = body["model"].as_str().unwrap_or_default();
+            let body = opened.response.text().await?;
+            return Err(models::not_found(model, api_error(opened.status, body)));
+        }
+        Ok(opened.response)
+    }
+
+    async fn send_stream_request(
+        &self,
+        body: &serde_json::Value,
+        idempotency_key: String,
+    ) -> Result<Opened> {
+        let request = Request {
+            method: Method::POST,
+            url: format!("{}/messages", self.config().base_url),
+            headers: self.headers(idempotency_key),
+            body: Some(body.clone()),
+        };
+        let response = self.send_http(request, true).await?;
+
+        Ok(Opened {
+            status: response.status,
+            headers: response.headers.clone(),
+            response: response.into_reqwest()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ClaudeError;
+
+    fn sse(events: &[&str]) -> String {
+        events
+            .iter()
+            .map(|data| {
+                let kind: serde_json::Value = serde_json::from_str(data).unwrap();
+                format!(
+                    "event: {}\ndata: {}\n\n",
+                    kind["type"].as_str().unwrap(),
+                    data
+                )
+            })
+            .collect()
+    }
+
+    const TOOL_STREAM: &[&str] = &[
+        r#"{"type":"message_start","message":{"id":"msg_1","type":"message","role":"assistant","content":[],"model":"claude-sonnet-4-5","stop_reason":null,"usage":{"input_tokens":20,"output_tokens":1}}}"#,
+        r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+        r#"{"type":"ping"}"#,
+        r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Checking"}}"#,
+        r#"{"type":"content_block_stop","index":0}"#,
+        r#"{"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_1","name":"get_weather","input":{}}}"#,
+        r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"city\": "}}"#,
+        r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"\"Paris\"}"}}"#,
+        r#"{"type":"content_block_stop","index":1}"#,
+        r#"{"type":"message_delta","delta":{"stop_reason":"tool_use","stop_sequence":null},"usage":{"output_tokens":30}}"#,
+        r#"{"type":"message_stop"}"#,
+    ];
+
+    #[test]
+    fn test_tool_use_events() {
+        let mut assembler = Assembler::default();
+        let mut events = VecDeque::new();
+        let body = sse(TOOL_STREAM);
+
+        // Deliver the body in awkward pieces, as the network might
+        for chunk in body.as_bytes().chunks(7) {
+            assembler.push(chunk, &mut events).unwrap();
+        }
+
+        let events: Vec<StreamEvent> = events.into();
+        assert_eq!(
+            events,
+            vec![
+                StreamEvent::MessageStart {
+                    id: "msg_1".into(),
+                    model: "claude-sonnet-4-5".into()
+                },
+                StreamEvent::TextDelta {
+                    index: 0,
+                    text: "Checking".into()
+                },
+                StreamEvent::BlockStop { index: 0 },
+                StreamEvent::ToolUseStart {
+                    index: 1,
+                    id: "toolu_1".into(),
+                    name: "get_weather".into()
+                },
+                StreamEvent::ToolInputDelta {
+                    index: 1,
+                    partial_json: "{\"city\": ".into(),
+                    input_so_far: "{\"city\": ".into()
+                },
+                StreamEvent::ToolInputDelta {
+                    index: 1,
+                    partial_json: "\"Paris\"}".into(),
+                    input_so_far: "{\"city\": \"Paris\"}".into()
+                },
+                StreamEvent::ToolUseStop {
+                    index: 1,
+                    id: "toolu_1".into(),
+                    name: "get_weather".into(),
+                    input: serde_json::json!({"city": "Paris"})
+                },
+                StreamEvent::MessageStop {
+                    stop_reason: Some(StopReason::ToolUse)
+                },
+            ]
+        );
+
+        assert!(assembler.complete);
+        assert_eq!(assembler.message.usage.input_tokens, 20);
+        assert_eq!(assembler.message.usage.output_tokens, 30);
+        let message = &assembler.message;
+        assert_eq!(message.text(), "Checking");
+        let tool_uses: Vec<_> = message.tool_uses().collect();
+        assert_eq!(
+            tool_uses,
+            vec![(
+                "toolu_1",
+                "get_weather",
+                &serde_json::json!({"city": "Paris"})
+            )]
+        );
+    }
+
+    #[test]
+    fn test_crlf_line_endings() {
+        let mut assembler = Assembler::default();
+        let mut events = VecDeque::new();
+        let body = sse(TOOL_STREAM).replace('\n', "\r\n");
+
+        // Pieces of 2 bytes split some CRLFs between chunks
+        for chunk in body.as_bytes().chunks(2) {
+            assembler.push(chunk, &mut events).unwrap();
+        }
+
+        assert!(assembler.complete);
+        assert_eq!(events.len(), 8);
+        assert_eq!(assembler.message.text(), "Checking");
+    }
+
+    #[test]
+    fn test_thinking_events() {
+        let mut assembler = Assembler::default();
+        let mut events = VecDeque::new();
+        let body = sse(&[
+            r#"{"type":"message_start","message":{"id":"msg_2","model":"claude-sonnet-4-5","usage":{"input_tokens":5}}}"#,
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"thinking","thinking":"","signature":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"2 + 2 is 4."}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"signature_delta","signature":"sig"}}"#,
+            r#"{"type":"content_block_stop","index":0}"#,
+            r#"{"type":"content_block_start","index":1,"content_block":{"type":"text","text":""}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"text_delta","text":"4"}}"#,
+            r#"{"type":"content_block_stop","index":1}"#,
+            r#"{"type":"message_stop"}"#,
+        ]);
+        assembler.push(body.as_bytes(), &mut events).unwrap();
+
+        assert!(events.contains(&StreamEvent::ThinkingDelta {
+            index: 0,
+            thinking: "2 + 2 is 4.".into()
+        }));
+        assert_eq!(assembler.message.thinking(), "2 + 2 is 4.");
+        assert_eq!(assembler.message.text(), "4");
+        assert_eq!(
+            assembler.message.content[0],
+            ContentBlock::Thinking {
+                thinking: "2 + 2 is 4.".into(),
+                signature: "sig".into()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_from_server_with_observer() {
+        use crate::client::tests::{http_stub, stub_client};
+        use crate::observer::tests::Recorder;
+
+        let body: &'static str = Box::leak(sse(TOOL_STREAM).into_boxed_str());
+        let (url, _) = http_stub(vec![Some((200, body))]).await;
+        let recorder = Recorder::default();
+        let client = stub_client(url).with_observer(recorder.clone());
+
+        let stream = client
+            .stream_message(
+                Messages::new().push_user("Weather?".into()).clone(),
+                None,
+                vec![Tool::new("get_weather", "Weather", serde_json::json!({}))],
+                None,
+            )
+            .await
+            .unwrap();
+        let message = stream.finish().await.unwrap();
+
+        assert_eq!(message.tool_uses().count(), 1);
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            vec!["request", "first_token", "token:Checking", "complete:30"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_output_redacted_when_finished() {
+        use crate::client::tests::{http_stub, stub_client};
+        use crate::guardrails::{Check, GuardAction, Rule, REDACTED};
+
+        let body: &'static str = Box::leak(sse(TOOL_STREAM).into_boxed_str());
+        let (url, _) = http_stub(vec![Some((200, body))]).await;
+        let client = stub_client(url).with_guardrail(Rule::new(
+            Check::DenyList {
+                patterns: vec!["Checking".into()],
+            },
+            GuardAction::Redact,
+        ));
+
+        let message = client
+            .stream_message(
+                Messages::new().push_user("Weather?".into()).clone(),
+                None,
+                Vec::new(),
+                None,
+            )
+            .await
+            .unwrap()
+            .finish()
+            .await
+            .unwrap();
+        assert!(message.text().starts_with(REDACTED));
+        assert!(!message.text().contains("Checking"));
+        assert_eq!(message.tool_uses().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_continue_after_max_tokens() {
+        use crate::client::tests::{http_stub, idempotency_key_of, stub_client};
+
+        let piece = |text: &str, stop_reason: &str, output_tokens: u64| -> &'static str {
+            let delta = serde_json::json!({
+                "type": "content_block_delta",
+                "index": 0,
+                "delta": {"type": "text_delta", "text": text}
+            });
+            let stop = serde_json::json!({
+                "type": "message_delta",
+                "delta": {"stop_reason": stop_reason},
+                "usage": {"output_tokens": output_tokens}
+            });
+            Box::leak(
+                sse(&[
+                    r#"{"type":"message_start","message":{"id":"msg_1","model":"claude-haiku-4-5","usage":{"input_tokens":5}}}"#,
+                    r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+                    &delta.to_string(),
+                    r#"{"type":"content_block_stop","index":0}"#,
+                    &stop.to_string(),
+                    r#"{"type":"message_stop"}"#,
+                ])
+                .into_boxed_str(),
+            )
+        };
+        let (url, requests) = http_stub(vec![
+            Some((200, piece("Once upon \n", "max_tokens", 2))),
+            Some((200, piece(" a time", "end_turn", 3))),
+        ])
+        .await;
+        let mut config = stub_client(url).config().clone();
+        config.max_continuations = 1;
+        let client = Client::new(config).unwrap();
+
+        let mut stream = client
+            .stream_message(
+                Messages::new().push_user("Tell a story".into()).clone(),
+                None,
+                Vec::new(),
+                None,
+            )
+            .await
+            .unwrap();
+        let mut stops = 0;
+        while let Some(event) = stream.next_event().await {
+            if let StreamEvent::MessageStop { stop_reason } = event.unwrap() {
+                assert_eq!(stop_reason, Some(StopReason::EndTurn));
+                stops += 1;
+            }
+        }
+        assert_eq!(stops, 1);
+
+        let message = stream.finish().await.unwrap();
+        assert_eq!(message.text(), "Once upon a time");
+        assert_eq!(message.stop_reason, Some(StopReason::EndTurn));
+        assert_eq!(message.usage.output_tokens, 5);
+        assert_eq!(message.usage.input_tokens, 10);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(
+            requests[1].contains(r#"{"content":"once upon","role":"assistant"}"#),
+            "{}",
+            requests[1]
+        );
+        let key = idempotency_key_of(&requests[1]);
+        assert_ne!(idempotency_key_of(&requests[0]), key);
+        assert_eq!(client.last_idempotency_key().unwrap().to_lowercase(), key);
+    }
+
+    #[tokio::test]
+    async fn test_stream_response_hooks() {
+        use crate::client::tests::{http_stub, stub_client};
+
+        let body: &'static str = Box::leak(sse(TOOL_STREAM).into_boxed_str());
+        let (url, _) = http_stub(vec![Some((200, body))]).await;
+        let client = stub_client(url).with_response_hook(|text: String| Ok(text.to_uppercase()));
+        assert!(client.has_response_hooks());
+
+        let message = client
+            .stream_message(
+                Messages::new().push_user("Weather?".into()).clone(),
+                None,
+                Vec::new(),
+                None,
+            )
+            .await
+            .unwrap()
+            .finish()
+            .await
+            .unwrap();
+        assert_eq!(message.text(), "CHECKING");
+    }
+
+    #[tokio::test]
+    async fn test_abort_keeps_partial_message() {
+        use crate::client::tests::{http_stub, stub_client};
+
+        // The response stops arriving partway through the first block
+        let body: &'static str = Box::leak(sse(&TOOL_STREAM[..4]).into_boxed_str());
+        let (url, _) = http_stub(vec![Some((200, body))]).await;
+        let client = stub_client(url).with_response_hook(|text: String| Ok(text.to_uppercase()));
+
+        let mut stream = client
+            .stream_message(
+                Messages::new().push_user("Weather?".into()).clone(),
+                None,
+                Vec::new(),
+                None,
+            )
+            .await
+            .unwrap();
+        while let Some(event) = stream.next_event().await {
+            if let StreamEvent::TextDelta { .. } = event.unwrap() {
+                break;
+            }
+        }
+        let message = stream.abort().unwrap();
+        assert_eq!(message.text(), "CHECKING");
+        assert_eq!(message.stop_reason, None);
+        assert_eq!(message.usage.input_tokens, 20);
+    }
+
+    #[test]
+    fn test_error_event() {
+        let mut assembler = Assembler::default();
+        let body = sse(&[
+            r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#,
+        ]);
+
+        let error = assembler
+            .push(body.as_bytes(), &mut VecDeque::new())
+            .unwrap_err();
+        assert!(matches!(error, ClaudeError::Api(ApiError::Overloaded(_))));
+    }
+}
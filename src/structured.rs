@@ -0,0 +1,450 @@
+use crate::client::{Client, Messages, StopReason, Usage};
+use crate::error::{ClaudeError, Result};
+use crate::repair::repair_json;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::sync::Arc;
+
+/// A structured response that could not be used
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailedAttempt {
+    /// Starting at 1
+    pub attempt: usize,
+    /// The response, including the leading `{`
+    pub response: String,
+    pub error: String,
+}
+
+/// Callback for [`StructuredRetry::with_failure_hook`]
+type FailureHook = Arc<dyn Fn(&FailedAttempt) + Send + Sync>;
+
+/// How to retry structured responses that are not valid JSON or don't match the schema
+///
+/// [`Client::send_constrained`] uses the same policy for responses that break
+/// their constraints.
+///
+/// A plain number of attempts converts into a policy with the other settings
+/// at their defaults.
+#[derive(Clone)]
+pub struct StructuredRetry {
+    /// Requests to make before giving up
+    pub max_attempts: usize,
+    /// Show the model its invalid response and the error before retrying,
+    /// rather than just asking again
+    pub feed_back_errors: bool,
+    /// Try to fix an invalid response locally, e.g. by removing a markdown
+    /// fence or closing a truncated object, before spending a request on it
+    pub repair: bool,
+    pub(crate) on_failure: Option<FailureHook>,
+}
+
+impl StructuredRetry {
+    pub fn new(max_attempts: usize) -> Self {
+        Self {
+            max_attempts,
+            feed_back_errors: true,
+            repair: true,
+            on_failure: None,
+        }
+    }
+
+    pub fn with_feedback(mut self, feed_back_errors: bool) -> Self {
+        self.feed_back_errors = feed_back_errors;
+        self
+    }
+
+    pub fn with_repair(mut self, repair: bool) -> Self {
+        self.repair = repair;
+        self
+    }
+
+    /// Call `hook` with every response that could not be used, e.g. to log it
+    pub fn with_failure_hook(
+        mut self,
+        hook: impl Fn(&FailedAttempt) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_failure = Some(Arc::new(hook));
+        self
+    }
+}
+
+impl Default for StructuredRetry {
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
+
+impl From<usize> for StructuredRetry {
+    fn from(max_attempts: usize) -> Self {
+        Self::new(max_attempts)
+    }
+}
+
+impl fmt::Debug for StructuredRetry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StructuredRetry")
+            .field("max_attempts", &self.max_attempts)
+            .field("feed_back_errors", &self.feed_back_errors)
+            .field("repair", &self.repair)
+            .field("on_failure", &self.on_failure.is_some())
+            .finish()
+    }
+}
+
+impl Client {
+    /// Sends a message to the Claude API with retry logic for JSON responses.
+    ///
+    /// This function attempts to get a valid JSON response of type `T` from the API,
+    /// retrying as `retry` allows if parsing fails. By default each failed attempt
+    /// includes the error in the conversation to help the model correct its response.
+    ///
+    /// # Arguments
+    /// * `messages` - The conversation messages to send
+    /// * `system` - Optional system prompt to guide the model's behavior
+    /// * `retry` - Maximum number of attempts, or a [`StructuredRetry`] policy
+    ///
+    /// # Returns
+    /// * `Ok(T)` - Successfully parsed response of type T
+    /// * `Err` - If all retry attempts fail or an API error occurs
+    pub async fn send_structured<T>(
+        &self,
+        messages: Messages,
+        system: Option<String>,
+        retry: impl Into<StructuredRetry>,
+    ) -> Result<T>
+    where
+        T: DeserializeOwned + JsonSchema,
+    {
+        let mut usage = Usage::default();
+        let result = self
+            .send_structured_counted(messages, system, retry, &mut usage)
+            .await;
+        // Report the whole call's usage, not just its last attempt's
+        self.set_last_usage(usage);
+        result
+    }
+
+    /// Like [`Client::send_structured`], but the usage of every attempt is
+    /// added to `usage` rather than reported by [`Client::last_usage`]
+    pub(crate) async fn send_structured_counted<T>(
+        &self,
+        messages: Messages,
+        system: Option<String>,
+        retry: impl Into<StructuredRetry>,
+        usage: &mut Usage,
+    ) -> Result<T>
+    where
+        T: DeserializeOwned + JsonSchema,
+    {
+        let schema = serde_json::to_value(schemars::schema_for!(T))?;
+
+        self.send_json(
+            messages,
+            system,
+            &schema,
+            &retry.into(),
+            |response| {
+                serde_json::from_str::<T>(response)
+                    .map_err(|error| format!("response did not match schema: {}", error))
+            },
+            usage,
+        )
+        .await
+    }
+
+    /// Like [`Client::send_structured`], but for a JSON schema only known at runtime
+    ///
+    /// The response must be a JSON object containing every top-level property
+    /// the schema lists as `required`; deeper validation is left to the caller.
+    pub async fn send_structured_value(
+        &self,
+        messages: Messages,
+        system: Option<String>,
+        schema: &serde_json::Value,
+        retry: impl Into<StructuredRetry>,
+    ) -> Result<serde_json::Value> {
+        let mut usage = Usage::default();
+        let result = self
+            .send_json(
+                messages,
+                system,
+                schema,
+                &retry.into(),
+                |response| check_required(schema, response),
+                &mut usage,
+            )
+            .await;
+        self.set_last_usage(usage);
+        result
+    }
+
+    /// Ask for JSON until `parse` accepts it, adding the usage of every
+    /// attempt to `usage`
+    ///
+    /// Usage and stop reasons come from each response, not the client's
+    /// last ones, which concurrent requests on the same client overwrite.
+    async fn send_json<T>(
+        &self,
+        mut messages: Messages,
+        system: Option<String>,
+        schema: &serde_json::Value,
+        retry: &StructuredRetry,
+        parse: impl Fn(&str) -> std::result::Result<T, String>,
+        usage: &mut Usage,
+    ) -> Result<T> {
+        let schema_json = serde_json::to_string_pretty(schema)?;
+        let jsonschema_system = format!(
+            "encode the result to a json object that matches the following JSON schema:\n\n{}",
+            schema_json
+        );
+        let system = if let Some(system) = system {
+            format!("{}\n\n{}", system, jsonschema_system)
+        } else {
+            jsonschema_system
+        };
+
+        let mut last_error = String::from("no attempts were made");
+        for attempt in 1..=retry.max_attempts {
+            // https://github.com/anthropics/claude-cookbooks/blob/main/misc/how_to_enable_json_mode.ipynb
+            let sent = self
+                .send_message_response(messages.clone(), Some("{".into()), Some(system.clone()))
+                .await?;
+            *usage += &sent.usage;
+            let response = sent.text();
+
+            // First validate as generic JSON, then try to parse into the specific type
+            let check = |response: &str| match json::parse(response) {
+                Err(error) => Err(error.to_string()),
+                Ok(_) => parse(response),
+            };
+            let error = match check(&response) {
+                Ok(r) => return Ok(r),
+                Err(error) => error,
+            };
+            // Asking again would be cut off the same way, and a repaired
+            // response would silently be missing its end
+            if let Some(StopReason::MaxTokens) = sent.stop_reason {
+                if let Some(hook) = &retry.on_failure {
+                    hook(&FailedAttempt {
+                        attempt,
+                        response,
+                        error,
+                    });
+                }
+                return Err(ClaudeError::Truncated {
+                    output_tokens: sent.usage.output_tokens,
+                });
+            }
+            if retry.repair {
+                if let Some(Ok(r)) = repair_json(&response).map(|repaired| check(&repaired)) {
+                    return Ok(r);
+                }
+            }
+
+            let failed = FailedAttempt {
+                attempt,
+                response,
+                error,
+            };
+            if let Some(hook) = &retry.on_failure {
+                hook(&failed);
+            }
+            if retry.feed_back_errors {
+                messages.push_assistant(failed.response);
+                messages.push_user(failed.error.clone());
+            }
+            last_error = failed.error;
+        }
+
+        Err(ClaudeError::StructuredOutput(format!(
+            "failed to get valid response despite {} attempts: {}",
+            retry.max_attempts, last_error
+        )))
+    }
+}
+
+/// Parse a response as a JSON object and check the schema's required properties
+fn check_required(
+    schema: &serde_json::Value,
+    response: &str,
+) -> std::result::Result<serde_json::Value, String> {
+    let value: serde_json::Value = serde_json::from_str(response).map_err(|e| e.to_string())?;
+    let object = value
+        .as_object()
+        .ok_or_else(|| "response must be a JSON object".to_string())?;
+
+    let required = schema
+        .get("required")
+        .and_then(|required| required.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|name| name.as_str());
+    let missing: Vec<&str> = required
+        .filter(|name| !object.contains_key(*name))
+        .collect();
+
+    if missing.is_empty() {
+        Ok(value)
+    } else {
+        Err(format!(
+            "response did not match schema: missing required properties {}",
+            missing.join(", ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_required() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "required": ["name", "age"],
+        });
+
+        assert!(check_required(&schema, r#"{"name": "a", "age": 3}"#).is_ok());
+        let error = check_required(&schema, r#"{"name": "a"}"#).unwrap_err();
+        assert!(error.contains("age"));
+        assert!(check_required(&schema, "[1, 2]").is_err());
+    }
+
+    fn reply(text: &str) -> &'static str {
+        reply_stopped(text, "end_turn")
+    }
+
+    fn reply_stopped(text: &str, stop_reason: &str) -> &'static str {
+        Box::leak(
+            serde_json::json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-haiku-4-5",
+                "content": [{"type": "text", "text": text}],
+                "stop_reason": stop_reason,
+                "usage": {"input_tokens": 1, "output_tokens": 1},
+            })
+            .to_string()
+            .into_boxed_str(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_retry_policy() {
+        use crate::client::tests::{http_stub, stub_client};
+        use std::sync::Mutex;
+
+        let (url, requests) = http_stub(vec![
+            Some((200, reply(r#""name": a"#))),
+            Some((200, reply(r#""name": "a"}"#))),
+            Some((200, reply(r#""name": "a", "age": 3}"#))),
+        ])
+        .await;
+        let client = stub_client(url);
+        let failures = Arc::new(Mutex::new(Vec::new()));
+        let seen = failures.clone();
+        let retry = StructuredRetry::new(3)
+            .with_feedback(false)
+            .with_failure_hook(move |failed| seen.lock().unwrap().push(failed.attempt));
+
+        let schema = serde_json::json!({"type": "object", "required": ["name", "age"]});
+        let value = client
+            .send_structured_value(
+                Messages::new().push_user("Ada, 3".into()).clone(),
+                None,
+                &schema,
+                retry,
+            )
+            .await
+            .unwrap();
+        assert_eq!(value["age"], 3);
+        assert_eq!(*failures.lock().unwrap(), vec![1, 2]);
+        // Usage covers every attempt
+        assert_eq!(client.last_usage().unwrap().output_tokens, 3);
+        // Without feedback the conversation is sent unchanged
+        let requests = requests.lock().unwrap();
+        assert!(!requests[2].contains("missing required properties"));
+    }
+
+    #[tokio::test]
+    async fn test_repair_before_retrying() {
+        use crate::client::tests::{http_stub, stub_client};
+
+        let (url, requests) = http_stub(vec![Some((
+            200,
+            reply("\"age\": 3,}\n\nI hope this helps!"),
+        ))])
+        .await;
+        let client = stub_client(url);
+
+        let schema = serde_json::json!({"type": "object", "required": ["age"]});
+        let messages = Messages::new().push_user("3".into()).clone();
+        let value = client
+            .send_structured_value(messages, None, &schema, 1)
+            .await
+            .unwrap();
+        assert_eq!(value["age"], 3);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_truncated_response_is_not_retried() {
+        use crate::client::tests::{http_stub, stub_client};
+
+        let (url, requests) = http_stub(vec![
+            Some((200, reply_stopped(r#""items": ["a", "b"#, "max_tokens"))),
+            Some((200, reply(r#""items": []}"#))),
+        ])
+        .await;
+        let client = stub_client(url);
+
+        let schema = serde_json::json!({"type": "object", "required": ["items"]});
+        let messages = Messages::new().push_user("list".into()).clone();
+        let error = client
+            .send_structured_value(messages, None, &schema, 3)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ClaudeError::Truncated { output_tokens: 1 }));
+        assert_eq!(client.last_stop_reason(), Some(StopReason::MaxTokens));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_concurrent_requests_keep_their_own_stop_reasons() {
+        use crate::client::tests::{http_stub, stub_client};
+
+        let (url, requests) = http_stub(vec![
+            Some((200, reply("not json"))),
+            Some((200, reply_stopped(r#""age": 4}"#, "max_tokens"))),
+            Some((200, reply(r#""age": 3}"#))),
+        ])
+        .await;
+        // Holding up the invalid response lets the cut off one finish meanwhile
+        let client = Arc::new(stub_client(url).with_response_hook(|text: String| {
+            if text.contains("not json") {
+                std::thread::sleep(std::time::Duration::from_millis(200));
+            }
+            Ok(text)
+        }));
+
+        let schema = serde_json::json!({"type": "object", "required": ["age"]});
+        let ask = |client: Arc<Client>, schema: serde_json::Value| async move {
+            let messages = Messages::new().push_user("age".into()).clone();
+            client
+                .send_structured_value(messages, None, &schema, 2)
+                .await
+        };
+        let first = tokio::spawn(ask(client.clone(), schema.clone()));
+        while requests.lock().unwrap().is_empty() {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        let second = tokio::spawn(ask(client.clone(), schema));
+
+        // The invalid response is retried, not mistaken for the other's cut off one
+        assert_eq!(first.await.unwrap().unwrap()["age"], 3);
+        assert_eq!(second.await.unwrap().unwrap()["age"], 4);
+        assert_eq!(requests.lock().unwrap().len(), 3);
+    }
+}
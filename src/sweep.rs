@@ -0,0 +1,203 @@
+use crate::client::{Client, Messages};
+use crate::critique::review_prompt;
+use crate::error::{ClaudeError, Result};
+use crate::structured::StructuredRetry;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+/// One combination of generation settings in a sweep
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SweepPoint {
+    pub model: String,
+    pub temperature: f32,
+}
+
+impl SweepPoint {
+    /// Every combination of `models` and `temperatures`, model by model
+    pub fn grid(models: &[String], temperatures: &[f32]) -> Vec<SweepPoint> {
+        models
+            .iter()
+            .flat_map(|model| {
+                temperatures.iter().map(|&temperature| SweepPoint {
+                    model: model.clone(),
+                    temperature,
+                })
+            })
+            .collect()
+    }
+}
+
+/// A judge's score of one output
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Judgement {
+    /// how well the response meets the criteria, from 0 (not at all) to 10 (fully)
+    pub score: f64,
+    /// briefly explain the score
+    pub reason: String,
+}
+
+/// The output of one sweep point, or why it has none
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SweepRun {
+    #[serde(flatten)]
+    pub point: SweepPoint,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub latency_ms: u64,
+    pub output_tokens: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub judgement: Option<Judgement>,
+}
+
+impl Client {
+    /// Send the same messages with each of `points`' settings
+    ///
+    /// The points run one after another, so their latencies are comparable.
+    /// With `judge` criteria, this client scores every output against them.
+    /// Points that fail are reported in their run; it's an error only if
+    /// every point fails.
+    pub async fn sweep(
+        &self,
+        messages: Messages,
+        system: Option<String>,
+        points: &[SweepPoint],
+        judge: Option<&str>,
+    ) -> Result<Vec<SweepRun>> {
+        if points.is_empty() {
+            return Err(ClaudeError::Unsupported(
+                "a sweep needs at least one point".into(),
+            ));
+        }
+
+        let mut runs = Vec::with_capacity(points.len());
+        let mut first_error = None;
+        for point in points {
+            let client = self
+                .for_model(point.model.as_str())?
+                .with_temperature(point.temperature)?;
+            let started = Instant::now();
+            let result = client
+                .send_message(messages.clone(), None, system.clone())
+                .await;
+            let latency_ms = started.elapsed().as_millis() as u64;
+            let (output, error) = match result {
+                Ok(output) => (Some(output), None),
+                Err(error) => {
+                    let message = error.to_string();
+                    first_error.get_or_insert(error);
+                    (None, Some(message))
+                }
+            };
+            runs.push(SweepRun {
+                point: point.clone(),
+                output,
+                error,
+                latency_ms,
+                output_tokens: client.last_usage().map_or(0, |usage| usage.output_tokens),
+                judgement: None,
+            });
+        }
+        if runs.iter().all(|run| run.output.is_none()) {
+            return Err(first_error.expect("a failure for every point"));
+        }
+
+        if let Some(criteria) = judge {
+            for run in &mut runs {
+                if let Some(output) = &run.output {
+                    run.judgement = Some(self.judge(&messages, output, criteria).await?);
+                }
+            }
+        }
+        Ok(runs)
+    }
+
+    /// Score `output`, the response to `messages`, against `criteria`
+    pub(crate) async fn judge(
+        &self,
+        messages: &Messages,
+        output: &str,
+        criteria: &str,
+    ) -> Result<Judgement> {
+        let messages = Messages::new()
+            .push_user(review_prompt(messages, output, criteria)?)
+            .clone();
+        let system = "You judge responses. Score how well the response to the conversation \
+                      meets the criteria, judging it on its own."
+            .to_string();
+        self.send_structured(messages, Some(system), StructuredRetry::default())
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::{http_stub, stub_client};
+
+    #[test]
+    fn test_grid() {
+        let models = vec!["a".to_string(), "b".to_string()];
+        let grid = SweepPoint::grid(&models, &[0.0, 1.0]);
+        let grid: Vec<(&str, f32)> = grid
+            .iter()
+            .map(|point| (point.model.as_str(), point.temperature))
+            .collect();
+        assert_eq!(grid, [("a", 0.0), ("a", 1.0), ("b", 0.0), ("b", 1.0)]);
+    }
+
+    fn reply(text: &str, output_tokens: u64) -> &'static str {
+        Box::leak(
+            serde_json::json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-haiku-4-5",
+                "content": [{"type": "text", "text": text}],
+                "stop_reason": "end_turn",
+                "usage": {"input_tokens": 1, "output_tokens": output_tokens},
+            })
+            .to_string()
+            .into_boxed_str(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_sweep_with_judge() {
+        let (url, requests) = http_stub(vec![
+            Some((200, reply("A haiku.", 5))),
+            Some((
+                400,
+                r#"{"type":"error","error":{"type":"invalid_request_error","message":"bad"}}"#,
+            )),
+            Some((200, reply(r#""score": 7, "reason": "Fine."}"#, 9))),
+        ])
+        .await;
+        let client = stub_client(url);
+        let points = SweepPoint::grid(&["claude-haiku-4-5".to_string()], &[0.0, 1.0]);
+
+        let runs = client
+            .sweep(
+                Messages::new().push_user("Write a haiku".into()).clone(),
+                None,
+                &points,
+                Some("Is it a haiku?"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].output.as_deref(), Some("A haiku."));
+        assert_eq!(runs[0].output_tokens, 5);
+        assert_eq!(runs[0].judgement.as_ref().unwrap().score, 7.0);
+        assert!(runs[1].error.is_some());
+        assert_eq!(runs[1].judgement, None);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[0].contains(r#""temperature":0.0"#));
+        assert!(requests[1].contains(r#""temperature":1.0"#));
+        assert!(requests[2].contains("<criteria>\\nis it a haiku?"));
+    }
+}
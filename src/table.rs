@@ -0,0 +1,1026 @@
+//! Answering questions about CSV tables
+//!
+//! A table is described to the model by a profile of its columns (type,
+//! number of values, distinct values, range and common values) and as many
+//! of its rows as fit the budget: all of them when the table is small,
+//! otherwise rows spread evenly through it.
+//!
+//! Since the model may not see every row, numeric answers can be verified:
+//! the model writes a [`TableQuery`] that computes the numbers it needs, which
+//! runs locally over every row, and answers from the exact result. Queries
+//! only filter, group, aggregate and sort, so nothing the model writes is
+//! executed as code.
+
+use crate::client::{Client, Messages};
+use crate::diagnostics;
+use crate::error::{ClaudeError, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Delimiters recognized in a table's header line
+const DELIMITERS: [u8; 4] = [b',', b'\t', b';', b'|'];
+
+/// Common values shown for each column
+const EXAMPLES: usize = 3;
+
+/// Characters of an example value shown before it's cut off
+const EXAMPLE_CHARS: usize = 40;
+
+/// Rows of a query's result shown to the model
+const MAX_RESULT_ROWS: usize = 200;
+
+const QUERY_SYSTEM: &str = "Write a query over the table that computes the numbers needed \
+     to answer the user's question. It runs over every row of the table, not just the rows \
+     shown. Use the column names exactly as given. Aggregates are named like \"sum(revenue)\" \
+     or \"count(*)\" for sorting.";
+
+const ANSWER_SYSTEM: &str = "Answer the user's question about the table concisely. The \
+     table may be shown in part; say so if the answer depends on rows that aren't shown.";
+
+const VERIFIED_SYSTEM: &str = "Answer the user's question about the table concisely. Take \
+     every number from the query result, which was computed exactly over all rows, rather \
+     than from the rows shown.";
+
+/// A table of text cells with a header row
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Table {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+/// The type of a column's values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnType {
+    Integer,
+    Number,
+    Boolean,
+    /// ISO 8601 dates, `YYYY-MM-DD`
+    Date,
+    Text,
+}
+
+impl ColumnType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ColumnType::Integer => "integer",
+            ColumnType::Number => "number",
+            ColumnType::Boolean => "boolean",
+            ColumnType::Date => "date",
+            ColumnType::Text => "text",
+        }
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A summary of one column of a table
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ColumnProfile {
+    pub name: String,
+    pub kind: ColumnType,
+    /// Cells that aren't empty
+    pub values: usize,
+    /// Empty cells
+    pub empty: usize,
+    pub distinct: usize,
+    /// Smallest and largest values of number and date columns
+    pub range: Option<(String, String)>,
+    /// The most common values, most common first
+    pub examples: Vec<String>,
+}
+
+impl fmt::Display for ColumnProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}): {} values, {} distinct",
+            self.name, self.kind, self.values, self.distinct
+        )?;
+        match &self.range {
+            Some((min, max)) => write!(f, ", from {} to {}", min, max)?,
+            None if !self.examples.is_empty() => {
+                let examples: Vec<String> = self
+                    .examples
+                    .iter()
+                    .map(|example| format!("{:?}", example))
+                    .collect();
+                write!(f, ", e.g. {}", examples.join(", "))?;
+            }
+            None => {}
+        }
+        if self.empty > 0 {
+            write!(f, "; {} empty", self.empty)?;
+        }
+        Ok(())
+    }
+}
+
+/// A query over a table's rows, written by the model and run locally
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TableQuery {
+    /// conditions a row must meet to be included; all of them must hold
+    #[serde(default)]
+    pub filters: Vec<Filter>,
+    /// columns whose combinations of values form the groups; empty for a single group of all rows
+    #[serde(default)]
+    pub group_by: Vec<String>,
+    /// values computed for each group
+    pub aggregates: Vec<Aggregate>,
+    /// result column to sort by: a group_by column or an aggregate's name, such as "sum(revenue)"
+    #[serde(default)]
+    pub sort_by: Option<String>,
+    /// sort from largest to smallest
+    #[serde(default)]
+    pub descending: bool,
+    /// result rows to keep after sorting
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// A condition on a column's value
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Filter {
+    pub column: String,
+    pub op: FilterOp,
+    /// compared as a number when both it and the cell are numbers, otherwise as text
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    /// the cell contains the value, ignoring case
+    Contains,
+}
+
+/// A value computed over the rows of a group
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Aggregate {
+    pub function: AggregateFunction,
+    /// the column to aggregate; omit it to count rows
+    #[serde(default)]
+    pub column: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AggregateFunction {
+    /// rows, or the non-empty cells of a column
+    Count,
+    CountDistinct,
+    Sum,
+    Mean,
+    Min,
+    Max,
+}
+
+impl AggregateFunction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AggregateFunction::Count => "count",
+            AggregateFunction::CountDistinct => "count_distinct",
+            AggregateFunction::Sum => "sum",
+            AggregateFunction::Mean => "mean",
+            AggregateFunction::Min => "min",
+            AggregateFunction::Max => "max",
+        }
+    }
+}
+
+impl Aggregate {
+    /// The aggregate's column in a query result, e.g. `sum(revenue)`
+    pub fn name(&self) -> String {
+        format!(
+            "{}({})",
+            self.function.as_str(),
+            self.column.as_deref().unwrap_or("*")
+        )
+    }
+}
+
+impl FilterOp {
+    fn symbol(&self) -> &'static str {
+        match self {
+            FilterOp::Eq => "=",
+            FilterOp::Ne => "!=",
+            FilterOp::Lt => "<",
+            FilterOp::Le => "<=",
+            FilterOp::Gt => ">",
+            FilterOp::Ge => ">=",
+            FilterOp::Contains => "CONTAINS",
+        }
+    }
+}
+
+/// The query in SQL-like form, for people reading what was computed
+impl fmt::Display for TableQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let columns: Vec<String> = self
+            .group_by
+            .iter()
+            .cloned()
+            .chain(self.aggregates.iter().map(Aggregate::name))
+            .collect();
+        write!(f, "SELECT {}", columns.join(", "))?;
+        if !self.filters.is_empty() {
+            let filters: Vec<String> = self
+                .filters
+                .iter()
+                .map(|filter| {
+                    format!(
+                        "{} {} {:?}",
+                        filter.column,
+                        filter.op.symbol(),
+                        filter.value
+                    )
+                })
+                .collect();
+            write!(f, " WHERE {}", filters.join(" AND "))?;
+        }
+        if !self.group_by.is_empty() {
+            write!(f, " GROUP BY {}", self.group_by.join(", "))?;
+        }
+        if let Some(column) = &self.sort_by {
+            write!(
+                f,
+                " ORDER BY {}{}",
+                column,
+                if self.descending { " DESC" } else { "" }
+            )?;
+        }
+        if let Some(limit) = self.limit {
+            write!(f, " LIMIT {}", limit)?;
+        }
+        Ok(())
+    }
+}
+
+/// A cell's value as a number, allowing thousands separators, a currency
+/// sign or a percent sign
+fn number(cell: &str) -> Option<f64> {
+    let cell = cell.trim();
+    let cell = cell.strip_suffix('%').unwrap_or(cell);
+    let cell = cell.trim_start_matches(['$', '€', '£']);
+    cell.replace(',', "")
+        .parse()
+        .ok()
+        .filter(|value: &f64| value.is_finite())
+}
+
+fn integer(cell: &str) -> bool {
+    number(cell).is_some() && !cell.contains(['.', 'e', 'E'])
+}
+
+fn boolean(cell: &str) -> bool {
+    ["true", "false", "yes", "no"]
+        .iter()
+        .any(|value| cell.trim().eq_ignore_ascii_case(value))
+}
+
+fn date(cell: &str) -> bool {
+    chrono::NaiveDate::parse_from_str(cell.trim(), "%Y-%m-%d").is_ok()
+}
+
+/// Numbers in numeric order, anything else in text order
+fn compare(a: &str, b: &str) -> Ordering {
+    match (number(a), number(b)) {
+        (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        _ => a.trim().cmp(b.trim()),
+    }
+}
+
+fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        format!("{}", (value * 1e6).round() / 1e6)
+    }
+}
+
+impl Filter {
+    fn matches(&self, cell: &str) -> bool {
+        let ordering = || compare(cell, &self.value);
+        let equal = || match (number(cell), number(&self.value)) {
+            (Some(a), Some(b)) => a == b,
+            _ => cell.trim().eq_ignore_ascii_case(self.value.trim()),
+        };
+        match self.op {
+            FilterOp::Eq => equal(),
+            FilterOp::Ne => !equal(),
+            FilterOp::Lt => ordering().is_lt(),
+            FilterOp::Le => ordering().is_le(),
+            FilterOp::Gt => ordering().is_gt(),
+            FilterOp::Ge => ordering().is_ge(),
+            FilterOp::Contains => cell.to_lowercase().contains(&self.value.to_lowercase()),
+        }
+    }
+}
+
+impl Table {
+    /// Parse delimited text whose first line is the header row
+    ///
+    /// The delimiter, a comma, tab, semicolon or pipe, is whichever appears
+    /// most in the header line.
+    pub fn parse(text: &str) -> Result<Table> {
+        let header = text.lines().next().unwrap_or_default();
+        let delimiter = DELIMITERS
+            .into_iter()
+            .max_by_key(|delimiter| header.bytes().filter(|byte| byte == delimiter).count())
+            .unwrap_or(b',');
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .from_reader(text.as_bytes());
+        let headers: Vec<String> = reader
+            .headers()
+            .map_err(|e| ClaudeError::Table(e.to_string()))?
+            .iter()
+            .map(|header| header.trim().to_string())
+            .collect();
+        if headers.iter().all(String::is_empty) {
+            return Err(ClaudeError::Table("the table has no header row".into()));
+        }
+        let rows = reader
+            .records()
+            .map(|record| {
+                record
+                    .map(|record| record.iter().map(str::to_string).collect())
+                    .map_err(|e| ClaudeError::Table(e.to_string()))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Table { headers, rows })
+    }
+
+    /// The table as CSV, with only its first `max_rows` rows
+    pub fn to_csv(&self, max_rows: usize) -> String {
+        csv_text(&self.headers, self.rows.iter().take(max_rows))
+    }
+
+    /// Summaries of the table's columns
+    pub fn profile(&self) -> Vec<ColumnProfile> {
+        self.headers
+            .iter()
+            .enumerate()
+            .map(|(index, name)| {
+                let cells: Vec<&str> = self
+                    .rows
+                    .iter()
+                    .map(|row| row[index].trim())
+                    .filter(|cell| !cell.is_empty())
+                    .collect();
+                let all =
+                    |test: fn(&str) -> bool| !cells.is_empty() && cells.iter().all(|c| test(c));
+                let kind = if all(integer) {
+                    ColumnType::Integer
+                } else if all(|cell| number(cell).is_some()) {
+                    ColumnType::Number
+                } else if all(boolean) {
+                    ColumnType::Boolean
+                } else if all(date) {
+                    ColumnType::Date
+                } else {
+                    ColumnType::Text
+                };
+
+                let mut counts: Vec<(&str, usize)> = Vec::new();
+                let mut positions: HashMap<&str, usize> = HashMap::new();
+                for cell in &cells {
+                    let position = *positions.entry(cell).or_insert_with(|| {
+                        counts.push((cell, 0));
+                        counts.len() - 1
+                    });
+                    counts[position].1 += 1;
+                }
+                let range = match kind {
+                    ColumnType::Integer | ColumnType::Number | ColumnType::Date => {
+                        let min = cells.iter().min_by(|a, b| compare(a, b));
+                        let max = cells.iter().max_by(|a, b| compare(a, b));
+                        min.zip(max)
+                            .map(|(min, max)| (min.to_string(), max.to_string()))
+                    }
+                    ColumnType::Boolean | ColumnType::Text => None,
+                };
+                // Stable, so ties stay in order of first appearance
+                counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+                let examples = counts
+                    .iter()
+                    .take(EXAMPLES)
+                    .map(|(cell, _)| match cell.char_indices().nth(EXAMPLE_CHARS) {
+                        Some((end, _)) => format!("{}…", &cell[..end]),
+                        None => cell.to_string(),
+                    })
+                    .collect();
+
+                ColumnProfile {
+                    name: name.clone(),
+                    kind,
+                    values: cells.len(),
+                    empty: self.rows.len() - cells.len(),
+                    distinct: counts.len(),
+                    range,
+                    examples,
+                }
+            })
+            .collect()
+    }
+
+    /// The profile of the table and as many of its rows as fit `budget_bytes`
+    ///
+    /// Rows that don't all fit are sampled evenly from the whole table.
+    pub fn describe(&self, budget_bytes: usize) -> String {
+        let mut text = format!(
+            "{} rows, {} columns\n\nColumns:\n",
+            self.rows.len(),
+            self.headers.len()
+        );
+        for profile in self.profile() {
+            text.push_str(&format!("- {}\n", profile));
+        }
+
+        let total = self.rows.len();
+        let remaining = budget_bytes.saturating_sub(text.len() + 64);
+        let sample = |count: usize| {
+            csv_text(
+                &self.headers,
+                (0..count).map(|index| &self.rows[index * total / count]),
+            )
+        };
+        let mut count = total;
+        let mut rows = sample(count);
+        if rows.len() > remaining {
+            // Start from the average row size, then shrink until it fits
+            count = (total * remaining / rows.len()).min(total);
+            rows = sample(count);
+            while rows.len() > remaining && count > 0 {
+                count = count * 9 / 10;
+                rows = sample(count);
+            }
+        }
+
+        match count {
+            0 => text.push_str("\nNo rows fit in the budget.\n"),
+            _ if count == total => text.push_str(&format!("\nAll rows:\n{}", rows)),
+            _ => text.push_str(&format!(
+                "\n{} of the {} rows, spread evenly through the table:\n{}",
+                count, total, rows
+            )),
+        }
+        text
+    }
+
+    /// Run `query` over every row
+    ///
+    /// The result has a column for each `group_by` column and aggregate, and
+    /// a row for each group, in order of first appearance unless sorted.
+    pub fn query(&self, query: &TableQuery) -> Result<Table> {
+        let column = |name: &str| {
+            self.headers
+                .iter()
+                .position(|header| header == name)
+                .ok_or_else(|| {
+                    ClaudeError::Table(format!(
+                        "no column named {:?}; the columns are {}",
+                        name,
+                        self.headers.join(", ")
+                    ))
+                })
+        };
+        let filters = query
+            .filters
+            .iter()
+            .map(|filter| Ok((column(&filter.column)?, filter)))
+            .collect::<Result<Vec<_>>>()?;
+        let groups = query
+            .group_by
+            .iter()
+            .map(|name| column(name))
+            .collect::<Result<Vec<_>>>()?;
+        let aggregates = query
+            .aggregates
+            .iter()
+            .map(|aggregate| {
+                Ok((
+                    aggregate.column.as_deref().map(column).transpose()?,
+                    aggregate,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if aggregates.is_empty() && groups.is_empty() {
+            return Err(ClaudeError::Table(
+                "the query has no group_by columns or aggregates".into(),
+            ));
+        }
+
+        let mut keys: Vec<Vec<&str>> = Vec::new();
+        let mut members: HashMap<Vec<&str>, Vec<&Vec<String>>> = HashMap::new();
+        for row in &self.rows {
+            if !filters
+                .iter()
+                .all(|(index, filter)| filter.matches(&row[*index]))
+            {
+                continue;
+            }
+            let key: Vec<&str> = groups.iter().map(|index| row[*index].as_str()).collect();
+            members
+                .entry(key.clone())
+                .or_insert_with(|| {
+                    keys.push(key);
+                    Vec::new()
+                })
+                .push(row);
+        }
+        // Aggregates over no rows still have a value, e.g. a count of 0
+        if groups.is_empty() && keys.is_empty() {
+            keys.push(Vec::new());
+        }
+
+        let mut rows = Vec::new();
+        for key in keys {
+            let group = members.get(&key).map(Vec::as_slice).unwrap_or_default();
+            let mut row: Vec<String> = key.iter().map(|cell| cell.to_string()).collect();
+            for (index, aggregate) in &aggregates {
+                row.push(aggregated(group, *index, aggregate)?);
+            }
+            rows.push(row);
+        }
+
+        let headers: Vec<String> = query
+            .group_by
+            .iter()
+            .cloned()
+            .chain(query.aggregates.iter().map(Aggregate::name))
+            .collect();
+        if let Some(name) = &query.sort_by {
+            let index = headers
+                .iter()
+                .position(|header| header == name)
+                .ok_or_else(|| {
+                    ClaudeError::Table(format!(
+                        "can't sort by {:?}; the result's columns are {}",
+                        name,
+                        headers.join(", ")
+                    ))
+                })?;
+            rows.sort_by(|a, b| compare(&a[index], &b[index]));
+            if query.descending {
+                rows.reverse();
+            }
+        }
+        if let Some(limit) = query.limit {
+            rows.truncate(limit);
+        }
+        Ok(Table { headers, rows })
+    }
+}
+
+/// The value of `aggregate` over `rows`, whose column `index` it aggregates
+fn aggregated(
+    rows: &[&Vec<String>],
+    index: Option<usize>,
+    aggregate: &Aggregate,
+) -> Result<String> {
+    let Some(index) = index else {
+        return match aggregate.function {
+            AggregateFunction::Count => Ok(rows.len().to_string()),
+            _ => Err(ClaudeError::Table(format!(
+                "{} needs a column",
+                aggregate.function.as_str()
+            ))),
+        };
+    };
+    let cells: Vec<&str> = rows
+        .iter()
+        .map(|row| row[index].trim())
+        .filter(|cell| !cell.is_empty())
+        .collect();
+    let numbers = || {
+        cells
+            .iter()
+            .map(|cell| {
+                number(cell).ok_or_else(|| {
+                    ClaudeError::Table(format!(
+                        "{} needs numbers, but {} has the value {:?}",
+                        aggregate.name(),
+                        aggregate.column.as_deref().unwrap_or_default(),
+                        cell
+                    ))
+                })
+            })
+            .collect::<Result<Vec<f64>>>()
+    };
+    Ok(match aggregate.function {
+        AggregateFunction::Count => cells.len().to_string(),
+        AggregateFunction::CountDistinct => cells.iter().collect::<HashSet<_>>().len().to_string(),
+        AggregateFunction::Sum => format_number(numbers()?.iter().sum()),
+        AggregateFunction::Mean => {
+            let numbers = numbers()?;
+            match numbers.len() {
+                0 => String::new(),
+                len => format_number(numbers.iter().sum::<f64>() / len as f64),
+            }
+        }
+        // Dates and text have a minimum and maximum too
+        AggregateFunction::Min => cells
+            .iter()
+            .min_by(|a, b| compare(a, b))
+            .map(|cell| cell.to_string())
+            .unwrap_or_default(),
+        AggregateFunction::Max => cells
+            .iter()
+            .max_by(|a, b| compare(a, b))
+            .map(|cell| cell.to_string())
+            .unwrap_or_default(),
+    })
+}
+
+fn csv_text<'a>(headers: &[String], rows: impl Iterator<Item = &'a Vec<String>>) -> String {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    // Writing to memory can't fail
+    let _ = writer.write_record(headers);
+    for row in rows {
+        let _ = writer.write_record(row);
+    }
+    String::from_utf8(writer.into_inner().unwrap_or_default()).unwrap_or_default()
+}
+
+/// How [`Client::ask_table`] shows a table to the model
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableOptions {
+    /// Bytes the table's profile and rows may take in the prompt; a token
+    /// covers at least one byte, so they never take more tokens than this
+    pub budget_bytes: usize,
+    /// Answer from a query run locally over every row
+    pub verify: bool,
+    /// Queries to try, while they fail to run, before giving up
+    pub max_attempts: usize,
+    /// Replace identifiers with consistent pseudonyms before anything is sent
+    pub pseudonymize: bool,
+    /// The columns to pseudonymize; when empty, identifier-like columns are
+    /// detected with [`Table::identifier_columns`]
+    pub identifier_columns: Vec<String>,
+}
+
+impl Default for TableOptions {
+    fn default() -> Self {
+        Self {
+            budget_bytes: 20_000,
+            verify: false,
+            max_attempts: 3,
+            pseudonymize: false,
+            identifier_columns: Vec::new(),
+        }
+    }
+}
+
+/// The result of [`Client::ask_table`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TableAnswer {
+    pub answer: String,
+    /// The query the answer's numbers come from, when verified
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<TableQuery>,
+    /// What the query computed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Table>,
+    /// The columns whose values the model only saw as pseudonyms
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pseudonymized: Vec<String>,
+}
+
+impl Client {
+    /// Answer `question` about `table`
+    ///
+    /// The model sees the table's profile and as many rows as fit the budget.
+    /// With `verify`, it first writes a [`TableQuery`] for the numbers it
+    /// needs, which runs over every row; a query that fails to run is shown
+    /// back to the model with the error. The answer is then written from the
+    /// query's exact result.
+    ///
+    /// With `pseudonymize`, the model sees the table and question with their
+    /// identifiers replaced by pseudonyms, and the answer, query and result
+    /// come back with the real values.
+    pub async fn ask_table(
+        &self,
+        table: &Table,
+        question: &str,
+        options: &TableOptions,
+    ) -> Result<TableAnswer> {
+        let pseudonymized = match options.pseudonymize {
+            true if options.identifier_columns.is_empty() => table.identifier_columns(),
+            true => options.identifier_columns.clone(),
+            false => Vec::new(),
+        };
+        let (shown, pseudonyms) = table.pseudonymize(&pseudonymized)?;
+        if !pseudonyms.is_empty() {
+            diagnostics::info(
+                "pseudonymize",
+                format!(
+                    "Pseudonymized {} values in {}",
+                    pseudonyms.len(),
+                    pseudonymized.join(", ")
+                ),
+                serde_json::json!({"columns": pseudonymized, "values": pseudonyms.len()}),
+            );
+        }
+        let question = pseudonyms.conceal(question);
+        let table = &shown;
+
+        let prompt = format!(
+            "<table>\n{}</table>\n\nQuestion: {}",
+            table.describe(options.budget_bytes),
+            question
+        );
+
+        let mut verified = None;
+        if options.verify {
+            let mut messages = Messages::new().push_user(prompt.clone()).clone();
+            for _ in 0..options.max_attempts.max(1) {
+                let query: TableQuery = self
+                    .send_structured(messages.clone(), Some(QUERY_SYSTEM.into()), 3)
+                    .await?;
+                match table.query(&query) {
+                    Ok(result) => {
+                        verified = Some((query, result));
+                        break;
+                    }
+                    Err(error) => {
+                        messages
+                            .push_assistant(serde_json::to_string(&query)?)
+                            .push_user(format!("The query failed: {}\nCorrect the query.", error));
+                    }
+                }
+            }
+            if verified.is_none() {
+                return Err(ClaudeError::Table(format!(
+                    "no query that runs after {} attempts",
+                    options.max_attempts.max(1)
+                )));
+            }
+        }
+
+        let (request, system) = match &verified {
+            Some((query, result)) => {
+                let shown = match result.rows.len() {
+                    len if len > MAX_RESULT_ROWS => {
+                        format!(" (the first {} of its {} rows)", MAX_RESULT_ROWS, len)
+                    }
+                    _ => String::new(),
+                };
+                let request = format!(
+                    "{}\n\nThis query was run over all {} rows:\n{}\n\nIts exact result{}:\n<result>\n{}</result>",
+                    prompt,
+                    table.rows.len(),
+                    query,
+                    shown,
+                    result.to_csv(MAX_RESULT_ROWS)
+                );
+                (request, VERIFIED_SYSTEM)
+            }
+            None => (prompt, ANSWER_SYSTEM),
+        };
+        let answer = self
+            .send_message(
+                Messages::new().push_user(request).clone(),
+                None,
+                Some(system.into()),
+            )
+            .await?;
+
+        let (mut query, result) = verified.unzip();
+        for filter in query.iter_mut().flat_map(|query| &mut query.filters) {
+            filter.value = pseudonyms.reveal(&filter.value);
+        }
+        Ok(TableAnswer {
+            answer: pseudonyms.reveal(&answer),
+            query,
+            result: result.map(|result| pseudonyms.reveal_table(&result)),
+            pseudonymized,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::http_stub;
+    use crate::config::Config;
+
+    const SALES: &str = "region,year,revenue,launched\n\
+                         North,2023,\"1,000\",2020-01-15\n\
+                         South,2023,800,2021-06-01\n\
+                         North,2024,1500,2020-01-15\n\
+                         South,2024,1600,2021-06-01\n\
+                         East,2024,,2024-03-01\n";
+
+    fn reply(text: &str) -> &'static str {
+        Box::leak(
+            serde_json::json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-haiku-4-5",
+                "content": [{"type": "text", "text": text}],
+                "stop_reason": "end_turn",
+                "usage": {"input_tokens": 1, "output_tokens": 1}
+            })
+            .to_string()
+            .into_boxed_str(),
+        )
+    }
+
+    fn query(json: serde_json::Value) -> TableQuery {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let table = Table::parse(SALES).unwrap();
+        assert_eq!(table.headers, ["region", "year", "revenue", "launched"]);
+        assert_eq!(table.rows.len(), 5);
+        assert_eq!(table.rows[0][2], "1,000");
+
+        let tabs = Table::parse("a\tb\n1\t2\n").unwrap();
+        assert_eq!(tabs.rows, [["1", "2"]]);
+        assert!(Table::parse("").is_err());
+        assert!(Table::parse("a,b\n1,2,3\n").is_err());
+    }
+
+    #[test]
+    fn test_profile() {
+        let profile = Table::parse(SALES).unwrap().profile();
+        let kinds: Vec<ColumnType> = profile.iter().map(|column| column.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                ColumnType::Text,
+                ColumnType::Integer,
+                ColumnType::Integer,
+                ColumnType::Date
+            ]
+        );
+        assert_eq!(
+            profile[0].to_string(),
+            "region (text): 5 values, 3 distinct, e.g. \"North\", \"South\", \"East\""
+        );
+        assert_eq!(
+            profile[2].to_string(),
+            "revenue (integer): 4 values, 4 distinct, from 800 to 1600; 1 empty"
+        );
+    }
+
+    #[test]
+    fn test_describe_within_budget() {
+        let all = Table::parse(SALES).unwrap().describe(10_000);
+        assert!(all.contains("All rows:\nregion,year,revenue,launched\nNorth,2023,\"1,000\""));
+
+        let mut big = "id,value\n".to_string();
+        for id in 0..1000 {
+            big.push_str(&format!("{},{}\n", id, id * 7));
+        }
+        let description = Table::parse(&big).unwrap().describe(2_000);
+        assert!(description.len() <= 2_000);
+        assert!(description.contains("of the 1000 rows, spread evenly"));
+        assert!(description.contains("\n0,0\n"));
+    }
+
+    #[test]
+    fn test_query() {
+        let table = Table::parse(SALES).unwrap();
+        let result = table
+            .query(&query(serde_json::json!({
+                "filters": [{"column": "year", "op": "ge", "value": "2023"}],
+                "group_by": ["region"],
+                "aggregates": [
+                    {"function": "sum", "column": "revenue"},
+                    {"function": "count"}
+                ],
+                "sort_by": "sum(revenue)",
+                "descending": true,
+                "limit": 2
+            })))
+            .unwrap();
+        assert_eq!(result.headers, ["region", "sum(revenue)", "count(*)"]);
+        assert_eq!(
+            result.rows,
+            [["North", "2500", "2"], ["South", "2400", "2"]]
+        );
+
+        let mean = table
+            .query(&query(serde_json::json!({
+                "filters": [{"column": "region", "op": "eq", "value": "north"}],
+                "aggregates": [
+                    {"function": "mean", "column": "revenue"},
+                    {"function": "max", "column": "launched"}
+                ]
+            })))
+            .unwrap();
+        assert_eq!(mean.rows, [["1250", "2020-01-15"]]);
+    }
+
+    #[test]
+    fn test_query_errors() {
+        let table = Table::parse(SALES).unwrap();
+        let unknown = table.query(&query(serde_json::json!({
+            "aggregates": [{"function": "sum", "column": "profit"}]
+        })));
+        assert!(unknown
+            .unwrap_err()
+            .to_string()
+            .contains("no column named \"profit\""));
+
+        let text = table.query(&query(serde_json::json!({
+            "aggregates": [{"function": "sum", "column": "region"}]
+        })));
+        assert!(text.unwrap_err().to_string().contains("needs numbers"));
+    }
+
+    #[test]
+    fn test_query_display() {
+        let query = query(serde_json::json!({
+            "filters": [{"column": "year", "op": "eq", "value": "2024"}],
+            "group_by": ["region"],
+            "aggregates": [{"function": "sum", "column": "revenue"}],
+            "sort_by": "sum(revenue)",
+            "descending": true,
+            "limit": 1
+        }));
+        assert_eq!(
+            query.to_string(),
+            "SELECT region, sum(revenue) WHERE year = \"2024\" GROUP BY region \
+             ORDER BY sum(revenue) DESC LIMIT 1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ask_table_verified() {
+        // The first query names a missing column and is shown its error
+        let (url, requests) = http_stub(vec![
+            Some((200, reply(r#""aggregates": [{"function": "sum", "column": "sales"}]}"#))),
+            Some((
+                200,
+                reply(r#""group_by": ["region"], "aggregates": [{"function": "sum", "column": "revenue"}], "sort_by": "sum(revenue)", "descending": true, "limit": 1}"#),
+            )),
+            Some((200, reply("North, with 2500 in revenue."))),
+        ])
+        .await;
+        let mut config = Config::new("sk-ant-test-key");
+        config.base_url = url;
+        let client = Client::new(config).unwrap();
+
+        let table = Table::parse(SALES).unwrap();
+        let options = TableOptions {
+            verify: true,
+            ..TableOptions::default()
+        };
+        let answer = client
+            .ask_table(&table, "Which region earned most?", &options)
+            .await
+            .unwrap();
+
+        assert_eq!(answer.answer, "North, with 2500 in revenue.");
+        assert!(answer.pseudonymized.is_empty());
+        assert_eq!(answer.result.unwrap().rows, [["North", "2500"]]);
+        let requests = requests.lock().unwrap();
+        assert!(requests[1].contains("no column named \\\"sales\\\""));
+        assert!(requests[2].contains("<result>\\nregion,sum(revenue)\\nnorth,2500\\n</result>"));
+    }
+
+    #[tokio::test]
+    async fn test_ask_table_pseudonymized() {
+        let (url, requests) =
+            http_stub(vec![Some((200, reply("customer_1 spent the most.")))]).await;
+        let mut config = Config::new("sk-ant-test-key");
+        config.base_url = url;
+        let client = Client::new(config).unwrap();
+
+        let table = Table::parse("customer,spent\nAcme Corp,900\nGlobex,100\n").unwrap();
+        let options = TableOptions {
+            pseudonymize: true,
+            ..TableOptions::default()
+        };
+        let answer = client
+            .ask_table(&table, "Did Acme Corp spend the most?", &options)
+            .await
+            .unwrap();
+
+        assert_eq!(answer.answer, "Acme Corp spent the most.");
+        assert_eq!(answer.pseudonymized, ["customer"]);
+        let requests = requests.lock().unwrap();
+        assert!(!requests[0].contains("acme"));
+        assert!(!requests[0].contains("globex"));
+        assert!(requests[0].contains("did customer_1 spend the most?"));
+    }
+}
@@ -0,0 +1,267 @@
+//! Counting the tokens of a prompt before sending it
+//!
+//! [`Client::count_tokens`] asks the API for an exact count. With the
+//! `estimate` feature, [`estimate_tokens`] gives an approximate count locally,
+//! for pre-flight checks without a network round trip.
+//!
+//! Requests that might not fit the model's context window are counted before
+//! they are sent and fail with [`ClaudeError::ContextTooLarge`] instead of the
+//! API's `400`.
+
+use crate::client::{api_error, Client, MessageRequest, Messages, Thinking};
+use crate::error::{ApiError, ClaudeError, Result};
+use crate::models;
+use crate::retry;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize)]
+struct TokenCount {
+    input_tokens: u64,
+}
+
+impl Client {
+    /// Input tokens the messages would take with the configured model, using
+    /// the API's free token counting endpoint
+    ///
+    /// The prompt is prepared exactly as for sending it, so guardrails and
+    /// redaction apply and the count matches what would be sent.
+    pub async fn count_tokens(&self, messages: Messages, system: Option<String>) -> Result<u64> {
+        let request = self.message_request(messages, None, system)?;
+        self.count_request_tokens(&request).await
+    }
+
+    async fn count_request_tokens(&self, request: &MessageRequest) -> Result<u64> {
+        let mut body = serde_json::to_value(request)?;
+        // Only the prompt counts; the endpoint rejects generation settings
+        if let Some(body) = body.as_object_mut() {
+            body.remove("max_tokens");
+            body.remove("temperature");
+            body.remove("stream");
+        }
+
+        let response = self
+            .post("messages/count_tokens", &body, retry::idempotency_key())
+            .await?;
+        if !(200..300).contains(&response.status) {
+            return Err(api_error(response.status, response.body));
+        }
+        let count: TokenCount = serde_json::from_str(&response.body)
+            .map_err(|e| ApiError::UnexpectedResponse(e.to_string()))?;
+        Ok(count.input_tokens)
+    }
+
+    /// The max_tokens to send the request with, or [`ClaudeError::ContextTooLarge`]
+    /// if it can't fit the model's context window
+    ///
+    /// With [`Config::auto_max_tokens`](crate::Config::auto_max_tokens) the
+    /// request's max_tokens is a ceiling, lowered to what the window leaves
+    /// after the prompt.
+    ///
+    /// Every token covers at least one byte of the request, so only requests
+    /// whose JSON comes close to the window are counted; the rest are sent
+    /// without the extra round trip. If counting fails the request is sent
+    /// as is and the API's own error, if any, is reported.
+    pub(crate) async fn fit_context(&self, request: &MessageRequest) -> Result<u32> {
+        let Some(limit) = models::context_window(&request.model) else {
+            return Ok(request.max_tokens);
+        };
+        let max_tokens = u64::from(request.max_tokens);
+        let bytes = serde_json::to_vec(request)?.len() as u64;
+        if bytes + HIDDEN_TOKENS + max_tokens <= limit {
+            return Ok(request.max_tokens);
+        }
+        let Ok(input) = self.count_request_tokens(request).await else {
+            return Ok(request.max_tokens);
+        };
+
+        // Thinking must leave room for at least one token of the answer
+        let needed = match request.thinking {
+            Some(Thinking::Enabled { budget_tokens }) => u64::from(budget_tokens) + 1,
+            None => 1,
+        };
+        let max_tokens = if self.config().auto_max_tokens {
+            max_tokens.min(limit.saturating_sub(input)).max(needed)
+        } else {
+            max_tokens
+        };
+        if input + max_tokens > limit {
+            return Err(ClaudeError::ContextTooLarge {
+                have: input + max_tokens,
+                limit,
+            });
+        }
+        Ok(max_tokens as u32)
+    }
+}
+
+/// Tokens the API adds to a request beyond its JSON, such as the system prompt
+/// explaining tool use, with room to spare
+const HIDDEN_TOKENS: u64 = 1_000;
+
+/// The tokens needed and the context window from the API's overflow errors
+///
+/// The API reports `prompt is too long: 208310 tokens > 200000 maximum` or
+/// `input length and `max_tokens` exceed context limit: 198000 + 4096 > 200000, ...`.
+pub(crate) fn context_overflow(message: &str) -> Option<(u64, u64)> {
+    let numbers = |text: &str| -> Vec<u64> {
+        text.split(|c: char| !c.is_ascii_digit())
+            .filter_map(|number| number.parse().ok())
+            .collect()
+    };
+
+    if let Some(rest) = message.strip_prefix("prompt is too long:") {
+        match numbers(rest)[..] {
+            [have, limit, ..] => Some((have, limit)),
+            _ => None,
+        }
+    } else if let Some(rest) = message.split("exceed context limit:").nth(1) {
+        match numbers(rest)[..] {
+            [input, max_tokens, limit, ..] => Some((input + max_tokens, limit)),
+            _ => None,
+        }
+    } else {
+        None
+    }
+}
+
+/// An approximate token count with the range the true count is expected in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TokenEstimate {
+    pub tokens: u64,
+    pub low: u64,
+    pub high: u64,
+}
+
+/// How Claude's token counts compare to `cl100k_base`, as (estimate, low, high) factors
+///
+/// Claude's tokenizer is not published. It tends to split text into somewhat
+/// more tokens than `cl100k_base`, so the estimate scales that count up and the
+/// bounds allow for prose using fewer (code, plain English) or more
+/// (non-Latin scripts, unusual formatting) tokens than the middle estimate.
+#[cfg(feature = "estimate")]
+const CLAUDE_FACTORS: (f64, f64, f64) = (1.15, 0.95, 1.5);
+
+/// Estimate the tokens `text` takes for `model` without calling the API
+///
+/// Claude models are estimated from a `cl100k_base` count scaled by a factor;
+/// the true count is expected between `low` and `high` but is not guaranteed
+/// to fall there. Other models are counted with `cl100k_base` as is. Use
+/// [`Client::count_tokens`] when the exact count matters, e.g. close to the
+/// context window.
+#[cfg(feature = "estimate")]
+pub fn estimate_tokens(text: &str, model: &str) -> TokenEstimate {
+    let base = tiktoken_rs::cl100k_base_singleton()
+        .encode_with_special_tokens(text)
+        .len() as f64;
+    let (estimate, low, high) = if model.starts_with("claude-") {
+        CLAUDE_FACTORS
+    } else {
+        (1.0, 1.0, 1.0)
+    };
+
+    TokenEstimate {
+        tokens: (base * estimate).ceil() as u64,
+        low: (base * low).floor() as u64,
+        high: (base * high).ceil() as u64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::{http_stub, stub_client};
+    use crate::config::Config;
+
+    #[tokio::test]
+    async fn test_count_tokens() {
+        let (url, requests) = http_stub(vec![Some((200, r#"{"input_tokens":14}"#))]).await;
+        let client = stub_client(url);
+
+        let count = client
+            .count_tokens(Messages::new().push_user("hi".into()).clone(), None)
+            .await
+            .unwrap();
+        assert_eq!(count, 14);
+
+        let request = &requests.lock().unwrap()[0];
+        assert!(request.starts_with("post /messages/count_tokens "));
+        assert!(!request.contains("max_tokens"));
+    }
+
+    /// A prompt close enough to the context window to be counted before sending
+    fn long_prompt() -> String {
+        "word ".repeat(30_000)
+    }
+
+    #[tokio::test]
+    async fn test_context_too_large() {
+        let (url, requests) = http_stub(vec![Some((200, r#"{"input_tokens":150000}"#))]).await;
+        let mut config = Config::new("sk-ant-test-key").with_max_tokens(64_000);
+        config.base_url = url;
+        let client = Client::new(config).unwrap();
+
+        let error = client
+            .send_message(Messages::new().push_user(long_prompt()).clone(), None, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ClaudeError::ContextTooLarge {
+                have: 214_000,
+                limit: 200_000
+            }
+        ));
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].starts_with("post /messages/count_tokens "));
+    }
+
+    #[tokio::test]
+    async fn test_auto_max_tokens() {
+        const REPLY: &str = r#"{"id":"msg_1","type":"message","role":"assistant","model":"claude-sonnet-4-5","content":[{"type":"text","text":"hi"}],"stop_reason":"end_turn","usage":{"input_tokens":150000,"output_tokens":1}}"#;
+        let (url, requests) = http_stub(vec![
+            Some((200, r#"{"input_tokens":150000}"#)),
+            Some((200, REPLY)),
+        ])
+        .await;
+        let mut config = Config::new("sk-ant-test-key").with_auto_max_tokens();
+        config.base_url = url;
+        let client = Client::new(config).unwrap();
+
+        client
+            .send_message(Messages::new().push_user(long_prompt()).clone(), None, None)
+            .await
+            .unwrap();
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].starts_with("post /messages/count_tokens "));
+        // What the window leaves, below Sonnet's 64k output limit
+        assert!(requests[1].contains(r#""max_tokens":50000"#));
+    }
+
+    #[test]
+    fn test_context_overflow() {
+        assert_eq!(
+            context_overflow("prompt is too long: 208310 tokens > 200000 maximum"),
+            Some((208_310, 200_000))
+        );
+        assert_eq!(
+            context_overflow("input length and `max_tokens` exceed context limit: 198000 + 4096 > 200000, decrease input length or `max_tokens` and try again"),
+            Some((202_096, 200_000))
+        );
+        assert_eq!(context_overflow("max_tokens: too large"), None);
+    }
+
+    #[cfg(feature = "estimate")]
+    #[test]
+    fn test_estimate_tokens() {
+        let text = "The quick brown fox jumps over the lazy dog. ".repeat(20);
+        let estimate = estimate_tokens(&text, "claude-sonnet-4-5");
+        assert!(estimate.low <= estimate.tokens && estimate.tokens <= estimate.high);
+        // About ten words per sentence
+        assert!((150..=400).contains(&estimate.tokens), "{:?}", estimate);
+
+        let exact = estimate_tokens(&text, "gpt-4");
+        assert_eq!(exact.low, exact.high);
+        assert_eq!(estimate_tokens("", "claude-haiku-4-5").tokens, 0);
+    }
+}
@@ -0,0 +1,437 @@
+//! Tools the model can call
+//!
+//! A [`Tool`] describes a function to the model by name, description and the
+//! JSON schema of its input. When the model decides to call one, the response
+//! contains a [`ContentBlock::ToolUse`](crate::ContentBlock::ToolUse) with
+//! the input it chose.
+//!
+//! [`Client::run_with_tools`] drives the whole exchange: it passes each call
+//! to a [`ToolExecutor`], sends the results back as `tool_result` blocks and
+//! repeats until the model gives its final answer.
+
+use crate::client::{Client, Content, ContentBlock, Message, Messages, StopReason, Usage};
+use crate::error::{ClaudeError, Result};
+use futures::stream::{self, StreamExt};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::time::Duration;
+
+/// A tool definition sent with a request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tool {
+    pub name: String,
+    /// What the tool does and when to use it, for the model
+    pub description: String,
+    /// JSON schema of the tool's input object
+    pub input_schema: serde_json::Value,
+}
+
+impl Tool {
+    pub fn new(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        input_schema: serde_json::Value,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            input_schema,
+        }
+    }
+
+    /// A tool whose input is described by the schema of `T`
+    pub fn for_input<T: JsonSchema>(
+        name: impl Into<String>,
+        description: impl Into<String>,
+    ) -> Self {
+        let schema = serde_json::to_value(schemars::schema_for!(T))
+            .expect("generated JSON schemas serialize");
+        Self::new(name, description, schema)
+    }
+}
+
+/// Whether and which tool the model must call
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolChoice {
+    /// The model decides, the default
+    Auto,
+    /// The model must call one of the tools
+    Any,
+    /// The model must not call any tool
+    None,
+    /// The model must call the named tool
+    Tool { name: String },
+}
+
+impl ToolChoice {
+    /// Call the named tool, e.g. to extract structured data with its schema
+    pub fn tool(name: impl Into<String>) -> Self {
+        ToolChoice::Tool { name: name.into() }
+    }
+
+    pub(crate) fn forces_call(&self) -> bool {
+        matches!(self, ToolChoice::Any | ToolChoice::Tool { .. })
+    }
+}
+
+/// A call the model made to one of the tools
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    pub input: serde_json::Value,
+}
+
+/// Runs the tool calls of [`Client::run_with_tools`]
+///
+/// An `Err` is reported to the model as a failed call, which it can react to,
+/// rather than ending the loop. Any `Fn(ToolCall)` returning a future of
+/// `Result<String, String>` is an executor.
+pub trait ToolExecutor: Send + Sync {
+    fn execute(
+        &self,
+        call: ToolCall,
+    ) -> impl Future<Output = std::result::Result<String, String>> + Send;
+}
+
+impl<F, Fut> ToolExecutor for F
+where
+    F: Fn(ToolCall) -> Fut + Send + Sync,
+    Fut: Future<Output = std::result::Result<String, String>> + Send,
+{
+    fn execute(
+        &self,
+        call: ToolCall,
+    ) -> impl Future<Output = std::result::Result<String, String>> + Send {
+        self(call)
+    }
+}
+
+/// Limits for [`Client::run_with_tools`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolLoopOptions {
+    /// Requests to make before giving up on a final answer
+    pub max_rounds: u32,
+    /// How long a single tool call may run before it is reported as failed
+    pub tool_timeout: Option<Duration>,
+    /// Calls from one response that may run at the same time; 1 runs them in turn
+    pub max_concurrent_tools: usize,
+    /// Tool choice for the first request; later ones leave it to the model
+    ///
+    /// Forcing a call on every request would never let the model answer.
+    pub tool_choice: Option<ToolChoice>,
+}
+
+impl Default for ToolLoopOptions {
+    fn default() -> Self {
+        Self {
+            max_rounds: 10,
+            tool_timeout: None,
+            max_concurrent_tools: 4,
+            tool_choice: None,
+        }
+    }
+}
+
+/// The outcome of [`Client::run_with_tools`]
+#[derive(Debug, Clone)]
+pub struct ToolRun {
+    /// The text of the final answer
+    pub text: String,
+    /// The conversation including every tool call and result, to continue it
+    pub messages: Messages,
+    /// Requests made
+    pub rounds: u32,
+    /// Tokens used over all rounds
+    pub usage: Usage,
+}
+
+impl Client {
+    /// Send messages with tools, running the calls the model makes until it answers
+    pub async fn run_with_tools<E: ToolExecutor>(
+        &self,
+        mut messages: Messages,
+        system: Option<String>,
+        tools: &[Tool],
+        executor: &E,
+        options: &ToolLoopOptions,
+    ) -> Result<ToolRun> {
+        let mut usage = Usage::default();
+        let result = self
+            .tool_rounds(&mut messages, system, tools, executor, options, &mut usage)
+            .await;
+        self.set_last_usage(usage.clone());
+
+        let (text, rounds) = result?;
+        Ok(ToolRun {
+            text,
+            messages,
+            rounds,
+            usage,
+        })
+    }
+
+    /// The tool loop, returning the final text and the number of requests made
+    async fn tool_rounds<E: ToolExecutor>(
+        &self,
+        messages: &mut Messages,
+        system: Option<String>,
+        tools: &[Tool],
+        executor: &E,
+        options: &ToolLoopOptions,
+        usage: &mut Usage,
+    ) -> Result<(String, u32)> {
+        for round in 1..=options.max_rounds {
+            let mut request = self.message_request(messages.clone(), None, system.clone())?;
+            let tool_choice = if round == 1 {
+                options.tool_choice.clone()
+            } else {
+                None
+            };
+            request.set_tools(tools.to_vec(), tool_choice)?;
+            let info = self.start_request(&request.model, false);
+
+            let response = match self
+                .post_message(&request, info.idempotency_key.clone())
+                .await
+            {
+                Ok(response) => response,
+                Err(error) => {
+                    self.notify(|observer| observer.on_error(&info, &error));
+                    return Err(error);
+                }
+            };
+            *usage += &response.usage;
+
+            let text: String = response
+                .content
+                .iter()
+                .filter_map(|block| match block {
+                    ContentBlock::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect();
+            let text = self.finish_output(text)?;
+            self.observe_response(
+                &info,
+                &response,
+                Some(text.as_str()).filter(|text| !text.is_empty()),
+            );
+
+            let calls: Vec<ToolCall> = response
+                .content
+                .iter()
+                .filter_map(|block| match block {
+                    ContentBlock::ToolUse { id, name, input } => Some(ToolCall {
+                        id: id.clone(),
+                        name: name.clone(),
+                        input: input.clone(),
+                    }),
+                    _ => None,
+                })
+                .collect();
+            messages.push(Message {
+                role: "assistant".into(),
+                content: Content::Blocks(response.content),
+            });
+
+            if response.stop_reason != Some(StopReason::ToolUse) || calls.is_empty() {
+                return Ok((text, round));
+            }
+
+            // Results must be in the order of the calls, whichever finishes first
+            let results = stream::iter(calls)
+                .map(|call| async move {
+                    let tool_use_id = call.id.clone();
+                    let outcome = run_tool(executor, call, options.tool_timeout).await;
+                    let is_error = outcome.is_err();
+                    ContentBlock::ToolResult {
+                        tool_use_id,
+                        content: outcome.unwrap_or_else(|error| error),
+                        is_error,
+                    }
+                })
+                .buffered(options.max_concurrent_tools.max(1))
+                .collect()
+                .await;
+            messages.push(Message {
+                role: "user".into(),
+                content: Content::Blocks(results),
+            });
+        }
+
+        Err(ClaudeError::Tool(format!(
+            "no final answer after {} rounds",
+            options.max_rounds
+        )))
+    }
+}
+
+async fn run_tool<E: ToolExecutor>(
+    executor: &E,
+    call: ToolCall,
+    timeout: Option<Duration>,
+) -> std::result::Result<String, String> {
+    let name = call.name.clone();
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, executor.execute(call))
+            .await
+            .unwrap_or_else(|_| Err(format!("{} timed out after {:?}", name, timeout))),
+        None => executor.execute(call).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::{http_stub, stub_client};
+
+    #[derive(JsonSchema)]
+    #[allow(dead_code)]
+    struct Weather {
+        city: String,
+    }
+
+    #[test]
+    fn test_tool_for_input() {
+        let tool = Tool::for_input::<Weather>("get_weather", "Current weather in a city");
+        let json = serde_json::to_value(&tool).unwrap();
+
+        assert_eq!(json["name"], "get_weather");
+        assert_eq!(json["input_schema"]["type"], "object");
+        assert_eq!(json["input_schema"]["required"][0], "city");
+    }
+
+    #[test]
+    fn test_tool_choice_serialization() {
+        assert_eq!(
+            serde_json::to_value(ToolChoice::Any).unwrap(),
+            serde_json::json!({"type": "any"})
+        );
+        assert_eq!(
+            serde_json::to_value(ToolChoice::tool("get_weather")).unwrap(),
+            serde_json::json!({"type": "tool", "name": "get_weather"})
+        );
+    }
+
+    const TOOL_USE: &str = r#"{"id":"msg_1","type":"message","role":"assistant","model":"claude-haiku-4-5","content":[{"type":"text","text":"Let me check."},{"type":"tool_use","id":"toolu_1","name":"get_weather","input":{"city":"Paris"}}],"stop_reason":"tool_use","usage":{"input_tokens":1,"output_tokens":1}}"#;
+    const ANSWER: &str = r#"{"id":"msg_2","type":"message","role":"assistant","model":"claude-haiku-4-5","content":[{"type":"text","text":"Sunny."}],"stop_reason":"end_turn","usage":{"input_tokens":1,"output_tokens":1}}"#;
+
+    fn question() -> Messages {
+        Messages::new()
+            .push_user("Weather in Paris?".into())
+            .clone()
+    }
+
+    #[tokio::test]
+    async fn test_run_with_tools() {
+        let (url, requests) = http_stub(vec![Some((200, TOOL_USE)), Some((200, ANSWER))]).await;
+        let client = stub_client(url);
+        let tools = [Tool::for_input::<Weather>("get_weather", "Current weather")];
+        let executor = |call: ToolCall| async move {
+            assert_eq!(call.input["city"], "Paris");
+            Ok("sunny, 22C".to_string())
+        };
+        let options = ToolLoopOptions {
+            tool_choice: Some(ToolChoice::tool("get_weather")),
+            ..Default::default()
+        };
+
+        let run = client
+            .run_with_tools(question(), None, &tools, &executor, &options)
+            .await
+            .unwrap();
+        assert_eq!(run.text, "Sunny.");
+        assert_eq!(run.rounds, 2);
+        assert_eq!(run.usage.output_tokens, 2);
+        assert_eq!(client.last_usage(), Some(run.usage.clone()));
+        // Question, tool call, tool result and answer
+        assert_eq!(run.messages.len(), 4);
+
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].contains("\"tools\""));
+        assert!(requests[0].contains("\"tool_choice\""));
+        assert!(!requests[1].contains("\"tool_choice\""));
+        assert!(requests[1].contains("\"tool_result\""));
+        assert!(requests[1].contains("sunny, 22c"));
+    }
+
+    #[tokio::test]
+    async fn test_tool_timeout_is_reported_to_the_model() {
+        let (url, requests) = http_stub(vec![Some((200, TOOL_USE)), Some((200, ANSWER))]).await;
+        let client = stub_client(url);
+        let executor = |_call: ToolCall| async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(String::new())
+        };
+        let options = ToolLoopOptions {
+            tool_timeout: Some(Duration::from_millis(10)),
+            ..Default::default()
+        };
+
+        let run = client
+            .run_with_tools(question(), None, &[], &executor, &options)
+            .await
+            .unwrap();
+        assert_eq!(run.text, "Sunny.");
+        let requests = requests.lock().unwrap();
+        assert!(requests[1].contains("timed out"));
+        assert!(requests[1].contains("\"is_error\":true"));
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_run_concurrently_in_order() {
+        const TWO_CALLS: &str = r#"{"id":"msg_1","type":"message","role":"assistant","model":"claude-haiku-4-5","content":[{"type":"tool_use","id":"toolu_slow","name":"wait","input":{"ms":200}},{"type":"tool_use","id":"toolu_fast","name":"wait","input":{"ms":0}}],"stop_reason":"tool_use","usage":{"input_tokens":1,"output_tokens":1}}"#;
+        let (url, _) = http_stub(vec![Some((200, TWO_CALLS)), Some((200, ANSWER))]).await;
+        let client = stub_client(url);
+        let finished = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let executor = |call: ToolCall| {
+            let finished = finished.clone();
+            async move {
+                let ms = call.input["ms"].as_u64().unwrap();
+                tokio::time::sleep(Duration::from_millis(ms)).await;
+                finished.lock().unwrap().push(call.id.clone());
+                Ok(call.id)
+            }
+        };
+
+        let run = client
+            .run_with_tools(question(), None, &[], &executor, &Default::default())
+            .await
+            .unwrap();
+        // The fast call wasn't held up by the slow one...
+        assert_eq!(*finished.lock().unwrap(), vec!["toolu_fast", "toolu_slow"]);
+        // ...but the results follow the order of the calls
+        let messages: Vec<Message> = run.messages.into();
+        let Content::Blocks(results) = &messages[2].content else {
+            panic!("expected tool results");
+        };
+        let ids: Vec<&str> = results
+            .iter()
+            .map(|block| match block {
+                ContentBlock::ToolResult { tool_use_id, .. } => tool_use_id.as_str(),
+                _ => panic!("expected a tool result"),
+            })
+            .collect();
+        assert_eq!(ids, vec!["toolu_slow", "toolu_fast"]);
+    }
+
+    #[tokio::test]
+    async fn test_max_rounds() {
+        let (url, _) = http_stub(vec![Some((200, TOOL_USE))]).await;
+        let client = stub_client(url);
+        let executor = |_call: ToolCall| async { Err("unavailable".to_string()) };
+        let options = ToolLoopOptions {
+            max_rounds: 1,
+            ..Default::default()
+        };
+
+        let error = client
+            .run_with_tools(question(), None, &[], &executor, &options)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ClaudeError::Tool(_)));
+    }
+}
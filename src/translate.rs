@@ -0,0 +1,115 @@
+use crate::client::{Client, Messages};
+use crate::error::{ClaudeError, Result};
+use regex::Regex;
+
+/// Matches spans that must survive translation verbatim: fenced code blocks,
+/// inline code and common placeholder syntaxes (`{{var}}`, `{name}`, `%s`, `%(name)s`)
+const PROTECTED_PATTERN: &str =
+    r"(?s)```.*?```|`[^`\n]+`|\{\{[^{}]*\}\}|\{[A-Za-z0-9_.]*\}|%\([A-Za-z0-9_]+\)[sdif]|%[sdif]";
+
+/// Text with its protected spans replaced by numbered markers
+#[derive(Debug)]
+struct Masked {
+    text: String,
+    spans: Vec<String>,
+}
+
+fn marker(index: usize) -> String {
+    format!("⟦{}⟧", index)
+}
+
+fn mask(text: &str) -> Masked {
+    let regex = Regex::new(PROTECTED_PATTERN).expect("valid regex");
+    let mut spans = Vec::new();
+    let masked = regex.replace_all(text, |captures: &regex::Captures| {
+        spans.push(captures[0].to_string());
+        marker(spans.len() - 1)
+    });
+
+    Masked {
+        text: masked.into_owned(),
+        spans,
+    }
+}
+
+fn unmask(text: &str, spans: &[String]) -> Result<String> {
+    let mut restored = text.to_string();
+    for (index, span) in spans.iter().enumerate() {
+        let marker = marker(index);
+        if !restored.contains(&marker) {
+            return Err(ClaudeError::Translate(format!(
+                "translation dropped protected text {:?}",
+                span
+            )));
+        }
+        restored = restored.replace(&marker, span);
+    }
+    Ok(restored)
+}
+
+impl Client {
+    /// Translate text into the target language
+    ///
+    /// The source language is detected automatically unless `source` is given.
+    /// Code blocks, inline code and placeholders are masked before sending and
+    /// restored afterwards, so they come back byte-for-byte unchanged.
+    pub async fn translate(
+        &self,
+        text: &str,
+        target: &str,
+        source: Option<&str>,
+    ) -> Result<String> {
+        let masked = mask(text);
+
+        let source = match source {
+            Some(source) => format!("The text is written in {}.", source),
+            None => "Detect the language of the text automatically.".to_string(),
+        };
+        let system = format!(
+            "Translate the user's text into {target}. {source} \
+             Respond with only the translation, without commentary or quotes. \
+             Keep the original formatting and line breaks. \
+             Tokens of the form ⟦0⟧, ⟦1⟧, ... are placeholders: copy each one \
+             into the translation exactly once, unchanged, at the appropriate position."
+        );
+
+        let response = self
+            .send_message(
+                Messages::new().push_user(masked.text).clone(),
+                None,
+                Some(system),
+            )
+            .await?;
+
+        unmask(&response, &masked.spans)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask_placeholders_and_code() {
+        let text = "Hello {name}, you have %d messages.\n```sh\nls -l\n```\nRun `make`.";
+        let masked = mask(text);
+        assert_eq!(
+            masked.text,
+            "Hello ⟦0⟧, you have ⟦1⟧ messages.\n⟦2⟧\nRun ⟦3⟧."
+        );
+        assert_eq!(masked.spans[2], "```sh\nls -l\n```");
+    }
+
+    #[test]
+    fn test_unmask_roundtrip() {
+        let text = "Bonjour {{user}}, voir `README`.";
+        let masked = mask(text);
+        assert_eq!(unmask(&masked.text, &masked.spans).unwrap(), text);
+    }
+
+    #[test]
+    fn test_unmask_missing_marker() {
+        let spans = vec!["{name}".to_string()];
+        assert!(unmask("Hallo", &spans).is_err());
+    }
+}
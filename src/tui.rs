@@ -0,0 +1,565 @@
+//! Full-screen chat with `ellm tui`
+//!
+//! A conversation pane shows the current session and streams responses into
+//! it, with the sessions of the run in a sidebar and the model and token
+//! counts in a status bar. Terminal events are read on a thread of their own
+//! and handled alongside the response stream, so the interface stays
+//! responsive and a response can be stopped while it arrives.
+
+use anyhow::Result;
+use base64::prelude::*;
+use ellm::{Client, MessageStream, Messages, StopReason, StreamEvent, StreamedMessage, Usage};
+use ratatui::crossterm::event::{
+    self, DisableBracketedPaste, EnableBracketedPaste, Event, KeyCode, KeyEvent, KeyEventKind,
+    KeyModifiers,
+};
+use ratatui::crossterm::execute;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::io::Write;
+use tokio::sync::mpsc;
+
+/// Lines scrolled by Page Up and Page Down
+const SCROLL_LINES: usize = 10;
+
+/// Characters of the first message shown as a session's title
+const TITLE_CHARS: usize = 20;
+
+const HINTS: &str =
+    "Enter send · ^R regenerate · ^Y copy · ^N new · Tab switch · PgUp/PgDn scroll · ^Q quit";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Turn {
+    role: Role,
+    text: String,
+}
+
+/// A conversation, kept for the rest of the run
+#[derive(Debug, Default)]
+struct Session {
+    turns: Vec<Turn>,
+    usage: Usage,
+}
+
+impl Session {
+    /// The start of the first message, or a placeholder before there is one
+    fn title(&self) -> String {
+        let Some(first) = self.turns.first() else {
+            return "New chat".to_string();
+        };
+        let line = first.text.lines().next().unwrap_or_default().trim();
+        match line.char_indices().nth(TITLE_CHARS) {
+            Some((end, _)) => format!("{}…", &line[..end]),
+            None => line.to_string(),
+        }
+    }
+}
+
+/// What the event loop should do after a terminal event
+#[derive(Debug, PartialEq)]
+enum Action {
+    None,
+    /// Ask for a response to the current session
+    Send,
+    /// Abandon the response being streamed
+    Stop,
+    /// Put the text on the clipboard
+    Copy(String),
+    Quit,
+}
+
+#[derive(Debug)]
+struct App {
+    sessions: Vec<Session>,
+    current: usize,
+    input: String,
+    streaming: bool,
+    /// Lines scrolled up from the end of the conversation
+    scroll_back: usize,
+    /// Shown in the status bar instead of the key hints until the next action
+    status: Option<String>,
+}
+
+impl Default for App {
+    fn default() -> Self {
+        Self {
+            sessions: vec![Session::default()],
+            current: 0,
+            input: String::new(),
+            streaming: false,
+            scroll_back: 0,
+            status: None,
+        }
+    }
+}
+
+impl App {
+    fn session(&self) -> &Session {
+        &self.sessions[self.current]
+    }
+
+    fn session_mut(&mut self) -> &mut Session {
+        &mut self.sessions[self.current]
+    }
+
+    fn handle(&mut self, event: Event) -> Action {
+        match event {
+            Event::Key(key) if key.kind == KeyEventKind::Press => self.handle_key(key),
+            Event::Paste(text) => {
+                self.input.push_str(&text);
+                Action::None
+            }
+            _ => Action::None,
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Action {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let idle = !self.streaming;
+        if idle {
+            self.status = None;
+        }
+        match key.code {
+            KeyCode::Char('c' | 'q') if ctrl => Action::Quit,
+            KeyCode::Esc if self.streaming => Action::Stop,
+            KeyCode::Char('n') if ctrl && idle => {
+                if !self.session().turns.is_empty() {
+                    self.sessions.push(Session::default());
+                    self.current = self.sessions.len() - 1;
+                    self.scroll_back = 0;
+                }
+                Action::None
+            }
+            KeyCode::Char('r') if ctrl && idle => {
+                let turns = &mut self.session_mut().turns;
+                if turns
+                    .last()
+                    .is_some_and(|turn| turn.role == Role::Assistant)
+                {
+                    turns.pop();
+                }
+                match turns.last() {
+                    Some(turn) if turn.role == Role::User => {
+                        self.scroll_back = 0;
+                        Action::Send
+                    }
+                    _ => Action::None,
+                }
+            }
+            KeyCode::Char('y') if ctrl => {
+                let reply = self
+                    .session()
+                    .turns
+                    .iter()
+                    .rev()
+                    .find(|turn| turn.role == Role::Assistant && !turn.text.is_empty());
+                match reply {
+                    Some(reply) => {
+                        let text = reply.text.clone();
+                        self.status = Some("Copied the last response".into());
+                        Action::Copy(text)
+                    }
+                    None => Action::None,
+                }
+            }
+            KeyCode::Tab | KeyCode::BackTab if idle => {
+                let count = self.sessions.len();
+                self.current = match key.code {
+                    KeyCode::Tab => (self.current + 1) % count,
+                    _ => (self.current + count - 1) % count,
+                };
+                self.scroll_back = 0;
+                Action::None
+            }
+            KeyCode::PageUp => {
+                self.scroll_back += SCROLL_LINES;
+                Action::None
+            }
+            KeyCode::PageDown => {
+                self.scroll_back = self.scroll_back.saturating_sub(SCROLL_LINES);
+                Action::None
+            }
+            KeyCode::End => {
+                self.scroll_back = 0;
+                Action::None
+            }
+            KeyCode::Enter if idle && !self.input.trim().is_empty() => {
+                let text = std::mem::take(&mut self.input);
+                self.session_mut().turns.push(Turn {
+                    role: Role::User,
+                    text,
+                });
+                self.scroll_back = 0;
+                Action::Send
+            }
+            KeyCode::Backspace => {
+                self.input.pop();
+                Action::None
+            }
+            KeyCode::Char(c) if !ctrl => {
+                self.input.push(c);
+                Action::None
+            }
+            _ => Action::None,
+        }
+    }
+
+    /// The current session as a request
+    fn messages(&self) -> Messages {
+        let mut messages = Messages::new();
+        for turn in &self.session().turns {
+            match turn.role {
+                Role::User => messages.push_user(turn.text.clone()),
+                Role::Assistant => messages.push_assistant(turn.text.clone()),
+            };
+        }
+        messages
+    }
+
+    fn start_response(&mut self) {
+        self.session_mut().turns.push(Turn {
+            role: Role::Assistant,
+            text: String::new(),
+        });
+        self.streaming = true;
+        self.status = None;
+    }
+
+    fn append(&mut self, text: &str) {
+        if let Some(turn) = self.session_mut().turns.last_mut() {
+            turn.text.push_str(text);
+        }
+    }
+
+    /// End the response with the complete message, whose text may have been
+    /// changed from what was streamed, e.g. by output guardrails
+    fn finish(&mut self, message: &StreamedMessage) {
+        let session = self.session_mut();
+        if let Some(turn) = session.turns.last_mut() {
+            turn.text = message.text();
+        }
+        session.usage += &message.usage;
+        self.streaming = false;
+        if message.stop_reason == Some(StopReason::MaxTokens) {
+            self.status = Some("The response was cut off at max_tokens".into());
+        }
+    }
+
+    /// End the response early, keeping what arrived of it
+    fn stop(&mut self, status: String) {
+        let turns = &mut self.session_mut().turns;
+        if turns
+            .last()
+            .is_some_and(|turn| turn.role == Role::Assistant && turn.text.is_empty())
+        {
+            turns.pop();
+        }
+        self.streaming = false;
+        self.status = Some(status);
+    }
+
+    fn draw(&self, frame: &mut Frame, model: &str) {
+        let [sidebar, main] =
+            Layout::horizontal([Constraint::Length(26), Constraint::Min(20)]).areas(frame.area());
+        let [conversation, input, status] = Layout::vertical([
+            Constraint::Min(3),
+            Constraint::Length(3),
+            Constraint::Length(1),
+        ])
+        .areas(main);
+
+        let items: Vec<ListItem> = self
+            .sessions
+            .iter()
+            .map(|session| ListItem::new(session.title()))
+            .collect();
+        let mut selected = ListState::default().with_selected(Some(self.current));
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::bordered().title(" Sessions "))
+                .highlight_style(Style::new().reversed()),
+            sidebar,
+            &mut selected,
+        );
+
+        let mut lines = Vec::new();
+        for turn in &self.session().turns {
+            let (name, color) = match turn.role {
+                Role::User => ("You", Color::Cyan),
+                Role::Assistant => ("Claude", Color::Green),
+            };
+            lines.push(Line::styled(
+                name,
+                Style::new().fg(color).add_modifier(Modifier::BOLD),
+            ));
+            lines.extend(turn.text.lines().map(|line| Line::raw(line.to_string())));
+            lines.push(Line::default());
+        }
+        let block = Block::bordered();
+        let inner = block.inner(conversation);
+        frame.render_widget(block, conversation);
+        let paragraph = Paragraph::new(lines).wrap(Wrap { trim: false });
+        let hidden = paragraph
+            .line_count(inner.width)
+            .saturating_sub(inner.height as usize);
+        let top = hidden.saturating_sub(self.scroll_back);
+        frame.render_widget(paragraph.scroll((top as u16, 0)), inner);
+
+        // Keep the end of the input, where the cursor is, in view
+        let width = input.width.saturating_sub(3) as usize;
+        let chars: Vec<char> = self.input.replace('\n', "↵").chars().collect();
+        let visible: String = chars[chars.len().saturating_sub(width)..].iter().collect();
+        let cursor = visible.chars().count() as u16;
+        frame.render_widget(
+            Paragraph::new(visible).block(Block::bordered().title(" Message ")),
+            input,
+        );
+        if !self.streaming {
+            frame.set_cursor_position((input.x + 1 + cursor, input.y + 1));
+        }
+
+        let usage = &self.session().usage;
+        let state = match (&self.status, self.streaming) {
+            (_, true) => "Streaming… Esc to stop".to_string(),
+            (Some(status), false) => status.clone(),
+            (None, false) => HINTS.to_string(),
+        };
+        frame.render_widget(
+            Line::from(vec![
+                Span::styled(format!(" {} ", model), Style::new().reversed()),
+                Span::raw(format!(
+                    " {} in / {} out │ ",
+                    usage.input_tokens + usage.cache_read_input_tokens,
+                    usage.output_tokens
+                )),
+                Span::raw(state),
+            ]),
+            status,
+        );
+    }
+}
+
+/// Run the chat interface until it's quit
+pub async fn run(client: Client, system: Option<String>) -> Result<()> {
+    let mut terminal = ratatui::init();
+    let result = match execute!(std::io::stdout(), EnableBracketedPaste) {
+        Ok(()) => chat(&mut terminal, &client, system).await,
+        Err(error) => Err(error.into()),
+    };
+    let _ = execute!(std::io::stdout(), DisableBracketedPaste);
+    ratatui::restore();
+    result
+}
+
+async fn chat(
+    terminal: &mut DefaultTerminal,
+    client: &Client,
+    system: Option<String>,
+) -> Result<()> {
+    let (sender, mut events) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if sender.send(event).is_err() {
+                break;
+            }
+        }
+    });
+
+    let model = client.config().model.clone();
+    let mut app = App::default();
+    let mut stream: Option<MessageStream<'_>> = None;
+    loop {
+        terminal.draw(|frame| app.draw(frame, &model))?;
+        tokio::select! {
+            event = events.recv() => {
+                let Some(event) = event else { break };
+                match app.handle(event) {
+                    Action::None => {}
+                    Action::Quit => break,
+                    Action::Stop => {
+                        // Dropping the stream closes the connection, ending the request
+                        stream = None;
+                        app.stop("Stopped".into());
+                    }
+                    Action::Copy(text) => copy(&text)?,
+                    Action::Send => {
+                        let messages = app.messages();
+                        app.start_response();
+                        terminal.draw(|frame| app.draw(frame, &model))?;
+                        match client
+                            .stream_message(messages, system.clone(), Vec::new(), None)
+                            .await
+                        {
+                            Ok(started) => stream = Some(started),
+                            Err(error) => app.stop(format!("Error: {} (^R to retry)", error)),
+                        }
+                    }
+                }
+            }
+            event = next_event(&mut stream) => match event {
+                Some(Ok(StreamEvent::TextDelta { text, .. })) => app.append(&text),
+                Some(Ok(_)) => {}
+                Some(Err(error)) => {
+                    stream = None;
+                    app.stop(format!("Error: {} (^R to retry)", error));
+                }
+                None => {
+                    if let Some(done) = stream.take() {
+                        app.finish(&done.message());
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The next event of the response being streamed, if there is one
+async fn next_event(stream: &mut Option<MessageStream<'_>>) -> Option<ellm::Result<StreamEvent>> {
+    match stream {
+        Some(stream) => stream.next_event().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Put `text` on the clipboard with an OSC 52 escape, which works over SSH too
+fn copy(text: &str) -> Result<()> {
+    let mut stdout = std::io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", BASE64_STANDARD.encode(text))?;
+    stdout.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> Event {
+        Event::Key(KeyEvent::new(code, modifiers))
+    }
+
+    fn type_text(app: &mut App, text: &str) {
+        for c in text.chars() {
+            app.handle(key(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+    }
+
+    fn reply(app: &mut App, text: &str) {
+        app.start_response();
+        app.append(text);
+        app.finish(&StreamedMessage {
+            content: vec![ellm::ContentBlock::Text { text: text.into() }],
+            ..StreamedMessage::default()
+        });
+    }
+
+    #[test]
+    fn test_send_and_regenerate() {
+        let mut app = App::default();
+        assert_eq!(
+            app.handle(key(KeyCode::Enter, KeyModifiers::NONE)),
+            Action::None
+        );
+
+        type_text(&mut app, "Hi there");
+        assert_eq!(
+            app.handle(key(KeyCode::Enter, KeyModifiers::NONE)),
+            Action::Send
+        );
+        assert!(app.input.is_empty());
+        reply(&mut app, "Hello!");
+        assert_eq!(app.session().turns.len(), 2);
+
+        assert_eq!(
+            app.handle(key(KeyCode::Char('y'), KeyModifiers::CONTROL)),
+            Action::Copy("Hello!".into())
+        );
+        // Regenerating drops the last response and asks again
+        assert_eq!(
+            app.handle(key(KeyCode::Char('r'), KeyModifiers::CONTROL)),
+            Action::Send
+        );
+        assert_eq!(app.session().turns.len(), 1);
+        let messages: Vec<ellm::Message> = app.messages().into();
+        assert_eq!(messages.len(), 1);
+    }
+
+    #[test]
+    fn test_stop_keeps_partial_response() {
+        let mut app = App::default();
+        type_text(&mut app, "Count to ten");
+        app.handle(key(KeyCode::Enter, KeyModifiers::NONE));
+        app.start_response();
+        app.append("One, two");
+        // Typing continues while streaming, but nothing is sent
+        type_text(&mut app, "x");
+        assert_eq!(
+            app.handle(key(KeyCode::Enter, KeyModifiers::NONE)),
+            Action::None
+        );
+        assert_eq!(
+            app.handle(key(KeyCode::Esc, KeyModifiers::NONE)),
+            Action::Stop
+        );
+
+        app.stop("Stopped".into());
+        assert!(!app.streaming);
+        assert_eq!(app.session().turns[1].text, "One, two");
+    }
+
+    #[test]
+    fn test_sessions() {
+        let mut app = App::default();
+        // An empty session isn't duplicated
+        app.handle(key(KeyCode::Char('n'), KeyModifiers::CONTROL));
+        assert_eq!(app.sessions.len(), 1);
+
+        type_text(&mut app, "A question that goes on for a while");
+        app.handle(key(KeyCode::Enter, KeyModifiers::NONE));
+        reply(&mut app, "An answer");
+        app.handle(key(KeyCode::Char('n'), KeyModifiers::CONTROL));
+        assert_eq!((app.sessions.len(), app.current), (2, 1));
+        assert_eq!(app.sessions[0].title(), "A question that goes…");
+        assert_eq!(app.sessions[1].title(), "New chat");
+
+        app.handle(key(KeyCode::Tab, KeyModifiers::NONE));
+        assert_eq!(app.current, 0);
+        app.handle(key(KeyCode::BackTab, KeyModifiers::SHIFT));
+        assert_eq!(app.current, 1);
+    }
+
+    #[test]
+    fn test_draw() {
+        let mut app = App::default();
+        type_text(&mut app, "What is Rust?");
+        app.handle(key(KeyCode::Enter, KeyModifiers::NONE));
+        reply(&mut app, "A systems programming language.");
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 12)).unwrap();
+        terminal
+            .draw(|frame| app.draw(frame, "claude-haiku-4-5"))
+            .unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("What is Rust?"));
+        assert!(screen.contains("A systems programming language."));
+        assert!(screen.contains("claude-haiku-4-5"));
+        assert!(screen.contains("Sessions"));
+    }
+}
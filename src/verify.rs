@@ -0,0 +1,220 @@
+use crate::client::{Client, Messages};
+use crate::critique::review_prompt;
+use crate::error::Result;
+use crate::structured::StructuredRetry;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Most verification questions asked about one answer
+const MAX_QUESTIONS: usize = 5;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct VerificationPlan {
+    /// up to five short, self-contained questions, each checking one factual claim of the response
+    questions: Vec<String>,
+}
+
+/// A verification question and its independently given answer
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VerificationCheck {
+    pub question: String,
+    pub answer: String,
+}
+
+/// The result of [`Client::verify`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Verified {
+    /// The answer that was verified
+    pub draft: String,
+    pub checks: Vec<VerificationCheck>,
+    /// The answer corrected where the checks contradict it
+    pub answer: String,
+}
+
+impl Client {
+    /// Answer `messages`, then check and correct the answer with [`Client::verify`]
+    pub async fn send_verified(
+        &self,
+        messages: Messages,
+        system: Option<String>,
+    ) -> Result<Verified> {
+        let draft = self
+            .send_message(messages.clone(), None, system.clone())
+            .await?;
+        self.verify(messages, system, &draft).await
+    }
+
+    /// Check `draft`, an answer to `messages`, by chain of verification
+    ///
+    /// Verification questions about the draft's claims are planned, then each
+    /// is answered at once in a fresh conversation, without the draft to bias
+    /// it. Finally the draft is corrected in the original conversation where
+    /// those answers contradict it. A draft without checkable claims is kept.
+    pub async fn verify(
+        &self,
+        messages: Messages,
+        system: Option<String>,
+        draft: &str,
+    ) -> Result<Verified> {
+        let plan: VerificationPlan = self
+            .send_structured(
+                Messages::new()
+                    .push_user(review_prompt(
+                        &messages,
+                        draft,
+                        "every factual claim is correct",
+                    )?)
+                    .clone(),
+                Some(
+                    "You fact-check responses. Plan questions that verify the claims of the \
+                     response against the criteria; ask nothing when it makes no claims."
+                        .into(),
+                ),
+                StructuredRetry::default(),
+            )
+            .await?;
+        let questions: Vec<String> = plan
+            .questions
+            .into_iter()
+            .map(|question| question.trim().to_string())
+            .filter(|question| !question.is_empty())
+            .take(MAX_QUESTIONS)
+            .collect();
+        if questions.is_empty() {
+            return Ok(Verified {
+                draft: draft.to_string(),
+                checks: Vec::new(),
+                answer: draft.to_string(),
+            });
+        }
+
+        let answers = futures::future::join_all(questions.iter().map(|question| {
+            self.send_message(
+                Messages::new().push_user(question.clone()).clone(),
+                None,
+                Some("Answer the question briefly and factually.".into()),
+            )
+        }))
+        .await;
+        let checks = questions
+            .into_iter()
+            .zip(answers)
+            .map(|(question, answer)| {
+                Ok(VerificationCheck {
+                    question,
+                    answer: answer?.trim().to_string(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let evidence = checks
+            .iter()
+            .map(|check| {
+                format!(
+                    "<check>\n<question>{}</question>\n<answer>{}</answer>\n</check>",
+                    check.question, check.answer
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut correct = messages;
+        correct.push_assistant(draft.to_string()).push_user(format!(
+            "These questions about your answer were answered independently:\n{}\n\n\
+             Correct your answer where these answers contradict it, keeping the rest. \
+             Reply with the final answer only.",
+            evidence
+        ));
+        let answer = self.send_message(correct, None, system).await?;
+
+        Ok(Verified {
+            draft: draft.to_string(),
+            checks,
+            answer,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::{http_stub, stub_client};
+
+    fn reply(text: &str) -> &'static str {
+        Box::leak(
+            serde_json::json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-haiku-4-5",
+                "content": [{"type": "text", "text": text}],
+                "stop_reason": "end_turn",
+                "usage": {"input_tokens": 1, "output_tokens": 1},
+            })
+            .to_string()
+            .into_boxed_str(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_send_verified() {
+        // Structured output prefills "{", so the plan continues from there
+        let (url, requests) = http_stub(vec![
+            Some((200, reply("Canberra is the capital; Sydney is the largest city."))),
+            Some((
+                200,
+                reply(r#""questions": ["What is the capital of Australia?", " ", "What is the largest city in Australia?"]}"#),
+            )),
+            Some((200, reply("Canberra."))),
+            Some((200, reply("Canberra."))),
+            Some((200, reply("Canberra is the capital."))),
+        ])
+        .await;
+        let client = stub_client(url);
+
+        let verified = client
+            .send_verified(
+                Messages::new()
+                    .push_user("Tell me about Australia's cities".into())
+                    .clone(),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            verified.draft,
+            "Canberra is the capital; Sydney is the largest city."
+        );
+        assert_eq!(verified.checks.len(), 2);
+        assert_eq!(
+            verified.checks[1].question,
+            "What is the largest city in Australia?"
+        );
+        assert_eq!(verified.answer, "Canberra is the capital.");
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 5);
+        // The checks don't see the draft
+        assert!(requests[2..4]
+            .iter()
+            .all(|request| !request.contains("sydney is the largest")));
+        assert!(requests[4].contains("<question>what is the capital of australia?</question>"));
+    }
+
+    #[tokio::test]
+    async fn test_verify_without_claims() {
+        let (url, requests) = http_stub(vec![Some((200, reply(r#""questions": []}"#)))]).await;
+        let client = stub_client(url);
+
+        let verified = client
+            .verify(
+                Messages::new().push_user("Write a haiku".into()).clone(),
+                None,
+                "Old pond, frog jumps in",
+            )
+            .await
+            .unwrap();
+        assert!(verified.checks.is_empty());
+        assert_eq!(verified.answer, "Old pond, frog jumps in");
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,152 @@
+//! Re-running work when files change
+//!
+//! [`FileWatcher`] polls modification times, which works the same on every
+//! platform and filesystem, and [`line_diff`] shows how a response changed
+//! between runs, for `ellm watch`.
+
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// Watches files for being created, modified or removed
+#[derive(Debug, Clone)]
+pub struct FileWatcher {
+    paths: Vec<PathBuf>,
+    seen: Vec<Option<SystemTime>>,
+}
+
+impl FileWatcher {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        let seen = stamps(&paths);
+        Self { paths, seen }
+    }
+
+    /// Whether any file changed since the last call, or since the watcher was created
+    pub fn poll(&mut self) -> bool {
+        let now = stamps(&self.paths);
+        if now == self.seen {
+            return false;
+        }
+        self.seen = now;
+        true
+    }
+
+    /// Wait until a file changes, checking every `interval`, and then until
+    /// none has changed for `debounce`, so a burst of saves counts as one change
+    pub async fn changed(&mut self, interval: Duration, debounce: Duration) {
+        while !self.poll() {
+            tokio::time::sleep(interval).await;
+        }
+        loop {
+            tokio::time::sleep(debounce).await;
+            if !self.poll() {
+                return;
+            }
+        }
+    }
+}
+
+fn stamps(paths: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    paths
+        .iter()
+        .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+/// A line of a [`line_diff`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+impl std::fmt::Display for DiffLine<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiffLine::Same(line) => write!(f, "  {}", line),
+            DiffLine::Removed(line) => write!(f, "- {}", line),
+            DiffLine::Added(line) => write!(f, "+ {}", line),
+        }
+    }
+}
+
+/// The lines of `new` compared to those of `old`, keeping the longest run of
+/// common lines in place
+pub fn line_diff<'a>(old: &'a str, new: &'a str) -> Vec<DiffLine<'a>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // common[i][j] is the longest common subsequence of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut diff = Vec::with_capacity(old.len().max(new.len()));
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            diff.push(DiffLine::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || common[i][j + 1] >= common[i + 1][j]) {
+            diff.push(DiffLine::Added(new[j]));
+            j += 1;
+        } else {
+            diff.push(DiffLine::Removed(old[i]));
+            i += 1;
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_diff() {
+        let old = "# Review\nLooks good.\nOne nit.\n";
+        let new = "# Review\nLooks fine.\nOne nit.\nShip it.";
+        assert_eq!(
+            line_diff(old, new),
+            [
+                DiffLine::Same("# Review"),
+                DiffLine::Added("Looks fine."),
+                DiffLine::Removed("Looks good."),
+                DiffLine::Same("One nit."),
+                DiffLine::Added("Ship it."),
+            ]
+        );
+        assert_eq!(DiffLine::Removed("x").to_string(), "- x");
+        assert!(line_diff("same", "same")
+            .iter()
+            .all(|line| matches!(line, DiffLine::Same(_))));
+    }
+
+    #[tokio::test]
+    async fn test_file_watcher() {
+        let path = std::env::temp_dir().join(format!("ellm-watch-{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut watcher = FileWatcher::new(vec![path.clone()]);
+        assert!(!watcher.poll());
+
+        std::fs::write(&path, "created").unwrap();
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            watcher.changed(Duration::from_millis(5), Duration::from_millis(20)),
+        )
+        .await
+        .unwrap();
+        assert!(!watcher.poll());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(watcher.poll());
+    }
+}
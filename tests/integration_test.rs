@@ -43,6 +43,77 @@ fn test_config_load_priority() {
     assert_eq!(config.unwrap().api_key, "sk-ant-explicit-key");
 }
 
+/// An HTTP server answering every request with `body`, for the CLI to talk to
+fn http_stub(body: String) -> String {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut buffer = [0u8; 65536];
+            let _ = stream.read(&mut buffer);
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+    format!("http://{}", address)
+}
+
+#[test]
+fn test_regex_failing_samples_exit_code() {
+    // The reply continues the "{" prefilled for structured output
+    let body = serde_json::json!({
+        "id": "msg_1",
+        "type": "message",
+        "role": "assistant",
+        "model": "claude-haiku-4-5",
+        "content": [{"type": "text", "text": r#""pattern": "^b$", "explanation": "b"}"#}],
+        "stop_reason": "end_turn",
+        "usage": {"input_tokens": 1, "output_tokens": 1},
+    });
+    let url = http_stub(body.to_string());
+
+    let home = std::env::temp_dir().join(format!("ellm-regex-test-{}", std::process::id()));
+    std::fs::create_dir_all(&home).unwrap();
+    let samples = home.join("samples.txt");
+    std::fs::write(&samples, "a\n").unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_ellm"))
+        .args(["--api-key", "sk-ant-test-key", "--base-url", &url])
+        .args([
+            "--model",
+            "claude-haiku-4-5",
+            "regex",
+            "match a",
+            "--attempts",
+            "1",
+        ])
+        .arg("--samples")
+        .arg(&samples)
+        .env("HOME", &home)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env("XDG_DATA_HOME", home.join("data"))
+        .env("XDG_CACHE_HOME", home.join("cache"))
+        .env_remove("ELLM_CONFIG_PATH")
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_dir_all(&home);
+
+    // A failure, so not 1, which `bool` answers false with
+    assert_eq!(
+        output.status.code(),
+        Some(6),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "^b$");
+}
+
 // Note: We don't test actual API calls in integration tests without mocking
 // to avoid requiring real API keys and making actual API requests during testing.
 // For real API testing, you would: